@@ -1,35 +1,21 @@
-use tokio_tungstenite::connect_async;
-use tokio_tungstenite::tungstenite::protocol::Message;
-use url::Url;
-use futures_util::{SinkExt, StreamExt};
+use quizgate_client::{Client, ClientConfig, DeliveryHandler};
+use std::io;
 
-#[tokio::main]
-async fn main() {
-    // 连接到 WebSocket 服务器
-    let url = Url::parse("ws://127.0.0.1:9001").unwrap();
-    let (mut ws_stream, _) = connect_async(url).await.expect("Failed to connect");
-
-    println!("WebSocket握手成功");
-
-    // 发送消息到服务器
-    let msg = Message::text("Hello, WebSocket server!");
-    ws_stream.send(msg).await.expect("Failed to send message");
+/// 只打印收到的玩家ID，用于本地调试服务端
+struct PrintAction;
 
-    // 接收服务器的响应
-    if let Some(Ok(message)) = ws_stream.next().await {
-        match message {
-            Message::Text(text) => {
-                println!("Received a text message from the server: {}", text);
-            }
-            Message::Binary(bin) => {
-                println!("Received a binary message from the server: {:?}", bin);
-            }
-            _ => {
-                println!("Received a different type of message from the server");
-            }
-        }
+impl DeliveryHandler for PrintAction {
+    async fn deliver(&mut self, player_id: &str) -> io::Result<()> {
+        println!("玩家{}通过了考试", player_id);
+        Ok(())
     }
+}
 
-    // 关闭 WebSocket 连接
-    ws_stream.close(None).await.expect("Failed to close the connection");
+#[tokio::main(flavor = "current_thread")]
+async fn main() {
+    env_logger::init_from_env(env_logger::Env::new().default_filter_or("info"));
+    // 连接到本地自托管的服务端
+    let client = Client::new(ClientConfig::new("ws://127.0.0.1:20000/ws", "123123123"));
+    let error = client.run(&mut PrintAction).await;
+    println!("客户端退出: {}", error);
 }
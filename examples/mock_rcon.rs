@@ -0,0 +1,45 @@
+use quizgate_client::rcon::{encode_packet, read_packet, TYPE_AUTH, TYPE_COMMAND, TYPE_RESPONSE};
+use tokio::io::AsyncWriteExt;
+use tokio::net::{TcpListener, TcpStream};
+
+/// 本地模拟的RCON服务器，打印收到的命令，用于调试RCON投递
+/// 用法: cargo run --example mock_rcon -- [地址] [密码]
+#[tokio::main(flavor = "current_thread")]
+async fn main() -> std::io::Result<()> {
+    let mut args = std::env::args().skip(1);
+    let address = args.next().unwrap_or_else(|| "127.0.0.1:25575".to_string());
+    let password = args.next().unwrap_or_else(|| "password".to_string());
+
+    let listener = TcpListener::bind(&address).await?;
+    println!("模拟RCON服务器已启动: {}", address);
+    loop {
+        let (stream, peer) = listener.accept().await?;
+        println!("{}已连接", peer);
+        let password = password.clone();
+        tokio::spawn(async move {
+            if let Err(e) = handle(stream, &password).await {
+                println!("{}断开连接: {}", peer, e);
+            }
+        });
+    }
+}
+
+async fn handle(mut stream: TcpStream, password: &str) -> std::io::Result<()> {
+    let mut authed = false;
+    loop {
+        let (id, packet_type, body) = read_packet(&mut stream).await?;
+        let response = match packet_type {
+            TYPE_AUTH => {
+                authed = body == password;
+                // 密码错误时按协议返回-1
+                encode_packet(if authed { id } else { -1 }, TYPE_COMMAND, "")
+            }
+            TYPE_COMMAND if authed => {
+                println!("执行命令: {}", body);
+                encode_packet(id, TYPE_RESPONSE, &format!("Executed: {}", body))
+            }
+            _ => encode_packet(id, TYPE_RESPONSE, "Unknown request"),
+        };
+        stream.write_all(&response).await?;
+    }
+}
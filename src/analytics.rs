@@ -0,0 +1,211 @@
+use serde::Serialize;
+use serde_json::{json, Value};
+use crate::r#struct::admin::SubmissionAnswers;
+use crate::utils::mark;
+
+/// 一个版本的试卷的统计结果
+#[derive(Serialize, Debug)]
+pub struct PaperAnalysis {
+    pub submissions: usize,
+    pub average_score: Option<f64>,
+    pub questions: Vec<QuestionAnalysis>,
+}
+
+/// 单个题目的统计结果，比例均为0~1之间的小数
+#[derive(Serialize, Debug)]
+pub struct QuestionAnalysis {
+    pub index: usize,
+    pub content: String,
+    #[serde(rename = "type")]
+    pub kind: String,
+    /// 作答的人数，未选择任何选项的不计入
+    pub answered: usize,
+    pub unanswered: usize,
+    /// 完全答对的比例，与评分时的判断一致
+    pub correct_rate: Option<f64>,
+    /// 每个选项被选择的次数，多选题中每个选中的选项分别计数
+    pub options: Vec<usize>,
+    /// 区分度：是否答对与其余题目得分之间的相关系数（修正的点二列相关），
+    /// 总分中不计入本题，否则本题的分值越高相关系数越虚高。
+    /// 接近0或为负数说明答对这道题的玩家其余题目的得分并不更高，题目可能有歧义
+    pub discrimination: Option<f64>,
+    /// 平均作答时间（秒），只统计提交了计时数据的记录
+    pub average_time: Option<f64>,
+}
+
+/// 根据提交记录统计每道题的作答情况，content为这些记录作答时使用的试卷版本
+pub fn analyze(content: &Value, submissions: &[SubmissionAnswers]) -> PaperAnalysis {
+    let questions = content["questions"].as_array().map(Vec::as_slice).unwrap_or_default();
+    let answers: Vec<Vec<Value>> = submissions
+        .iter()
+        .map(|submission| serde_json::from_str(&submission.answers).unwrap_or_default())
+        .collect();
+    let durations: Vec<Option<Vec<f64>>> = submissions
+        .iter()
+        .map(|submission| submission.durations.as_deref().and_then(|durations| serde_json::from_str(durations).ok()))
+        .collect();
+    let scores: Vec<f64> = submissions.iter().map(|submission| submission.score as f64).collect();
+
+    let questions = questions
+        .iter()
+        .enumerate()
+        .map(|(index, question)| {
+            let option_count = question["options"].as_array().map_or(0, Vec::len);
+            let mut options = vec![0; option_count];
+            let mut answered = 0;
+            let mut correct = Vec::with_capacity(submissions.len());
+            let mut rest_scores = Vec::with_capacity(submissions.len());
+            let single = json!({"questions": [question]});
+            for (answer, score) in answers.iter().zip(&scores) {
+                let answer = answer.get(index).unwrap_or(&Value::Null);
+                let selected: Vec<u64> = match answer {
+                    Value::Array(selected) => selected.iter().filter_map(Value::as_u64).collect(),
+                    other => other.as_u64().into_iter().collect(),
+                };
+                if !selected.is_empty() {
+                    answered += 1;
+                }
+                for selection in selected {
+                    if let Some(count) = options.get_mut((selection as usize).wrapping_sub(1)) {
+                        *count += 1;
+                    }
+                }
+                correct.push(if *answer == question["correct"] { 1.0 } else { 0.0 });
+                // 与评分时使用相同的规则计算本题的得分
+                rest_scores.push(score - mark(std::slice::from_ref(answer), &single) as f64);
+            }
+            let times: Vec<f64> = durations
+                .iter()
+                .filter_map(|durations| durations.as_ref()?.get(index).copied())
+                .collect();
+            QuestionAnalysis {
+                index,
+                content: question["content"].as_str().unwrap_or_default().to_string(),
+                kind: question["type"].as_str().unwrap_or_default().to_string(),
+                answered,
+                unanswered: submissions.len() - answered,
+                correct_rate: mean(&correct).map(round),
+                options,
+                discrimination: correlation(&correct, &rest_scores).map(round),
+                average_time: mean(&times).map(round),
+            }
+        })
+        .collect();
+
+    PaperAnalysis {
+        submissions: submissions.len(),
+        average_score: mean(&scores).map(round),
+        questions,
+    }
+}
+
+fn mean(values: &[f64]) -> Option<f64> {
+    if values.is_empty() {
+        return None;
+    }
+    Some(values.iter().sum::<f64>() / values.len() as f64)
+}
+
+/// 皮尔逊相关系数，任一变量没有变化时无法计算
+fn correlation(x: &[f64], y: &[f64]) -> Option<f64> {
+    let mean_x = mean(x)?;
+    let mean_y = mean(y)?;
+    let (mut covariance, mut variance_x, mut variance_y) = (0.0, 0.0, 0.0);
+    for (x, y) in x.iter().zip(y) {
+        covariance += (x - mean_x) * (y - mean_y);
+        variance_x += (x - mean_x).powi(2);
+        variance_y += (y - mean_y).powi(2);
+    }
+    if variance_x == 0.0 || variance_y == 0.0 {
+        return None;
+    }
+    Some(covariance / (variance_x * variance_y).sqrt())
+}
+
+fn round(value: f64) -> f64 {
+    (value * 1000.0).round() / 1000.0
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn paper() -> Value {
+        json!({
+            "questions": [
+                {"type": "radio", "content": "q1", "options": ["a", "b"], "correct": 1, "score": 10},
+                {"type": "multiple", "content": "q2", "options": ["a", "b", "c"], "correct": [1, 2], "score": [0, 1]}
+            ]
+        })
+    }
+
+    fn submission(answers: Value, durations: Option<Value>) -> SubmissionAnswers {
+        let score = mark(answers.as_array().unwrap(), &paper());
+        SubmissionAnswers { answers: answers.to_string(), score, durations: durations.map(|durations| durations.to_string()) }
+    }
+
+    #[test]
+    fn counts_answers_and_options() {
+        let submissions = [
+            submission(json!([1, [1, 2]]), Some(json!([2.0, 4.0]))),
+            submission(json!([2, [3]]), Some(json!([4.0, 8.0]))),
+            submission(json!([null, []]), None),
+        ];
+        let analysis = analyze(&paper(), &submissions);
+        assert_eq!(analysis.submissions, 3);
+        assert_eq!(analysis.average_score, Some(3.667));
+        let [q1, q2] = analysis.questions.as_slice() else { panic!() };
+        assert_eq!((q1.answered, q1.unanswered), (2, 1));
+        assert_eq!(q1.options, vec![1, 1]);
+        assert_eq!(q1.correct_rate, Some(0.333));
+        assert_eq!(q1.average_time, Some(3.0));
+        assert_eq!((q2.answered, q2.unanswered), (2, 1));
+        assert_eq!(q2.options, vec![1, 1, 1]);
+        assert_eq!(q2.average_time, Some(6.0));
+    }
+
+    #[test]
+    fn discrimination_excludes_the_item_itself() {
+        // 第一题与第二题的答对情况无关，第一题分值很高，计入自身时相关系数接近1
+        let submissions = [
+            submission(json!([1, [3]]), None),
+            submission(json!([2, [1, 2]]), None),
+            submission(json!([1, [1, 2]]), None),
+            submission(json!([2, [3]]), None),
+        ];
+        let analysis = analyze(&paper(), &submissions);
+        assert_eq!(analysis.questions[0].discrimination, Some(0.0));
+        assert_eq!(analysis.questions[1].discrimination, Some(0.0));
+
+        // 答对第一题的玩家第二题也答对
+        let submissions = [
+            submission(json!([1, [1, 2]]), None),
+            submission(json!([2, [3]]), None),
+            submission(json!([1, [1, 2]]), None),
+            submission(json!([2, [3]]), None),
+        ];
+        let analysis = analyze(&paper(), &submissions);
+        assert_eq!(analysis.questions[0].discrimination, Some(1.0));
+        assert_eq!(analysis.questions[1].discrimination, Some(1.0));
+    }
+
+    #[test]
+    fn undefined_statistics_are_none() {
+        let analysis = analyze(&paper(), &[]);
+        assert_eq!(analysis.average_score, None);
+        assert_eq!(analysis.questions[0].correct_rate, None);
+        assert_eq!(analysis.questions[0].discrimination, None);
+
+        // 所有人都答对时没有区分度
+        let submissions = [submission(json!([1, [3]]), None), submission(json!([1, [1, 2]]), None)];
+        assert_eq!(analyze(&paper(), &submissions).questions[0].discrimination, None);
+    }
+
+    #[test]
+    fn correlation_matches_pearson() {
+        assert_eq!(correlation(&[1.0, 2.0, 3.0], &[2.0, 4.0, 6.0]), Some(1.0));
+        assert_eq!(correlation(&[1.0, 2.0, 3.0], &[3.0, 2.0, 1.0]), Some(-1.0));
+        assert_eq!(correlation(&[1.0, 1.0], &[1.0, 2.0]), None);
+        assert_eq!(correlation(&[], &[]), None);
+    }
+}
@@ -0,0 +1,207 @@
+use clap::Parser;
+use quizgate_client::{Client, ClientConfig, CommandAction, DeliveryHandler, RconAction, WhitelistFileAction};
+use std::collections::HashMap;
+use std::io;
+use std::path::PathBuf;
+use std::process::ExitCode;
+
+/// QuizGate命令行客户端，玩家通过考试后执行命令或写入whitelist.json
+#[derive(Parser, Debug)]
+#[command(name = "quizgate-client", version)]
+struct Args {
+    /// 服务端ws地址，例如ws://127.0.0.1:20000/ws
+    #[arg(long)]
+    url: String,
+
+    /// 客户端密钥
+    #[arg(long, env = "QUIZGATE_KEY")]
+    key: String,
+
+    /// 玩家通过后执行的命令，{player}会被替换为玩家ID
+    #[arg(long)]
+    command: Option<String>,
+
+    /// 玩家的资格被撤销时执行的命令
+    #[arg(long, requires = "command")]
+    revoke_command: Option<String>,
+
+    /// 试卷指定投递目标时执行的命令，格式为NAME=CMD，可以重复指定
+    #[arg(long = "target", value_name = "NAME=CMD", requires = "command", value_parser = parse_target)]
+    targets: Vec<(String, String)>,
+
+    /// 玩家通过后写入的whitelist.json路径
+    #[arg(long)]
+    whitelist: Option<PathBuf>,
+
+    /// 服务器为离线模式，写入whitelist.json时使用离线UUID
+    #[arg(long)]
+    offline: bool,
+
+    /// 玩家通过后连接该RCON地址执行命令，例如127.0.0.1:25575
+    #[arg(long, requires = "rcon_password")]
+    rcon_address: Option<String>,
+
+    /// RCON密码
+    #[arg(long, env = "QUIZGATE_RCON_PASSWORD")]
+    rcon_password: Option<String>,
+
+    /// RCON命令模板，默认为whitelist add {player}
+    #[arg(long)]
+    rcon_command: Option<String>,
+
+    /// 试卷指定投递目标时执行的RCON命令，格式为NAME=CMD，可以重复指定
+    #[arg(long = "rcon-target", value_name = "NAME=CMD", requires = "rcon_address", value_parser = parse_target)]
+    rcon_targets: Vec<(String, String)>,
+}
+
+fn parse_target(value: &str) -> Result<(String, String), String> {
+    match value.split_once('=') {
+        Some((name, command)) if !name.is_empty() && !command.is_empty() => Ok((name.to_string(), command.to_string())),
+        _ => Err(format!("投递目标格式应为NAME=CMD: {}", value)),
+    }
+}
+
+/// 对玩家执行的操作，服务端未收到确认时会重新发送同一操作
+#[derive(Debug, Clone, PartialEq, Eq)]
+enum Operation {
+    Deliver(Option<String>),
+    Revoke,
+}
+
+async fn run_step<H: DeliveryHandler>(handler: Option<&mut H>, player_id: &str, operation: &Operation) -> io::Result<()> {
+    let Some(handler) = handler else {
+        return Ok(());
+    };
+    match operation {
+        Operation::Deliver(None) => handler.deliver(player_id).await,
+        Operation::Deliver(Some(target)) => handler.deliver_target(player_id, target).await,
+        Operation::Revoke => handler.revoke(player_id).await,
+    }
+}
+
+/// 先写入whitelist.json再执行命令，便于用命令触发`whitelist reload`
+struct Actions {
+    whitelist: Option<WhitelistFileAction>,
+    command: Option<CommandAction>,
+    rcon: Option<RconAction>,
+    /// 玩家未完成的操作及其中已成功的步骤数。命令可能发放物品等不能重复执行，
+    /// 服务端重新发送同一操作时从失败的步骤继续
+    progress: HashMap<String, (Operation, usize)>,
+}
+
+impl Actions {
+    async fn run(&mut self, player_id: &str, operation: Operation) -> io::Result<()> {
+        let done = match self.progress.remove(player_id) {
+            Some((previous, done)) if previous == operation => done,
+            _ => 0,
+        };
+        for step in done..3 {
+            let result = match step {
+                0 => run_step(self.whitelist.as_mut(), player_id, &operation).await,
+                1 => run_step(self.command.as_mut(), player_id, &operation).await,
+                _ => run_step(self.rcon.as_mut(), player_id, &operation).await,
+            };
+            if let Err(e) = result {
+                self.progress.insert(player_id.to_string(), (operation, step));
+                return Err(e);
+            }
+        }
+        Ok(())
+    }
+}
+
+impl DeliveryHandler for Actions {
+    async fn deliver(&mut self, player_id: &str) -> io::Result<()> {
+        self.run(player_id, Operation::Deliver(None)).await
+    }
+
+    async fn deliver_target(&mut self, player_id: &str, target: &str) -> io::Result<()> {
+        self.run(player_id, Operation::Deliver(Some(target.to_string()))).await
+    }
+
+    async fn revoke(&mut self, player_id: &str) -> io::Result<()> {
+        self.run(player_id, Operation::Revoke).await
+    }
+}
+
+#[tokio::main(flavor = "current_thread")]
+async fn main() -> ExitCode {
+    env_logger::init_from_env(env_logger::Env::new().default_filter_or("info"));
+    let args = Args::parse();
+    if args.command.is_none() && args.whitelist.is_none() && args.rcon_address.is_none() {
+        eprintln!("至少需要指定--command、--whitelist或--rcon-address中的一项");
+        return ExitCode::FAILURE;
+    }
+
+    let mut actions = Actions {
+        whitelist: args.whitelist.map(|path| WhitelistFileAction::new(path, args.offline)),
+        command: args.command.map(|command| {
+            let action = args
+                .targets
+                .into_iter()
+                .fold(CommandAction::new(command), |action, (name, command)| action.with_target(name, command));
+            match args.revoke_command {
+                Some(revoke) => action.with_revoke(revoke),
+                None => action,
+            }
+        }),
+        rcon: args.rcon_address.map(|address| {
+            args.rcon_targets.into_iter().fold(
+                RconAction::new(address, args.rcon_password.unwrap_or_default(), args.rcon_command),
+                |action, (name, command)| action.with_target(name, command),
+            )
+        }),
+        progress: HashMap::new(),
+    };
+    let client = Client::new(ClientConfig::new(args.url, args.key));
+    let error = client.run(&mut actions).await;
+    log::error!("{}", error);
+    ExitCode::FAILURE
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn lines(path: &PathBuf) -> Vec<String> {
+        std::fs::read_to_string(path).unwrap_or_default().lines().map(str::to_string).collect()
+    }
+
+    #[tokio::test]
+    async fn retry_skips_completed_steps() {
+        let log = std::env::temp_dir().join(format!("quizgate-client-actions-{}.log", std::process::id()));
+        let _ = std::fs::remove_file(&log);
+        // 没有服务端监听的RCON地址，最后一步总是失败
+        let listener = std::net::TcpListener::bind("127.0.0.1:0").unwrap();
+        let address = listener.local_addr().unwrap().to_string();
+        drop(listener);
+        let mut actions = Actions {
+            whitelist: None,
+            command: Some(
+                CommandAction::new(format!("echo give {{player}} >> {}", log.display()))
+                    .with_revoke(format!("echo take {{player}} >> {}", log.display())),
+            ),
+            rcon: Some(RconAction::new(address, "password", None)),
+            progress: HashMap::new(),
+        };
+
+        assert!(actions.deliver("Steve").await.is_err());
+        assert!(actions.deliver("Steve").await.is_err());
+        // 重试时不再执行已成功的命令
+        assert_eq!(lines(&log), ["give Steve"]);
+
+        // 其他操作不会继承之前的进度
+        assert!(actions.deliver_target("Steve", "vip").await.is_err());
+        assert!(actions.revoke("Steve").await.is_err());
+        assert!(actions.revoke("Steve").await.is_err());
+        assert!(actions.deliver("Alex").await.is_err());
+        assert_eq!(lines(&log), ["give Steve", "give Steve", "take Steve", "give Alex"]);
+
+        // 从失败的步骤继续，完成后清除进度，之后的同一操作重新执行所有步骤
+        actions.rcon = None;
+        actions.deliver("Alex").await.unwrap();
+        actions.deliver("Alex").await.unwrap();
+        assert_eq!(lines(&log)[4..], ["give Alex"]);
+        std::fs::remove_file(&log).unwrap();
+    }
+}
@@ -0,0 +1,253 @@
+use std::fs::{self, File};
+use std::io::{self, BufWriter, Write};
+use std::path::{Path, PathBuf};
+use std::process::ExitCode;
+use clap::{Parser, Subcommand, ValueEnum};
+use serde_json::json;
+use time::{Date, Month};
+use crate::error::NoSuchValueError;
+use crate::export::{ExportFormat, EXPORT_BATCH_SIZE};
+use crate::import::{import_paper, ImportFormat};
+use crate::r#struct::admin::SubmissionFilter;
+use crate::sql_server::SqlServer;
+use crate::validate::validate_paper;
+use crate::CONFIG;
+
+/// QuizGate服务端，不带子命令时启动HTTP服务
+#[derive(Parser, Debug)]
+#[command(name = "QuizGate-server", version)]
+pub struct Args {
+    /// 配置文件，默认为当前目录下的config.toml
+    #[arg(long, global = true)]
+    pub config: Option<PathBuf>,
+
+    #[command(subcommand)]
+    pub command: Option<Command>,
+}
+
+#[derive(Subcommand, Debug)]
+pub enum Command {
+    /// 将Moodle XML、GIFT或Markdown题库转换为QuizGate试卷
+    Import {
+        /// 题库文件
+        input: PathBuf,
+
+        /// 输出的试卷文件，默认输出到标准输出
+        #[arg(short, long)]
+        output: Option<PathBuf>,
+
+        /// 题库格式（moodle、gift或markdown），默认根据扩展名判断
+        #[arg(short, long, value_parser = parse_format)]
+        format: Option<ImportFormat>,
+
+        /// 试卷标题，默认使用题库中的标题或文件名
+        #[arg(long)]
+        title: Option<String>,
+
+        /// 及格分数，默认为总分的60%
+        #[arg(long)]
+        pass: Option<i64>,
+    },
+    /// 从数据库导出提交记录或通过记录
+    Export {
+        /// 导出的内容
+        #[arg(value_enum)]
+        kind: ExportKind,
+
+        /// 服务器id，自托管模式下为0
+        #[arg(long)]
+        server: u32,
+
+        /// 只导出该试卷的记录，可以是试卷id或slug
+        #[arg(long)]
+        paper: Option<String>,
+
+        /// 只导出该玩家的记录
+        #[arg(long)]
+        player: Option<String>,
+
+        /// 起始时间（包含），格式为YYYY-MM-DD或unix时间戳
+        #[arg(long, value_parser = parse_time)]
+        since: Option<i64>,
+
+        /// 结束时间（不包含），格式同上
+        #[arg(long, value_parser = parse_time)]
+        until: Option<i64>,
+
+        /// 导出格式（csv或ndjson）
+        #[arg(short, long, default_value = "csv", value_parser = parse_export_format)]
+        format: ExportFormat,
+
+        /// 数据库文件，默认使用配置文件中的database
+        #[arg(long)]
+        database: Option<String>,
+
+        /// 输出文件，默认输出到标准输出
+        #[arg(short, long)]
+        output: Option<PathBuf>,
+    },
+}
+
+#[derive(ValueEnum, Debug, Clone, Copy)]
+pub enum ExportKind {
+    Submissions,
+    Passes,
+}
+
+fn parse_export_format(value: &str) -> Result<ExportFormat, String> {
+    ExportFormat::from_name(value).ok_or_else(|| format!("不支持的导出格式{}，只能是csv或ndjson", value))
+}
+
+/// 日期按UTC零点计算
+fn parse_time(value: &str) -> Result<i64, String> {
+    if let Ok(timestamp) = value.parse() {
+        return Ok(timestamp);
+    }
+    let invalid = || format!("时间格式应为YYYY-MM-DD或unix时间戳: {}", value);
+    let parts: Vec<&str> = value.split('-').collect();
+    let [year, month, day] = parts[..] else {
+        return Err(invalid());
+    };
+    let year = year.parse().map_err(|_| invalid())?;
+    let month = month.parse::<u8>().ok().and_then(|month| Month::try_from(month).ok()).ok_or_else(invalid)?;
+    let day = day.parse().map_err(|_| invalid())?;
+    let date = Date::from_calendar_date(year, month, day).map_err(|_| invalid())?;
+    Ok(date.midnight().assume_utc().unix_timestamp())
+}
+
+fn parse_format(value: &str) -> Result<ImportFormat, String> {
+    ImportFormat::from_name(value).ok_or_else(|| format!("不支持的题库格式{}，只能是moodle、gift或markdown", value))
+}
+
+/// 执行子命令，返回进程的退出码
+pub async fn run(command: Command) -> ExitCode {
+    match command {
+        Command::Import { input, output, format, title, pass } => import(input, output, format, title, pass),
+        Command::Export { kind, server, paper, player, since, until, format, database, output } => {
+            let filter = SubmissionFilter {
+                player_id: player,
+                pass: matches!(kind, ExportKind::Passes).then_some(true),
+                since,
+                until,
+                ..Default::default()
+            };
+            let database = database.unwrap_or_else(|| CONFIG.database.clone());
+            match export(&database, server, paper, filter, format, output).await {
+                Ok(count) => {
+                    eprintln!("已导出{}条记录", count);
+                    ExitCode::SUCCESS
+                }
+                Err(message) => {
+                    eprintln!("{}", message);
+                    ExitCode::FAILURE
+                }
+            }
+        }
+    }
+}
+
+/// 与导出接口相同，分批读取记录并逐行写入，返回导出的记录数
+async fn export(
+    database: &str,
+    server_id: u32,
+    paper: Option<String>,
+    mut filter: SubmissionFilter,
+    format: ExportFormat,
+    output: Option<PathBuf>,
+) -> Result<usize, String> {
+    // 数据库不存在时SqlServer会新建一个空数据库
+    if !Path::new(database).exists() {
+        return Err(format!("数据库{}不存在", database));
+    }
+    let (sql_server, sql_server_handle) = SqlServer::new(database.to_string())
+        .await
+        .map_err(|e| format!("无法打开数据库{}: {}", database, e))?;
+    tokio::spawn(sql_server.run());
+
+    if let Some(reference) = paper {
+        let paper = match sql_server_handle.get_paper(reference.clone()).await {
+            Ok(paper) if paper.server_id == server_id => paper,
+            Ok(_) => return Err(format!("试卷{}不属于服务器{}", reference, server_id)),
+            Err(e) if e.is::<NoSuchValueError>() => return Err(format!("试卷{}不存在", reference)),
+            Err(e) => return Err(format!("查询试卷失败: {}", e)),
+        };
+        filter.paper_id = Some(paper.id.to_string());
+    }
+
+    let mut writer: BufWriter<Box<dyn Write>> = match &output {
+        Some(path) => BufWriter::new(Box::new(File::create(path).map_err(|e| format!("无法写入{}: {}", path.display(), e))?)),
+        None => BufWriter::new(Box::new(io::stdout())),
+    };
+    let write_error = |e: io::Error| format!("写入失败: {}", e);
+    writer.write_all(format.header().as_bytes()).map_err(write_error)?;
+    let mut count = 0;
+    let mut after_id = 0;
+    loop {
+        let records = sql_server_handle
+            .export_submissions(server_id, filter.clone(), after_id, EXPORT_BATCH_SIZE)
+            .await
+            .map_err(|e| format!("读取提交记录失败: {}", e))?;
+        for record in &records {
+            writer.write_all(format.encode(record).as_bytes()).map_err(write_error)?;
+        }
+        count += records.len();
+        match records.last() {
+            Some(record) if records.len() as u32 == EXPORT_BATCH_SIZE => after_id = record.id,
+            _ => break,
+        }
+    }
+    writer.flush().map_err(write_error)?;
+    Ok(count)
+}
+
+fn import(input: PathBuf, output: Option<PathBuf>, format: Option<ImportFormat>, title: Option<String>, pass: Option<i64>) -> ExitCode {
+    let file_name = input.file_name().map(|name| name.to_string_lossy().to_string()).unwrap_or_default();
+    let text = match fs::read_to_string(&input) {
+        Ok(text) => text,
+        Err(e) => {
+            eprintln!("无法读取{}: {}", input.display(), e);
+            return ExitCode::FAILURE;
+        }
+    };
+    let Some(format) = format.or_else(|| ImportFormat::detect(Some(&file_name), &text)) else {
+        eprintln!("无法判断{}的格式，请使用--format指定", input.display());
+        return ExitCode::FAILURE;
+    };
+    let default_title = file_name.rsplit_once('.').map_or(file_name.as_str(), |(stem, _)| stem);
+    let mut imported = match import_paper(format, &text, default_title) {
+        Ok(imported) => imported,
+        Err(message) => {
+            eprintln!("{}", message);
+            return ExitCode::FAILURE;
+        }
+    };
+    for warning in &imported.warnings {
+        eprintln!("警告 {}", warning.message);
+    }
+    if let Some(title) = title {
+        imported.content["main_title"] = json!(title);
+    }
+    if let Some(pass) = pass {
+        imported.content["pass"] = json!(pass);
+    }
+
+    let diagnostics = validate_paper(&imported.content);
+    if !diagnostics.is_empty() {
+        for diagnostic in diagnostics {
+            eprintln!("错误 {}: {}", diagnostic.path, diagnostic.message);
+        }
+        return ExitCode::FAILURE;
+    }
+    let paper = serde_json::to_string_pretty(&imported.content).unwrap_or_default();
+    match output {
+        Some(output) => {
+            if let Err(e) = fs::write(&output, paper) {
+                eprintln!("无法写入{}: {}", output.display(), e);
+                return ExitCode::FAILURE;
+            }
+            eprintln!("已导入{}道题目", imported.content["questions"].as_array().map_or(0, Vec::len));
+        }
+        None => println!("{}", paper),
+    }
+    ExitCode::SUCCESS
+}
@@ -0,0 +1,240 @@
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+use std::future::Future;
+use std::io;
+use std::path::PathBuf;
+use tokio::process::Command;
+
+/// 收到通过考试的玩家后执行的动作，返回Ok时才会向服务端确认投递
+pub trait DeliveryHandler {
+    fn deliver(&mut self, player_id: &str) -> impl Future<Output = io::Result<()>>;
+
+    /// 按试卷指定的投递目标处理玩家，默认与`deliver`相同
+    fn deliver_target(&mut self, player_id: &str, target: &str) -> impl Future<Output = io::Result<()>> {
+        let _ = target;
+        self.deliver(player_id)
+    }
+
+    /// 玩家的资格被服务器所有者撤销，默认不做任何处理
+    fn revoke(&mut self, player_id: &str) -> impl Future<Output = io::Result<()>> {
+        let _ = player_id;
+        async { Ok(()) }
+    }
+}
+
+/// 玩家ID会被拼接进命令或写入文件，只接受Java版/基岩版玩家名中可能出现的字符
+pub fn is_valid_player_id(player_id: &str) -> bool {
+    !player_id.is_empty()
+        && player_id.len() <= 32
+        && player_id.chars().all(|c| c.is_ascii_alphanumeric() || c == '_' || c == '.')
+}
+
+fn invalid_player(player_id: &str) -> io::Error {
+    io::Error::new(io::ErrorKind::InvalidInput, format!("非法的玩家ID: {:?}", player_id))
+}
+
+/// 执行一条shell命令，命令中的`{player}`会被替换为玩家ID，
+/// 同时玩家ID也会以环境变量`QUIZGATE_PLAYER`的形式传入
+#[derive(Debug, Clone)]
+pub struct CommandAction {
+    pub template: String,
+    /// 撤销资格时执行的命令，未设置时忽略撤销
+    pub revoke_template: Option<String>,
+    /// 投递目标对应的命令，未配置的目标使用`template`
+    pub targets: HashMap<String, String>,
+}
+
+impl CommandAction {
+    pub fn new(template: impl Into<String>) -> Self {
+        CommandAction { template: template.into(), revoke_template: None, targets: HashMap::new() }
+    }
+
+    pub fn with_target(mut self, target: impl Into<String>, template: impl Into<String>) -> Self {
+        self.targets.insert(target.into(), template.into());
+        self
+    }
+
+    pub fn with_revoke(mut self, template: impl Into<String>) -> Self {
+        self.revoke_template = Some(template.into());
+        self
+    }
+}
+
+impl DeliveryHandler for CommandAction {
+    async fn deliver(&mut self, player_id: &str) -> io::Result<()> {
+        run_command(&self.template, player_id).await
+    }
+
+    async fn deliver_target(&mut self, player_id: &str, target: &str) -> io::Result<()> {
+        let template = self.targets.get(target).unwrap_or(&self.template);
+        run_command(template, player_id).await
+    }
+
+    async fn revoke(&mut self, player_id: &str) -> io::Result<()> {
+        match &self.revoke_template {
+            Some(template) => run_command(template, player_id).await,
+            None => Ok(()),
+        }
+    }
+}
+
+async fn run_command(template: &str, player_id: &str) -> io::Result<()> {
+    if !is_valid_player_id(player_id) {
+        return Err(invalid_player(player_id));
+    }
+    let command = template.replace("{player}", player_id);
+    let mut process = if cfg!(windows) {
+        let mut process = Command::new("cmd");
+        process.arg("/C").arg(&command);
+        process
+    } else {
+        let mut process = Command::new("sh");
+        process.arg("-c").arg(&command);
+        process
+    };
+    let status = process.env("QUIZGATE_PLAYER", player_id).status().await?;
+    if status.success() {
+        Ok(())
+    } else {
+        Err(io::Error::other(format!("命令`{}`执行失败: {}", command, status)))
+    }
+}
+
+/// Minecraft服务端whitelist.json中的一项
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct WhitelistEntry {
+    pub uuid: String,
+    pub name: String,
+}
+
+/// 直接编辑服务端的whitelist.json，需要服务端执行`whitelist reload`后生效
+#[derive(Debug, Clone)]
+pub struct WhitelistFileAction {
+    pub path: PathBuf,
+    /// 离线模式服务器使用`OfflinePlayer:<name>`计算UUID，否则向Mojang查询
+    pub offline: bool,
+}
+
+impl WhitelistFileAction {
+    pub fn new(path: impl Into<PathBuf>, offline: bool) -> Self {
+        WhitelistFileAction { path: path.into(), offline }
+    }
+
+    async fn lookup_uuid(&self, player_id: &str) -> io::Result<String> {
+        if self.offline {
+            return Ok(offline_uuid(player_id));
+        }
+        mojang_uuid(player_id).await
+    }
+
+    fn read_entries(&self) -> io::Result<Vec<WhitelistEntry>> {
+        match std::fs::read_to_string(&self.path) {
+            Ok(contents) if contents.trim().is_empty() => Ok(Vec::new()),
+            Ok(contents) => serde_json::from_str(&contents).map_err(io::Error::other),
+            Err(e) if e.kind() == io::ErrorKind::NotFound => Ok(Vec::new()),
+            Err(e) => Err(e),
+        }
+    }
+
+    fn write_entries(&self, entries: &[WhitelistEntry]) -> io::Result<()> {
+        // 先写入临时文件再替换，避免服务端读到写了一半的文件
+        let tmp_path = self.path.with_extension("json.tmp");
+        let contents = serde_json::to_string_pretty(entries).map_err(io::Error::other)?;
+        std::fs::write(&tmp_path, contents)?;
+        std::fs::rename(&tmp_path, &self.path)
+    }
+}
+
+impl DeliveryHandler for WhitelistFileAction {
+    async fn deliver(&mut self, player_id: &str) -> io::Result<()> {
+        if !is_valid_player_id(player_id) {
+            return Err(invalid_player(player_id));
+        }
+        let mut entries = self.read_entries()?;
+        if entries.iter().any(|entry| entry.name.eq_ignore_ascii_case(player_id)) {
+            return Ok(());
+        }
+        let uuid = self.lookup_uuid(player_id).await?;
+        entries.push(WhitelistEntry { uuid, name: player_id.to_string() });
+        self.write_entries(&entries)
+    }
+
+    async fn revoke(&mut self, player_id: &str) -> io::Result<()> {
+        let mut entries = self.read_entries()?;
+        let count = entries.len();
+        entries.retain(|entry| !entry.name.eq_ignore_ascii_case(player_id));
+        if entries.len() == count {
+            return Ok(());
+        }
+        self.write_entries(&entries)
+    }
+}
+
+/// 向Mojang查询正版玩家当前使用该名称的UUID
+pub async fn mojang_uuid(player_id: &str) -> io::Result<String> {
+    #[derive(Deserialize)]
+    struct Profile {
+        id: String,
+    }
+    let url = format!("https://api.mojang.com/users/profiles/minecraft/{}", player_id);
+    let response = reqwest::get(url).await.map_err(io::Error::other)?;
+    if !response.status().is_success() {
+        return Err(io::Error::new(io::ErrorKind::NotFound, format!("Mojang中不存在玩家{}", player_id)));
+    }
+    let profile: Profile = response.json().await.map_err(io::Error::other)?;
+    Ok(hyphenate(&profile.id))
+}
+
+/// 与Java的`UUID.nameUUIDFromBytes`一致的离线UUID
+pub fn offline_uuid(player_id: &str) -> String {
+    let mut bytes = md5::compute(format!("OfflinePlayer:{}", player_id)).0;
+    bytes[6] = (bytes[6] & 0x0f) | 0x30;
+    bytes[8] = (bytes[8] & 0x3f) | 0x80;
+    uuid::Uuid::from_bytes(bytes).hyphenated().to_string()
+}
+
+fn hyphenate(id: &str) -> String {
+    match uuid::Uuid::parse_str(id) {
+        Ok(uuid) => uuid.hyphenated().to_string(),
+        Err(_) => id.to_string(),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn offline_uuid_matches_java() {
+        // 与Java的UUID.nameUUIDFromBytes("OfflinePlayer:Notch")相同
+        assert_eq!(offline_uuid("Notch"), "b50ad385-829d-3141-a216-7e7d7539ba7f");
+        assert_eq!(offline_uuid("jeb_"), "a762f560-4fce-3236-812a-b80efff0b62b");
+        // 玩家名区分大小写
+        assert_ne!(offline_uuid("notch"), offline_uuid("Notch"));
+    }
+
+    #[tokio::test]
+    async fn whitelist_file_adds_and_removes_players() {
+        let path = std::env::temp_dir().join(format!("quizgate-whitelist-{}.json", std::process::id()));
+        let _ = std::fs::remove_file(&path);
+        let mut action = WhitelistFileAction::new(&path, true);
+
+        action.deliver("Steve").await.unwrap();
+        action.deliver("Alex").await.unwrap();
+        // 已存在的玩家不区分大小写，不会重复添加
+        action.deliver("steve").await.unwrap();
+        let entries = action.read_entries().unwrap();
+        let names: Vec<_> = entries.iter().map(|entry| entry.name.as_str()).collect();
+        assert_eq!(names, ["Steve", "Alex"]);
+        assert_eq!(entries[0].uuid, offline_uuid("Steve"));
+
+        action.revoke("STEVE").await.unwrap();
+        action.revoke("Herobrine").await.unwrap();
+        let names: Vec<_> = action.read_entries().unwrap().into_iter().map(|entry| entry.name).collect();
+        assert_eq!(names, ["Alex"]);
+        assert!(!path.with_extension("json.tmp").exists());
+
+        assert_eq!(action.deliver("a b").await.unwrap_err().kind(), io::ErrorKind::InvalidInput);
+        std::fs::remove_file(&path).unwrap();
+    }
+}
@@ -0,0 +1,200 @@
+use crate::action::DeliveryHandler;
+use crate::error::ClientError;
+use crate::protocol::{ClientMessage, ServerMessage};
+use futures_util::{SinkExt, StreamExt};
+use std::time::{Duration, Instant};
+use tokio::time::{interval, sleep, timeout};
+use tokio_tungstenite::connect_async;
+use tokio_tungstenite::tungstenite::protocol::Message;
+
+#[derive(Debug, Clone)]
+pub struct ClientConfig {
+    /// 服务端ws地址，例如ws://127.0.0.1:20000/ws
+    pub url: String,
+    /// 客户端密钥
+    pub key: String,
+    /// 心跳包发送频率
+    pub heartbeat_interval: Duration,
+    /// 超过该时间未收到服务端任何消息则视为断线
+    pub timeout: Duration,
+    /// 重连等待时间的下限和上限，每次失败后翻倍
+    pub min_backoff: Duration,
+    pub max_backoff: Duration,
+}
+
+impl ClientConfig {
+    pub fn new(url: impl Into<String>, key: impl Into<String>) -> Self {
+        ClientConfig {
+            url: url.into(),
+            key: key.into(),
+            heartbeat_interval: Duration::from_secs(5),
+            timeout: Duration::from_secs(15),
+            min_backoff: Duration::from_secs(1),
+            max_backoff: Duration::from_secs(60),
+        }
+    }
+}
+
+pub struct Client {
+    config: ClientConfig,
+}
+
+/// 重连等待时间，从下限开始每次失败后翻倍，不超过上限
+#[derive(Debug)]
+struct Backoff {
+    current: Duration,
+    min: Duration,
+    max: Duration,
+}
+
+impl Backoff {
+    fn new(min: Duration, max: Duration) -> Self {
+        Backoff { current: min, min, max }
+    }
+
+    /// 返回本次的等待时间，并将下一次的等待时间翻倍
+    fn next(&mut self) -> Duration {
+        let current = self.current;
+        self.current = (self.current * 2).min(self.max);
+        current
+    }
+
+    fn reset(&mut self) {
+        self.current = self.min;
+    }
+}
+
+impl Client {
+    pub fn new(config: ClientConfig) -> Self {
+        Client { config }
+    }
+
+    /// 持续保持与服务端的连接，断线后按退避时间重连，只有在密钥被拒绝或服务器被停用时才会返回
+    pub async fn run<H: DeliveryHandler>(&self, handler: &mut H) -> ClientError {
+        let mut backoff = Backoff::new(self.config.min_backoff, self.config.max_backoff);
+        loop {
+            let mut verified = false;
+            match self.run_once(handler, &mut verified).await {
+                // 服务端正常重启，按其建议的时间重连
+                Ok(Some(reconnect_in)) => {
+                    log::info!("服务端即将重启，{:?}后重连", reconnect_in);
+                    sleep(reconnect_in).await;
+                    backoff.reset();
+                    continue;
+                }
+                Ok(None) => log::warn!("服务端关闭了连接"),
+                Err(e) if !e.is_retryable() => return e,
+                Err(e) => log::warn!("{}", e),
+            }
+            // 成功验证过说明配置无误，重新从最短等待时间开始
+            if verified {
+                backoff.reset();
+            }
+            let wait = backoff.next();
+            log::info!("{:?}后尝试重连", wait);
+            sleep(wait).await;
+        }
+    }
+
+    /// 建立一次连接并处理消息直到断开，服务端即将关闭时返回其建议的重连等待时间
+    pub async fn run_once<H: DeliveryHandler>(&self, handler: &mut H, verified: &mut bool) -> Result<Option<Duration>, ClientError> {
+        let (ws_stream, _) = connect_async(self.config.url.as_str()).await?;
+        let (mut write, mut read) = ws_stream.split();
+
+        let verify = ClientMessage::Verify { key: self.config.key.clone(), ack: true };
+        write.send(Message::text(verify.to_text())).await?;
+
+        // 等待验证结果，期间的心跳包直接跳过
+        let deadline = Instant::now() + self.config.timeout;
+        loop {
+            let remaining = deadline.saturating_duration_since(Instant::now());
+            let msg = match timeout(remaining, read.next()).await {
+                Ok(Some(msg)) => msg?,
+                Ok(None) => return Ok(None),
+                Err(_) => return Err(ClientError::Protocol("等待验证结果超时".to_string())),
+            };
+            if let Message::Text(text) = msg {
+                match ServerMessage::parse(&text) {
+                    ServerMessage::Verified { server_name } => {
+                        log::info!("验证成功，服务器名为{}", server_name);
+                        *verified = true;
+                        break;
+                    }
+                    ServerMessage::InvalidKey => return Err(ClientError::InvalidKey),
+                    ServerMessage::Duplicate => return Err(ClientError::DuplicateConnection),
+                    ServerMessage::Suspended => return Err(ClientError::Suspended),
+                    ServerMessage::GoingAway { reconnect_in } => return Ok(Some(reconnect_in)),
+                    other => return Err(ClientError::Protocol(format!("验证阶段收到意外消息: {:?}", other))),
+                }
+            }
+        }
+
+        let mut heartbeat = interval(self.config.heartbeat_interval);
+        let mut last_seen = Instant::now();
+        loop {
+            tokio::select! {
+                msg = read.next() => {
+                    let msg = match msg {
+                        Some(msg) => msg?,
+                        None => return Ok(None),
+                    };
+                    last_seen = Instant::now();
+                    match msg {
+                        Message::Text(text) => match ServerMessage::parse(&text) {
+                            ServerMessage::Deliver { player_id, target, id } => {
+                                let result = match &target {
+                                    Some(target) => handler.deliver_target(&player_id, target).await,
+                                    None => handler.deliver(&player_id).await,
+                                };
+                                match result {
+                                    Ok(()) => {
+                                        log::info!("已处理玩家{}", player_id);
+                                        let ack = ClientMessage::Ack { player_id, id };
+                                        write.send(Message::text(ack.to_text())).await?;
+                                    }
+                                    // 不确认投递，服务端会在超时后重新发送
+                                    Err(e) => log::error!("处理玩家{}失败: {}", player_id, e),
+                                }
+                            }
+                            ServerMessage::Revoke { player_id, id } => {
+                                match handler.revoke(&player_id).await {
+                                    Ok(()) => {
+                                        log::info!("已撤销玩家{}", player_id);
+                                        let ack = ClientMessage::Ack { player_id, id };
+                                        write.send(Message::text(ack.to_text())).await?;
+                                    }
+                                    Err(e) => log::error!("撤销玩家{}失败: {}", player_id, e),
+                                }
+                            }
+                            ServerMessage::GoingAway { reconnect_in } => return Ok(Some(reconnect_in)),
+                            ServerMessage::Other(text) => log::info!("服务端消息: {}", text),
+                            other => log::warn!("收到意外消息: {:?}", other),
+                        },
+                        Message::Close(_) => return Ok(None),
+                        _ => {}
+                    }
+                }
+                _ = heartbeat.tick() => {
+                    if last_seen.elapsed() > self.config.timeout {
+                        return Err(ClientError::Protocol("服务端心跳超时".to_string()));
+                    }
+                    write.send(Message::Ping(Vec::new())).await?;
+                }
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn backoff_doubles_up_to_max() {
+        let mut backoff = Backoff::new(Duration::from_secs(1), Duration::from_secs(10));
+        let waits: Vec<u64> = (0..6).map(|_| backoff.next().as_secs()).collect();
+        assert_eq!(waits, [1, 2, 4, 8, 10, 10]);
+        backoff.reset();
+        assert_eq!(backoff.next(), Duration::from_secs(1));
+    }
+}
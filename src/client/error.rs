@@ -0,0 +1,45 @@
+use std::error::Error;
+use std::fmt;
+use std::fmt::{Display, Formatter};
+
+#[derive(Debug)]
+pub enum ClientError {
+    /// 服务端拒绝了密钥，重连无意义
+    InvalidKey,
+    /// 当前密钥已有在线客户端
+    DuplicateConnection,
+    /// 服务器已被停用，恢复前无法上线
+    Suspended,
+    /// 连接或读写失败
+    Connection(tokio_tungstenite::tungstenite::Error),
+    /// 服务端返回了无法理解的内容或长时间无响应
+    Protocol(String),
+}
+
+impl ClientError {
+    /// 是否值得重连，密钥无效或服务器被停用时重连无意义；
+    /// 重复连接在旧链接超时断开后即可恢复，因此仍会重试
+    pub fn is_retryable(&self) -> bool {
+        !matches!(self, ClientError::InvalidKey | ClientError::Suspended)
+    }
+}
+
+impl Display for ClientError {
+    fn fmt(&self, f: &mut Formatter) -> fmt::Result {
+        match self {
+            ClientError::InvalidKey => write!(f, "服务端拒绝了当前key"),
+            ClientError::DuplicateConnection => write!(f, "存在使用当前key的在线客户端"),
+            ClientError::Suspended => write!(f, "服务器已被停用"),
+            ClientError::Connection(e) => write!(f, "连接出错: {}", e),
+            ClientError::Protocol(msg) => write!(f, "协议错误: {}", msg),
+        }
+    }
+}
+
+impl Error for ClientError {}
+
+impl From<tokio_tungstenite::tungstenite::Error> for ClientError {
+    fn from(e: tokio_tungstenite::tungstenite::Error) -> Self {
+        ClientError::Connection(e)
+    }
+}
@@ -0,0 +1,17 @@
+//! QuizGate客户端库
+//!
+//! 实现了QuizGate服务端`/ws`接口的客户端协议：握手验证、心跳、断线重连与投递确认，
+//! 以及通过RCON直接在Minecraft服务器上执行命令的投递方式。
+//! 适用于无法安装Java插件的服务端以及测试环境，配合`quizgate-client`可执行文件使用。
+
+pub mod action;
+pub mod client;
+pub mod error;
+pub mod protocol;
+pub mod rcon;
+
+pub use action::{CommandAction, DeliveryHandler, WhitelistFileAction};
+pub use client::{Client, ClientConfig};
+pub use error::ClientError;
+pub use protocol::{ClientMessage, ServerMessage};
+pub use rcon::{RconAction, RconClient};
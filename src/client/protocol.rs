@@ -0,0 +1,122 @@
+use serde_json::{json, Value};
+use std::time::Duration;
+
+/// 客户端发起验证
+pub const CODE_VERIFY: i64 = 0;
+/// 验证成功
+pub const CODE_VERIFIED: i64 = 1;
+/// 向客户端投递通过考试的玩家
+pub const CODE_DELIVER: i64 = 2;
+/// 客户端确认已处理投递
+pub const CODE_ACK: i64 = 3;
+/// 玩家的资格被撤销，需要移出白名单
+pub const CODE_REVOKE: i64 = 4;
+/// 服务端即将关闭，客户端应在reconnect_in秒后重连
+pub const CODE_GOING_AWAY: i64 = 5;
+/// 密钥无效
+pub const CODE_INVALID_KEY: i64 = -1;
+/// 当前密钥已有在线客户端
+pub const CODE_DUPLICATE: i64 = -2;
+/// 服务器已被运营者停用
+pub const CODE_SUSPENDED: i64 = -3;
+
+/// 服务端发送给客户端的消息
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum ServerMessage {
+    Verified { server_name: String },
+    /// target为试卷指定的投递目标，为空时使用默认动作；id为投递编号，确认时原样返回
+    Deliver { player_id: String, target: Option<String>, id: Option<u64> },
+    Revoke { player_id: String, id: Option<u64> },
+    GoingAway { reconnect_in: Duration },
+    InvalidKey,
+    Duplicate,
+    Suspended,
+    /// 无法识别的消息，例如超时提示等纯文本
+    Other(String),
+}
+
+impl ServerMessage {
+    pub fn parse(text: &str) -> ServerMessage {
+        let json: Value = match serde_json::from_str(text) {
+            Ok(json) => json,
+            Err(_) => return ServerMessage::Other(text.to_string()),
+        };
+        match json["code"].as_i64() {
+            Some(CODE_VERIFIED) => ServerMessage::Verified {
+                server_name: json["server_name"].as_str().unwrap_or_default().to_string(),
+            },
+            Some(CODE_DELIVER) => match json["msg"].as_str() {
+                Some(player_id) => ServerMessage::Deliver {
+                    player_id: player_id.to_string(),
+                    target: json["target"].as_str().map(str::to_string),
+                    id: json["id"].as_u64(),
+                },
+                None => ServerMessage::Other(text.to_string()),
+            },
+            Some(CODE_REVOKE) => match json["msg"].as_str() {
+                Some(player_id) => ServerMessage::Revoke { player_id: player_id.to_string(), id: json["id"].as_u64() },
+                None => ServerMessage::Other(text.to_string()),
+            },
+            Some(CODE_GOING_AWAY) => ServerMessage::GoingAway {
+                reconnect_in: Duration::from_secs(json["reconnect_in"].as_u64().unwrap_or_default()),
+            },
+            Some(CODE_INVALID_KEY) => ServerMessage::InvalidKey,
+            Some(CODE_DUPLICATE) => ServerMessage::Duplicate,
+            Some(CODE_SUSPENDED) => ServerMessage::Suspended,
+            _ => ServerMessage::Other(text.to_string()),
+        }
+    }
+}
+
+/// 客户端发送给服务端的消息
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum ClientMessage {
+    /// 携带密钥进行验证，`ack`为true时服务端会等待投递确认
+    Verify { key: String, ack: bool },
+    /// 确认已处理某次投递，id为空时服务端按玩家匹配
+    Ack { player_id: String, id: Option<u64> },
+}
+
+impl ClientMessage {
+    pub fn to_text(&self) -> String {
+        match self {
+            ClientMessage::Verify { key, ack } => json!({
+                "code": CODE_VERIFY,
+                "key": key,
+                "ack": ack,
+            }),
+            ClientMessage::Ack { player_id, id: Some(id) } => json!({
+                "code": CODE_ACK,
+                "msg": player_id,
+                "id": id,
+            }),
+            ClientMessage::Ack { player_id, id: None } => json!({
+                "code": CODE_ACK,
+                "msg": player_id,
+            }),
+        }
+        .to_string()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn ack_echoes_delivery_id() {
+        let msg = ServerMessage::parse(r#"{"code":4,"msg":"Steve","id":7}"#);
+        assert_eq!(msg, ServerMessage::Revoke { player_id: "Steve".to_string(), id: Some(7) });
+        let ServerMessage::Revoke { player_id, id } = msg else { unreachable!() };
+        let ack: Value = serde_json::from_str(&ClientMessage::Ack { player_id, id }.to_text()).unwrap();
+        assert_eq!(ack, json!({"code": CODE_ACK, "msg": "Steve", "id": 7}));
+    }
+
+    #[test]
+    fn deliver_without_id_still_parses() {
+        let msg = ServerMessage::parse(r#"{"code":2,"msg":"Alex","target":"vip"}"#);
+        assert_eq!(msg, ServerMessage::Deliver { player_id: "Alex".to_string(), target: Some("vip".to_string()), id: None });
+        let ack: Value = serde_json::from_str(&ClientMessage::Ack { player_id: "Alex".to_string(), id: None }.to_text()).unwrap();
+        assert_eq!(ack, json!({"code": CODE_ACK, "msg": "Alex"}));
+    }
+}
@@ -0,0 +1,273 @@
+use crate::action::{is_valid_player_id, DeliveryHandler};
+use std::collections::HashMap;
+use std::io;
+use tokio::io::{AsyncRead, AsyncReadExt, AsyncWrite, AsyncWriteExt};
+use tokio::net::TcpStream;
+use tokio::time::{timeout, Duration};
+
+/// 登录包
+pub const TYPE_AUTH: i32 = 3;
+/// 执行命令包，与登录结果包的类型相同
+pub const TYPE_COMMAND: i32 = 2;
+/// 命令执行结果包
+pub const TYPE_RESPONSE: i32 = 0;
+
+/// 单次读写超时时间
+const RCON_TIMEOUT: Duration = Duration::from_secs(5);
+
+/// 未配置命令模板时使用的命令
+pub const DEFAULT_COMMAND: &str = "whitelist add {player}";
+
+/// 撤销玩家资格时使用的命令
+pub const DEFAULT_REMOVE_COMMAND: &str = "whitelist remove {player}";
+
+/// Minecraft RCON协议客户端
+pub struct RconClient<S> {
+    stream: S,
+    next_id: i32,
+}
+
+impl RconClient<TcpStream> {
+    pub async fn connect(address: &str, password: &str) -> io::Result<RconClient<TcpStream>> {
+        let stream = timeout(RCON_TIMEOUT, TcpStream::connect(address))
+            .await
+            .map_err(|_| io::Error::new(io::ErrorKind::TimedOut, "连接超时"))??;
+        let mut client = RconClient::new(stream);
+        client.login(password).await?;
+        Ok(client)
+    }
+}
+
+impl<S: AsyncRead + AsyncWrite + Unpin> RconClient<S> {
+    pub fn new(stream: S) -> Self {
+        RconClient { stream, next_id: 1 }
+    }
+
+    pub async fn login(&mut self, password: &str) -> io::Result<()> {
+        let id = self.send(TYPE_AUTH, password).await?;
+        // 部分服务端会在登录结果前先发送一个空的响应包
+        loop {
+            let (response_id, packet_type, _) = self.receive().await?;
+            if packet_type != TYPE_COMMAND {
+                continue;
+            }
+            return if response_id == id {
+                Ok(())
+            } else {
+                Err(io::Error::new(io::ErrorKind::PermissionDenied, "RCON密码错误"))
+            };
+        }
+    }
+
+    pub async fn command(&mut self, command: &str) -> io::Result<String> {
+        let id = self.send(TYPE_COMMAND, command).await?;
+        loop {
+            let (response_id, _, body) = self.receive().await?;
+            if response_id == id {
+                return Ok(body);
+            }
+        }
+    }
+
+    async fn send(&mut self, packet_type: i32, body: &str) -> io::Result<i32> {
+        let id = self.next_id;
+        self.next_id += 1;
+        let packet = encode_packet(id, packet_type, body);
+        timeout(RCON_TIMEOUT, self.stream.write_all(&packet))
+            .await
+            .map_err(|_| io::Error::new(io::ErrorKind::TimedOut, "发送超时"))??;
+        Ok(id)
+    }
+
+    async fn receive(&mut self) -> io::Result<(i32, i32, String)> {
+        timeout(RCON_TIMEOUT, read_packet(&mut self.stream))
+            .await
+            .map_err(|_| io::Error::new(io::ErrorKind::TimedOut, "接收超时"))?
+    }
+}
+
+/// 数据包格式：长度、请求ID、类型、以\0结尾的内容和一个额外的\0，数字均为小端序
+pub fn encode_packet(id: i32, packet_type: i32, body: &str) -> Vec<u8> {
+    let length = (4 + 4 + body.len() + 2) as i32;
+    let mut packet = Vec::with_capacity(length as usize + 4);
+    packet.extend_from_slice(&length.to_le_bytes());
+    packet.extend_from_slice(&id.to_le_bytes());
+    packet.extend_from_slice(&packet_type.to_le_bytes());
+    packet.extend_from_slice(body.as_bytes());
+    packet.extend_from_slice(&[0, 0]);
+    packet
+}
+
+pub async fn read_packet<S: AsyncRead + Unpin>(stream: &mut S) -> io::Result<(i32, i32, String)> {
+    let length = stream.read_i32_le().await?;
+    if !(10..=4110).contains(&length) {
+        return Err(io::Error::new(io::ErrorKind::InvalidData, format!("数据包长度异常: {}", length)));
+    }
+    let id = stream.read_i32_le().await?;
+    let packet_type = stream.read_i32_le().await?;
+    let mut body = vec![0; length as usize - 8];
+    stream.read_exact(&mut body).await?;
+    body.truncate(body.len() - 2);
+    Ok((id, packet_type, String::from_utf8_lossy(&body).into_owned()))
+}
+
+/// 通过RCON为玩家执行命令，每次投递都会重新建立连接
+#[derive(Debug, Clone)]
+pub struct RconAction {
+    pub address: String,
+    pub password: String,
+    /// 命令模板，{player}会被替换为玩家ID
+    pub template: String,
+    /// 投递目标对应的命令模板，未配置的目标使用`template`
+    pub targets: HashMap<String, String>,
+}
+
+impl RconAction {
+    pub fn new(address: impl Into<String>, password: impl Into<String>, template: Option<String>) -> Self {
+        RconAction {
+            address: address.into(),
+            password: password.into(),
+            template: template.unwrap_or_else(|| DEFAULT_COMMAND.to_string()),
+            targets: HashMap::new(),
+        }
+    }
+
+    pub fn with_target(mut self, target: impl Into<String>, template: impl Into<String>) -> Self {
+        self.targets.insert(target.into(), template.into());
+        self
+    }
+}
+
+impl RconAction {
+    async fn run(&self, template: &str, player_id: &str) -> io::Result<()> {
+        if !is_valid_player_id(player_id) {
+            return Err(io::Error::new(io::ErrorKind::InvalidInput, format!("非法的玩家ID: {:?}", player_id)));
+        }
+        let mut client = RconClient::connect(&self.address, &self.password).await?;
+        let response = client.command(&template.replace("{player}", player_id)).await?;
+        log::info!("RCON: {}", response);
+        Ok(())
+    }
+}
+
+impl DeliveryHandler for RconAction {
+    async fn deliver(&mut self, player_id: &str) -> io::Result<()> {
+        self.run(&self.template, player_id).await
+    }
+
+    async fn deliver_target(&mut self, player_id: &str, target: &str) -> io::Result<()> {
+        let template = self.targets.get(target).unwrap_or(&self.template);
+        self.run(template, player_id).await
+    }
+
+    async fn revoke(&mut self, player_id: &str) -> io::Result<()> {
+        self.run(DEFAULT_REMOVE_COMMAND, player_id).await
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use tokio::io::DuplexStream;
+    use tokio::net::TcpListener;
+    use tokio::sync::mpsc;
+
+    /// 与examples/mock_rcon.rs相同的模拟服务端，收到的命令发送到commands
+    async fn mock_server<S: AsyncRead + AsyncWrite + Unpin>(mut stream: S, password: &str, commands: mpsc::UnboundedSender<String>) {
+        let mut authed = false;
+        while let Ok((id, packet_type, body)) = read_packet(&mut stream).await {
+            let response = match packet_type {
+                TYPE_AUTH => {
+                    authed = body == password;
+                    // 与部分服务端相同，先发送一个空的响应包
+                    stream.write_all(&encode_packet(id, TYPE_RESPONSE, "")).await.unwrap();
+                    encode_packet(if authed { id } else { -1 }, TYPE_COMMAND, "")
+                }
+                TYPE_COMMAND if authed => {
+                    let _ = commands.send(body.clone());
+                    encode_packet(id, TYPE_RESPONSE, &format!("Executed: {}", body))
+                }
+                _ => encode_packet(id, TYPE_RESPONSE, "Unknown request"),
+            };
+            if stream.write_all(&response).await.is_err() {
+                break;
+            }
+        }
+    }
+
+    fn connect_mock(password: &'static str) -> (RconClient<DuplexStream>, mpsc::UnboundedReceiver<String>) {
+        let (client, server) = tokio::io::duplex(4096);
+        let (commands_tx, commands_rx) = mpsc::unbounded_channel();
+        tokio::spawn(async move { mock_server(server, password, commands_tx).await });
+        (RconClient::new(client), commands_rx)
+    }
+
+    #[tokio::test]
+    async fn packet_round_trip() {
+        let packet = encode_packet(7, TYPE_COMMAND, "list");
+        // 长度不包含长度字段本身
+        assert_eq!(packet.len(), 4 + 4 + 4 + 4 + 2);
+        assert_eq!(i32::from_le_bytes(packet[0..4].try_into().unwrap()), 14);
+        let parsed = read_packet(&mut packet.as_slice()).await.unwrap();
+        assert_eq!(parsed, (7, TYPE_COMMAND, "list".to_string()));
+    }
+
+    #[tokio::test]
+    async fn rejects_invalid_length() {
+        let mut packet = encode_packet(1, TYPE_COMMAND, "");
+        packet[0..4].copy_from_slice(&5i32.to_le_bytes());
+        let error = read_packet(&mut packet.as_slice()).await.unwrap_err();
+        assert_eq!(error.kind(), io::ErrorKind::InvalidData);
+    }
+
+    #[tokio::test]
+    async fn command_returns_output() {
+        let (mut client, mut commands) = connect_mock("secret");
+        client.login("secret").await.unwrap();
+        assert_eq!(client.command("whitelist add Steve").await.unwrap(), "Executed: whitelist add Steve");
+        assert_eq!(client.command("list").await.unwrap(), "Executed: list");
+        assert_eq!(commands.recv().await.unwrap(), "whitelist add Steve");
+        assert_eq!(commands.recv().await.unwrap(), "list");
+    }
+
+    #[tokio::test]
+    async fn wrong_password_is_rejected() {
+        let (mut client, mut commands) = connect_mock("secret");
+        let error = client.login("wrong").await.unwrap_err();
+        assert_eq!(error.kind(), io::ErrorKind::PermissionDenied);
+        // 未登录时服务端不执行命令
+        assert_eq!(client.command("op Steve").await.unwrap(), "Unknown request");
+        assert!(commands.try_recv().is_err());
+    }
+
+    #[tokio::test]
+    async fn action_uses_target_template() {
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let address = listener.local_addr().unwrap().to_string();
+        let (commands_tx, mut commands) = mpsc::unbounded_channel();
+        tokio::spawn(async move {
+            loop {
+                let (stream, _) = listener.accept().await.unwrap();
+                tokio::spawn(mock_server(stream, "secret", commands_tx.clone()));
+            }
+        });
+
+        let mut action = RconAction::new(&address, "secret", None).with_target("vip", "lp user {player} parent add vip");
+        action.deliver("Steve").await.unwrap();
+        action.deliver_target("Alex", "vip").await.unwrap();
+        action.deliver_target("Notch", "unknown").await.unwrap();
+        action.revoke("Steve").await.unwrap();
+        assert_eq!(commands.recv().await.unwrap(), "whitelist add Steve");
+        assert_eq!(commands.recv().await.unwrap(), "lp user Alex parent add vip");
+        assert_eq!(commands.recv().await.unwrap(), "whitelist add Notch");
+        assert_eq!(commands.recv().await.unwrap(), "whitelist remove Steve");
+
+        // 非法的玩家ID不会发送给服务端
+        let error = action.deliver("Steve; op Alex").await.unwrap_err();
+        assert_eq!(error.kind(), io::ErrorKind::InvalidInput);
+
+        let mut wrong = RconAction::new(&address, "wrong", None);
+        assert_eq!(wrong.deliver("Steve").await.unwrap_err().kind(), io::ErrorKind::PermissionDenied);
+        assert!(commands.try_recv().is_err());
+    }
+}
@@ -0,0 +1,680 @@
+use std::collections::HashSet;
+use std::ops::Deref;
+use std::path::{Path, PathBuf};
+use std::sync::{Arc, OnceLock, RwLock};
+use std::{env, fs, io};
+use lettre::message::Mailbox;
+use serde::de::DeserializeOwned;
+use serde::{Deserialize, Serialize};
+use toml::value::{Table, Value};
+use crate::rcon::RconTarget;
+use crate::r#struct::paper::is_valid_slug;
+use crate::r#struct::quota::Quotas;
+use crate::r#struct::webhook::WebhookTarget;
+
+/// 未指定--config时读取的配置文件
+pub const DEFAULT_CONFIG_FILE: &str = "config.toml";
+
+/// 覆盖配置项的环境变量前缀，嵌套的配置项用两个下划线分隔，例如QUIZGATE_QUOTAS__MAX_PAPERS
+const ENV_PREFIX: &str = "QUIZGATE_";
+
+/// 服务端的配置，除self_hosted_key等少数几项外都有默认值
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct Config {
+    /// 是否为本地自托管模式
+    pub self_hosted: bool,
+    /// 自托管模式下使用0.json的客户端的key，配置了papers时可以为空
+    pub self_hosted_key: String,
+    /// 自托管模式下的其他试卷，每张试卷对应一个使用独立key的客户端
+    pub papers: Vec<LocalPaper>,
+    pub address: String,
+    pub port: u16,
+    /// 数据库文件
+    pub database: String,
+    /// HTTP服务的工作线程数
+    pub workers: usize,
+    /// 旧版本保存试卷的目录，启动时其中的试卷会被迁移到数据库
+    pub tests_dir: String,
+    pub timeouts: Timeouts,
+    /// 自托管模式下通过RCON投递，未配置时使用websocket
+    pub rcon: Option<RconTarget>,
+    /// 自托管模式下接收事件的webhook
+    pub webhooks: Vec<WebhookTarget>,
+    /// 托管模式下运营者的密钥，未配置时运营接口不可用
+    pub operator_key: Option<String>,
+    /// 访问/metrics时需携带的令牌，未配置时不需要认证，但托管模式下只输出合计值
+    pub metrics_token: Option<String>,
+    /// 托管模式下所有服务器的默认配额
+    pub quotas: Quotas,
+    /// 托管服务对外的地址，邮件中的链接以此开头
+    pub public_url: String,
+    pub captcha: CaptchaConfig,
+    pub email: EmailConfig,
+    pub features: Features,
+    /// 日志格式，日志级别仍由RUST_LOG控制
+    pub log_format: LogFormat,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, Default, PartialEq, Eq)]
+#[serde(rename_all = "lowercase")]
+pub enum LogFormat {
+    /// 便于阅读的单行文本，附加字段以key=value的形式输出
+    #[default]
+    Text,
+    /// 每行一个JSON对象，便于日志系统检索
+    Json,
+}
+
+/// 自托管模式下的一张试卷，通过/{slug}访问
+#[derive(Serialize, Deserialize, Debug, Clone)]
+#[serde(deny_unknown_fields)]
+pub struct LocalPaper {
+    pub slug: String,
+    /// 试卷文件，格式与0.json相同，也可以是json5、yaml或toml
+    pub file: String,
+    /// 接收该试卷投递的客户端使用的key
+    pub key: String,
+    /// 通过RCON投递，未配置时使用websocket
+    pub rcon: Option<RconTarget>,
+}
+
+/// 托管模式下可以单独关闭的功能，未设置时托管模式下开启，自托管模式下不可用
+#[derive(Serialize, Deserialize, Debug, Clone, Default, PartialEq, Eq)]
+#[serde(default, deny_unknown_fields)]
+pub struct Features {
+    /// 注册新服务器，需要同时开启email
+    pub registration: Option<bool>,
+    /// 发送注册验证及找回密钥的邮件
+    pub email: Option<bool>,
+    /// 通过网页上传试卷
+    pub upload: Option<bool>,
+}
+
+/// 根据运行模式及features得出的实际开启的功能
+#[derive(Debug, Clone, Copy)]
+pub struct Capabilities {
+    /// 托管模式下才有的功能：多服务器、投递方式、webhook、试卷编辑及运营接口
+    pub hosted: bool,
+    pub registration: bool,
+    pub email: bool,
+    pub upload: bool,
+}
+
+/// 托管模式下注册及找回密钥时使用的hCaptcha
+#[derive(Serialize, Deserialize, Debug, Clone)]
+#[serde(default, deny_unknown_fields)]
+pub struct CaptchaConfig {
+    /// 页面中使用的site key
+    pub site_key: String,
+    pub secret: String,
+    /// 从该文件读取secret，设置后忽略secret
+    pub secret_file: Option<String>,
+    pub verify_url: String,
+}
+
+/// 托管模式下发送验证及找回邮件的SMTP服务器
+#[derive(Serialize, Deserialize, Debug, Clone, Default, PartialEq, Eq)]
+#[serde(default, deny_unknown_fields)]
+pub struct EmailConfig {
+    pub smtp_host: String,
+    /// 未设置时使用SMTPS的默认端口
+    pub smtp_port: Option<u16>,
+    pub username: String,
+    pub password: String,
+    /// 从该文件读取password，设置后忽略password
+    pub password_file: Option<String>,
+    /// 发件人，未设置时使用username
+    pub from: String,
+}
+
+impl Default for CaptchaConfig {
+    fn default() -> Self {
+        CaptchaConfig {
+            site_key: String::new(),
+            secret: String::new(),
+            secret_file: None,
+            verify_url: "https://hcaptcha.com/siteverify".to_string(),
+        }
+    }
+}
+
+impl EmailConfig {
+    pub fn sender(&self) -> &str {
+        if self.from.is_empty() { &self.username } else { &self.from }
+    }
+}
+
+/// 各项超时及间隔，单位均为秒
+#[derive(Serialize, Deserialize, Debug, Clone)]
+#[serde(default, deny_unknown_fields)]
+pub struct Timeouts {
+    /// 向客户端发送心跳包的间隔
+    pub heartbeat_interval: u64,
+    /// 客户端超过该时间未发送心跳包或未完成验证时断开链接
+    pub client_timeout: u64,
+    /// 开启投递确认的客户端超过该时间未确认则重新投递
+    pub ack_timeout: u64,
+    /// webhook请求的超时时间
+    pub webhook_timeout: u64,
+    /// webhook第一次重试前的等待时间，之后每次翻倍
+    pub webhook_retry: u64,
+    /// 向Mojang查询玩家UUID的超时时间
+    pub uuid_lookup: u64,
+    /// 注册及找回密钥邮件中链接的有效期
+    pub email_token: u64,
+    /// 关闭时等待进行中的请求及投递完成的最长时间
+    pub shutdown_grace: u64,
+    /// 关闭时通知客户端在该时间后重连
+    pub reconnect_delay: u64,
+}
+
+impl Default for Timeouts {
+    fn default() -> Self {
+        Timeouts {
+            heartbeat_interval: 5,
+            client_timeout: 10,
+            ack_timeout: 60,
+            webhook_timeout: 10,
+            webhook_retry: 10,
+            uuid_lookup: 10,
+            email_token: 600,
+            shutdown_grace: 10,
+            reconnect_delay: 5,
+        }
+    }
+}
+
+impl Default for Config {
+    fn default() -> Self {
+        Config {
+            self_hosted: false,
+            self_hosted_key: String::new(),
+            papers: Vec::new(),
+            address: "127.0.0.1".to_string(),
+            port: 8081,
+            database: "data.db".to_string(),
+            workers: 2,
+            tests_dir: "tests".to_string(),
+            timeouts: Timeouts::default(),
+            rcon: None,
+            webhooks: Vec::new(),
+            operator_key: None,
+            metrics_token: None,
+            quotas: Quotas::default(),
+            public_url: String::new(),
+            captcha: CaptchaConfig::default(),
+            email: EmailConfig::default(),
+            features: Features::default(),
+            log_format: LogFormat::default(),
+        }
+    }
+}
+
+impl Config {
+    /// 读取配置文件并应用QUIZGATE_开头的环境变量，失败时返回发现的所有问题。
+    /// 未指定配置文件且默认的config.toml不存在时只使用默认值和环境变量
+    pub fn load(path: Option<&Path>) -> Result<Config, Vec<String>> {
+        let mut config = Config::load_lenient(path)?;
+        let mut errors = Vec::new();
+        read_secret(&mut config.captcha.secret, "captcha.secret_file", config.captcha.secret_file.as_deref(), &mut errors);
+        read_secret(&mut config.email.password, "email.password_file", config.email.password_file.as_deref(), &mut errors);
+        errors.extend(config.validate());
+        if !errors.is_empty() {
+            return Err(errors);
+        }
+        Ok(config)
+    }
+
+    /// 与load相同，但只检查各配置项的类型，不检查取值是否合理，也不读取密钥文件。
+    /// 用于导入导出等不启动服务的子命令，这些命令不需要完整的托管模式配置
+    pub fn load_lenient(path: Option<&Path>) -> Result<Config, Vec<String>> {
+        let file = path.unwrap_or(Path::new(DEFAULT_CONFIG_FILE));
+        let mut table = match fs::read_to_string(file) {
+            Ok(text) => toml::from_str::<Table>(&text).map_err(|e| vec![format!("{}格式错误: {}", file.display(), e)])?,
+            Err(e) if path.is_none() && e.kind() == io::ErrorKind::NotFound => Table::new(),
+            Err(e) => return Err(vec![format!("无法读取{}: {}", file.display(), e)]),
+        };
+
+        let mut errors = Vec::new();
+        let defaults = match Value::try_from(Config::default()) {
+            Ok(Value::Table(defaults)) => defaults,
+            _ => Table::new(),
+        };
+        let mut overrides: Vec<(String, String)> = env::vars().filter(|(name, _)| name.starts_with(ENV_PREFIX)).collect();
+        overrides.sort();
+        for (name, raw) in overrides {
+            let keys: Vec<String> = name[ENV_PREFIX.len()..].to_lowercase().split("__").map(str::to_string).collect();
+            if let Err(message) = apply_override(&mut table, &defaults, &keys, raw) {
+                errors.push(format!("环境变量{}: {}", name, message));
+            }
+        }
+
+        let mut config = Config::default();
+        for (key, value) in table {
+            match key.as_str() {
+                "self_hosted" => set(&mut config.self_hosted, &key, value, &mut errors),
+                "self_hosted_key" => set(&mut config.self_hosted_key, &key, value, &mut errors),
+                "papers" => set(&mut config.papers, &key, value, &mut errors),
+                "address" => set(&mut config.address, &key, value, &mut errors),
+                "port" => set(&mut config.port, &key, value, &mut errors),
+                "database" => set(&mut config.database, &key, value, &mut errors),
+                "workers" => set(&mut config.workers, &key, value, &mut errors),
+                "tests_dir" => set(&mut config.tests_dir, &key, value, &mut errors),
+                "timeouts" => set(&mut config.timeouts, &key, value, &mut errors),
+                "rcon" => set(&mut config.rcon, &key, value, &mut errors),
+                "webhooks" => set(&mut config.webhooks, &key, value, &mut errors),
+                "operator_key" => set(&mut config.operator_key, &key, value, &mut errors),
+                "metrics_token" => set(&mut config.metrics_token, &key, value, &mut errors),
+                "quotas" => set(&mut config.quotas, &key, value, &mut errors),
+                "public_url" => set(&mut config.public_url, &key, value, &mut errors),
+                "captcha" => set(&mut config.captcha, &key, value, &mut errors),
+                "email" => set(&mut config.email, &key, value, &mut errors),
+                "features" => set(&mut config.features, &key, value, &mut errors),
+                "log_format" => set(&mut config.log_format, &key, value, &mut errors),
+                _ => errors.push(format!("未知的配置项{}", key)),
+            }
+        }
+        if !errors.is_empty() {
+            return Err(errors);
+        }
+        Ok(config)
+    }
+
+    /// 检查各配置项的取值是否合理
+    fn validate(&self) -> Vec<String> {
+        let mut errors = Vec::new();
+        if self.self_hosted && self.self_hosted_key.trim().is_empty() && self.papers.is_empty() {
+            errors.push("自托管模式下必须设置self_hosted_key或papers".to_string());
+        }
+        if !self.self_hosted && !self.papers.is_empty() {
+            errors.push("papers只能在自托管模式下使用".to_string());
+        }
+        let mut slugs = HashSet::new();
+        let mut keys = HashSet::new();
+        keys.insert(self.self_hosted_key.as_str());
+        for (index, paper) in self.papers.iter().enumerate() {
+            if !is_valid_slug(&paper.slug) {
+                errors.push(format!("papers[{}].slug只能包含小写字母、数字和-，且不能是纯数字或保留路径", index));
+            } else if !slugs.insert(paper.slug.as_str()) {
+                errors.push(format!("papers[{}].slug与其他试卷重复: {}", index, paper.slug));
+            }
+            if paper.file.trim().is_empty() {
+                errors.push(format!("papers[{}].file不能为空", index));
+            }
+            if paper.key.trim().is_empty() {
+                errors.push(format!("papers[{}].key不能为空", index));
+            } else if !keys.insert(paper.key.as_str()) {
+                errors.push(format!("papers[{}].key与self_hosted_key或其他试卷重复", index));
+            }
+            if paper.rcon.as_ref().is_some_and(|rcon| !is_valid_rcon_address(&rcon.address)) {
+                errors.push(format!("papers[{}].rcon.address应为host:port格式", index));
+            }
+        }
+        if self.address.trim().is_empty() {
+            errors.push("address不能为空".to_string());
+        }
+        if self.port == 0 {
+            errors.push("port不能为0".to_string());
+        }
+        if self.database.trim().is_empty() {
+            errors.push("database不能为空".to_string());
+        }
+        if self.workers == 0 {
+            errors.push("workers至少为1".to_string());
+        }
+        let timeouts = [
+            ("heartbeat_interval", self.timeouts.heartbeat_interval),
+            ("client_timeout", self.timeouts.client_timeout),
+            ("ack_timeout", self.timeouts.ack_timeout),
+            ("webhook_timeout", self.timeouts.webhook_timeout),
+            ("webhook_retry", self.timeouts.webhook_retry),
+            ("uuid_lookup", self.timeouts.uuid_lookup),
+            ("email_token", self.timeouts.email_token),
+            ("shutdown_grace", self.timeouts.shutdown_grace),
+            ("reconnect_delay", self.timeouts.reconnect_delay),
+        ];
+        for (name, value) in timeouts {
+            if value == 0 {
+                errors.push(format!("timeouts.{}必须大于0", name));
+            }
+        }
+        if self.timeouts.client_timeout <= self.timeouts.heartbeat_interval {
+            errors.push("timeouts.client_timeout必须大于timeouts.heartbeat_interval".to_string());
+        }
+        if let Some(rcon) = &self.rcon {
+            if !is_valid_rcon_address(&rcon.address) {
+                errors.push(format!("rcon.address应为host:port格式: {}", rcon.address));
+            }
+        }
+        for (index, webhook) in self.webhooks.iter().enumerate() {
+            if !webhook.url.starts_with("http://") && !webhook.url.starts_with("https://") {
+                errors.push(format!("webhooks[{}].url必须以http://或https://开头", index));
+            }
+        }
+        if self.operator_key.as_deref().is_some_and(|key| key.trim().is_empty()) {
+            errors.push("operator_key不能为空".to_string());
+        }
+        if self.metrics_token.as_deref().is_some_and(|token| token.trim().is_empty()) {
+            errors.push("metrics_token不能为空".to_string());
+        }
+        if self.quotas.retention_days == Some(0) {
+            errors.push("quotas.retention_days必须大于0".to_string());
+        }
+        let features = [
+            ("registration", self.features.registration),
+            ("email", self.features.email),
+            ("upload", self.features.upload),
+        ];
+        for (name, enabled) in features {
+            if self.self_hosted && enabled == Some(true) {
+                errors.push(format!("自托管模式下不支持features.{}", name));
+            }
+        }
+        if self.features.registration == Some(true) && self.features.email == Some(false) {
+            errors.push("开启features.registration时必须同时开启features.email".to_string());
+        }
+        if self.capabilities().email {
+            // 注册及找回密钥依赖以下配置
+            if !self.public_url.starts_with("http://") && !self.public_url.starts_with("https://") {
+                errors.push("开启邮件功能时必须设置public_url，且以http://或https://开头".to_string());
+            }
+            let required = [
+                ("captcha.site_key", &self.captcha.site_key),
+                ("captcha.secret", &self.captcha.secret),
+                ("captcha.verify_url", &self.captcha.verify_url),
+                ("email.smtp_host", &self.email.smtp_host),
+                ("email.username", &self.email.username),
+                ("email.password", &self.email.password),
+            ];
+            for (name, value) in required {
+                if value.trim().is_empty() {
+                    errors.push(format!("开启邮件功能时必须设置{}", name));
+                }
+            }
+            if !self.email.sender().is_empty() && self.email.sender().parse::<Mailbox>().is_err() {
+                errors.push(format!("email.from不是有效的邮箱地址: {}", self.email.sender()));
+            }
+        }
+        errors
+    }
+}
+
+fn is_valid_rcon_address(address: &str) -> bool {
+    address.rsplit_once(':').is_some_and(|(_, port)| port.parse::<u16>().is_ok())
+}
+
+/// 反序列化一个配置项，失败时记录错误并保留默认值
+fn set<T: DeserializeOwned>(field: &mut T, key: &str, value: Value, errors: &mut Vec<String>) {
+    match value.try_into() {
+        Ok(value) => *field = value,
+        Err(e) => errors.push(format!("{}: {}", key, e.to_string().trim())),
+    }
+}
+
+/// 从文件读取密钥，去掉末尾的换行
+fn read_secret(secret: &mut String, key: &str, file: Option<&str>, errors: &mut Vec<String>) {
+    let Some(file) = file else {
+        return;
+    };
+    match fs::read_to_string(file) {
+        Ok(content) => *secret = content.trim_end().to_string(),
+        Err(e) => errors.push(format!("{}: 无法读取{}: {}", key, file, e)),
+    }
+}
+
+/// 将环境变量写入配置表。默认值或配置文件中为字符串的配置项直接使用原文，
+/// 其他配置项按toml的值解析，例如true、10或[1, 2]，无法解析时作为字符串
+fn apply_override(table: &mut Table, defaults: &Table, keys: &[String], raw: String) -> Result<(), String> {
+    let (key, parents) = keys.split_last().ok_or("配置项名称为空")?;
+    let mut table = table;
+    let mut defaults = Some(defaults);
+    for parent in parents {
+        defaults = defaults.and_then(|defaults| defaults.get(parent)).and_then(Value::as_table);
+        table = table
+            .entry(parent.clone())
+            .or_insert_with(|| Value::Table(Table::new()))
+            .as_table_mut()
+            .ok_or_else(|| format!("{}不是一个表", parent))?;
+    }
+    let is_string = table.get(key).or_else(|| defaults.and_then(|defaults| defaults.get(key))).is_some_and(Value::is_str);
+    let value = if is_string {
+        Value::String(raw)
+    } else {
+        toml::from_str::<Table>(&format!("value = {}", raw))
+            .ok()
+            .and_then(|mut parsed| parsed.remove("value"))
+            .unwrap_or(Value::String(raw))
+    };
+    table.insert(key.clone(), value);
+    Ok(())
+}
+
+impl Config {
+    pub fn capabilities(&self) -> Capabilities {
+        let hosted = !self.self_hosted;
+        let email = hosted && self.features.email.unwrap_or(true);
+        Capabilities {
+            hosted,
+            registration: email && self.features.registration.unwrap_or(true),
+            email,
+            upload: hosted && self.features.upload.unwrap_or(true),
+        }
+    }
+
+    /// 修改后需要重启才能生效的配置项，重新加载时沿用启动时的值
+    fn keep_restart_only(&self, mut reloaded: Config) -> Config {
+        let changed = [
+            ("self_hosted", self.self_hosted != reloaded.self_hosted),
+            ("address", self.address != reloaded.address),
+            ("port", self.port != reloaded.port),
+            ("database", self.database != reloaded.database),
+            ("workers", self.workers != reloaded.workers),
+            ("tests_dir", self.tests_dir != reloaded.tests_dir),
+            ("features", self.features != reloaded.features),
+            ("log_format", self.log_format != reloaded.log_format),
+        ];
+        for (name, changed) in changed {
+            if changed {
+                log::warn!("配置项{}需要重启才能生效", name);
+            }
+        }
+        reloaded.self_hosted = self.self_hosted;
+        reloaded.address = self.address.clone();
+        reloaded.port = self.port;
+        reloaded.database = self.database.clone();
+        reloaded.workers = self.workers;
+        reloaded.tests_dir = self.tests_dir.clone();
+        reloaded.features = self.features.clone();
+        reloaded.log_format = self.log_format;
+        reloaded
+    }
+}
+
+static LOADED: OnceLock<(Config, Option<PathBuf>)> = OnceLock::new();
+
+static CURRENT: RwLock<Option<Arc<Config>>> = RwLock::new(None);
+
+/// 全局配置，启动时由init设置后才能使用。
+/// 解引用得到启动时的配置，只用于需要重启才能生效的配置项，其余配置项通过current()读取
+pub struct GlobalConfig;
+
+pub static CONFIG: GlobalConfig = GlobalConfig;
+
+impl Deref for GlobalConfig {
+    type Target = Config;
+
+    fn deref(&self) -> &Config {
+        #[cfg(test)]
+        if let Some(config) = TEST_CONFIG.get() {
+            return config;
+        }
+        &LOADED.get().expect("配置尚未加载").0
+    }
+}
+
+impl GlobalConfig {
+    /// 最近一次成功加载的配置
+    pub fn current(&self) -> Arc<Config> {
+        #[cfg(test)]
+        if let Some(config) = TEST_CONFIG.get() {
+            return Arc::new(config.clone());
+        }
+        CURRENT.read().unwrap().clone().expect("配置尚未加载")
+    }
+
+    /// 启动时使用的配置文件，未通过--config指定时为默认的config.toml
+    pub fn file(&self) -> &Path {
+        LOADED.get().expect("配置尚未加载").1.as_deref().unwrap_or(Path::new(DEFAULT_CONFIG_FILE))
+    }
+
+    /// 重新读取启动时使用的配置文件及环境变量，配置有误时保留当前的配置并返回所有问题
+    pub fn reload(&self) -> Result<(), Vec<String>> {
+        let (startup, path) = LOADED.get().expect("配置尚未加载");
+        let reloaded = startup.keep_restart_only(Config::load(path.as_deref())?);
+        *CURRENT.write().unwrap() = Some(Arc::new(reloaded));
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+thread_local! {
+    static TEST_CONFIG: std::cell::Cell<Option<&'static Config>> = const { std::cell::Cell::new(None) };
+}
+
+/// 测试并行运行且需要不同的配置，只对当前线程生效，优先于init设置的全局配置
+#[cfg(test)]
+pub fn init_for_test(config: Config) {
+    TEST_CONFIG.set(Some(Box::leak(Box::new(config))));
+}
+
+/// 设置全局配置，path为启动时通过--config指定的配置文件，只有第一次调用生效
+pub fn init(config: Config, path: Option<PathBuf>) {
+    if LOADED.set((config.clone(), path)).is_ok() {
+        *CURRENT.write().unwrap() = Some(Arc::new(config));
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::path::PathBuf;
+    use std::sync::Mutex;
+
+    /// 环境变量在整个进程内共享，读取配置的测试需要依次执行
+    static ENV_LOCK: Mutex<()> = Mutex::new(());
+
+    fn write_config(name: &str, text: &str) -> PathBuf {
+        let file = env::temp_dir().join(format!("quizgate-config-{}-{}.toml", name, std::process::id()));
+        fs::write(&file, text).unwrap();
+        file
+    }
+
+    fn with_env<T>(vars: &[(&str, &str)], f: impl FnOnce() -> T) -> T {
+        let _lock = ENV_LOCK.lock().unwrap_or_else(|e| e.into_inner());
+        for (name, value) in vars {
+            env::set_var(name, value);
+        }
+        let result = f();
+        for (name, _) in vars {
+            env::remove_var(name);
+        }
+        result
+    }
+
+    #[test]
+    fn file_values_override_defaults() {
+        let file = write_config("file", "self_hosted = true\nself_hosted_key = \"k1\"\nport = 9000\n[timeouts]\nack_timeout = 30\n");
+        let config = with_env(&[], || Config::load(Some(&file))).unwrap();
+        fs::remove_file(&file).unwrap();
+        assert!(config.self_hosted);
+        assert_eq!(config.self_hosted_key, "k1");
+        assert_eq!(config.port, 9000);
+        assert_eq!(config.timeouts.ack_timeout, 30);
+        // 未出现的配置项使用默认值
+        assert_eq!(config.address, Config::default().address);
+        assert_eq!(config.timeouts.heartbeat_interval, Timeouts::default().heartbeat_interval);
+    }
+
+    #[test]
+    fn env_overrides_file_values() {
+        let file = write_config("env", "self_hosted = true\nself_hosted_key = \"k1\"\nport = 9000\n");
+        let config = with_env(
+            &[
+                ("QUIZGATE_PORT", "9100"),
+                ("QUIZGATE_TIMEOUTS__ACK_TIMEOUT", "45"),
+                // 字符串类型的配置项即使看起来像数字也按原文使用
+                ("QUIZGATE_SELF_HOSTED_KEY", "12345"),
+                ("QUIZGATE_OPERATOR_KEY", "op"),
+                ("QUIZGATE_FEATURES__EMAIL", "false"),
+            ],
+            || Config::load(Some(&file)),
+        )
+        .unwrap();
+        fs::remove_file(&file).unwrap();
+        assert_eq!(config.port, 9100);
+        assert_eq!(config.timeouts.ack_timeout, 45);
+        assert_eq!(config.self_hosted_key, "12345");
+        assert_eq!(config.operator_key.as_deref(), Some("op"));
+        assert_eq!(config.features.email, Some(false));
+    }
+
+    #[test]
+    fn invalid_values_are_all_reported() {
+        let file = write_config("invalid", "self_hosted = true\nself_hosted_key = \"k1\"\nworkers = \"two\"\n");
+        let errors = with_env(&[("QUIZGATE_PORT", "http"), ("QUIZGATE_NO_SUCH_OPTION", "1")], || Config::load(Some(&file))).unwrap_err();
+        fs::remove_file(&file).unwrap();
+        assert_eq!(errors.len(), 3, "{:?}", errors);
+        assert!(errors.iter().any(|error| error.starts_with("port")), "{:?}", errors);
+        assert!(errors.iter().any(|error| error.starts_with("workers")), "{:?}", errors);
+        assert!(errors.contains(&"未知的配置项no_such_option".to_string()), "{:?}", errors);
+    }
+
+    #[test]
+    fn lenient_loading_skips_validation() {
+        let file = write_config("lenient", "self_hosted = true\n");
+        let errors = with_env(&[], || Config::load(Some(&file))).unwrap_err();
+        assert_eq!(errors, vec!["自托管模式下必须设置self_hosted_key或papers".to_string()]);
+        assert!(with_env(&[], || Config::load_lenient(Some(&file))).is_ok());
+        fs::remove_file(&file).unwrap();
+
+        let missing = env::temp_dir().join("quizgate-config-missing.toml");
+        assert!(with_env(&[], || Config::load(Some(&missing))).is_err());
+    }
+
+    #[test]
+    fn lenient_loading_still_checks_types() {
+        // 端口为0、密钥文件不存在等只影响服务启动的问题不会阻止子命令运行
+        let file = write_config(
+            "lenient-types",
+            "port = 0\ndatabase = \"data.db\"\n[email]\npassword_file = \"/nonexistent/quizgate-password\"\n",
+        );
+        let config = with_env(&[("QUIZGATE_DATABASE", "env.db")], || Config::load_lenient(Some(&file))).unwrap();
+        assert_eq!(config.port, 0);
+        assert_eq!(config.database, "env.db");
+        assert!(config.email.password.is_empty());
+        let errors = with_env(&[], || Config::load(Some(&file))).unwrap_err();
+        assert!(errors.iter().any(|error| error.starts_with("email.password_file")), "{:?}", errors);
+        assert!(errors.contains(&"port不能为0".to_string()), "{:?}", errors);
+        fs::remove_file(&file).unwrap();
+
+        // 类型错误、未知的配置项和格式错误仍然会被报告
+        let file = write_config("lenient-invalid", "database = 1\n");
+        let errors = with_env(&[("QUIZGATE_NO_SUCH_OPTION", "1")], || Config::load_lenient(Some(&file))).unwrap_err();
+        assert_eq!(errors.len(), 2, "{:?}", errors);
+        assert!(errors.iter().any(|error| error.starts_with("database")), "{:?}", errors);
+        fs::write(&file, "database = ").unwrap();
+        assert!(with_env(&[], || Config::load_lenient(Some(&file))).is_err());
+        fs::remove_file(&file).unwrap();
+    }
+
+    #[test]
+    fn override_rejects_non_table_parents() {
+        let mut table: Table = toml::from_str("port = 1").unwrap();
+        let keys = ["port".to_string(), "value".to_string()];
+        assert!(apply_override(&mut table, &Table::new(), &keys, "1".to_string()).is_err());
+
+        let keys = ["captcha".to_string(), "secret".to_string()];
+        apply_override(&mut table, &Table::new(), &keys, "[not toml".to_string()).unwrap();
+        assert_eq!(table["captcha"]["secret"].as_str(), Some("[not toml"));
+    }
+}
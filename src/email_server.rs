@@ -1,4 +1,8 @@
 use crate::error::NoSuchValueError;
+use crate::metrics::{self, CommandReceiver, CommandSender};
+use crate::CONFIG;
+use crate::config::EmailConfig;
+use crate::r#struct::awl_type::ServerId;
 use lettre::transport::smtp::authentication::Credentials;
 use lettre::{AsyncSmtpTransport, AsyncTransport, Message, Tokio1Executor};
 use rand::distributions::Alphanumeric;
@@ -7,120 +11,201 @@ use std::collections::HashMap;
 use std::sync::Arc;
 use std::{error::Error, io};
 use tokio::sync::RwLock;
-use tokio::sync::{mpsc, oneshot};
+use tokio::sync::oneshot;
 use tokio::time::{self, Duration};
 
+/// token的有效期
+fn token_lifetime() -> Duration {
+    Duration::from_secs(CONFIG.current().timeouts.email_token)
+}
+
+/// token的用途，不同用途的token不能混用
+#[derive(Debug, Clone)]
+pub enum TokenPurpose {
+    /// 注册新的服务器
+    Register { server_name: String },
+    /// 找回已注册服务器的密钥
+    Recover { server_id: ServerId, server_name: String },
+}
+
+#[derive(Debug)]
+struct PendingToken {
+    email: String,
+    purpose: TokenPurpose,
+    created_at: time::Instant,
+}
+
 #[derive(Debug)]
 enum Command {
     SendToken {
         email: String,
-        server_name: String,
+        purpose: TokenPurpose,
         res_tx: oneshot::Sender<Result<(), Box<dyn Error + Send + Sync>>>,
     },
     ValidateToken {
         token: String,
-        res_tx: oneshot::Sender<Result<String, Box<dyn Error + Send + Sync>>>,
+        res_tx: oneshot::Sender<Result<(String, String), Box<dyn Error + Send + Sync>>>,
+    },
+    ValidateRecovery {
+        token: String,
+        res_tx: oneshot::Sender<Result<ServerId, Box<dyn Error + Send + Sync>>>,
+    },
+    /// 检查服务是否仍在处理命令
+    Ping {
+        res_tx: oneshot::Sender<()>,
+    },
+    /// 处理完之前的所有命令后退出
+    Shutdown {
+        res_tx: oneshot::Sender<()>,
     },
 }
 
 pub struct EmailServer {
-    cmd_rx: mpsc::UnboundedReceiver<Command>,
-    /// 邮件地址和(token,过期时间)的HashMap
-    tokens: Arc<RwLock<HashMap<String, (String, time::Instant)>>>,
-    /// token和服务器名的HashMap
-    server_names: Arc<RwLock<HashMap<String, String>>>,
+    cmd_rx: CommandReceiver<Command>,
+    /// token和对应的邮箱、用途及生成时间的HashMap
+    tokens: Arc<RwLock<HashMap<String, PendingToken>>>,
+    /// 创建smtp_transport时使用的配置，配置重新加载后据此判断是否需要重新创建
+    smtp_config: EmailConfig,
     smtp_transport: AsyncSmtpTransport<Tokio1Executor>,
 }
 
+fn smtp_transport(config: &EmailConfig) -> Result<AsyncSmtpTransport<Tokio1Executor>, lettre::transport::smtp::Error> {
+    let creds = Credentials::new(config.username.clone(), config.password.clone());
+    let mut smtp_transport = AsyncSmtpTransport::<Tokio1Executor>::relay(&config.smtp_host)?.credentials(creds);
+    if let Some(port) = config.smtp_port {
+        smtp_transport = smtp_transport.port(port);
+    }
+    Ok(smtp_transport.build())
+}
+
 impl EmailServer {
-    pub fn new() -> (EmailServer, EmailServerHandle) {
-        let (cmd_tx, cmd_rx) = mpsc::unbounded_channel();
+    pub fn new() -> Result<(EmailServer, EmailServerHandle), Box<dyn Error>> {
+        let (cmd_tx, cmd_rx) = metrics::command_channel("email_server");
 
-        let creds = Credentials::new("notify@toho.red".to_string(), "vi3tSdd&".to_string());
-        let smtp_transport = AsyncSmtpTransport::<Tokio1Executor>::relay("smtp.zoho.com")
-            .unwrap()
-            .credentials(creds)
-            .build();
+        let smtp_config = CONFIG.current().email.clone();
+        let smtp_transport = smtp_transport(&smtp_config)?;
 
-        (
+        Ok((
             EmailServer {
                 cmd_rx,
                 tokens: Arc::new(RwLock::new(HashMap::new())),
-                server_names: Arc::new(RwLock::new(HashMap::new())),
+                smtp_config,
                 smtp_transport,
             },
             EmailServerHandle {
                 cmd_tx,
             },
-        )
+        ))
     }
 
-    pub async fn send_token(&mut self, email: String, server_name: String) -> Result<(), Box<dyn Error + Send + Sync>> {
+    pub async fn send_token(&mut self, email: String, purpose: TokenPurpose) -> Result<(), Box<dyn Error + Send + Sync>> {
         // 生成16位随机字符串以作为token
         let token: String = thread_rng()
             .sample_iter(&Alphanumeric)
             .take(16)
             .map(char::from)
             .collect();
-        let link = format!("https://awl.toho.red/verify/{}",token);
 
+        let config = CONFIG.current();
+        if config.email != self.smtp_config {
+            self.smtp_transport = smtp_transport(&config.email)?;
+            self.smtp_config = config.email.clone();
+            log::info!("邮件配置已更新");
+        }
+        let base_url = config.public_url.trim_end_matches('/');
+        let minutes = config.timeouts.email_token.div_ceil(60);
+        let (subject, body) = match &purpose {
+            TokenPurpose::Register { .. } => {
+                let link = format!("{}/verify/{}", base_url, token);
+                ("autowhitelist验证邮件", format!(
+                    "尊敬的用户您好，欢迎注册autowhitelist服务，您的验证链接为: {} ，只需点击即可完成注册，验证链接{}分钟内有效。\n\
+                    如果您没有注册过相关服务，请忽略本邮件，祝您生活愉快。
+                ", link, minutes))
+            }
+            TokenPurpose::Recover { server_name, .. } => {
+                let link = format!("{}/recover/{}", base_url, token);
+                ("autowhitelist找回密钥", format!(
+                    "尊敬的用户您好，您正在找回服务器{}的client_key，请打开以下链接生成新的client_key: {} ，旧的client_key将立即失效。\n\
+                    链接{}分钟内有效且只能使用一次。如果这不是您本人的操作，请忽略本邮件，您的client_key不会发生变化。
+                ", server_name, link, minutes))
+            }
+        };
         let message = Message::builder()
-            .from("notify@toho.red".parse()?)
+            .from(config.email.sender().parse()?)
             .to(email.parse()?)
-            .subject("autowhitelist验证邮件")
-            .body(format!(
-                "尊敬的用户您好，欢迎注册autowhitelist服务，您的验证链接为: {} ，只需点击即可完成注册，验证链接10分钟内有效。\n\
-                如果您没有注册过相关服务，请忽略本邮件，祝您生活愉快。
-            ", link))?;
+            .subject(subject)
+            .body(body)?;
 
-        self.smtp_transport.send(message).await.map_err(|e| Box::new(e) as Box<dyn Error + Send + Sync>)?;
+        self.smtp_transport.send(message).await.map_err(|e| {
+            metrics::email_send_failed();
+            Box::new(e) as Box<dyn Error + Send + Sync>
+        })?;
 
         // 写入HashMap
-        self.tokens.write().await.insert(token.clone(), (email, time::Instant::now()));
-        self.server_names.write().await.insert(token, server_name);
+        self.tokens.write().await.insert(token, PendingToken { email, purpose, created_at: time::Instant::now() });
         Ok(())
     }
 
-    pub async fn validate_token(&self, token: String) -> Result<String, Box<dyn Error + Send + Sync>> {
+    /// 取出未过期且用途符合的token，token只能使用一次
+    async fn take_token<T>(&self, token: &str, select: impl Fn(&TokenPurpose) -> Option<T>) -> Result<(String, T), Box<dyn Error + Send + Sync>> {
         let mut tokens = self.tokens.write().await;
-        let mut server_names = self.server_names.write().await;
-        // 如果token存在则返回对应服务器名
-        if let Some((_email, _)) = tokens.remove(&token) {
-            return Ok(server_names.remove(&token).unwrap());
-        }
-        Err(Box::new(NoSuchValueError))
+        let value = tokens
+            .get(token)
+            .filter(|pending| pending.created_at.elapsed() <= token_lifetime())
+            .and_then(|pending| select(&pending.purpose))
+            .ok_or(NoSuchValueError)?;
+        let pending = tokens.remove(token).ok_or(NoSuchValueError)?;
+        Ok((pending.email, value))
+    }
+
+    pub async fn validate_token(&self, token: String) -> Result<(String, String), Box<dyn Error + Send + Sync>> {
+        // 如果token存在则返回邮箱和对应服务器名
+        self.take_token(&token, |purpose| match purpose {
+            TokenPurpose::Register { server_name } => Some(server_name.clone()),
+            _ => None,
+        }).await
+    }
+
+    pub async fn validate_recovery_token(&self, token: String) -> Result<ServerId, Box<dyn Error + Send + Sync>> {
+        let (_email, server_id) = self.take_token(&token, |purpose| match purpose {
+            TokenPurpose::Recover { server_id, .. } => Some(*server_id),
+            _ => None,
+        }).await?;
+        Ok(server_id)
     }
 
     pub async fn run(mut self) -> io::Result<()> {
         let mut interval = time::interval(Duration::from_secs(60));
         let tokens = self.tokens.clone();
-        let server_names = self.server_names.clone();
         loop {
             tokio::select! {
                 // 处理命令
                 Some(cmd) = self.cmd_rx.recv() => {
                     match cmd {
-                        Command::SendToken { email, server_name, res_tx } => {
-                            let result = self.send_token(email, server_name).await;
+                        Command::SendToken { email, purpose, res_tx } => {
+                            let result = self.send_token(email, purpose).await;
                             let _ = res_tx.send(result);
                         }
                         Command::ValidateToken { token, res_tx } => {
                             let result = self.validate_token(token).await;
                             let _ = res_tx.send(result);
                         }
+                        Command::ValidateRecovery { token, res_tx } => {
+                            let result = self.validate_recovery_token(token).await;
+                            let _ = res_tx.send(result);
+                        }
+                        Command::Ping { res_tx } => {
+                            let _ = res_tx.send(());
+                        }
+                        Command::Shutdown { res_tx } => {
+                            let _ = res_tx.send(());
+                            return Ok(());
+                        }
                     }
                 }
                 // 定时清除过期token
                 _ = interval.tick() => {
-                    let now = time::Instant::now();
-                    let tokens = tokens.write().await;
-                    let mut server_names = server_names.write().await;
-                    // 移除过期的token以及对应的服务器名
-                    for (token, (_, expire_time)) in tokens.iter() {
-                        if now - *expire_time > Duration::from_secs(600) {
-                            server_names.remove(token);
-                        }
-                    }
+                    tokens.write().await.retain(|_, pending| pending.created_at.elapsed() <= token_lifetime());
                 }
             }
         }
@@ -129,22 +214,22 @@ impl EmailServer {
 
 #[derive(Debug, Clone)]
 pub struct EmailServerHandle {
-    cmd_tx: mpsc::UnboundedSender<Command>,
+    cmd_tx: CommandSender<Command>,
 }
 
 impl EmailServerHandle {
-    /// 生成一个新的携带token的链接并发送至指定邮箱，同时存储服务器名
-    pub async fn send_token(&self, email: String, server_name: String) -> Result<(), Box<dyn Error + Send + Sync>> {
+    /// 生成一个新的携带token的链接并发送至指定邮箱，同时存储token的用途
+    pub async fn send_token(&self, email: String, purpose: TokenPurpose) -> Result<(), Box<dyn Error + Send + Sync>> {
         let (res_tx, res_rx) = oneshot::channel();
         self.cmd_tx
-            .send(Command::SendToken { email, server_name, res_tx })
+            .send(Command::SendToken { email, purpose, res_tx })
             .unwrap();
 
         res_rx.await.unwrap()
     }
 
-    /// 当用户点击url时验证token合法性，如果合法则返回服务器名
-    pub async fn validate_token(&self, token: String) -> Result<String, Box<dyn Error + Send + Sync>> {
+    /// 当用户点击注册链接时验证token合法性，如果合法则返回邮箱和服务器名
+    pub async fn validate_token(&self, token: String) -> Result<(String, String), Box<dyn Error + Send + Sync>> {
         let (res_tx, res_rx) = oneshot::channel();
         self.cmd_tx
             .send(Command::ValidateToken { token, res_tx })
@@ -156,6 +241,35 @@ impl EmailServerHandle {
             Err(e) => Err(e.into()),
         }
     }
+
+    /// 验证找回密钥的token，如果合法则返回服务器id
+    pub async fn validate_recovery_token(&self, token: String) -> Result<ServerId, Box<dyn Error + Send + Sync>> {
+        let (res_tx, res_rx) = oneshot::channel();
+        self.cmd_tx
+            .send(Command::ValidateRecovery { token, res_tx })
+            .unwrap();
+
+        match res_rx.await{
+            Ok(Ok(res)) => Ok(res),
+            Ok(Err(e)) => Err(e),
+            Err(e) => Err(e.into()),
+        }
+    }
+
+    /// 服务已退出时返回false，正在发送邮件时需要等待发送完毕
+    pub async fn ping(&self) -> bool {
+        let (res_tx, res_rx) = oneshot::channel();
+        self.cmd_tx.send(Command::Ping { res_tx }).is_ok() && res_rx.await.is_ok()
+    }
+
+    /// 等待正在发送的邮件发送完毕后退出
+    pub async fn shutdown(&self) {
+        let (res_tx, res_rx) = oneshot::channel();
+        self.cmd_tx
+            .send(Command::Shutdown { res_tx })
+            .unwrap();
+        let _ = res_rx.await;
+    }
 }
 
 // 单元测试（这一个服务bug实在太多了被迫复习了一下）
@@ -9,7 +9,14 @@ pub struct DuplicateConnectionsError;
 pub struct NoSuchValueError;
 
 #[derive(Debug)]
-pub struct CreateSqlPoolError;
+pub struct SlugConflictError;
+
+#[derive(Debug)]
+pub struct VersionConflictError;
+
+#[derive(Debug)]
+pub struct ServerSuspendedError;
+
 
 impl Display for NoSuchValueError {
     fn fmt(&self, f: &mut Formatter) -> fmt::Result {
@@ -23,13 +30,28 @@ impl Display for DuplicateConnectionsError {
     }
 }
 
-impl Display for CreateSqlPoolError {
+impl Display for SlugConflictError {
+    fn fmt(&self, f: &mut Formatter) -> fmt::Result {
+        write!(f, "该slug已被其他试卷使用")
+    }
+}
+
+impl Display for VersionConflictError {
+    fn fmt(&self, f: &mut Formatter) -> fmt::Result {
+        write!(f, "试卷已被修改，请刷新后重试")
+    }
+}
+
+impl Display for ServerSuspendedError {
     fn fmt(&self, f: &mut Formatter) -> fmt::Result {
-        write!(f, "创建sql池失败") // user-facing output
+        write!(f, "该服务器已被停用，请联系运营者")
     }
 }
 
 impl Error for NoSuchValueError {}
+impl Error for ServerSuspendedError {}
+impl Error for VersionConflictError {}
+impl Error for SlugConflictError {}
 impl Error for DuplicateConnectionsError {}
 
 unsafe impl Send for DuplicateConnectionsError {}
@@ -38,5 +60,3 @@ unsafe impl Sync for DuplicateConnectionsError {}
 unsafe impl Send for NoSuchValueError {}
 unsafe impl Sync for NoSuchValueError {}
 
-unsafe impl Send for CreateSqlPoolError {}
-unsafe impl Sync for CreateSqlPoolError {}
@@ -0,0 +1,167 @@
+use time::format_description::well_known::Rfc3339;
+use time::OffsetDateTime;
+use serde_json::json;
+use crate::r#struct::admin::SubmissionRecord;
+
+/// 导出时每次从数据库读取的记录数
+pub const EXPORT_BATCH_SIZE: u32 = 500;
+
+/// 导出提交记录的格式
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ExportFormat {
+    Csv,
+    /// 每行一个json对象
+    Ndjson,
+}
+
+const CSV_COLUMNS: [&str; 11] = [
+    "id", "player", "uuid", "paper_id", "paper_version", "score", "pass", "revoked", "ip_address", "time", "timestamp",
+];
+
+impl ExportFormat {
+    pub fn from_name(name: &str) -> Option<Self> {
+        match name.to_ascii_lowercase().as_str() {
+            "csv" => Some(ExportFormat::Csv),
+            "ndjson" | "jsonl" => Some(ExportFormat::Ndjson),
+            _ => None,
+        }
+    }
+
+    pub fn content_type(self) -> &'static str {
+        match self {
+            ExportFormat::Csv => "text/csv; charset=utf-8",
+            ExportFormat::Ndjson => "application/x-ndjson",
+        }
+    }
+
+    pub fn extension(self) -> &'static str {
+        match self {
+            ExportFormat::Csv => "csv",
+            ExportFormat::Ndjson => "ndjson",
+        }
+    }
+
+    /// 文件开头的内容，csv为表头
+    pub fn header(self) -> String {
+        match self {
+            ExportFormat::Csv => format!("{}\r\n", CSV_COLUMNS.join(",")),
+            ExportFormat::Ndjson => String::new(),
+        }
+    }
+
+    /// 将一条记录编码为一行
+    pub fn encode(self, record: &SubmissionRecord) -> String {
+        let time = format_time(record.created_at);
+        match self {
+            ExportFormat::Csv => {
+                let fields = [
+                    record.id.to_string(),
+                    csv_field(&record.player_id),
+                    csv_field(record.player_uuid.as_deref().unwrap_or_default()),
+                    csv_field(&record.paper_id),
+                    record.paper_version.map(|version| version.to_string()).unwrap_or_default(),
+                    record.score.to_string(),
+                    record.pass.to_string(),
+                    record.revoked.to_string(),
+                    csv_field(&record.ip_address),
+                    time,
+                    record.created_at.to_string(),
+                ];
+                format!("{}\r\n", fields.join(","))
+            }
+            ExportFormat::Ndjson => {
+                let line = json!({
+                    "id": record.id,
+                    "player": record.player_id,
+                    "uuid": record.player_uuid,
+                    "paper_id": record.paper_id,
+                    "paper_version": record.paper_version,
+                    "score": record.score,
+                    "pass": record.pass,
+                    "revoked": record.revoked,
+                    "ip_address": record.ip_address,
+                    "time": time,
+                    "timestamp": record.created_at,
+                });
+                format!("{}\n", line)
+            }
+        }
+    }
+}
+
+/// 包含逗号、引号或换行的字段需要用引号包裹。
+/// 以`=`、`+`、`-`、`@`、制表符或回车开头的字段会被电子表格当作公式执行，在前面加上`'`
+fn csv_field(value: &str) -> String {
+    let value = if value.starts_with(['=', '+', '-', '@', '\t', '\r']) {
+        format!("'{}", value)
+    } else {
+        value.to_string()
+    };
+    if value.contains([',', '"', '\r', '\n']) {
+        format!("\"{}\"", value.replace('"', "\"\""))
+    } else {
+        value
+    }
+}
+
+/// 以RFC 3339格式表示的UTC时间
+fn format_time(timestamp: i64) -> String {
+    OffsetDateTime::from_unix_timestamp(timestamp)
+        .ok()
+        .and_then(|time| time.format(&Rfc3339).ok())
+        .unwrap_or_default()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn record(player_id: &str) -> SubmissionRecord {
+        SubmissionRecord {
+            id: 7,
+            paper_id: "survival".to_string(),
+            paper_version: Some(2),
+            player_id: player_id.to_string(),
+            player_uuid: None,
+            score: 80,
+            pass: true,
+            revoked: false,
+            ip_address: "1.2.3.4".to_string(),
+            created_at: 0,
+        }
+    }
+
+    #[test]
+    fn csv_field_quotes_and_escapes_formulas() {
+        assert_eq!(csv_field("Steve"), "Steve");
+        assert_eq!(csv_field("a,b"), "\"a,b\"");
+        assert_eq!(csv_field("say \"hi\""), "\"say \"\"hi\"\"\"");
+        assert_eq!(csv_field("=HYPERLINK(\"http://x\")"), "\"'=HYPERLINK(\"\"http://x\"\")\"");
+        for formula in ["=1+1", "+1", "-1", "@SUM(A1)", "\tx"] {
+            assert_eq!(csv_field(formula), format!("'{}", formula));
+        }
+        assert_eq!(csv_field("\r\n"), "\"'\r\n\"");
+        // 只检查开头的字符
+        assert_eq!(csv_field("a=1"), "a=1");
+    }
+
+    #[test]
+    fn encode_matches_header() {
+        let header = ExportFormat::Csv.header();
+        let line = ExportFormat::Csv.encode(&record("Steve"));
+        assert_eq!(line, "7,Steve,,survival,2,80,true,false,1.2.3.4,1970-01-01T00:00:00Z,0\r\n");
+        assert_eq!(line.split(',').count(), header.split(',').count());
+
+        let line = ExportFormat::Csv.encode(&record("=cmd"));
+        assert!(line.starts_with("7,'=cmd,"), "{}", line);
+
+        let line = ExportFormat::Ndjson.encode(&record("=cmd"));
+        assert!(line.ends_with('\n'));
+        let value: serde_json::Value = serde_json::from_str(&line).unwrap();
+        // json中的字段原样保留
+        assert_eq!(value["player"], "=cmd");
+        assert_eq!(value["paper_version"], 2);
+        assert_eq!(value["uuid"], serde_json::Value::Null);
+        assert_eq!(value["time"], "1970-01-01T00:00:00Z");
+    }
+}
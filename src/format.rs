@@ -0,0 +1,164 @@
+use serde_json::Value;
+
+/// 试卷文件支持的格式，解析后统一转换为json保存
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum PaperFormat {
+    Json,
+    /// 同时兼容带注释的jsonc
+    Json5,
+    Yaml,
+    Toml,
+}
+
+/// 自托管模式下依次查找的试卷文件
+pub const SELF_HOSTED_PAPER_FILES: [&str; 6] = ["0.json", "0.json5", "0.jsonc", "0.yaml", "0.yml", "0.toml"];
+
+impl PaperFormat {
+    /// 根据文件扩展名判断格式，无法识别时返回None
+    pub fn from_file_name(file_name: &str) -> Option<Self> {
+        let (_, extension) = file_name.rsplit_once('.')?;
+        match extension.to_ascii_lowercase().as_str() {
+            "json" => Some(PaperFormat::Json),
+            "json5" | "jsonc" => Some(PaperFormat::Json5),
+            "yaml" | "yml" => Some(PaperFormat::Yaml),
+            "toml" => Some(PaperFormat::Toml),
+            _ => None,
+        }
+    }
+
+    /// 根据内容推测格式：以{或注释开头的视为json，含有[表头]或key = value的视为toml，其余视为yaml
+    pub fn sniff(text: &str) -> Self {
+        let first_line = text
+            .lines()
+            .map(str::trim)
+            .find(|line| !line.is_empty() && !line.starts_with('#'))
+            .unwrap_or_default();
+        if first_line.starts_with('{') || first_line.starts_with("//") || first_line.starts_with("/*") {
+            PaperFormat::Json
+        } else if first_line.starts_with('[') || first_line.split_once('=').is_some_and(|(key, _)| !key.contains(':')) {
+            PaperFormat::Toml
+        } else {
+            PaperFormat::Yaml
+        }
+    }
+
+    pub fn name(self) -> &'static str {
+        match self {
+            PaperFormat::Json => "json",
+            PaperFormat::Json5 => "json5",
+            PaperFormat::Yaml => "yaml",
+            PaperFormat::Toml => "toml",
+        }
+    }
+
+    fn parse(self, text: &str) -> Result<Value, String> {
+        match self {
+            PaperFormat::Json => serde_json::from_str(text).map_err(|e| e.to_string()),
+            PaperFormat::Json5 => json5::from_str(text).map_err(|e| e.to_string()),
+            PaperFormat::Yaml => serde_yaml::from_str(text).map_err(|e| e.to_string()),
+            PaperFormat::Toml => toml::from_str(text).map_err(|e| e.to_string()),
+        }
+    }
+}
+
+/// 解析试卷文件，优先按文件名判断格式，否则根据内容推测。
+/// 严格的json解析失败时会再按json5解析，因此带注释的.json文件也可以使用
+pub fn parse_paper(text: &str, file_name: Option<&str>) -> Result<Value, String> {
+    let text = text.trim_start_matches('\u{feff}');
+    let format = file_name
+        .and_then(PaperFormat::from_file_name)
+        .unwrap_or_else(|| PaperFormat::sniff(text));
+    match format.parse(text) {
+        Ok(value) => Ok(value),
+        Err(_) if format == PaperFormat::Json => PaperFormat::Json5
+            .parse(text)
+            .map_err(|e| format!("{}格式错误: {}", format.name(), e)),
+        Err(e) => Err(format!("{}格式错误: {}", format.name(), e)),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    fn expected() -> Value {
+        json!({
+            "main_title": "生存服入服考试",
+            "score": 2,
+            "pass": 1,
+            "questions": [
+                {"type": "radio", "content": "苦力怕怕什么？", "options": ["猫", "狗"], "correct": 1, "score": 2}
+            ]
+        })
+    }
+
+    const JSON5: &str = r#"
+// 带注释的json
+{
+  main_title: '生存服入服考试',
+  score: 2,
+  pass: 1,
+  questions: [
+    {type: "radio", content: "苦力怕怕什么？", options: ["猫", "狗"], correct: 1, score: 2,},
+  ],
+}"#;
+
+    const YAML: &str = "
+# 注释
+main_title: 生存服入服考试
+score: 2
+pass: 1
+questions:
+  - type: radio
+    content: 苦力怕怕什么？
+    options: [猫, 狗]
+    correct: 1
+    score: 2
+";
+
+    const TOML: &str = r#"
+main_title = "生存服入服考试"
+score = 2
+pass = 1
+
+[[questions]]
+type = "radio"
+content = "苦力怕怕什么？"
+options = ["猫", "狗"]
+correct = 1
+score = 2
+"#;
+
+    #[test]
+    fn all_formats_produce_the_same_paper() {
+        let json = expected().to_string();
+        assert_eq!(parse_paper(&json, Some("0.json")).unwrap(), expected());
+        assert_eq!(parse_paper(JSON5, Some("0.json5")).unwrap(), expected());
+        assert_eq!(parse_paper(YAML, Some("paper.YML")).unwrap(), expected());
+        assert_eq!(parse_paper(TOML, Some("paper.toml")).unwrap(), expected());
+    }
+
+    #[test]
+    fn format_is_sniffed_without_a_file_name() {
+        assert_eq!(PaperFormat::sniff(JSON5), PaperFormat::Json);
+        assert_eq!(PaperFormat::sniff(YAML), PaperFormat::Yaml);
+        assert_eq!(PaperFormat::sniff(TOML), PaperFormat::Toml);
+        assert_eq!(PaperFormat::sniff("url: \"https://a?b=c\""), PaperFormat::Yaml);
+        for text in [JSON5, YAML, TOML] {
+            assert_eq!(parse_paper(&format!("\u{feff}{}", text), None).unwrap(), expected());
+        }
+    }
+
+    #[test]
+    fn json_files_with_comments_fall_back_to_json5() {
+        assert_eq!(parse_paper(JSON5, Some("0.json")).unwrap(), expected());
+    }
+
+    #[test]
+    fn errors_name_the_format() {
+        assert!(parse_paper("{\"main_title\": ", Some("0.json")).unwrap_err().starts_with("json格式错误"));
+        assert!(parse_paper("main_title: [", Some("0.yaml")).unwrap_err().starts_with("yaml格式错误"));
+        assert!(parse_paper("main_title = ", Some("0.toml")).unwrap_err().starts_with("toml格式错误"));
+    }
+}
@@ -0,0 +1,233 @@
+use super::{multiple, radio, ImportedQuestions};
+
+/// GIFT中需要转义的特殊字符
+const SPECIAL: [char; 7] = ['~', '=', '#', '{', '}', ':', '\\'];
+
+/// 查找第一个未转义的字符
+fn find_unescaped(text: &str, target: impl Fn(char) -> bool) -> Option<usize> {
+    let mut escaped = false;
+    for (index, c) in text.char_indices() {
+        if escaped {
+            escaped = false;
+        } else if c == '\\' {
+            escaped = true;
+        } else if target(c) {
+            return Some(index);
+        }
+    }
+    None
+}
+
+fn unescape(text: &str) -> String {
+    let mut result = String::new();
+    let mut chars = text.chars().peekable();
+    while let Some(c) = chars.next() {
+        match (c, chars.peek()) {
+            ('\\', Some('n')) => {
+                chars.next();
+                result.push('\n');
+            }
+            ('\\', Some(next)) if SPECIAL.contains(next) => {
+                result.push(*next);
+                chars.next();
+            }
+            _ => result.push(c),
+        }
+    }
+    result.trim().to_string()
+}
+
+/// 去掉::标题::和[html]之类的格式标记
+fn strip_title(text: &str) -> &str {
+    let mut text = text.trim();
+    if let Some(rest) = text.strip_prefix("::") {
+        if let Some(end) = rest.find("::") {
+            text = rest[end + 2..].trim_start();
+        }
+    }
+    for format in ["[html]", "[moodle]", "[plain]", "[markdown]"] {
+        if let Some(rest) = text.strip_prefix(format) {
+            return rest.trim_start();
+        }
+    }
+    text
+}
+
+/// 一个答案，weight为~%50%这样的得分百分比
+struct Answer {
+    text: String,
+    correct: bool,
+    weight: Option<f64>,
+}
+
+/// 拆分{}中的答案，每个答案以未转义的=或~开头，#之后的反馈会被丢弃
+fn split_answers(body: &str) -> Vec<Answer> {
+    let mut answers = Vec::new();
+    let mut rest = body.trim();
+    while let Some(start) = find_unescaped(rest, |c| c == '=' || c == '~') {
+        let correct = rest[start..].starts_with('=');
+        let after = &rest[start + 1..];
+        let end = find_unescaped(after, |c| c == '=' || c == '~').unwrap_or(after.len());
+        let mut text = &after[..end];
+        if let Some(feedback) = find_unescaped(text, |c| c == '#') {
+            text = &text[..feedback];
+        }
+        let mut weight = None;
+        if let Some(weighted) = text.trim_start().strip_prefix('%') {
+            if let Some((value, remain)) = weighted.split_once('%') {
+                weight = value.trim().parse().ok();
+                text = remain;
+            }
+        }
+        answers.push(Answer { text: unescape(text), correct, weight });
+        rest = &after[end..];
+    }
+    answers
+}
+
+/// 解析GIFT格式，支持单选、多选（~%50%这样带权重的选项）和判断题
+pub(super) fn parse(text: &str) -> ImportedQuestions {
+    let mut imported = ImportedQuestions::default();
+
+    // 以空行分隔题目，记录每道题开始的行号
+    let mut blocks: Vec<(usize, String)> = Vec::new();
+    let mut current: Option<(usize, String)> = None;
+    for (number, line) in text.lines().enumerate() {
+        let trimmed = line.trim();
+        if trimmed.starts_with("//") || trimmed.starts_with("$CATEGORY:") {
+            continue;
+        }
+        if trimmed.is_empty() {
+            blocks.extend(current.take());
+            continue;
+        }
+        match &mut current {
+            Some((_, block)) => {
+                block.push('\n');
+                block.push_str(line);
+            }
+            None => current = Some((number + 1, line.to_string())),
+        }
+    }
+    blocks.extend(current);
+
+    for (line, block) in blocks {
+        let location = format!("第{}行", line);
+        let block = strip_title(&block);
+        let Some(open) = find_unescaped(block, |c| c == '{') else {
+            imported.warn(location, "没有答案，已跳过");
+            continue;
+        };
+        let Some(close) = find_unescaped(&block[open..], |c| c == '}').map(|close| open + close) else {
+            imported.warn(location, "答案缺少结尾的}，已跳过");
+            continue;
+        };
+        // 填空形式的题目用下划线代替答案的位置
+        let before = unescape(&block[..open]);
+        let after = unescape(&block[close + 1..]);
+        let content = if after.is_empty() { before } else { format!("{} _____ {}", before, after) };
+        let body = block[open + 1..close].trim();
+
+        let keyword = body.split('#').next().unwrap_or_default().trim().to_ascii_uppercase();
+        if matches!(keyword.as_str(), "T" | "TRUE" | "F" | "FALSE") {
+            let options = vec!["正确".to_string(), "错误".to_string()];
+            let correct = if keyword.starts_with('T') { 1 } else { 2 };
+            imported.questions.push(radio(content, options, correct, 1));
+            continue;
+        }
+        if body.is_empty() {
+            imported.warn(location, "不支持的题型essay，已跳过");
+            continue;
+        }
+        if body.starts_with('#') {
+            imported.warn(location, "不支持的题型numerical，已跳过");
+            continue;
+        }
+        let answers = split_answers(body);
+        if answers.iter().any(|answer| answer.text.contains("->")) {
+            imported.warn(location, "不支持的题型matching，已跳过");
+            continue;
+        }
+        if answers.iter().all(|answer| answer.correct) {
+            imported.warn(location, "不支持的题型shortanswer，已跳过");
+            continue;
+        }
+
+        let correct: Vec<usize> = answers
+            .iter()
+            .enumerate()
+            .filter(|(_, answer)| answer.correct || answer.weight.is_some_and(|weight| weight > 0.0))
+            .map(|(index, _)| index + 1)
+            .collect();
+        let weighted = answers.iter().any(|answer| answer.weight.is_some());
+        let options = answers.into_iter().map(|answer| answer.text).collect();
+        match correct.len() {
+            0 => imported.warn(location, "没有正确答案，已跳过"),
+            1 if !weighted => imported.questions.push(radio(content, options, correct[0], 1)),
+            _ => imported.questions.push(multiple(content, options, correct, 1)),
+        }
+    }
+    imported
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    #[test]
+    fn parses_choice_and_true_false_questions() {
+        let text = "\
+// 注释和分类会被忽略
+$CATEGORY: survival
+
+::q1:: 苦力怕怕什么？ {=猫 ~狗 ~猪 #反馈}
+
+::q2:: [html]哪些方块可以合成工作台？ {~%50%橡木木板 ~%50%白桦木板 ~%-100%圆石}
+
+下界没有水。{T}
+";
+        let imported = parse(text);
+        assert!(imported.warnings.is_empty());
+        assert_eq!(imported.questions, vec![
+            radio("苦力怕怕什么？".to_string(), vec!["猫".to_string(), "狗".to_string(), "猪".to_string()], 1, 1),
+            multiple("哪些方块可以合成工作台？".to_string(), vec!["橡木木板".to_string(), "白桦木板".to_string(), "圆石".to_string()], vec![1, 2], 1),
+            json!({"type": "radio", "content": "下界没有水。", "options": ["正确", "错误"], "correct": 1, "score": 1}),
+        ]);
+    }
+
+    #[test]
+    fn escaped_characters_and_blanks() {
+        let imported = parse("1 \\= 1 的结果是 {=真 ~假} 吗\\: 是");
+        assert_eq!(imported.questions[0]["content"], "1 = 1 的结果是 _____ 吗: 是");
+        assert_eq!(imported.questions[0]["options"], json!(["真", "假"]));
+    }
+
+    #[test]
+    fn unsupported_questions_are_skipped_with_warnings() {
+        let text = "\
+没有答案
+
+写一篇作文 {}
+
+1+1=? {#2}
+
+配对 {=猫 -> 鱼 =狗 -> 骨头}
+
+简答 {=答案一 =答案二}
+
+缺少结尾 {=a ~b
+";
+        let imported = parse(text);
+        assert!(imported.questions.is_empty());
+        let messages: Vec<&str> = imported.warnings.iter().map(|warning| warning.message.as_str()).collect();
+        assert_eq!(messages, vec![
+            "第1行：没有答案，已跳过",
+            "第3行：不支持的题型essay，已跳过",
+            "第5行：不支持的题型numerical，已跳过",
+            "第7行：不支持的题型matching，已跳过",
+            "第9行：不支持的题型shortanswer，已跳过",
+            "第11行：答案缺少结尾的}，已跳过",
+        ]);
+    }
+}
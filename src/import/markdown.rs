@@ -0,0 +1,187 @@
+use super::{multiple, radio, ImportedQuestions};
+
+/// 正在解析的题目
+struct Question {
+    line: usize,
+    content: String,
+    score: u64,
+    force_multiple: bool,
+    options: Vec<String>,
+    correct: Vec<usize>,
+}
+
+impl Question {
+    /// 解析“## 1. 题目内容 [多选] (2分)”形式的标题，序号、题型和分值都是可选的
+    fn from_heading(line: usize, heading: &str) -> Self {
+        let mut content = heading.trim().to_string();
+        let mut score = 1;
+        for (open, close) in [("(", "分)"), ("（", "分）")] {
+            if let Some(start) = content.rfind(open) {
+                if let Some(value) = content[start + open.len()..].strip_suffix(close).and_then(|value| value.trim().parse().ok()) {
+                    score = value;
+                    content.truncate(start);
+                    break;
+                }
+            }
+        }
+        let force_multiple = content.contains("[多选]");
+        let content = content.replace("[多选]", "");
+        let content = content.trim();
+        let content = match content.split_once(['.', '、']) {
+            Some((number, rest)) if !number.is_empty() && number.chars().all(|c| c.is_ascii_digit()) => rest,
+            _ => content,
+        };
+        Question {
+            line,
+            content: content.trim().to_string(),
+            score,
+            force_multiple,
+            options: Vec::new(),
+            correct: Vec::new(),
+        }
+    }
+
+    fn finish(self, imported: &mut ImportedQuestions) {
+        let location = format!("第{}行", self.line);
+        if self.options.len() < 2 {
+            imported.warn(location, "题目至少需要两个选项（- [ ] 选项），已跳过");
+        } else if self.correct.is_empty() {
+            imported.warn(location, "没有标记正确答案（- [x] 选项），已跳过");
+        } else if self.correct.len() == 1 && !self.force_multiple {
+            imported.questions.push(radio(self.content, self.options, self.correct[0], self.score));
+        } else {
+            imported.questions.push(multiple(self.content, self.options, self.correct, self.score));
+        }
+    }
+}
+
+/// 解析“- [x] 选项”形式的列表项，返回是否为正确答案及选项内容
+fn parse_option(line: &str) -> Option<(bool, &str)> {
+    let item = line.strip_prefix(['-', '*', '+'])?.trim_start();
+    let (mark, text) = item.strip_prefix('[')?.split_once(']')?;
+    match mark {
+        " " | "" => Some((false, text.trim())),
+        "x" | "X" => Some((true, text.trim())),
+        _ => None,
+    }
+}
+
+/// 解析Markdown格式：一级标题为试卷标题，之后的段落为副标题，`pass: 分数`指定及格分数；
+/// 每道题以二级标题开始，选项为任务列表，勾选的选项为正确答案，有多个正确答案或标题中带有[多选]时为多选题
+pub(super) fn parse(text: &str) -> ImportedQuestions {
+    let mut imported = ImportedQuestions::default();
+    let mut sub_title: Vec<&str> = Vec::new();
+    let mut current: Option<Question> = None;
+
+    for (number, line) in text.lines().enumerate() {
+        let line = line.trim();
+        if line.is_empty() || line.starts_with("<!--") {
+            continue;
+        }
+        if let Some(heading) = line.strip_prefix("## ") {
+            if let Some(question) = current.take() {
+                question.finish(&mut imported);
+            }
+            current = Some(Question::from_heading(number + 1, heading));
+            continue;
+        }
+        match &mut current {
+            None => {
+                if let Some(title) = line.strip_prefix("# ") {
+                    imported.title.get_or_insert_with(|| title.trim().to_string());
+                } else if let Some(pass) = ["pass:", "及格分数:", "及格分数："].iter().find_map(|prefix| line.strip_prefix(prefix)) {
+                    match pass.trim().parse() {
+                        Ok(pass) => imported.pass = Some(pass),
+                        Err(_) => imported.warn(format!("第{}行", number + 1), "及格分数必须是整数"),
+                    }
+                } else {
+                    sub_title.push(line);
+                }
+            }
+            Some(question) => match parse_option(line) {
+                Some((correct, option)) => {
+                    question.options.push(option.to_string());
+                    if correct {
+                        question.correct.push(question.options.len());
+                    }
+                }
+                None if line.starts_with(['-', '*', '+']) => {
+                    imported.warn(format!("第{}行", number + 1), "选项需要以[ ]或[x]标记，已忽略");
+                }
+                // 题目内容可以跨越多行
+                None => {
+                    question.content.push(' ');
+                    question.content.push_str(line);
+                }
+            },
+        }
+    }
+    if let Some(question) = current {
+        question.finish(&mut imported);
+    }
+    if !sub_title.is_empty() {
+        imported.sub_title = Some(sub_title.join(" "));
+    }
+    imported
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parses_title_pass_and_questions() {
+        let text = "\
+# 生存服入服考试
+请认真作答
+pass: 3
+
+## 1. 苦力怕怕什么？ (2分)
+- [x] 猫
+- [ ] 狗
+
+## 2、哪些方块可以合成工作台？[多选]（3分）
+- [x] 橡木木板
+- [ ] 圆石
+
+## 跨越多行的
+题目
+* [ ] 是
+* [X] 否
+";
+        let imported = parse(text);
+        assert!(imported.warnings.is_empty());
+        assert_eq!(imported.title.as_deref(), Some("生存服入服考试"));
+        assert_eq!(imported.sub_title.as_deref(), Some("请认真作答"));
+        assert_eq!(imported.pass, Some(3));
+        assert_eq!(imported.questions, vec![
+            radio("苦力怕怕什么？".to_string(), vec!["猫".to_string(), "狗".to_string()], 1, 2),
+            multiple("哪些方块可以合成工作台？".to_string(), vec!["橡木木板".to_string(), "圆石".to_string()], vec![1], 3),
+            radio("跨越多行的 题目".to_string(), vec!["是".to_string(), "否".to_string()], 2, 1),
+        ]);
+    }
+
+    #[test]
+    fn incomplete_questions_are_skipped_with_warnings() {
+        let text = "\
+pass: 六十
+
+## 只有一个选项
+- [x] 是
+
+## 没有正确答案
+- [ ] 是
+- [ ] 否
+- 未标记的选项
+";
+        let imported = parse(text);
+        assert!(imported.questions.is_empty());
+        let messages: Vec<&str> = imported.warnings.iter().map(|warning| warning.message.as_str()).collect();
+        assert_eq!(messages, vec![
+            "第1行：及格分数必须是整数",
+            "第3行：题目至少需要两个选项（- [ ] 选项），已跳过",
+            "第9行：选项需要以[ ]或[x]标记，已忽略",
+            "第6行：没有标记正确答案（- [x] 选项），已跳过",
+        ]);
+    }
+}
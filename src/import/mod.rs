@@ -0,0 +1,159 @@
+use serde_json::{json, Value};
+use crate::r#struct::paper::total_score;
+use crate::validate::Diagnostic;
+
+mod gift;
+mod markdown;
+mod moodle;
+
+/// 可以导入的其他题库格式
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ImportFormat {
+    MoodleXml,
+    Gift,
+    Markdown,
+}
+
+impl ImportFormat {
+    /// 根据名称选择格式，供命令行的--format参数使用
+    pub fn from_name(name: &str) -> Option<Self> {
+        match name.to_ascii_lowercase().as_str() {
+            "moodle" | "xml" => Some(ImportFormat::MoodleXml),
+            "gift" => Some(ImportFormat::Gift),
+            "markdown" | "md" => Some(ImportFormat::Markdown),
+            _ => None,
+        }
+    }
+
+    pub fn from_file_name(file_name: &str) -> Option<Self> {
+        let (_, extension) = file_name.rsplit_once('.')?;
+        match extension.to_ascii_lowercase().as_str() {
+            "xml" => Some(ImportFormat::MoodleXml),
+            "gift" => Some(ImportFormat::Gift),
+            "md" | "markdown" => Some(ImportFormat::Markdown),
+            _ => None,
+        }
+    }
+
+    /// 优先按扩展名判断，没有扩展名时只识别以xml声明或quiz元素开头的Moodle XML，
+    /// 其余内容交给试卷格式的解析器处理
+    pub fn detect(file_name: Option<&str>, text: &str) -> Option<Self> {
+        if let Some(format) = file_name.and_then(Self::from_file_name) {
+            return Some(format);
+        }
+        if file_name.is_some_and(|name| name.contains('.')) {
+            return None;
+        }
+        let text = text.trim_start_matches('\u{feff}').trim_start();
+        (text.starts_with("<?xml") || text.starts_with("<quiz")).then_some(ImportFormat::MoodleXml)
+    }
+}
+
+/// 导入后的试卷，warnings中为被跳过的题目等不影响导入的问题
+pub struct ImportedPaper {
+    pub content: Value,
+    pub warnings: Vec<Diagnostic>,
+}
+
+/// 各格式解析出的题目，尚未组成完整的试卷
+#[derive(Default)]
+struct ImportedQuestions {
+    title: Option<String>,
+    sub_title: Option<String>,
+    pass: Option<i64>,
+    questions: Vec<Value>,
+    warnings: Vec<Diagnostic>,
+}
+
+impl ImportedQuestions {
+    fn warn(&mut self, location: impl std::fmt::Display, message: impl std::fmt::Display) {
+        self.warnings.push(Diagnostic::new("", format!("{}：{}", location, message)));
+    }
+}
+
+/// 将其他格式的题库转换为QuizGate试卷，源文件中没有标题时使用default_title。
+/// 未指定及格分数时按总分的60%向上取整
+pub fn import_paper(format: ImportFormat, text: &str, default_title: &str) -> Result<ImportedPaper, String> {
+    let text = text.trim_start_matches('\u{feff}');
+    let imported = match format {
+        ImportFormat::MoodleXml => moodle::parse(text)?,
+        ImportFormat::Gift => gift::parse(text),
+        ImportFormat::Markdown => markdown::parse(text),
+    };
+    let mut content = json!({
+        "main_title": imported.title.unwrap_or_else(|| default_title.to_string()),
+        "sub_title": imported.sub_title.unwrap_or_default(),
+        "questions": imported.questions,
+    });
+    let score = total_score(&content);
+    content["score"] = json!(score);
+    content["pass"] = json!(imported.pass.unwrap_or(((score * 3 + 4) / 5).max(1)));
+    Ok(ImportedPaper { content, warnings: imported.warnings })
+}
+
+fn radio(content: String, options: Vec<String>, correct: usize, score: u64) -> Value {
+    json!({"type": "radio", "content": content, "options": options, "correct": correct, "score": score})
+}
+
+/// 多选题部分正确时不得分
+fn multiple(content: String, options: Vec<String>, correct: Vec<usize>, score: u64) -> Value {
+    json!({"type": "multiple", "content": content, "options": options, "correct": correct, "score": [0, score]})
+}
+
+/// 去掉html标签并还原常见的实体，连续的空白合并为一个空格
+fn strip_html(html: &str) -> String {
+    let mut text = String::new();
+    let mut in_tag = false;
+    for c in html.chars() {
+        match c {
+            '<' => in_tag = true,
+            '>' if in_tag => {
+                in_tag = false;
+                text.push(' ');
+            }
+            _ if !in_tag => text.push(c),
+            _ => {}
+        }
+    }
+    let text = text
+        .replace("&nbsp;", " ")
+        .replace("&lt;", "<")
+        .replace("&gt;", ">")
+        .replace("&quot;", "\"")
+        .replace("&#39;", "'")
+        .replace("&amp;", "&");
+    text.split_whitespace().collect::<Vec<_>>().join(" ")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::validate::validate_paper;
+
+    #[test]
+    fn detects_format_by_name_or_content() {
+        assert_eq!(ImportFormat::detect(Some("bank.XML"), ""), Some(ImportFormat::MoodleXml));
+        assert_eq!(ImportFormat::detect(Some("bank.gift"), ""), Some(ImportFormat::Gift));
+        assert_eq!(ImportFormat::detect(Some("bank.md"), ""), Some(ImportFormat::Markdown));
+        assert_eq!(ImportFormat::detect(Some("paper.json"), "<quiz></quiz>"), None);
+        assert_eq!(ImportFormat::detect(None, "\u{feff}  <?xml version=\"1.0\"?><quiz/>"), Some(ImportFormat::MoodleXml));
+        assert_eq!(ImportFormat::detect(Some("bank"), "<quiz></quiz>"), Some(ImportFormat::MoodleXml));
+        assert_eq!(ImportFormat::detect(None, "{\"main_title\": \"A\"}"), None);
+    }
+
+    #[test]
+    fn imported_papers_pass_validation() {
+        let text = "## 苦力怕怕什么？ (3分)\n- [x] 猫\n- [ ] 狗\n\n## 哪些是木板？\n- [x] 橡木木板\n- [x] 白桦木板\n- [ ] 圆石\n";
+        let imported = import_paper(ImportFormat::Markdown, text, "题库").unwrap();
+        assert_eq!(imported.content["main_title"], "题库");
+        assert_eq!(imported.content["score"], 4);
+        // 未指定及格分数时为总分的60%向上取整
+        assert_eq!(imported.content["pass"], 3);
+        assert_eq!(validate_paper(&imported.content), vec![]);
+    }
+
+    #[test]
+    fn strips_html_tags_and_entities() {
+        assert_eq!(strip_html("<p>a&nbsp;&lt;b&gt;</p>\n<br/>&amp;c"), "a <b> &c");
+    }
+}
@@ -0,0 +1,147 @@
+use roxmltree::{Document, Node};
+use super::{multiple, radio, strip_html, ImportedQuestions};
+
+/// 读取子元素中text元素的内容
+fn child_text(node: Node, name: &str) -> Option<String> {
+    let child = node.children().find(|child| child.has_tag_name(name))?;
+    let text = child.children().find(|child| child.has_tag_name("text"))?;
+    Some(strip_html(text.text().unwrap_or_default()))
+}
+
+/// 解析Moodle XML，支持单选、多选和判断题，分类信息会被忽略
+pub(super) fn parse(text: &str) -> Result<ImportedQuestions, String> {
+    let document = Document::parse(text).map_err(|e| format!("xml格式错误: {}", e))?;
+    let root = document.root_element();
+    if !root.has_tag_name("quiz") {
+        return Err("Moodle XML的根元素必须是quiz".to_string());
+    }
+
+    let mut imported = ImportedQuestions::default();
+    let questions = root
+        .children()
+        .filter(|node| node.has_tag_name("question") && node.attribute("type") != Some("category"));
+    for (index, question) in questions.enumerate() {
+        let location = format!("第{}题", index + 1);
+        let content = child_text(question, "questiontext")
+            .filter(|content| !content.is_empty())
+            .or_else(|| child_text(question, "name"))
+            .unwrap_or_default();
+        let score = question
+            .children()
+            .find(|child| child.has_tag_name("defaultgrade"))
+            .and_then(|grade| grade.text()?.trim().parse::<f64>().ok())
+            .map(|grade| grade.round().max(0.0) as u64)
+            .unwrap_or(1);
+        // fraction为该选项的得分百分比，大于0即视为正确答案
+        let answers: Vec<(String, f64)> = question
+            .children()
+            .filter(|child| child.has_tag_name("answer"))
+            .map(|answer| {
+                let text = answer
+                    .children()
+                    .find(|child| child.has_tag_name("text"))
+                    .map(|text| strip_html(text.text().unwrap_or_default()))
+                    .unwrap_or_default();
+                let fraction = answer.attribute("fraction").and_then(|fraction| fraction.parse().ok()).unwrap_or(0.0);
+                (text, fraction)
+            })
+            .collect();
+
+        match question.attribute("type").unwrap_or_default() {
+            "multichoice" => {
+                let single = question
+                    .children()
+                    .find(|child| child.has_tag_name("single"))
+                    .and_then(|single| single.text())
+                    .is_none_or(|single| single.trim() != "false");
+                let options = answers.iter().map(|(text, _)| text.clone()).collect();
+                let correct: Vec<usize> = answers
+                    .iter()
+                    .enumerate()
+                    .filter(|(_, (_, fraction))| *fraction > 0.0)
+                    .map(|(index, _)| index + 1)
+                    .collect();
+                if correct.is_empty() {
+                    imported.warn(location, "没有正确答案，已跳过");
+                } else if single {
+                    // 单选题中有多个得分选项时取得分最高的一项
+                    let best = correct
+                        .iter()
+                        .copied()
+                        .max_by(|a, b| answers[a - 1].1.total_cmp(&answers[b - 1].1))
+                        .unwrap_or(correct[0]);
+                    imported.questions.push(radio(content, options, best, score));
+                } else {
+                    imported.questions.push(multiple(content, options, correct, score));
+                }
+            }
+            "truefalse" => {
+                let is_true = answers
+                    .iter()
+                    .find(|(_, fraction)| *fraction > 0.0)
+                    .map(|(text, _)| text.eq_ignore_ascii_case("true"));
+                match is_true {
+                    Some(is_true) => {
+                        let options = vec!["正确".to_string(), "错误".to_string()];
+                        imported.questions.push(radio(content, options, if is_true { 1 } else { 2 }, score));
+                    }
+                    None => imported.warn(location, "没有正确答案，已跳过"),
+                }
+            }
+            other => imported.warn(location, format!("不支持的题型{}，已跳过", other)),
+        }
+    }
+    Ok(imported)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const QUIZ: &str = r#"<?xml version="1.0" encoding="UTF-8"?>
+<quiz>
+  <question type="category"><category><text>$course$/survival</text></category></question>
+  <question type="multichoice">
+    <name><text>q1</text></name>
+    <questiontext format="html"><text><![CDATA[<p>苦力怕&nbsp;怕什么？</p>]]></text></questiontext>
+    <defaultgrade>2.0000000</defaultgrade>
+    <single>true</single>
+    <answer fraction="50"><text>狗</text></answer>
+    <answer fraction="100"><text>猫</text></answer>
+  </question>
+  <question type="multichoice">
+    <name><text>哪些方块可以合成工作台？</text></name>
+    <questiontext format="html"><text></text></questiontext>
+    <single>false</single>
+    <answer fraction="50"><text>橡木木板</text></answer>
+    <answer fraction="50"><text>白桦木板</text></answer>
+    <answer fraction="-100"><text>圆石</text></answer>
+  </question>
+  <question type="truefalse">
+    <questiontext><text>下界没有水。</text></questiontext>
+    <answer fraction="0"><text>true</text></answer>
+    <answer fraction="100"><text>false</text></answer>
+  </question>
+  <question type="essay">
+    <questiontext><text>写一篇作文</text></questiontext>
+  </question>
+</quiz>"#;
+
+    #[test]
+    fn parses_supported_question_types() {
+        let imported = parse(QUIZ).unwrap();
+        assert_eq!(imported.questions, vec![
+            radio("苦力怕 怕什么？".to_string(), vec!["狗".to_string(), "猫".to_string()], 2, 2),
+            multiple("哪些方块可以合成工作台？".to_string(), vec!["橡木木板".to_string(), "白桦木板".to_string(), "圆石".to_string()], vec![1, 2], 1),
+            radio("下界没有水。".to_string(), vec!["正确".to_string(), "错误".to_string()], 2, 1),
+        ]);
+        let messages: Vec<&str> = imported.warnings.iter().map(|warning| warning.message.as_str()).collect();
+        assert_eq!(messages, vec!["第4题：不支持的题型essay，已跳过"]);
+    }
+
+    #[test]
+    fn rejects_invalid_documents() {
+        assert!(parse("<quiz>").is_err());
+        assert!(parse("<paper></paper>").is_err());
+    }
+}
@@ -0,0 +1,126 @@
+use std::fmt::{self, Display, Formatter};
+use std::future::{ready, Ready};
+use std::io::Write;
+use std::time::Instant;
+use actix_web::body::MessageBody;
+use actix_web::dev::{Payload, ServiceRequest, ServiceResponse};
+use actix_web::http::header::{HeaderName, HeaderValue};
+use actix_web::middleware::Next;
+use actix_web::{Error, FromRequest, HttpMessage, HttpRequest};
+use log::kv::{self, Key, Value, VisitSource};
+use serde_json::{Map, Value as JsonValue};
+use crate::config::LogFormat;
+
+/// 客户端可以通过该请求头指定请求id，响应中也会带上该请求头
+const REQUEST_ID_HEADER: HeaderName = HeaderName::from_static("x-request-id");
+
+/// 初始化日志，需要在读取配置后、启动各服务前调用
+pub fn init(format: LogFormat) {
+    let mut builder = env_logger::Builder::from_env(env_logger::Env::new().default_filter_or("info"));
+    if format == LogFormat::Json {
+        builder.format(|buf, record| {
+            let mut fields = Map::new();
+            fields.insert("ts".to_string(), buf.timestamp().to_string().into());
+            fields.insert("level".to_string(), record.level().as_str().into());
+            fields.insert("target".to_string(), record.target().into());
+            fields.insert("msg".to_string(), record.args().to_string().into());
+            // 附加字段与固定字段同名时覆盖固定字段，因此附加字段不应使用上述名称
+            let _ = record.key_values().visit(&mut JsonFields(&mut fields));
+            writeln!(buf, "{}", JsonValue::Object(fields))
+        });
+    }
+    builder.init();
+}
+
+/// 将日志的附加字段写入JSON对象，数字和布尔值保留原类型
+struct JsonFields<'a>(&'a mut Map<String, JsonValue>);
+
+impl<'kvs> VisitSource<'kvs> for JsonFields<'_> {
+    fn visit_pair(&mut self, key: Key<'kvs>, value: Value<'kvs>) -> Result<(), kv::Error> {
+        let value = if let Some(number) = value.to_u64() {
+            number.into()
+        } else if let Some(number) = value.to_i64() {
+            number.into()
+        } else if let Some(number) = value.to_f64() {
+            number.into()
+        } else if let Some(boolean) = value.to_bool() {
+            boolean.into()
+        } else {
+            value.to_string().into()
+        };
+        self.0.insert(key.to_string(), value);
+        Ok(())
+    }
+}
+
+/// 每个HTTP请求的id，用于在日志中关联同一请求产生的提交、投递等记录
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct RequestId(String);
+
+impl RequestId {
+    fn new() -> RequestId {
+        RequestId(uuid::Uuid::new_v4().simple().to_string())
+    }
+
+    /// 沿用反向代理等上游传入的id，过长或含有其他字符时重新生成
+    fn from_header(value: &HeaderValue) -> Option<RequestId> {
+        let value = value.to_str().ok()?;
+        let valid = !value.is_empty()
+            && value.len() <= 64
+            && value.chars().all(|c| c.is_ascii_alphanumeric() || c == '-' || c == '_');
+        valid.then(|| RequestId(value.to_string()))
+    }
+
+    pub fn as_str(&self) -> &str {
+        &self.0
+    }
+}
+
+impl Display for RequestId {
+    fn fmt(&self, f: &mut Formatter) -> fmt::Result {
+        f.write_str(&self.0)
+    }
+}
+
+/// 从中间件写入的扩展中读取请求id，未经过中间件时生成一个新的id
+impl FromRequest for RequestId {
+    type Error = Error;
+    type Future = Ready<Result<RequestId, Error>>;
+
+    fn from_request(req: &HttpRequest, _: &mut Payload) -> Self::Future {
+        ready(Ok(req.extensions().get::<RequestId>().cloned().unwrap_or_else(RequestId::new)))
+    }
+}
+
+/// 为每个请求分配id，在响应头中返回并在请求结束后记录一条日志。
+/// 日志中的路径使用路由模板，避免/verify/{token}等路径中的token被写入日志
+pub async fn trace_requests(req: ServiceRequest, next: Next<impl MessageBody>) -> Result<ServiceResponse<impl MessageBody>, Error> {
+    let request_id = req
+        .headers()
+        .get(&REQUEST_ID_HEADER)
+        .and_then(RequestId::from_header)
+        .unwrap_or_else(RequestId::new);
+    req.extensions_mut().insert(request_id.clone());
+    let started = Instant::now();
+    let mut res = next.call(req).await?;
+
+    let request = res.request();
+    let path = request.match_pattern().unwrap_or_else(|| "unmatched".to_string());
+    // 监控系统会频繁请求这些接口，只在debug级别记录
+    let level = match path.as_str() {
+        "/healthz" | "/readyz" | "/metrics" => log::Level::Debug,
+        _ => log::Level::Info,
+    };
+    log::log!(
+        level,
+        request_id = request_id.as_str(),
+        method = request.method().as_str(),
+        path = path.as_str(),
+        status = res.status().as_u16(),
+        elapsed_ms = started.elapsed().as_millis() as u64;
+        "HTTP请求"
+    );
+    // unwrap: 请求id只包含字母、数字、-和_
+    res.headers_mut().insert(REQUEST_ID_HEADER, HeaderValue::from_str(request_id.as_str()).unwrap());
+    Ok(res)
+}
@@ -1,17 +1,22 @@
 #![allow(unused_assignments)]
 
-use std::{fs, io};
+use std::io;
+use std::process::ExitCode;
+use clap::Parser;
 
 pub use crate::r#struct::submit::{SubmitRequest, SubmitResponse};
 use crate::ws_server::{WsServer, WsServerHandle};
-use actix_web::{web, App, Error, HttpRequest, HttpResponse, HttpServer, Result};
+use actix_web::{middleware, web, App, Error, HttpRequest, HttpResponse, HttpServer, Result};
+use crate::logging::RequestId;
 use sql_server::SqlServer;
 use r#struct::awl_type::SqlFile;
 use tokio::task::{spawn, spawn_local};
-use lazy_static::lazy_static;
-use toml::Value;
 use crate::email_server::{EmailServer};
-use crate::service::{register, upload, resources, pages, quiz};
+use crate::service::{register, upload, resources, pages, quiz, delivery, webhook, admin, paper, export as export_service, operator, health};
+use crate::webhook_server::WebhookServer;
+use crate::reload_server::ReloadServer;
+use crate::config::Capabilities;
+pub use crate::config::CONFIG;
 
 mod sql_server;
 mod error;
@@ -21,30 +26,23 @@ mod ws_handler;
 mod ws_server;
 mod email_server;
 mod service;
-
-// 读取配置文件config.toml并初始化全局变量
-pub struct Config {
-    pub self_hosted: bool,
-    pub self_hosted_key: String,
-    pub address: String,
-    pub port: u16
-}
-
-lazy_static! {
-        pub static ref CONFIG: Config = {
-            let config_content = fs::read_to_string("config.toml").expect("无法读取配置文件！");
-            let config: Value = toml::from_str(&config_content).expect("配置文件格式错误，请再次确认！");
-            Config {
-                self_hosted: config["self_hosted"].as_bool().expect("无法读取self_hosted字段！"),
-                self_hosted_key: config["self_hosted_key"].as_str().expect("无法读取self_hosted_key字段！").to_string(),
-                address: config["address"].as_str().expect("无法读取address字段！").to_string(),
-                port: config["port"].as_integer().expect("无法读取port字段！") as u16,
-            }
-        };
-    }
+mod rcon;
+mod webhook_server;
+mod validate;
+mod format;
+mod import;
+mod cli;
+mod export;
+mod analytics;
+mod config;
+mod reload_server;
+mod shutdown;
+mod metrics;
+mod logging;
 
 async fn handle_ws_connection(
     req: HttpRequest,
+    request_id: RequestId,
     stream: web::Payload,
     ws_server: web::Data<WsServerHandle>,
 ) -> Result<HttpResponse, Error> {
@@ -54,86 +52,283 @@ async fn handle_ws_connection(
         (**ws_server).clone(),
         session,
         msg_stream,
+        request_id,
     ));
 
     Ok(res)
 }
+/// 根据开启的功能注册路由，/{test_id}会匹配所有单级路径，必须最后注册
+fn routes(cfg: &mut web::ServiceConfig, capabilities: Capabilities) {
+    cfg.service(web::resource("/ws").route(web::get().to(handle_ws_connection)))
+        .service(web::resource("/").route(web::get().to(pages::index)))
+        .service(web::resource("/dashboard").route(web::get().to(pages::dashboard_page)))
+        .service(web::resource("/healthz").route(web::get().to(health::healthz)))
+        .service(web::resource("/readyz").route(web::get().to(health::readyz)))
+        .service(web::resource("/metrics").route(web::get().to(health::metrics)))
+        .route("/resources/{filename:.*}", web::get().to(resources::resources));
+    if capabilities.hosted {
+        cfg.service(web::resource("/editor").route(web::get().to(pages::editor_page)));
+    }
+    if capabilities.upload {
+        cfg.service(web::resource("/upload").route(web::get().to(pages::upload_page)));
+    }
+    if capabilities.registration {
+        cfg.service(web::resource("/register").route(web::get().to(pages::register_page)))
+            .service(web::resource("/verify/{token}").route(web::get().to(register::verify)));
+    }
+    if capabilities.email {
+        cfg.service(web::resource("/recover").route(web::get().to(pages::recover_page)))
+            .service(web::resource("/recover/{token}").route(web::get().to(pages::recover_page)));
+    }
+    cfg.service(web::resource("/{test_id}").route(web::get().to(pages::index)));
+
+    // 自托管模式下管理接口使用self_hosted_key或papers中试卷的key，只能查询记录和修改设置
+    let mut admin_scope = web::scope("/admin")
+        .app_data(web::JsonConfig::default().limit(validate::MAX_PAPER_SIZE))
+        .route("/token", web::get().to(admin::token))
+        .route("/submissions", web::get().to(admin::submissions))
+        .route("/passes", web::get().to(admin::passes))
+        .route("/stats", web::get().to(admin::stats))
+        .route("/stats/daily", web::get().to(admin::daily_stats))
+        .route("/status", web::get().to(admin::status))
+        .route("/revoke", web::post().to(admin::revoke))
+        .route("/approvals", web::get().to(admin::approvals))
+        .route("/approve", web::post().to(admin::approve))
+        .route("/resend", web::post().to(admin::resend))
+        .route("/settings", web::get().to(admin::get_settings))
+        .route("/settings", web::post().to(admin::set_settings))
+        .route("/export/submissions", web::get().to(export_service::submissions))
+        .route("/export/passes", web::get().to(export_service::passes));
+    let mut api_scope = web::scope("/api")
+        .route("/get_test/{filename:.*}", web::get().to(quiz::get_test))
+        .route("/submit", web::post().to(quiz::submit));
+    if capabilities.hosted {
+        admin_scope = admin_scope
+            .route("/rotate_key", web::post().to(admin::rotate_key))
+            .route("/quotas", web::get().to(admin::quotas))
+            .route("/papers", web::get().to(admin::papers))
+            .route("/papers", web::post().to(paper::create_paper))
+            .route("/papers/{id}", web::get().to(paper::get_paper))
+            .route("/papers/{id}", web::put().to(paper::update_paper))
+            .route("/papers/{id}", web::delete().to(paper::delete_paper))
+            .route("/papers/{id}/preview", web::get().to(paper::preview_paper))
+            .route("/papers/{id}/questions", web::post().to(paper::add_question))
+            .route("/papers/{id}/questions/{index}", web::put().to(paper::update_question))
+            .route("/papers/{id}/questions/{index}", web::delete().to(paper::delete_question))
+            .route("/papers/{id}/questions/{index}/move", web::post().to(paper::move_question))
+            .route("/preview", web::post().to(paper::preview_draft))
+            .route("/paper", web::get().to(admin::paper))
+            .route("/paper/versions", web::get().to(admin::paper_versions))
+            .route("/paper/diff", web::get().to(admin::paper_diff))
+            .route("/paper/analysis", web::get().to(admin::paper_analysis))
+            .route("/paper/rollback", web::post().to(admin::paper_rollback));
+        api_scope = api_scope
+            .route("/delivery", web::post().to(delivery::set_delivery))
+            .route("/webhook", web::post().to(webhook::add_webhook))
+            .route("/webhook/list", web::post().to(webhook::list_webhooks))
+            .route("/webhook/delete", web::post().to(webhook::delete_webhook))
+            .service(
+                web::scope("/operator")
+                    .route("/servers", web::get().to(operator::servers))
+                    .route("/servers/{id}", web::delete().to(operator::purge))
+                    .route("/servers/{id}/suspend", web::post().to(operator::suspend))
+                    .route("/servers/{id}/unsuspend", web::post().to(operator::unsuspend))
+                    .route("/servers/{id}/quotas", web::get().to(operator::get_quotas))
+                    .route("/servers/{id}/quotas", web::put().to(operator::set_quotas)),
+            );
+    }
+    if capabilities.upload {
+        api_scope = api_scope.route("/upload", web::post().to(upload::upload));
+    }
+    if capabilities.registration {
+        api_scope = api_scope.route("/register", web::post().to(register::register_pending));
+    }
+    if capabilities.email {
+        api_scope = api_scope
+            .route("/recover", web::post().to(register::recover_pending))
+            .route("/recover/confirm", web::post().to(register::recover));
+    }
+    cfg.service(api_scope.service(admin_scope));
+}
+
 // 启动actix服务
 #[tokio::main(flavor = "current_thread")]
-async fn main() -> io::Result<()> {
-    let sql_file:SqlFile = "data.db".to_string();
+async fn main() -> io::Result<ExitCode> {
+    let args = cli::Args::parse();
+    // 子命令只用到数据库路径等少数配置项，不要求完整的服务配置
+    let loaded = if args.command.is_some() {
+        config::Config::load_lenient(args.config.as_deref())
+    } else {
+        config::Config::load(args.config.as_deref())
+    };
+    match loaded {
+        Ok(config) => {
+            logging::init(config.log_format);
+            config::init(config, args.config.clone());
+        }
+        Err(errors) => {
+            eprintln!("配置有误，请修改后重新启动：");
+            for error in errors {
+                eprintln!("  {}", error);
+            }
+            return Ok(ExitCode::FAILURE);
+        }
+    }
+    if let Some(command) = args.command {
+        return Ok(cli::run(command).await);
+    }
+    let sql_file:SqlFile = CONFIG.database.clone();
+    let capabilities = CONFIG.capabilities();
 
     if let Ok((sql_server,sql_server_tx)) = SqlServer::new(sql_file).await {
         // 启动线程
         let (ws_server, ws_server_tx) = WsServer::new(sql_server_tx.clone());
 
-        let (email_server, email_server_tx) = EmailServer::new();
+        let (webhook_server, webhook_server_tx) = WebhookServer::new(sql_server_tx.clone());
+
+        let (reload_server, reload_server_tx) = ReloadServer::new();
 
+        let _sql_server = spawn(sql_server.run());
         let _ws_server = spawn(ws_server.run());
-        
-        // 如果为自托管模式则不创建邮件服务和数据库服务
-        if !CONFIG.self_hosted {
+        let _reload_server = spawn(reload_server.run());
+        let _webhook_server = spawn(webhook_server.run());
+
+        // 只有开启邮件功能时才创建邮件服务
+        let email_server_tx = if capabilities.email {
+            let (email_server, email_server_tx) = match EmailServer::new() {
+                Ok(email_server) => email_server,
+                Err(e) => {
+                    eprintln!("无法创建邮件服务，请检查email配置: {}", e);
+                    return Ok(ExitCode::FAILURE);
+                }
+            };
             let _email_server = spawn(email_server.run());
-            let _sql_server = spawn(sql_server.run());
-        }
-        
-        // 启动HTTP服务
-        // 非自托管模式
-        if !CONFIG.self_hosted {
-            let server = HttpServer::new(move || {
-                App::new()
-                    .app_data(web::Data::new(ws_server_tx.clone()))
-                    .app_data(web::Data::new(email_server_tx.clone()))
-                    .app_data(web::Data::new(sql_server_tx.clone()))
-                    .service(web::resource("/ws").route(web::get().to(handle_ws_connection)))
-                    .service(web::resource("/upload").route(web::get().to(pages::upload_page)))
-                    .service(web::resource("/register").route(web::get().to(pages::register_page)))
-                    .service(web::resource("/").route(web::get().to(pages::index)))
-                    .service(web::resource("/verify/{token}").route(web::get().to(register::verify)))
-                    .service(web::resource("/{test_id}").route(web::get().to(pages::index)))
-                    .route("/resources/{filename:.*}", web::get().to(resources::resources))
-                    .service(
-                        web::scope("/api")
-                            .route("/get_test/{filename:.*}", web::get().to(quiz::get_test))
-                            .route("/upload", web::post().to(upload::upload))
-                            .route("/submit", web::post().to(quiz::submit))
-                            .route("/register", web::post().to(register::register_pending)),
-                    )
-            })
-            .workers(2)
-            .bind(format!("{address}:{port}",address=CONFIG.address,port=CONFIG.port))
-            .expect("端口被占用，无法启动HTTP服务！")
-            .run();
-            env_logger::init_from_env(env_logger::Env::new().default_filter_or("info"));
-            log::info!("{}", format!("starting HTTP server at http://{address}:{port}",address=CONFIG.address,port=CONFIG.port));
-            server.await.expect("HTTP服务意外退出:");
+            Some(email_server_tx)
         } else {
-            // 自托管模式
-            let server = HttpServer::new(move || {
-                App::new()
-                    .app_data(web::Data::new(ws_server_tx.clone()))
-                    .app_data(web::Data::new(sql_server_tx.clone()))
-                    .service(web::resource("/ws").route(web::get().to(handle_ws_connection)))
-                    .service(web::resource("/").route(web::get().to(pages::index)))
-                    .service(web::resource("/verify/{token}").route(web::get().to(register::verify)))
-                    .route("/resources/{filename:.*}", web::get().to(resources::resources))
-                    .service(
-                        web::scope("/api")
-                            .route("/get_test/{filename:.*}", web::get().to(quiz::get_test))
-                            .route("/submit", web::post().to(quiz::submit))
-                    )
-            })
-            .workers(2)
-            .bind(format!("{address}:{port}",address=CONFIG.address,port=CONFIG.port))
-            .expect("端口被占用，无法启动HTTP服务！")
-            .run();
-            env_logger::init_from_env(env_logger::Env::new().default_filter_or("info"));
-            log::info!("{}", format!("starting HTTP server at http://{address}:{port}",address=CONFIG.address,port=CONFIG.port));
+            None
+        };
+
+        // HTTP服务停止后用于依次关闭各服务
+        let ws_server_handle = ws_server_tx.clone();
+        let sql_server_handle = sql_server_tx.clone();
+        let webhook_server_handle = webhook_server_tx.clone();
+        let email_server_handle = email_server_tx.clone();
+
+        // 启动HTTP服务
+        let server = HttpServer::new(move || {
+            App::new()
+                .wrap(middleware::from_fn(logging::trace_requests))
+                .app_data(web::Data::new(ws_server_tx.clone()))
+                .app_data(web::Data::new(sql_server_tx.clone()))
+                .app_data(web::Data::new(webhook_server_tx.clone()))
+                .app_data(web::Data::new(reload_server_tx.clone()))
+                .configure(|cfg| {
+                    if let Some(email_server_tx) = &email_server_tx {
+                        cfg.app_data(web::Data::new(email_server_tx.clone()));
+                    }
+                    routes(cfg, capabilities);
+                })
+        })
+        .workers(CONFIG.workers)
+        // 信号由下方统一处理，先通知客户端再停止HTTP服务
+        .disable_signals()
+        .shutdown_timeout(CONFIG.timeouts.shutdown_grace)
+        .bind(format!("{address}:{port}",address=CONFIG.address,port=CONFIG.port))
+        .expect("端口被占用，无法启动HTTP服务！")
+        .run();
+        log::info!("starting HTTP server at http://{address}:{port}",address=CONFIG.address,port=CONFIG.port);
+        if CONFIG.self_hosted {
             log::info!("running in self-hosted mode");
-            server.await.expect("HTTP服务意外退出:");
         }
-        Ok(())
+        let server_handle = server.handle();
+        let mut server = spawn(server);
+        tokio::select! {
+            result = &mut server => {
+                result.expect("HTTP服务意外退出:").expect("HTTP服务意外退出:");
+                return Ok(ExitCode::SUCCESS);
+            }
+            result = shutdown::wait_for_signal() => result?,
+        }
+
+        // 不再接受新的提交，通知客户端稍后重连，然后等待进行中的请求完成
+        shutdown::begin();
+        ws_server_handle.going_away();
+        server_handle.stop(true).await;
+        let _ = server.await;
+
+        // 保存尚未投递的消息，sql server处理完其他服务的命令后最后关闭
+        ws_server_handle.shutdown().await;
+        webhook_server_handle.shutdown().await;
+        if let Some(email_server_handle) = &email_server_handle {
+            email_server_handle.shutdown().await;
+        }
+        sql_server_handle.shutdown().await;
+        log::info!("已关闭所有服务");
+        Ok(ExitCode::SUCCESS)
     } else {
         panic!("服务启动失败！");
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use actix_web::http::{Method, StatusCode};
+    use actix_web::test::{call_service, init_service, TestRequest};
+    use crate::config::{Config, Features};
+
+    /// 未注册的路由返回404，已注册的路由因为缺少各服务的handle而返回其他错误
+    async fn registered(capabilities: Capabilities, method: Method, path: &str) -> bool {
+        let app = init_service(App::new().configure(|cfg| routes(cfg, capabilities))).await;
+        let res = call_service(&app, TestRequest::default().method(method).uri(path).to_request()).await;
+        res.status() != StatusCode::NOT_FOUND
+    }
+
+    const HOSTED_ONLY: [(&str, &str); 8] = [
+        ("POST", "/api/admin/rotate_key"),
+        ("GET", "/api/admin/quotas"),
+        ("POST", "/api/admin/papers"),
+        ("POST", "/api/delivery"),
+        ("POST", "/api/webhook"),
+        ("GET", "/api/operator/servers"),
+        ("POST", "/api/upload"),
+        ("POST", "/api/register"),
+    ];
+
+    #[actix_web::test]
+    async fn self_hosted_mode_omits_hosted_routes() {
+        let capabilities = Config { self_hosted: true, ..Config::default() }.capabilities();
+        for (method, path) in HOSTED_ONLY {
+            assert!(!registered(capabilities, method.parse().unwrap(), path).await, "{} {}", method, path);
+        }
+        assert!(!registered(capabilities, Method::POST, "/api/recover").await);
+        assert!(!registered(capabilities, Method::GET, "/verify/token").await);
+        // 查询记录和修改设置在两种模式下都可用
+        for (method, path) in [("POST", "/api/submit"), ("GET", "/api/admin/stats"), ("POST", "/api/admin/settings"), ("GET", "/api/get_test/survival")] {
+            assert!(registered(capabilities, method.parse().unwrap(), path).await, "{} {}", method, path);
+        }
+
+        let capabilities = Config::default().capabilities();
+        for (method, path) in HOSTED_ONLY {
+            assert!(registered(capabilities, method.parse().unwrap(), path).await, "{} {}", method, path);
+        }
+    }
+
+    #[actix_web::test]
+    async fn hosted_features_can_be_disabled() {
+        let features = Features { upload: Some(false), registration: Some(false), ..Features::default() };
+        let capabilities = Config { features, ..Config::default() }.capabilities();
+        assert!(!registered(capabilities, Method::POST, "/api/upload").await);
+        assert!(!registered(capabilities, Method::POST, "/api/register").await);
+        assert!(!registered(capabilities, Method::GET, "/verify/token").await);
+        assert!(registered(capabilities, Method::POST, "/api/recover").await);
+        assert!(registered(capabilities, Method::POST, "/api/webhook").await);
+
+        // 关闭邮件时注册和找回密钥都无法使用
+        let features = Features { email: Some(false), ..Features::default() };
+        let capabilities = Config { features, ..Config::default() }.capabilities();
+        assert!(!registered(capabilities, Method::POST, "/api/register").await);
+        assert!(!registered(capabilities, Method::POST, "/api/recover").await);
+        assert!(registered(capabilities, Method::POST, "/api/upload").await);
+    }
+}
@@ -0,0 +1,225 @@
+use crate::r#struct::awl_type::ServerId;
+use crate::r#struct::metrics::SessionMetrics;
+use std::collections::BTreeMap;
+use std::fmt::{self, Write};
+use std::sync::atomic::{AtomicU64, AtomicUsize, Ordering};
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+use tokio::sync::mpsc;
+
+/// 评分耗时直方图的分桶上限，单位为秒
+const GRADING_BUCKETS: [f64; 10] = [0.005, 0.01, 0.025, 0.05, 0.1, 0.25, 0.5, 1.0, 2.5, 5.0];
+
+/// 单张试卷的提交及通过次数
+#[derive(Default)]
+struct PaperCounts {
+    submissions: u64,
+    passes: u64,
+}
+
+struct Histogram {
+    /// 每个分桶的计数，不累加
+    buckets: [u64; GRADING_BUCKETS.len()],
+    sum: f64,
+    count: u64,
+}
+
+/// 进程内的所有指标，重启后清零
+struct Registry {
+    papers: Mutex<BTreeMap<(ServerId, String), PaperCounts>>,
+    grading: Mutex<Histogram>,
+    email_failures: AtomicU64,
+    /// 各服务的名称及尚未处理的命令数
+    queues: Mutex<Vec<(&'static str, Arc<AtomicUsize>)>>,
+}
+
+static REGISTRY: Registry = Registry {
+    papers: Mutex::new(BTreeMap::new()),
+    grading: Mutex::new(Histogram { buckets: [0; GRADING_BUCKETS.len()], sum: 0.0, count: 0 }),
+    email_failures: AtomicU64::new(0),
+    queues: Mutex::new(Vec::new()),
+};
+
+/// 记录一次评分的结果
+pub fn record_submission(server_id: ServerId, paper_id: &str, pass: bool) {
+    let mut papers = REGISTRY.papers.lock().unwrap();
+    let counts = papers.entry((server_id, paper_id.to_string())).or_default();
+    counts.submissions += 1;
+    if pass {
+        counts.passes += 1;
+    }
+}
+
+/// 记录从收到提交到返回评分结果的耗时
+pub fn observe_grading(duration: Duration) {
+    let seconds = duration.as_secs_f64();
+    let mut grading = REGISTRY.grading.lock().unwrap();
+    if let Some(index) = GRADING_BUCKETS.iter().position(|bound| seconds <= *bound) {
+        grading.buckets[index] += 1;
+    }
+    grading.sum += seconds;
+    grading.count += 1;
+}
+
+pub fn email_send_failed() {
+    REGISTRY.email_failures.fetch_add(1, Ordering::Relaxed);
+}
+
+/// 创建一个会统计积压命令数的管道，actor为/metrics中显示的服务名
+pub fn command_channel<T>(actor: &'static str) -> (CommandSender<T>, CommandReceiver<T>) {
+    let (tx, rx) = mpsc::unbounded_channel();
+    let depth = Arc::new(AtomicUsize::new(0));
+    let mut queues = REGISTRY.queues.lock().unwrap();
+    // 同一服务重新创建时替换原来的计数
+    queues.retain(|(name, _)| *name != actor);
+    queues.push((actor, depth.clone()));
+    (CommandSender { tx, depth: depth.clone() }, CommandReceiver { rx, depth })
+}
+
+#[derive(Debug)]
+pub struct CommandSender<T> {
+    tx: mpsc::UnboundedSender<T>,
+    depth: Arc<AtomicUsize>,
+}
+
+// 手动实现以免要求T: Clone
+impl<T> Clone for CommandSender<T> {
+    fn clone(&self) -> Self {
+        CommandSender { tx: self.tx.clone(), depth: self.depth.clone() }
+    }
+}
+
+impl<T> CommandSender<T> {
+    pub fn send(&self, cmd: T) -> Result<(), mpsc::error::SendError<T>> {
+        // 先计数再发送，避免接收方先减到负数
+        self.depth.fetch_add(1, Ordering::Relaxed);
+        self.tx.send(cmd).inspect_err(|_| {
+            self.depth.fetch_sub(1, Ordering::Relaxed);
+        })
+    }
+}
+
+#[derive(Debug)]
+pub struct CommandReceiver<T> {
+    rx: mpsc::UnboundedReceiver<T>,
+    depth: Arc<AtomicUsize>,
+}
+
+impl<T> CommandReceiver<T> {
+    /// 与mpsc::UnboundedReceiver::recv相同，可以在select!中使用
+    pub async fn recv(&mut self) -> Option<T> {
+        let cmd = self.rx.recv().await;
+        if cmd.is_some() {
+            self.depth.fetch_sub(1, Ordering::Relaxed);
+        }
+        cmd
+    }
+}
+
+/// 按Prometheus文本格式输出所有指标，ws server无响应时sessions为None，
+/// per_server为false时不输出带server_id和paper_id标签的指标，只输出合计值
+pub fn render(sessions: Option<&SessionMetrics>, per_server: bool) -> String {
+    let mut out = String::new();
+    // unwrap: 写入String不会失败
+    write_metrics(&mut out, sessions, per_server).unwrap();
+    out
+}
+
+fn write_metrics(out: &mut String, sessions: Option<&SessionMetrics>, per_server: bool) -> fmt::Result {
+    if let Some(sessions) = sessions {
+        header(out, "quizgate_connected_clients", "gauge", "当前的websocket链接数")?;
+        writeln!(out, "quizgate_connected_clients {}", sessions.connections)?;
+        header(out, "quizgate_verified_sessions", "gauge", "已通过密钥验证的链接数")?;
+        writeln!(out, "quizgate_verified_sessions {}", sessions.verified)?;
+        header(out, "quizgate_pending_deliveries", "gauge", "各客户端等待投递及等待确认的消息数")?;
+        if per_server {
+            for (server_id, queue) in &sessions.queues {
+                writeln!(out, "quizgate_pending_deliveries{{server_id=\"{}\",state=\"pending\"}} {}", server_id, queue.pending)?;
+                writeln!(out, "quizgate_pending_deliveries{{server_id=\"{}\",state=\"inflight\"}} {}", server_id, queue.inflight)?;
+            }
+        } else {
+            let pending: usize = sessions.queues.values().map(|queue| queue.pending).sum();
+            let inflight: usize = sessions.queues.values().map(|queue| queue.inflight).sum();
+            writeln!(out, "quizgate_pending_deliveries{{state=\"pending\"}} {}", pending)?;
+            writeln!(out, "quizgate_pending_deliveries{{state=\"inflight\"}} {}", inflight)?;
+        }
+    }
+
+    {
+        let papers = REGISTRY.papers.lock().unwrap();
+        header(out, "quizgate_submissions_total", "counter", "各试卷的提交次数")?;
+        if per_server {
+            for ((server_id, paper_id), counts) in papers.iter() {
+                writeln!(out, "quizgate_submissions_total{{server_id=\"{}\",paper_id=\"{}\"}} {}", server_id, escape(paper_id), counts.submissions)?;
+            }
+        } else {
+            writeln!(out, "quizgate_submissions_total {}", papers.values().map(|counts| counts.submissions).sum::<u64>())?;
+        }
+        header(out, "quizgate_passes_total", "counter", "各试卷的通过次数")?;
+        if per_server {
+            for ((server_id, paper_id), counts) in papers.iter() {
+                writeln!(out, "quizgate_passes_total{{server_id=\"{}\",paper_id=\"{}\"}} {}", server_id, escape(paper_id), counts.passes)?;
+            }
+        } else {
+            writeln!(out, "quizgate_passes_total {}", papers.values().map(|counts| counts.passes).sum::<u64>())?;
+        }
+    }
+
+    {
+        let grading = REGISTRY.grading.lock().unwrap();
+        header(out, "quizgate_grading_duration_seconds", "histogram", "从收到提交到返回评分结果的耗时")?;
+        let mut cumulative = 0;
+        for (bound, count) in GRADING_BUCKETS.iter().zip(grading.buckets) {
+            cumulative += count;
+            writeln!(out, "quizgate_grading_duration_seconds_bucket{{le=\"{}\"}} {}", bound, cumulative)?;
+        }
+        writeln!(out, "quizgate_grading_duration_seconds_bucket{{le=\"+Inf\"}} {}", grading.count)?;
+        writeln!(out, "quizgate_grading_duration_seconds_sum {}", grading.sum)?;
+        writeln!(out, "quizgate_grading_duration_seconds_count {}", grading.count)?;
+    }
+
+    header(out, "quizgate_actor_queue_length", "gauge", "各服务尚未处理的命令数")?;
+    for (actor, depth) in REGISTRY.queues.lock().unwrap().iter() {
+        writeln!(out, "quizgate_actor_queue_length{{actor=\"{}\"}} {}", actor, depth.load(Ordering::Relaxed))?;
+    }
+
+    header(out, "quizgate_email_send_failures_total", "counter", "发送邮件失败的次数")?;
+    writeln!(out, "quizgate_email_send_failures_total {}", REGISTRY.email_failures.load(Ordering::Relaxed))
+}
+
+fn header(out: &mut String, name: &str, kind: &str, help: &str) -> fmt::Result {
+    writeln!(out, "# HELP {} {}", name, help)?;
+    writeln!(out, "# TYPE {} {}", name, kind)
+}
+
+/// 转义标签值中的反斜杠、双引号和换行
+fn escape(value: &str) -> String {
+    value.replace('\\', "\\\\").replace('"', "\\\"").replace('\n', "\\n")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::r#struct::metrics::QueueDepth;
+
+    #[test]
+    fn aggregated_metrics_have_no_server_labels() {
+        record_submission(7, "survival", true);
+        let sessions = SessionMetrics {
+            connections: 3,
+            verified: 2,
+            queues: [(7, QueueDepth { pending: 2, inflight: 1 }), (8, QueueDepth { pending: 1, inflight: 0 })].into_iter().collect(),
+        };
+
+        let out = render(Some(&sessions), false);
+        assert!(!out.contains("server_id"));
+        assert!(!out.contains("paper_id"));
+        assert!(out.contains("quizgate_pending_deliveries{state=\"pending\"} 3\n"));
+        assert!(out.contains("quizgate_pending_deliveries{state=\"inflight\"} 1\n"));
+        assert!(out.lines().any(|line| line.starts_with("quizgate_submissions_total ")));
+
+        let out = render(Some(&sessions), true);
+        assert!(out.contains("quizgate_pending_deliveries{server_id=\"7\",state=\"pending\"} 2\n"));
+        assert!(out.contains("quizgate_submissions_total{server_id=\"7\",paper_id=\"survival\"}"));
+    }
+}
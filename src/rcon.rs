@@ -0,0 +1,159 @@
+use crate::r#struct::delivery::PlayerAction;
+use crate::CONFIG;
+use quizgate_client::action::is_valid_player_id;
+use quizgate_client::rcon::{RconClient, DEFAULT_COMMAND, DEFAULT_REMOVE_COMMAND};
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
+use std::io;
+use std::net::{IpAddr, Ipv4Addr, Ipv6Addr, SocketAddr};
+use tokio::net::lookup_host;
+
+/// 通过RCON投递时的目标服务器
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq, Eq)]
+pub struct RconTarget {
+    /// host:port形式的RCON地址
+    pub address: String,
+    pub password: String,
+    /// 命令模板，{player}会被替换为玩家ID
+    pub command: Option<String>,
+    /// 试卷指定了投递目标时使用的命令模板，未配置的目标使用默认命令
+    #[serde(default)]
+    pub targets: BTreeMap<String, String>,
+}
+
+impl RconTarget {
+    /// 在同一个连接上依次执行命令，返回未能投递的操作
+    pub async fn deliver(&self, actions: Vec<PlayerAction>) -> Vec<PlayerAction> {
+        // 托管模式下每次连接前重新检查，避免域名在设置后被解析到内网地址
+        let address = if CONFIG.self_hosted {
+            self.address.clone()
+        } else {
+            match resolve_public(&self.address).await {
+                Ok(address) => address.to_string(),
+                Err(e) => {
+                    log::warn!("无法连接RCON服务器{}: {}", self.address, e);
+                    return actions;
+                }
+            }
+        };
+        let mut client = match RconClient::connect(&address, &self.password).await {
+            Ok(client) => client,
+            Err(e) => {
+                log::warn!("连接RCON服务器{}失败: {}", self.address, e);
+                return actions;
+            }
+        };
+        let mut actions = actions.into_iter();
+        while let Some(action) = actions.next() {
+            let player_id = action.player_id();
+            // 非法的玩家ID直接丢弃，重试也不会成功
+            if !is_valid_player_id(player_id) {
+                log::error!(request_id = action.request_id(); "玩家ID{:?}含有非法字符，已丢弃", player_id);
+                continue;
+            }
+            let template = match &action {
+                PlayerAction::Add { target: Some(target), .. } if self.targets.contains_key(target) => &self.targets[target],
+                PlayerAction::Add { .. } => self.command.as_deref().unwrap_or(DEFAULT_COMMAND),
+                PlayerAction::Remove { .. } => DEFAULT_REMOVE_COMMAND,
+            };
+            match client.command(&template.replace("{player}", player_id)).await {
+                Ok(response) => log::info!(
+                    request_id = action.request_id(),
+                    player_id = player_id.as_str(),
+                    rcon = self.address.as_str();
+                    "已通过RCON为玩家{}执行命令: {}", player_id, response
+                ),
+                Err(e) => {
+                    log::warn!(
+                        request_id = action.request_id(),
+                        player_id = player_id.as_str(),
+                        rcon = self.address.as_str();
+                        "通过RCON为玩家{}执行命令失败: {}", player_id, e
+                    );
+                    let mut failed = vec![action];
+                    failed.extend(actions);
+                    return failed;
+                }
+            }
+        }
+        Vec::new()
+    }
+}
+
+/// 解析RCON地址，只允许公网地址。托管模式下地址由服务器所有者填写，
+/// 不限制时可以借助服务端访问本机或内网中的其他服务
+pub async fn resolve_public(address: &str) -> io::Result<SocketAddr> {
+    let addresses: Vec<SocketAddr> = lookup_host(address).await?.collect();
+    if let Some(address) = addresses.iter().find(|address| !is_public_ip(address.ip())) {
+        return Err(io::Error::new(io::ErrorKind::PermissionDenied, format!("不能使用内网或保留地址{}", address.ip())));
+    }
+    addresses
+        .into_iter()
+        .next()
+        .ok_or_else(|| io::Error::new(io::ErrorKind::NotFound, format!("无法解析{}", address)))
+}
+
+fn is_public_ip(ip: IpAddr) -> bool {
+    match ip {
+        IpAddr::V4(ip) => is_public_ipv4(ip),
+        IpAddr::V6(ip) => match ip.to_ipv4_mapped() {
+            Some(ip) => is_public_ipv4(ip),
+            None => is_public_ipv6(ip),
+        },
+    }
+}
+
+fn is_public_ipv4(ip: Ipv4Addr) -> bool {
+    let [a, b, c, _] = ip.octets();
+    !(ip.is_unspecified()
+        || ip.is_loopback()
+        || ip.is_private()
+        || ip.is_link_local()
+        || ip.is_broadcast()
+        || ip.is_documentation()
+        || ip.is_multicast()
+        // 0.0.0.0/8、100.64.0.0/10（运营商NAT）、192.0.0.0/24、198.18.0.0/15及240.0.0.0/4
+        || a == 0
+        || (a == 100 && (64..128).contains(&b))
+        || (a == 192 && b == 0 && c == 0)
+        || (a == 198 && (b == 18 || b == 19))
+        || a >= 240)
+}
+
+fn is_public_ipv6(ip: Ipv6Addr) -> bool {
+    let first = ip.segments()[0];
+    !(ip.is_unspecified()
+        || ip.is_loopback()
+        || ip.is_multicast()
+        // fc00::/7（唯一本地地址）、fe80::/10（链路本地地址）及2001:db8::/32（文档地址）
+        || (first & 0xfe00) == 0xfc00
+        || (first & 0xffc0) == 0xfe80
+        || (first == 0x2001 && ip.segments()[1] == 0x0db8))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn rejects_internal_addresses() {
+        for ip in [
+            "127.0.0.1", "10.0.0.5", "172.16.3.4", "192.168.1.1", "169.254.169.254", "0.0.0.0", "100.64.0.1",
+            "255.255.255.255", "::1", "::", "fd00::1", "fe80::1", "::ffff:127.0.0.1", "::ffff:10.1.2.3",
+        ] {
+            assert!(!is_public_ip(ip.parse().unwrap()), "{}", ip);
+        }
+        for ip in ["1.1.1.1", "8.8.8.8", "172.32.0.1", "100.128.0.1", "2606:4700::1111", "::ffff:1.1.1.1"] {
+            assert!(is_public_ip(ip.parse().unwrap()), "{}", ip);
+        }
+    }
+
+    #[tokio::test]
+    async fn resolve_rejects_loopback() {
+        let error = resolve_public("127.0.0.1:25575").await.unwrap_err();
+        assert_eq!(error.kind(), io::ErrorKind::PermissionDenied);
+        let error = resolve_public("localhost:25575").await.unwrap_err();
+        assert_eq!(error.kind(), io::ErrorKind::PermissionDenied);
+        assert_eq!(resolve_public("1.1.1.1:25575").await.unwrap(), "1.1.1.1:25575".parse().unwrap());
+    }
+}
@@ -0,0 +1,330 @@
+use crate::config::LocalPaper;
+use crate::metrics::{self, CommandReceiver, CommandSender};
+use crate::format::{parse_paper, SELF_HOSTED_PAPER_FILES};
+use crate::sql_server::SELF_HOSTED_SLUG;
+use crate::validate::validate_paper;
+use crate::CONFIG;
+use notify::{Event, RecommendedWatcher, RecursiveMode, Watcher};
+use serde_json::Value;
+use std::collections::{HashMap, HashSet};
+use std::ffi::OsString;
+use std::path::{Path, PathBuf};
+use std::{fs, io};
+use tokio::sync::{mpsc, oneshot};
+use tokio::time::{self, Duration};
+
+/// 收到文件变化后等待该时间再重新加载，合并编辑器保存时产生的多个事件
+const DEBOUNCE: Duration = Duration::from_millis(200);
+
+#[derive(Debug)]
+enum Command {
+    GetPaper {
+        reference: String,
+        res_tx: oneshot::Sender<Option<LocalPaperContent>>,
+    },
+    /// 检查服务是否仍在处理命令
+    Ping {
+        res_tx: oneshot::Sender<()>,
+    },
+}
+
+/// 自托管模式下的试卷及其slug，0.json的slug为0
+#[derive(Debug)]
+pub struct LocalPaperContent {
+    pub slug: String,
+    pub content: Value,
+}
+
+/// 监视配置文件及自托管模式下的试卷文件，文件变化或收到SIGHUP时重新加载。
+/// 试卷解析后缓存在内存中，文件有误时继续使用上一次成功读取的版本
+pub struct ReloadServer {
+    /// 接收命令的管道
+    cmd_rx: CommandReceiver<Command>,
+
+    /// 试卷文件路径及解析后的内容
+    papers: HashMap<String, Value>,
+
+    /// 需要持有watcher才能持续监视，配置文件中的试卷可能位于不同的目录
+    watcher: Option<RecommendedWatcher>,
+    watched: HashSet<PathBuf>,
+}
+
+impl ReloadServer {
+    pub fn new() -> (ReloadServer, ReloadServerHandle) {
+        let (cmd_tx, cmd_rx) = metrics::command_channel("reload_server");
+        (
+            ReloadServer {
+                cmd_rx,
+                papers: HashMap::new(),
+                watcher: None,
+                watched: HashSet::new(),
+            },
+            ReloadServerHandle { cmd_tx },
+        )
+    }
+
+    /// 按slug查找试卷，0.json按SELF_HOSTED_PAPER_FILES的顺序使用第一份可用的文件。
+    /// 访问根路径时优先使用0.json，没有时使用papers中的第一张试卷
+    fn paper(&self, reference: &str) -> Option<LocalPaperContent> {
+        let config = CONFIG.current();
+        let legacy = || {
+            let content = SELF_HOSTED_PAPER_FILES.iter().find_map(|file| self.papers.get(*file))?;
+            Some(LocalPaperContent { slug: SELF_HOSTED_SLUG.to_string(), content: content.clone() })
+        };
+        let configured = |paper: &LocalPaper| {
+            let content = self.papers.get(&paper.file)?;
+            Some(LocalPaperContent { slug: paper.slug.clone(), content: content.clone() })
+        };
+        match reference {
+            "" => legacy().or_else(|| config.papers.first().and_then(configured)),
+            SELF_HOSTED_SLUG => legacy(),
+            slug => config.papers.iter().find(|paper| paper.slug == slug).and_then(configured),
+        }
+    }
+
+    /// 0.json及配置文件中的所有试卷文件
+    fn paper_files() -> Vec<String> {
+        let mut files: Vec<String> = SELF_HOSTED_PAPER_FILES.iter().map(|file| file.to_string()).collect();
+        files.extend(CONFIG.current().papers.iter().map(|paper| paper.file.clone()));
+        files
+    }
+
+    fn reload_papers(&mut self) {
+        if !CONFIG.self_hosted {
+            return;
+        }
+        let files = Self::paper_files();
+        // 从配置文件中移除的试卷不再缓存
+        self.papers.retain(|file, _| files.contains(file));
+        for file in files {
+            let text = match fs::read_to_string(&file) {
+                Ok(text) => text,
+                Err(e) if e.kind() == io::ErrorKind::NotFound => {
+                    if self.papers.remove(&file).is_some() {
+                        log::info!("{}已被删除", file);
+                    }
+                    continue;
+                }
+                Err(e) => {
+                    log::error!("读取{}时出现错误，继续使用上一次读取的版本：{}", file, e);
+                    continue;
+                }
+            };
+            let content = match parse_paper(&text, Some(&file)) {
+                Ok(content) => content,
+                Err(message) => {
+                    log::error!("读取{}时出现错误，继续使用上一次读取的版本：{}", file, message);
+                    continue;
+                }
+            };
+            // 与上传试卷时相同的校验，避免缺少及格分数等问题的试卷在评分时出错
+            let diagnostics = validate_paper(&content);
+            if !diagnostics.is_empty() {
+                log::error!("{}有误，继续使用上一次读取的版本：", file);
+                for diagnostic in diagnostics {
+                    log::error!("  {}: {}", diagnostic.path, diagnostic.message);
+                }
+                continue;
+            }
+            if self.papers.get(&file) != Some(&content) {
+                log::info!("已加载{}", file);
+                self.papers.insert(file, content);
+            }
+        }
+    }
+
+    fn reload_config(&self) {
+        match CONFIG.reload() {
+            Ok(()) => log::info!("已重新加载{}", CONFIG.file().display()),
+            Err(errors) => {
+                log::error!("{}有误，继续使用当前的配置：", CONFIG.file().display());
+                for error in errors {
+                    log::error!("  {}", error);
+                }
+            }
+        }
+    }
+
+    /// 监视配置文件及试卷所在的目录，编辑器保存时常常会替换整个文件，因此不直接监视文件本身
+    fn watch(&mut self) {
+        let Some(watcher) = &mut self.watcher else {
+            return;
+        };
+        let mut directories = HashSet::new();
+        directories.insert(PathBuf::from("."));
+        directories.insert(directory_of(CONFIG.file()));
+        if CONFIG.self_hosted {
+            directories.extend(CONFIG.current().papers.iter().map(|paper| directory_of(Path::new(&paper.file))));
+        }
+        for directory in directories {
+            if self.watched.contains(&directory) {
+                continue;
+            }
+            match watcher.watch(&directory, RecursiveMode::NonRecursive) {
+                Ok(()) => {
+                    self.watched.insert(directory);
+                }
+                Err(e) => log::warn!("无法监视{}，其中的文件变化后需要通过SIGHUP重新加载：{}", directory.display(), e),
+            }
+        }
+    }
+
+    pub async fn run(mut self) -> io::Result<()> {
+        self.reload_papers();
+
+        let (event_tx, mut event_rx) = mpsc::unbounded_channel();
+        let watcher = notify::recommended_watcher(move |event: notify::Result<Event>| match event {
+            Ok(event) => {
+                let _ = event_tx.send(event);
+            }
+            Err(e) => log::warn!("监视文件时出现错误：{}", e),
+        });
+        match watcher {
+            Ok(watcher) => self.watcher = Some(watcher),
+            Err(e) => log::warn!("无法监视文件变化，只能通过SIGHUP重新加载：{}", e),
+        }
+        self.watch();
+        let (hangup_tx, mut hangup_rx) = mpsc::unbounded_channel();
+        listen_hangup(hangup_tx)?;
+
+        let config_name = CONFIG.file().file_name().map(OsString::from);
+        loop {
+            tokio::select! {
+                Some(cmd) = self.cmd_rx.recv() => {
+                    match cmd {
+                        Command::GetPaper { reference, res_tx } => {
+                            let _ = res_tx.send(self.paper(&reference));
+                        }
+                        Command::Ping { res_tx } => {
+                            let _ = res_tx.send(());
+                        }
+                    }
+                }
+                Some(event) = event_rx.recv() => {
+                    time::sleep(DEBOUNCE).await;
+                    let mut events = vec![event];
+                    while let Ok(event) = event_rx.try_recv() {
+                        events.push(event);
+                    }
+                    // 读取文件本身也会产生事件，只处理创建、修改和删除
+                    let names: HashSet<OsString> = events
+                        .into_iter()
+                        .filter(|event| event.kind.is_create() || event.kind.is_modify() || event.kind.is_remove())
+                        .flat_map(|event| event.paths)
+                        .filter_map(|path| path.file_name().map(OsString::from))
+                        .collect();
+                    let config_changed = config_name.as_ref().is_some_and(|name| names.contains(name));
+                    if config_changed {
+                        self.reload_config();
+                        self.watch();
+                    }
+                    let paper_changed = Self::paper_files()
+                        .iter()
+                        .any(|file| Path::new(file).file_name().is_some_and(|name| names.contains(name)));
+                    // 配置文件中的试卷可能有增减，也需要重新加载
+                    if config_changed || paper_changed {
+                        self.reload_papers();
+                    }
+                }
+                Some(()) = hangup_rx.recv() => {
+                    log::info!("收到SIGHUP，重新加载配置及试卷");
+                    self.reload_config();
+                    self.reload_papers();
+                    self.watch();
+                }
+            }
+        }
+    }
+}
+
+fn directory_of(file: &Path) -> PathBuf {
+    match file.parent() {
+        Some(parent) if !parent.as_os_str().is_empty() => parent.to_path_buf(),
+        _ => PathBuf::from("."),
+    }
+}
+
+#[cfg(unix)]
+fn listen_hangup(hangup_tx: mpsc::UnboundedSender<()>) -> io::Result<()> {
+    use tokio::signal::unix::{signal, SignalKind};
+    let mut hangup = signal(SignalKind::hangup())?;
+    tokio::spawn(async move {
+        while hangup.recv().await.is_some() {
+            if hangup_tx.send(()).is_err() {
+                break;
+            }
+        }
+    });
+    Ok(())
+}
+
+/// 非unix平台没有SIGHUP，只能通过监视文件重新加载
+#[cfg(not(unix))]
+fn listen_hangup(_hangup_tx: mpsc::UnboundedSender<()>) -> io::Result<()> {
+    Ok(())
+}
+
+#[derive(Debug, Clone)]
+pub struct ReloadServerHandle {
+    cmd_tx: CommandSender<Command>,
+}
+
+impl ReloadServerHandle {
+    /// 自托管模式下按slug查找试卷，没有可用的试卷时返回None
+    pub async fn paper(&self, reference: String) -> Option<LocalPaperContent> {
+        let (res_tx, res_rx) = oneshot::channel();
+        // unwrap: reload server should not have been dropped
+        self.cmd_tx.send(Command::GetPaper { reference, res_tx }).unwrap();
+        res_rx.await.unwrap_or_default()
+    }
+
+    /// 服务已退出时返回false，例如无法监听SIGHUP
+    pub async fn ping(&self) -> bool {
+        let (res_tx, res_rx) = oneshot::channel();
+        self.cmd_tx.send(Command::Ping { res_tx }).is_ok() && res_rx.await.is_ok()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::config::{self, Config};
+    use std::env;
+
+    const GOOD: &str = r#"{"main_title":"A","score":1,"pass":1,"questions":[{"type":"radio","content":"q","options":["a","b"],"correct":1,"score":1}]}"#;
+
+    #[test]
+    fn invalid_paper_keeps_last_good_version() {
+        let file = env::temp_dir().join(format!("quizgate-reload-{}.json", std::process::id()));
+        let paper = LocalPaper {
+            slug: "survival".to_string(),
+            file: file.to_string_lossy().to_string(),
+            key: "survival_key".to_string(),
+            rcon: None,
+        };
+        config::init_for_test(Config { self_hosted: true, papers: vec![paper], ..Config::default() });
+        let (mut server, _handle) = ReloadServer::new();
+
+        fs::write(&file, GOOD).unwrap();
+        server.reload_papers();
+        assert_eq!(server.paper("survival").unwrap().content["main_title"], "A");
+
+        // 语法正确但缺少及格分数、及格分数不是整数、缺少题目的文件都不应替换已加载的试卷
+        let invalid = [
+            r#"{"main_title":"B","score":1,"questions":[{"type":"radio","content":"q","options":["a","b"],"correct":1,"score":1}]}"#,
+            r#"{"main_title":"B","score":1,"pass":"1","questions":[{"type":"radio","content":"q","options":["a","b"],"correct":1,"score":1}]}"#,
+            r#"{"main_title":"B","score":1,"pass":1}"#,
+            "{not json",
+        ];
+        for text in invalid {
+            fs::write(&file, text).unwrap();
+            server.reload_papers();
+            assert_eq!(server.paper("survival").unwrap().content["main_title"], "A", "{}", text);
+        }
+
+        fs::write(&file, GOOD.replace("\"A\"", "\"C\"")).unwrap();
+        server.reload_papers();
+        assert_eq!(server.paper("survival").unwrap().content["main_title"], "C");
+        fs::remove_file(&file).unwrap();
+    }
+}
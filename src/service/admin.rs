@@ -0,0 +1,579 @@
+use actix_web::{http::Method, web, HttpRequest, HttpResponse};
+use serde_json::{json, Value};
+use crate::analytics::analyze;
+use crate::error::{NoSuchValueError, ServerSuspendedError};
+use crate::logging::RequestId;
+use crate::r#struct::admin::{ClientInfo, DailyStatsQuery, PaperDiffQuery, PaperQuery, PlayerRequest, RollbackRequest, RotateKeyRequest, ServerSettings, SubmissionFilter};
+use crate::r#struct::awl_type::ServerId;
+use crate::r#struct::webhook::{WebhookEvent, WebhookEventKind};
+use crate::r#struct::paper::{diff_papers, Paper};
+use crate::sql_server::{key_prefix, SqlServerHandle};
+use crate::utils::{hmac_sha256_hex, unix_timestamp, verify_hmac_sha256_hex};
+use crate::webhook_server::WebhookServerHandle;
+use crate::ws_server::WsServerHandle;
+
+/// 由当前client_key派生的管理令牌，格式为`客户端id.签名`，可以交给管理员而不泄露client_key
+pub(crate) fn admin_token(client: &ClientInfo) -> String {
+    format!("{}.{}", client.id, hmac_sha256_hex(&client.token_secret, &format!("admin:{}", client.id)))
+}
+
+/// 通过`Authorization: Bearer <client_key或管理令牌>`验证服务器所有者
+pub(crate) async fn authenticate(req: &HttpRequest, sql_server: &SqlServerHandle) -> Result<ClientInfo, HttpResponse> {
+    let unauthorized = || HttpResponse::Unauthorized().json(json!({"code": 401}));
+    let credential = req
+        .headers()
+        .get("Authorization")
+        .and_then(|value| value.to_str().ok())
+        .and_then(|value| value.strip_prefix("Bearer "))
+        .map(str::trim)
+        .ok_or_else(unauthorized)?;
+
+    let result = match credential.split_once('.') {
+        Some((id, signature)) => match id.parse::<u32>() {
+            Ok(id) => match sql_server.get_client_info_by_id(id).await {
+                Ok(client) if verify_hmac_sha256_hex(&client.token_secret, &format!("admin:{}", client.id), signature) => Ok(client),
+                Ok(_) => return Err(unauthorized()),
+                Err(e) => Err(e),
+            },
+            Err(_) => return Err(unauthorized()),
+        },
+        None => sql_server.get_client_info(credential.to_string()).await,
+    };
+    match result {
+        // 被停用的服务器只能查询数据
+        Ok(client) if client.suspended && req.method() != Method::GET => Err(HttpResponse::Forbidden().json(json!({
+            "code": 403,
+            "message": ServerSuspendedError.to_string()
+        }))),
+        Ok(client) => Ok(client),
+        Err(e) if e.is::<NoSuchValueError>() => Err(unauthorized()),
+        Err(e) => {
+            log::error!("验证管理员身份时出错: {:?}", e);
+            Err(HttpResponse::InternalServerError().json(json!({"code": 500})))
+        }
+    }
+}
+
+/// 记录操作者时使用的凭据描述，不保存完整的密钥或令牌
+pub(crate) fn credential_label(req: &HttpRequest) -> String {
+    let credential = req
+        .headers()
+        .get("Authorization")
+        .and_then(|value| value.to_str().ok())
+        .and_then(|value| value.strip_prefix("Bearer "))
+        .map(str::trim)
+        .unwrap_or_default();
+    if credential.contains('.') {
+        "token".to_string()
+    } else {
+        format!("key:{}", key_prefix(credential))
+    }
+}
+
+pub(crate) fn internal_error(e: Box<dyn std::error::Error + Send + Sync>) -> HttpResponse {
+    log::error!("处理管理请求时出错: {:?}", e);
+    HttpResponse::InternalServerError().json(json!({"code": 500}))
+}
+
+// 获取派生的管理令牌
+pub(crate) async fn token(req: HttpRequest, sql_server: web::Data<SqlServerHandle>) -> HttpResponse {
+    let client = match authenticate(&req, &sql_server).await {
+        Ok(client) => client,
+        Err(response) => return response,
+    };
+    HttpResponse::Ok().json(json!({"code": 200, "token": admin_token(&client)}))
+}
+
+// 查询提交记录
+pub(crate) async fn submissions(
+    req: HttpRequest,
+    filter: web::Query<SubmissionFilter>,
+    sql_server: web::Data<SqlServerHandle>,
+) -> HttpResponse {
+    let client = match authenticate(&req, &sql_server).await {
+        Ok(client) => client,
+        Err(response) => return response,
+    };
+    match sql_server.list_submissions(client.id, filter.into_inner()).await {
+        Ok(records) => HttpResponse::Ok().json(json!({"code": 200, "data": records})),
+        Err(e) => internal_error(e),
+    }
+}
+
+// 查询通过记录
+pub(crate) async fn passes(
+    req: HttpRequest,
+    filter: web::Query<SubmissionFilter>,
+    sql_server: web::Data<SqlServerHandle>,
+) -> HttpResponse {
+    let client = match authenticate(&req, &sql_server).await {
+        Ok(client) => client,
+        Err(response) => return response,
+    };
+    let filter = SubmissionFilter { pass: Some(true), ..filter.into_inner() };
+    match sql_server.list_submissions(client.id, filter).await {
+        Ok(records) => HttpResponse::Ok().json(json!({"code": 200, "data": records})),
+        Err(e) => internal_error(e),
+    }
+}
+
+// 查询统计数据
+pub(crate) async fn stats(req: HttpRequest, sql_server: web::Data<SqlServerHandle>) -> HttpResponse {
+    let client = match authenticate(&req, &sql_server).await {
+        Ok(client) => client,
+        Err(response) => return response,
+    };
+    match sql_server.get_submission_stats(client.id).await {
+        Ok(stats) => HttpResponse::Ok().json(json!({"code": 200, "data": stats})),
+        Err(e) => internal_error(e),
+    }
+}
+
+// 查询客户端连接状态及投递队列
+pub(crate) async fn status(
+    req: HttpRequest,
+    sql_server: web::Data<SqlServerHandle>,
+    ws_server: web::Data<WsServerHandle>,
+) -> HttpResponse {
+    let client = match authenticate(&req, &sql_server).await {
+        Ok(client) => client,
+        Err(response) => return response,
+    };
+    let status = ws_server.status(client.id).await;
+    HttpResponse::Ok().json(json!({"code": 200, "server_name": client.name, "data": status}))
+}
+
+// 撤销玩家资格，通知客户端将其移出白名单
+pub(crate) async fn revoke(
+    req: HttpRequest,
+    req_body: web::Json<PlayerRequest>,
+    request_id: RequestId,
+    sql_server: web::Data<SqlServerHandle>,
+    ws_server: web::Data<WsServerHandle>,
+    webhook_server: web::Data<WebhookServerHandle>,
+) -> HttpResponse {
+    let client = match authenticate(&req, &sql_server).await {
+        Ok(client) => client,
+        Err(response) => return response,
+    };
+    let player_id = req_body.into_inner().player_id;
+    if player_id.is_empty() {
+        return HttpResponse::BadRequest().json(json!({"code": 400}));
+    }
+    let mut paper_ids = match sql_server.revoke_player(client.id, player_id.clone()).await {
+        Ok(paper_ids) => paper_ids,
+        Err(e) => return internal_error(e),
+    };
+    let revoked = paper_ids.len();
+    // 同时拒绝尚未审核的申请
+    match sql_server.remove_approval(client.id, player_id.clone()).await {
+        Ok(pending) => paper_ids.extend(pending),
+        Err(e) => return internal_error(e),
+    }
+    ws_server.revoke_player(client.id, player_id.clone(), &request_id);
+    // 与批准时一样，按被撤销的每张试卷分别发送事件
+    paper_ids.sort();
+    paper_ids.dedup();
+    for paper_id in paper_ids {
+        webhook_server.dispatch(client.id, WebhookEvent {
+            event: WebhookEventKind::Revoked,
+            player_id: player_id.clone(),
+            paper_id,
+            score: None,
+            timestamp: unix_timestamp(),
+        });
+    }
+    HttpResponse::Ok().json(json!({"code": 200, "revoked": revoked}))
+}
+
+// 查询最近若干天每天的提交及通过数量，默认30天
+pub(crate) async fn daily_stats(
+    req: HttpRequest,
+    query: web::Query<DailyStatsQuery>,
+    sql_server: web::Data<SqlServerHandle>,
+) -> HttpResponse {
+    let client = match authenticate(&req, &sql_server).await {
+        Ok(client) => client,
+        Err(response) => return response,
+    };
+    let days = query.days.unwrap_or(30).clamp(1, 365);
+    match sql_server.get_daily_stats(client.id, days).await {
+        Ok(stats) => HttpResponse::Ok().json(json!({"code": 200, "data": stats})),
+        Err(e) => internal_error(e),
+    }
+}
+
+// 查询服务器的配额及当前用量
+pub(crate) async fn quotas(req: HttpRequest, sql_server: web::Data<SqlServerHandle>) -> HttpResponse {
+    let client = match authenticate(&req, &sql_server).await {
+        Ok(client) => client,
+        Err(response) => return response,
+    };
+    let quotas = match sql_server.get_quotas(client.id).await {
+        Ok(quotas) => quotas,
+        Err(e) => return internal_error(e),
+    };
+    let papers = match sql_server.list_papers(client.id).await {
+        Ok(papers) => papers.len(),
+        Err(e) => return internal_error(e),
+    };
+    let today = unix_timestamp() as i64 / 86400 * 86400;
+    let submissions_today = match sql_server.count_submissions_since(client.id, today).await {
+        Ok(count) => count,
+        Err(e) => return internal_error(e),
+    };
+    HttpResponse::Ok().json(json!({
+        "code": 200,
+        "quotas": quotas,
+        "usage": {"papers": papers, "submissions_today": submissions_today}
+    }))
+}
+
+// 查询服务器设置
+pub(crate) async fn get_settings(req: HttpRequest, sql_server: web::Data<SqlServerHandle>) -> HttpResponse {
+    let client = match authenticate(&req, &sql_server).await {
+        Ok(client) => client,
+        Err(response) => return response,
+    };
+    match sql_server.get_settings(client.id).await {
+        Ok(settings) => HttpResponse::Ok().json(json!({"code": 200, "data": settings})),
+        Err(e) => internal_error(e),
+    }
+}
+
+// 修改服务器设置
+pub(crate) async fn set_settings(
+    req: HttpRequest,
+    req_body: web::Json<ServerSettings>,
+    sql_server: web::Data<SqlServerHandle>,
+) -> HttpResponse {
+    let client = match authenticate(&req, &sql_server).await {
+        Ok(client) => client,
+        Err(response) => return response,
+    };
+    match sql_server.set_settings(client.id, req_body.into_inner()).await {
+        Ok(()) => HttpResponse::Ok().json(json!({"code": 200})),
+        Err(e) => internal_error(e),
+    }
+}
+
+// 查询等待审核的玩家
+pub(crate) async fn approvals(req: HttpRequest, sql_server: web::Data<SqlServerHandle>) -> HttpResponse {
+    let client = match authenticate(&req, &sql_server).await {
+        Ok(client) => client,
+        Err(response) => return response,
+    };
+    match sql_server.list_approvals(client.id).await {
+        Ok(records) => HttpResponse::Ok().json(json!({"code": 200, "data": records})),
+        Err(e) => internal_error(e),
+    }
+}
+
+// 批准等待审核的玩家并投递给客户端
+pub(crate) async fn approve(
+    req: HttpRequest,
+    req_body: web::Json<PlayerRequest>,
+    request_id: RequestId,
+    sql_server: web::Data<SqlServerHandle>,
+    ws_server: web::Data<WsServerHandle>,
+    webhook_server: web::Data<WebhookServerHandle>,
+) -> HttpResponse {
+    let client = match authenticate(&req, &sql_server).await {
+        Ok(client) => client,
+        Err(response) => return response,
+    };
+    let player_id = req_body.into_inner().player_id;
+    let paper_ids = match sql_server.remove_approval(client.id, player_id.clone()).await {
+        Ok(paper_ids) if paper_ids.is_empty() => return HttpResponse::NotFound().json(json!({"code": 404})),
+        Ok(paper_ids) => paper_ids,
+        Err(e) => return internal_error(e),
+    };
+    // 玩家可能通过了多张试卷，按每张试卷的投递目标分别投递
+    for paper_id in paper_ids {
+        let target = paper_target(&sql_server, client.id, &paper_id).await;
+        ws_server.send_message(client.id, player_id.clone(), target, &request_id).await;
+        webhook_server.dispatch(client.id, WebhookEvent {
+            event: WebhookEventKind::Approved,
+            player_id: player_id.clone(),
+            paper_id,
+            score: None,
+            timestamp: unix_timestamp(),
+        });
+    }
+    HttpResponse::Ok().json(json!({"code": 200}))
+}
+
+// 重新向客户端投递已通过的玩家，用于客户端丢失白名单等情况
+pub(crate) async fn resend(
+    req: HttpRequest,
+    req_body: web::Json<PlayerRequest>,
+    request_id: RequestId,
+    sql_server: web::Data<SqlServerHandle>,
+    ws_server: web::Data<WsServerHandle>,
+) -> HttpResponse {
+    let client = match authenticate(&req, &sql_server).await {
+        Ok(client) => client,
+        Err(response) => return response,
+    };
+    let player_id = req_body.into_inner().player_id;
+    let filter = SubmissionFilter {
+        paper_id: None,
+        player_id: Some(player_id.clone()),
+        pass: Some(true),
+        since: None,
+        until: None,
+        limit: Some(1),
+        offset: None,
+    };
+    let record = match sql_server.list_submissions(client.id, filter).await {
+        Ok(records) => match records.into_iter().find(|record| !record.revoked) {
+            Some(record) => record,
+            None => return HttpResponse::NotFound().json(json!({"code": 404})),
+        },
+        Err(e) => return internal_error(e),
+    };
+    let target = paper_target(&sql_server, client.id, &record.paper_id).await;
+    ws_server.send_message(client.id, player_id, target, &request_id).await;
+    HttpResponse::Ok().json(json!({"code": 200}))
+}
+
+/// 旧密钥最长的宽限期
+const MAX_GRACE_PERIOD: u64 = 7 * 24 * 3600;
+
+// 轮换客户端密钥，旧密钥在宽限期后失效，使用旧密钥的链接届时会被断开
+pub(crate) async fn rotate_key(
+    req: HttpRequest,
+    req_body: web::Json<RotateKeyRequest>,
+    sql_server: web::Data<SqlServerHandle>,
+    ws_server: web::Data<WsServerHandle>,
+) -> HttpResponse {
+    let client = match authenticate(&req, &sql_server).await {
+        Ok(client) => client,
+        Err(response) => return response,
+    };
+    let grace_period = req_body.grace_period.unwrap_or(0).min(MAX_GRACE_PERIOD);
+    let (key, expires_at) = match sql_server.rotate_key(client.id, grace_period).await {
+        Ok(result) => result,
+        Err(e) => return internal_error(e),
+    };
+    ws_server.key_rotated(client.id, expires_at);
+    // 旧的管理令牌随密钥一起失效，返回新的令牌
+    let client = match sql_server.get_client_info_by_id(client.id).await {
+        Ok(client) => client,
+        Err(e) => return internal_error(e),
+    };
+    log::info!("客户端{}轮换了密钥，旧密钥将于{}失效", client.id, expires_at);
+    HttpResponse::Ok().json(json!({
+        "code": 200,
+        "key": key,
+        "token": admin_token(&client),
+        "old_key_expires_at": expires_at
+    }))
+}
+
+/// 查询试卷所属服务器的投递目标，试卷已被删除或不属于该服务器时使用默认命令
+async fn paper_target(sql_server: &SqlServerHandle, server_id: ServerId, paper_id: &str) -> Option<String> {
+    match sql_server.get_paper(paper_id.to_string()).await {
+        Ok(paper) if paper.server_id == server_id => paper.target,
+        _ => None,
+    }
+}
+
+// 列出服务器的所有试卷
+pub(crate) async fn papers(req: HttpRequest, sql_server: web::Data<SqlServerHandle>) -> HttpResponse {
+    let client = match authenticate(&req, &sql_server).await {
+        Ok(client) => client,
+        Err(response) => return response,
+    };
+    match sql_server.list_papers(client.id).await {
+        Ok(papers) => HttpResponse::Ok().json(json!({"code": 200, "data": papers})),
+        Err(e) => internal_error(e),
+    }
+}
+
+/// 按id或slug查询属于该服务器的试卷，未指定时使用服务器的第一张试卷
+pub(crate) async fn owned_paper(sql_server: &SqlServerHandle, server_id: ServerId, reference: Option<String>) -> Result<Paper, HttpResponse> {
+    let not_found = || HttpResponse::NotFound().json(json!({"code": 404}));
+    let reference = match reference {
+        Some(reference) => reference,
+        None => match sql_server.list_papers(server_id).await {
+            Ok(papers) => match papers.into_iter().next() {
+                Some(paper) => paper.id.to_string(),
+                None => return Err(not_found()),
+            },
+            Err(e) => return Err(internal_error(e)),
+        },
+    };
+    match sql_server.get_paper(reference).await {
+        Ok(paper) if paper.server_id == server_id => Ok(paper),
+        Ok(_) => Err(not_found()),
+        Err(e) if e.is::<NoSuchValueError>() => Err(not_found()),
+        Err(e) => Err(internal_error(e)),
+    }
+}
+
+/// 读取试卷的指定版本，未指定时为当前版本
+async fn paper_content(sql_server: &SqlServerHandle, paper: &Paper, version: Option<u32>) -> Result<Value, HttpResponse> {
+    let content = match version {
+        Some(version) if version != paper.version => match sql_server.get_paper_version(paper.id, version).await {
+            Ok(version) => version.content,
+            Err(e) if e.is::<NoSuchValueError>() => return Err(HttpResponse::NotFound().json(json!({"code": 404}))),
+            Err(e) => return Err(internal_error(e)),
+        },
+        _ => paper.content.clone(),
+    };
+    serde_json::from_str(&content).map_err(|e| internal_error(Box::new(e)))
+}
+
+// 获取试卷内容，未指定id时返回服务器的第一张试卷
+pub(crate) async fn paper(
+    req: HttpRequest,
+    query: web::Query<PaperQuery>,
+    sql_server: web::Data<SqlServerHandle>,
+) -> HttpResponse {
+    let client = match authenticate(&req, &sql_server).await {
+        Ok(client) => client,
+        Err(response) => return response,
+    };
+    let query = query.into_inner();
+    let paper = match owned_paper(&sql_server, client.id, query.id).await {
+        Ok(paper) => paper,
+        Err(response) => return response,
+    };
+    let content = match paper_content(&sql_server, &paper, query.version).await {
+        Ok(content) => content,
+        Err(response) => return response,
+    };
+    HttpResponse::Ok().json(json!({"code": 200, "version": query.version.unwrap_or(paper.version), "paper": paper, "data": content}))
+}
+
+// 统计试卷某个版本每道题的正确率、选项分布、区分度及平均作答时间
+pub(crate) async fn paper_analysis(
+    req: HttpRequest,
+    query: web::Query<PaperQuery>,
+    sql_server: web::Data<SqlServerHandle>,
+) -> HttpResponse {
+    let client = match authenticate(&req, &sql_server).await {
+        Ok(client) => client,
+        Err(response) => return response,
+    };
+    let query = query.into_inner();
+    let paper = match owned_paper(&sql_server, client.id, query.id).await {
+        Ok(paper) => paper,
+        Err(response) => return response,
+    };
+    let version = query.version.unwrap_or(paper.version);
+    let content = match paper_content(&sql_server, &paper, Some(version)).await {
+        Ok(content) => content,
+        Err(response) => return response,
+    };
+    match sql_server.list_submission_answers(client.id, paper.id, version).await {
+        Ok(submissions) => HttpResponse::Ok().json(json!({
+            "code": 200,
+            "id": paper.id,
+            "version": version,
+            "data": analyze(&content, &submissions)
+        })),
+        Err(e) => internal_error(e),
+    }
+}
+
+// 列出试卷的所有历史版本
+pub(crate) async fn paper_versions(
+    req: HttpRequest,
+    query: web::Query<PaperQuery>,
+    sql_server: web::Data<SqlServerHandle>,
+) -> HttpResponse {
+    let client = match authenticate(&req, &sql_server).await {
+        Ok(client) => client,
+        Err(response) => return response,
+    };
+    let paper = match owned_paper(&sql_server, client.id, query.into_inner().id).await {
+        Ok(paper) => paper,
+        Err(response) => return response,
+    };
+    match sql_server.list_paper_versions(paper.id).await {
+        Ok(versions) => HttpResponse::Ok().json(json!({"code": 200, "current": paper.version, "data": versions})),
+        Err(e) => internal_error(e),
+    }
+}
+
+// 比较试卷的两个版本
+pub(crate) async fn paper_diff(
+    req: HttpRequest,
+    query: web::Query<PaperDiffQuery>,
+    sql_server: web::Data<SqlServerHandle>,
+) -> HttpResponse {
+    let client = match authenticate(&req, &sql_server).await {
+        Ok(client) => client,
+        Err(response) => return response,
+    };
+    let query = query.into_inner();
+    let paper = match owned_paper(&sql_server, client.id, Some(query.id)).await {
+        Ok(paper) => paper,
+        Err(response) => return response,
+    };
+    let to = query.to.unwrap_or(paper.version);
+    let old = match paper_content(&sql_server, &paper, Some(query.from)).await {
+        Ok(content) => content,
+        Err(response) => return response,
+    };
+    let new = match paper_content(&sql_server, &paper, Some(to)).await {
+        Ok(content) => content,
+        Err(response) => return response,
+    };
+    HttpResponse::Ok().json(json!({"code": 200, "from": query.from, "to": to, "data": diff_papers(&old, &new)}))
+}
+
+// 将试卷回滚到指定版本，回滚本身也会产生一个新版本
+pub(crate) async fn paper_rollback(
+    req: HttpRequest,
+    req_body: web::Json<RollbackRequest>,
+    sql_server: web::Data<SqlServerHandle>,
+) -> HttpResponse {
+    let client = match authenticate(&req, &sql_server).await {
+        Ok(client) => client,
+        Err(response) => return response,
+    };
+    let request = req_body.into_inner();
+    let paper = match owned_paper(&sql_server, client.id, Some(request.id)).await {
+        Ok(paper) => paper,
+        Err(response) => return response,
+    };
+    let ip_address = req.connection_info().peer_addr().unwrap_or_default().to_string();
+    match sql_server.rollback_paper(paper.id, request.version, credential_label(&req), ip_address).await {
+        Ok(version) => HttpResponse::Ok().json(json!({"code": 200, "version": version})),
+        Err(e) if e.is::<NoSuchValueError>() => HttpResponse::NotFound().json(json!({"code": 404})),
+        Err(e) => internal_error(e),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use actix_web::{http::StatusCode, test::TestRequest};
+    use crate::config::Config;
+    use crate::r#struct::admin::RotateKeyRequest;
+    use crate::sql_server::tests::test_server;
+    use crate::ws_server::WsServer;
+
+    #[actix_web::test]
+    async fn old_key_cannot_mint_token_or_rotate() {
+        let (server, handle) = test_server("admin-old-key", Config::default()).await;
+        actix_web::rt::spawn(server.run());
+        let old = handle.register_new_client("a".to_string(), "a@example.com".to_string()).await.unwrap();
+        let server_id = handle.get_client_id(old.clone()).await.unwrap();
+        handle.rotate_key(server_id, 3600).await.unwrap();
+        let (_ws_server, ws_handle) = WsServer::new(handle.clone());
+        let bearer = ("Authorization", format!("Bearer {}", old));
+
+        let req = TestRequest::get().insert_header(bearer.clone()).to_http_request();
+        let res = token(req, web::Data::new(handle.clone())).await;
+        assert_eq!(res.status(), StatusCode::UNAUTHORIZED);
+
+        let req = TestRequest::post().insert_header(bearer).to_http_request();
+        let body = web::Json(RotateKeyRequest { grace_period: Some(3600) });
+        let res = rotate_key(req, body, web::Data::new(handle.clone()), web::Data::new(ws_handle)).await;
+        assert_eq!(res.status(), StatusCode::UNAUTHORIZED);
+    }
+}
@@ -0,0 +1,49 @@
+use actix_web::{web, HttpResponse};
+use serde_json::json;
+use std::error::Error;
+use crate::error::{NoSuchValueError, ServerSuspendedError};
+use crate::r#struct::delivery::{Delivery, DeliveryRequest};
+use crate::rcon::resolve_public;
+use crate::sql_server::SqlServerHandle;
+use crate::ws_server::WsServerHandle;
+
+fn error_response(e: Box<dyn Error + Send + Sync>) -> HttpResponse {
+    if e.is::<NoSuchValueError>() {
+        return HttpResponse::Forbidden().json(json!({"code": 403}));
+    }
+    if e.is::<ServerSuspendedError>() {
+        return HttpResponse::Forbidden().json(json!({"code": 403, "message": e.to_string()}));
+    }
+    log::error!("修改投递方式时出错: {:?}", e);
+    HttpResponse::InternalServerError().json(json!({"code": 500}))
+}
+
+// 修改客户端的投递方式
+pub(crate) async fn set_delivery(
+    req_body: web::Json<DeliveryRequest>,
+    sql_server: web::Data<SqlServerHandle>,
+    ws_server: web::Data<WsServerHandle>,
+) -> HttpResponse {
+    let req_body = req_body.into_inner();
+    let delivery = match (req_body.mode.as_str(), req_body.rcon) {
+        ("websocket", _) => Delivery::WebSocket,
+        ("rcon", Some(target)) if !target.address.is_empty() => Delivery::Rcon(target),
+        _ => return HttpResponse::BadRequest().json(json!({"code": 400})),
+    };
+    let server_id = match sql_server.get_client_id(req_body.client_key.clone()).await {
+        Ok(server_id) => server_id,
+        Err(e) => return error_response(e),
+    };
+    if let Delivery::Rcon(target) = &delivery {
+        if let Err(e) = resolve_public(&target.address).await {
+            return HttpResponse::BadRequest().json(json!({"code": 400, "message": format!("RCON地址{}不可用: {}", target.address, e)}));
+        }
+    }
+    match sql_server.set_delivery(req_body.client_key, delivery).await {
+        Ok(()) => {
+            ws_server.reload_delivery(server_id);
+            HttpResponse::Ok().json(json!({"code": 200}))
+        }
+        Err(e) => error_response(e),
+    }
+}
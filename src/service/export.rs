@@ -0,0 +1,92 @@
+use actix_web::http::header::{ContentDisposition, DispositionParam, DispositionType};
+use actix_web::web::Bytes;
+use actix_web::{web, HttpRequest, HttpResponse};
+use futures_util::{stream, StreamExt};
+use serde_json::json;
+use crate::export::{ExportFormat, EXPORT_BATCH_SIZE};
+use crate::r#struct::admin::{ExportQuery, SubmissionFilter};
+use crate::service::admin::{authenticate, owned_paper};
+use crate::sql_server::SqlServerHandle;
+
+// 导出所有提交记录
+pub(crate) async fn submissions(
+    req: HttpRequest,
+    query: web::Query<ExportQuery>,
+    sql_server: web::Data<SqlServerHandle>,
+) -> HttpResponse {
+    export(req, query.into_inner(), sql_server, None, "submissions").await
+}
+
+// 导出通过记录，包括已被撤销的
+pub(crate) async fn passes(
+    req: HttpRequest,
+    query: web::Query<ExportQuery>,
+    sql_server: web::Data<SqlServerHandle>,
+) -> HttpResponse {
+    export(req, query.into_inner(), sql_server, Some(true), "passes").await
+}
+
+/// 分批从数据库读取记录并以流的形式返回，导出大量记录时不需要一次性读入内存
+async fn export(
+    req: HttpRequest,
+    query: ExportQuery,
+    sql_server: web::Data<SqlServerHandle>,
+    pass: Option<bool>,
+    name: &str,
+) -> HttpResponse {
+    let client = match authenticate(&req, &sql_server).await {
+        Ok(client) => client,
+        Err(response) => return response,
+    };
+    let format = match query.format.as_deref().map(ExportFormat::from_name) {
+        None => ExportFormat::Csv,
+        Some(Some(format)) => format,
+        Some(None) => return HttpResponse::BadRequest().json(json!({"code": 400, "message": "format只能是csv或ndjson"})),
+    };
+    let paper_id = match query.paper {
+        Some(reference) => match owned_paper(&sql_server, client.id, Some(reference)).await {
+            Ok(paper) => Some(paper.id.to_string()),
+            Err(response) => return response,
+        },
+        None => None,
+    };
+    let filter = SubmissionFilter {
+        paper_id,
+        player_id: query.player_id,
+        pass,
+        since: query.since,
+        until: query.until,
+        ..Default::default()
+    };
+
+    let sql_server = (**sql_server).clone();
+    let server_id = client.id;
+    let records = stream::unfold(Some(0), move |after_id| {
+        let sql_server = sql_server.clone();
+        let filter = filter.clone();
+        async move {
+            let after_id = after_id?;
+            match sql_server.export_submissions(server_id, filter, after_id, EXPORT_BATCH_SIZE).await {
+                Ok(records) if records.is_empty() => None,
+                Ok(records) => {
+                    // 不足一批说明已经读取完毕
+                    let next = (records.len() as u32 == EXPORT_BATCH_SIZE).then(|| records[records.len() - 1].id);
+                    let chunk: String = records.iter().map(|record| format.encode(record)).collect();
+                    Some((Ok(Bytes::from(chunk)), next))
+                }
+                Err(e) => {
+                    log::error!("导出提交记录失败: {:?}", e);
+                    Some((Err(actix_web::error::ErrorInternalServerError("导出失败")), None))
+                }
+            }
+        }
+    });
+    let body = stream::once(async move { Ok::<_, actix_web::Error>(Bytes::from(format.header())) }).chain(records);
+    HttpResponse::Ok()
+        .content_type(format.content_type())
+        .insert_header(ContentDisposition {
+            disposition: DispositionType::Attachment,
+            parameters: vec![DispositionParam::Filename(format!("{}.{}", name, format.extension()))],
+        })
+        .streaming(body)
+}
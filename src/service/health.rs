@@ -0,0 +1,99 @@
+use std::future::Future;
+use std::time::Duration;
+use actix_web::{web, HttpRequest, HttpResponse};
+use serde_json::json;
+use tokio::time::timeout;
+use crate::email_server::EmailServerHandle;
+use crate::metrics;
+use crate::reload_server::ReloadServerHandle;
+use crate::shutdown;
+use crate::sql_server::SqlServerHandle;
+use crate::webhook_server::WebhookServerHandle;
+use crate::utils::constant_time_eq;
+use crate::ws_server::WsServerHandle;
+use crate::CONFIG;
+
+/// 检查各服务时的最长等待时间，超时视为不可用
+const CHECK_TIMEOUT: Duration = Duration::from_secs(2);
+
+// 进程能处理请求即视为存活，不检查数据库，避免数据库故障时被反复重启
+pub(crate) async fn healthz() -> HttpResponse {
+    HttpResponse::Ok().json(json!({"code": 200}))
+}
+
+// 数据库及各服务均可用时返回200，否则返回503，未开启邮件功能时不检查邮件服务
+pub(crate) async fn readyz(
+    sql_server: web::Data<SqlServerHandle>,
+    ws_server: web::Data<WsServerHandle>,
+    webhook_server: web::Data<WebhookServerHandle>,
+    reload_server: web::Data<ReloadServerHandle>,
+    email_server: Option<web::Data<EmailServerHandle>>,
+) -> HttpResponse {
+    // 关闭过程中各服务会依次退出，不再检查
+    if shutdown::is_shutting_down() {
+        return HttpResponse::ServiceUnavailable().json(json!({"code": 503, "checks": {"shutting_down": true}}));
+    }
+    let database = match timeout(CHECK_TIMEOUT, sql_server.ping()).await {
+        Ok(Ok(())) => "ok".to_string(),
+        Ok(Err(e)) => {
+            log::error!("数据库检查失败: {}", e);
+            e.to_string()
+        }
+        Err(_) => "timeout".to_string(),
+    };
+    let mut checks = json!({
+        "database": database,
+        "ws_server": check(async { ws_server.metrics().await; true }).await,
+        "webhook_server": check(webhook_server.ping()).await,
+        "reload_server": check(reload_server.ping()).await,
+    });
+    if let Some(email_server) = email_server {
+        checks["email_server"] = json!(check(email_server.ping()).await);
+    }
+    // unwrap: checks为上面构造的对象
+    let ready = checks.as_object().unwrap().values().all(|value| value == "ok");
+    if ready {
+        HttpResponse::Ok().json(json!({"code": 200, "checks": checks}))
+    } else {
+        HttpResponse::ServiceUnavailable().json(json!({"code": 503, "checks": checks}))
+    }
+}
+
+/// 等待服务响应，服务已退出时为stopped
+async fn check(ping: impl Future<Output = bool>) -> &'static str {
+    match timeout(CHECK_TIMEOUT, ping).await {
+        Ok(true) => "ok",
+        Ok(false) => "stopped",
+        Err(_) => "timeout",
+    }
+}
+
+// Prometheus格式的指标，配置了metrics_token时需携带`Authorization: Bearer <metrics_token>`，
+// 托管模式下未配置时只输出合计值，避免公开各服务器的数据
+pub(crate) async fn metrics(req: HttpRequest, ws_server: web::Data<WsServerHandle>) -> HttpResponse {
+    let config = CONFIG.current();
+    let per_server = match config.metrics_token.as_deref().filter(|token| !token.is_empty()) {
+        Some(token) => {
+            let authorized = req
+                .headers()
+                .get("Authorization")
+                .and_then(|value| value.to_str().ok())
+                .and_then(|value| value.strip_prefix("Bearer "))
+                .is_some_and(|credential| constant_time_eq(credential.trim(), token));
+            if !authorized {
+                return HttpResponse::Unauthorized().json(json!({"code": 401}));
+            }
+            true
+        }
+        None => CONFIG.self_hosted,
+    };
+    // ws server无响应或正在关闭时省略链接相关的指标
+    let sessions = if shutdown::is_shutting_down() {
+        None
+    } else {
+        timeout(CHECK_TIMEOUT, ws_server.metrics()).await.ok()
+    };
+    HttpResponse::Ok()
+        .content_type("text/plain; version=0.0.4; charset=utf-8")
+        .body(metrics::render(sessions.as_ref(), per_server))
+}
@@ -2,4 +2,10 @@ pub mod register;
 pub mod upload;
 pub(crate) mod resources;
 pub(crate) mod pages;
-pub(crate) mod quiz;
\ No newline at end of file
+pub(crate) mod quiz;pub(crate) mod delivery;
+pub(crate) mod webhook;
+pub(crate) mod admin;
+pub(crate) mod paper;
+pub(crate) mod export;
+pub(crate) mod operator;
+pub(crate) mod health;
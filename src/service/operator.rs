@@ -0,0 +1,285 @@
+use actix_web::{web, HttpRequest, HttpResponse};
+use serde_json::json;
+use crate::error::NoSuchValueError;
+use crate::r#struct::awl_type::ServerId;
+use crate::r#struct::quota::Quotas;
+use crate::service::admin::internal_error;
+use crate::sql_server::SqlServerHandle;
+use crate::utils::constant_time_eq;
+use crate::ws_server::WsServerHandle;
+use crate::CONFIG;
+
+/// 通过`Authorization: Bearer <operator_key>`验证运营者，未配置operator_key时总是失败
+fn is_operator(req: &HttpRequest) -> bool {
+    let config = CONFIG.current();
+    let Some(operator_key) = config.operator_key.as_deref().filter(|key| !key.is_empty()) else {
+        return false;
+    };
+    req.headers()
+        .get("Authorization")
+        .and_then(|value| value.to_str().ok())
+        .and_then(|value| value.strip_prefix("Bearer "))
+        .is_some_and(|credential| constant_time_eq(credential.trim(), operator_key))
+}
+
+fn unauthorized() -> HttpResponse {
+    HttpResponse::Unauthorized().json(json!({"code": 401}))
+}
+
+fn not_found() -> HttpResponse {
+    HttpResponse::NotFound().json(json!({"code": 404, "message": "服务器不存在"}))
+}
+
+// 列出所有注册的服务器
+pub(crate) async fn servers(
+    req: HttpRequest,
+    sql_server: web::Data<SqlServerHandle>,
+    ws_server: web::Data<WsServerHandle>,
+) -> HttpResponse {
+    if !is_operator(&req) {
+        return unauthorized();
+    }
+    let mut servers = match sql_server.list_servers().await {
+        Ok(servers) => servers,
+        Err(e) => return internal_error(e),
+    };
+    let online = ws_server.online_servers().await;
+    for server in &mut servers {
+        server.online = online.contains(&server.id);
+    }
+    HttpResponse::Ok().json(json!({"code": 200, "servers": servers}))
+}
+
+// 停用服务器，断开在线的客户端并拒绝其上传试卷
+pub(crate) async fn suspend(
+    req: HttpRequest,
+    path: web::Path<ServerId>,
+    sql_server: web::Data<SqlServerHandle>,
+    ws_server: web::Data<WsServerHandle>,
+) -> HttpResponse {
+    set_suspended(req, path.into_inner(), true, sql_server, ws_server).await
+}
+
+// 恢复被停用的服务器
+pub(crate) async fn unsuspend(
+    req: HttpRequest,
+    path: web::Path<ServerId>,
+    sql_server: web::Data<SqlServerHandle>,
+    ws_server: web::Data<WsServerHandle>,
+) -> HttpResponse {
+    set_suspended(req, path.into_inner(), false, sql_server, ws_server).await
+}
+
+async fn set_suspended(
+    req: HttpRequest,
+    server_id: ServerId,
+    suspended: bool,
+    sql_server: web::Data<SqlServerHandle>,
+    ws_server: web::Data<WsServerHandle>,
+) -> HttpResponse {
+    if !is_operator(&req) {
+        return unauthorized();
+    }
+    match sql_server.set_suspended(server_id, suspended).await {
+        Ok(()) => {}
+        Err(e) if e.is::<NoSuchValueError>() => return not_found(),
+        Err(e) => return internal_error(e),
+    }
+    if suspended {
+        ws_server.kick(server_id, false);
+        log::warn!("运营者停用了服务器{}", server_id);
+    } else {
+        log::warn!("运营者恢复了服务器{}", server_id);
+    }
+    HttpResponse::Ok().json(json!({"code": 200}))
+}
+
+// 删除服务器及其所有数据，无法恢复
+pub(crate) async fn purge(
+    req: HttpRequest,
+    path: web::Path<ServerId>,
+    sql_server: web::Data<SqlServerHandle>,
+    ws_server: web::Data<WsServerHandle>,
+) -> HttpResponse {
+    if !is_operator(&req) {
+        return unauthorized();
+    }
+    let server_id = path.into_inner();
+    match sql_server.purge_server(server_id).await {
+        Ok(()) => {}
+        Err(e) if e.is::<NoSuchValueError>() => return not_found(),
+        Err(e) => return internal_error(e),
+    }
+    ws_server.kick(server_id, true);
+    log::warn!("运营者删除了服务器{}及其所有数据", server_id);
+    HttpResponse::Ok().json(json!({"code": 200}))
+}
+
+// 查询服务器单独设置的配额及实际生效的配额
+pub(crate) async fn get_quotas(
+    req: HttpRequest,
+    path: web::Path<ServerId>,
+    sql_server: web::Data<SqlServerHandle>,
+) -> HttpResponse {
+    if !is_operator(&req) {
+        return unauthorized();
+    }
+    let server_id = path.into_inner();
+    if let Err(e) = sql_server.get_client_info_by_id(server_id).await {
+        return if e.is::<NoSuchValueError>() { not_found() } else { internal_error(e) };
+    }
+    let quotas = match sql_server.get_server_quotas(server_id).await {
+        Ok(quotas) => quotas,
+        Err(e) => return internal_error(e),
+    };
+    let effective = quotas.clone().or(&CONFIG.current().quotas);
+    HttpResponse::Ok().json(json!({"code": 200, "quotas": quotas, "effective": effective}))
+}
+
+// 覆盖服务器单独设置的配额，为null的字段使用默认配额
+pub(crate) async fn set_quotas(
+    req: HttpRequest,
+    path: web::Path<ServerId>,
+    req_body: web::Json<Quotas>,
+    sql_server: web::Data<SqlServerHandle>,
+) -> HttpResponse {
+    if !is_operator(&req) {
+        return unauthorized();
+    }
+    let server_id = path.into_inner();
+    match sql_server.set_server_quotas(server_id, req_body.into_inner()).await {
+        Ok(()) => {
+            log::info!("运营者修改了服务器{}的配额", server_id);
+            HttpResponse::Ok().json(json!({"code": 200}))
+        }
+        Err(e) if e.is::<NoSuchValueError>() => not_found(),
+        Err(e) => internal_error(e),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use actix_web::{body::to_bytes, http::StatusCode, test::TestRequest};
+    use serde_json::Value;
+    use crate::config::{self, Config};
+    use crate::error::ServerSuspendedError;
+    use crate::r#struct::admin::NewSubmission;
+    use crate::r#struct::paper::NewPaper;
+    use crate::sql_server::tests::test_server;
+    use crate::ws_server::WsServer;
+
+    fn bearer(credential: &str) -> HttpRequest {
+        TestRequest::default().insert_header(("Authorization", format!("Bearer {}", credential))).to_http_request()
+    }
+
+    async fn json_body(res: HttpResponse) -> Value {
+        serde_json::from_slice(&to_bytes(res.into_body()).await.unwrap()).unwrap()
+    }
+
+    #[test]
+    fn operator_key_fails_closed() {
+        config::init_for_test(Config { operator_key: None, ..Config::default() });
+        assert!(!is_operator(&bearer("")));
+        assert!(!is_operator(&bearer("anything")));
+
+        // 空的operator_key与未配置相同，不能用空凭据通过
+        config::init_for_test(Config { operator_key: Some(String::new()), ..Config::default() });
+        assert!(!is_operator(&bearer("")));
+        assert!(!is_operator(&TestRequest::default().insert_header(("Authorization", "Bearer")).to_http_request()));
+
+        config::init_for_test(Config { operator_key: Some("operator".to_string()), ..Config::default() });
+        assert!(!is_operator(&TestRequest::default().to_http_request()));
+        assert!(!is_operator(&bearer("operato")));
+        assert!(!is_operator(&TestRequest::default().insert_header(("Authorization", "operator")).to_http_request()));
+        assert!(is_operator(&bearer("operator")));
+    }
+
+    #[actix_web::test]
+    async fn suspend_quotas_and_purge() {
+        let config = Config {
+            operator_key: Some("operator".to_string()),
+            quotas: Quotas { max_questions: Some(5), max_papers: Some(10), ..Quotas::default() },
+            ..Config::default()
+        };
+        let (server, handle) = test_server("operator", config).await;
+        actix_web::rt::spawn(server.run());
+        let (_ws_server, ws_handle) = WsServer::new(handle.clone());
+        let sql_server = web::Data::new(handle.clone());
+        let ws_server = web::Data::new(ws_handle);
+        let key = handle.register_new_client("a".to_string(), "a@example.com".to_string()).await.unwrap();
+        let id = handle.get_client_id(key.clone()).await.unwrap();
+        let other = handle.register_new_client("b".to_string(), "b@example.com".to_string()).await.unwrap();
+
+        // 非运营者不能调用任何接口
+        let res = suspend(bearer(&key), web::Path::from(id), sql_server.clone(), ws_server.clone()).await;
+        assert_eq!(res.status(), StatusCode::UNAUTHORIZED);
+        let res = purge(bearer(&key), web::Path::from(id), sql_server.clone(), ws_server.clone()).await;
+        assert_eq!(res.status(), StatusCode::UNAUTHORIZED);
+
+        let res = suspend(bearer("operator"), web::Path::from(id), sql_server.clone(), ws_server.clone()).await;
+        assert_eq!(res.status(), StatusCode::OK);
+        assert!(handle.get_client_id(key.clone()).await.unwrap_err().is::<ServerSuspendedError>());
+        assert!(handle.get_client_info(key.clone()).await.unwrap().suspended);
+        let res = unsuspend(bearer("operator"), web::Path::from(id), sql_server.clone(), ws_server.clone()).await;
+        assert_eq!(res.status(), StatusCode::OK);
+        assert_eq!(handle.get_client_id(key.clone()).await.unwrap(), id);
+        let res = suspend(bearer("operator"), web::Path::from(999), sql_server.clone(), ws_server.clone()).await;
+        assert_eq!(res.status(), StatusCode::NOT_FOUND);
+
+        // 单独设置的配额覆盖默认值，未设置的项沿用默认值
+        let quotas = Quotas { max_papers: Some(1), max_questions: None, ..Quotas::default() };
+        let res = set_quotas(bearer("operator"), web::Path::from(id), web::Json(quotas), sql_server.clone()).await;
+        assert_eq!(res.status(), StatusCode::OK);
+        let body = json_body(get_quotas(bearer("operator"), web::Path::from(id), sql_server.clone()).await).await;
+        assert_eq!(body["quotas"]["max_papers"], 1);
+        assert_eq!(body["quotas"]["max_questions"], Value::Null);
+        assert_eq!(body["effective"]["max_papers"], 1);
+        assert_eq!(body["effective"]["max_questions"], 5);
+        let res = set_quotas(bearer("operator"), web::Path::from(999), web::Json(Quotas::default()), sql_server.clone()).await;
+        assert_eq!(res.status(), StatusCode::NOT_FOUND);
+        let res = get_quotas(bearer("operator"), web::Path::from(999), sql_server.clone()).await;
+        assert_eq!(res.status(), StatusCode::NOT_FOUND);
+
+        handle
+            .create_paper(NewPaper {
+                server_id: id,
+                slug: Some("survival".to_string()),
+                title: "A".to_string(),
+                target: None,
+                content: "{}".to_string(),
+                uploaded_by: "test".to_string(),
+                ip_address: "127.0.0.1".to_string(),
+            })
+            .await
+            .unwrap();
+        handle
+            .record_submission(NewSubmission {
+                server_id: id,
+                paper_id: "survival".to_string(),
+                paper_version: Some(1),
+                player_id: "Steve".to_string(),
+                score: 1,
+                pass: true,
+                ip_address: "127.0.0.1".to_string(),
+                answers: "[]".to_string(),
+                durations: None,
+            })
+            .await
+            .unwrap();
+
+        // 删除后密钥、试卷和记录都不再存在，其他服务器不受影响
+        let res = purge(bearer("operator"), web::Path::from(id), sql_server.clone(), ws_server.clone()).await;
+        assert_eq!(res.status(), StatusCode::OK);
+        assert!(handle.verify_key(key).await.unwrap_err().is::<NoSuchValueError>());
+        assert!(handle.list_papers(id).await.unwrap().is_empty());
+        assert!(handle.get_paper("survival".to_string()).await.is_err());
+        assert_eq!(handle.get_submission_stats(id).await.unwrap().submissions, 0);
+        assert_eq!(handle.get_server_quotas(id).await.unwrap(), Quotas::default());
+        let servers: Vec<_> = handle.list_servers().await.unwrap().into_iter().map(|server| server.name).collect();
+        assert_eq!(servers, ["b"]);
+        assert!(handle.verify_key(other).await.is_ok());
+        let res = purge(bearer("operator"), web::Path::from(id), sql_server, ws_server).await;
+        assert_eq!(res.status(), StatusCode::NOT_FOUND);
+    }
+}
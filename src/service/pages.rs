@@ -1,5 +1,15 @@
 use std::path::PathBuf;
 use actix_files::NamedFile;
+use actix_web::HttpResponse;
+use crate::CONFIG;
+
+/// 带有hCaptcha的页面，读取时填入配置中的site key
+async fn captcha_page(path: &str) -> actix_web::Result<HttpResponse> {
+    let page = tokio::fs::read_to_string(path).await?;
+    Ok(HttpResponse::Ok()
+        .content_type("text/html; charset=utf-8")
+        .body(page.replace("{{captcha_site_key}}", &CONFIG.current().captcha.site_key)))
+}
 
 pub(crate) async fn index() -> actix_web::Result<NamedFile> {
     Ok(NamedFile::open(PathBuf::from("templates/exam.html"))?)
@@ -9,6 +19,17 @@ pub(crate) async fn upload_page() -> actix_web::Result<NamedFile> {
     Ok(NamedFile::open(PathBuf::from("templates/upload.html"))?)
 }
 
-pub(crate) async fn register_page() -> actix_web::Result<NamedFile> {
-    Ok(NamedFile::open(PathBuf::from("templates/register.html"))?)
-}
\ No newline at end of file
+pub(crate) async fn register_page() -> actix_web::Result<HttpResponse> {
+    captcha_page("templates/register.html").await
+}
+
+pub(crate) async fn dashboard_page() -> actix_web::Result<NamedFile> {
+    Ok(NamedFile::open(PathBuf::from("templates/dashboard.html"))?)
+}
+
+pub(crate) async fn editor_page() -> actix_web::Result<NamedFile> {
+    Ok(NamedFile::open(PathBuf::from("templates/editor.html"))?)
+}
+pub(crate) async fn recover_page() -> actix_web::Result<HttpResponse> {
+    captcha_page("templates/recover.html").await
+}
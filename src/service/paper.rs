@@ -0,0 +1,357 @@
+use actix_web::{web, HttpRequest, HttpResponse};
+use serde_json::{json, Value};
+use crate::error::{NoSuchValueError, SlugConflictError, VersionConflictError};
+use crate::r#struct::admin::{BaseVersionQuery, MoveQuestionRequest, PaperBody, QuestionBody};
+use crate::r#struct::awl_type::ServerId;
+use crate::r#struct::paper::{is_valid_slug, is_valid_target, total_score, NewPaper, Paper, PaperContentUpdate};
+use crate::service::admin::{authenticate, credential_label, internal_error, owned_paper};
+use crate::service::quiz::test_response;
+use crate::sql_server::SqlServerHandle;
+use crate::validate::{validate_paper, validate_question, Diagnostic};
+
+/// 试卷未通过校验时返回所有问题
+pub(crate) fn invalid_paper(diagnostics: Vec<Diagnostic>) -> HttpResponse {
+    HttpResponse::BadRequest().json(json!({"code": 400, "errors": diagnostics}))
+}
+
+/// 试卷超出服务器的配额时返回所有问题
+pub(crate) fn quota_exceeded(diagnostics: Vec<Diagnostic>) -> HttpResponse {
+    HttpResponse::Forbidden().json(json!({"code": 403, "errors": diagnostics}))
+}
+
+/// 检查试卷是否超出服务器的配额，creates根据服务器现有的试卷判断这次保存是否会新建试卷
+pub(crate) async fn check_quotas(
+    sql_server: &SqlServerHandle,
+    server_id: ServerId,
+    content: &Value,
+    creates: impl FnOnce(&[Paper]) -> bool,
+) -> Result<(), HttpResponse> {
+    let quotas = sql_server.get_quotas(server_id).await.map_err(internal_error)?;
+    let mut diagnostics = quotas.check_paper(content);
+    if quotas.max_papers.is_some() {
+        let papers = sql_server.list_papers(server_id).await.map_err(internal_error)?;
+        if creates(&papers) {
+            diagnostics.extend(quotas.check_new_paper(papers.len()));
+        }
+    }
+    if !diagnostics.is_empty() {
+        return Err(quota_exceeded(diagnostics));
+    }
+    Ok(())
+}
+
+/// 将保存试卷时的错误转换为响应
+fn save_error(e: Box<dyn std::error::Error + Send + Sync>) -> HttpResponse {
+    if e.is::<SlugConflictError>() || e.is::<VersionConflictError>() {
+        HttpResponse::Conflict().json(json!({"code": 409, "message": e.to_string()}))
+    } else if e.is::<NoSuchValueError>() {
+        HttpResponse::NotFound().json(json!({"code": 404}))
+    } else {
+        internal_error(e)
+    }
+}
+
+/// 检查请求体中的试卷及slug、投递目标，返回去掉client_key后的试卷
+fn check_body(body: &PaperBody) -> Result<Value, Vec<Diagnostic>> {
+    let mut diagnostics = Vec::new();
+    // 空字符串表示清除，修改时未提供的字段保持不变
+    if body.slug.as_deref().is_some_and(|slug| !slug.is_empty() && !is_valid_slug(slug)) {
+        diagnostics.push(Diagnostic::new("/slug", "slug只能包含小写字母、数字和-，且不能是纯数字或保留路径"));
+    }
+    if body.target.as_deref().is_some_and(|target| !target.is_empty() && !is_valid_target(target)) {
+        diagnostics.push(Diagnostic::new("/target", "投递目标只能包含字母、数字、-和_"));
+    }
+    diagnostics.extend(validate_paper(&body.content).into_iter().map(|diagnostic| Diagnostic {
+        path: format!("/content{}", diagnostic.path),
+        ..diagnostic
+    }));
+    if !diagnostics.is_empty() {
+        return Err(diagnostics);
+    }
+    let mut content = body.content.clone();
+    content.as_object_mut().unwrap().remove("client_key");
+    Ok(content)
+}
+
+fn ip_address(req: &HttpRequest) -> String {
+    req.connection_info().peer_addr().unwrap_or_default().to_string()
+}
+
+// 新建试卷
+pub(crate) async fn create_paper(
+    req: HttpRequest,
+    req_body: web::Json<PaperBody>,
+    sql_server: web::Data<SqlServerHandle>,
+) -> HttpResponse {
+    let client = match authenticate(&req, &sql_server).await {
+        Ok(client) => client,
+        Err(response) => return response,
+    };
+    let body = req_body.into_inner();
+    let content = match check_body(&body) {
+        Ok(content) => content,
+        Err(diagnostics) => return invalid_paper(diagnostics),
+    };
+    if let Err(response) = check_quotas(&sql_server, client.id, &content, |_| true).await {
+        return response;
+    }
+    let paper = NewPaper {
+        server_id: client.id,
+        slug: body.slug.filter(|slug| !slug.is_empty()),
+        title: content["main_title"].as_str().unwrap_or_default().to_string(),
+        target: body.target.filter(|target| !target.is_empty()),
+        content: content.to_string(),
+        uploaded_by: credential_label(&req),
+        ip_address: ip_address(&req),
+    };
+    match sql_server.create_paper(paper).await {
+        Ok((id, version)) => HttpResponse::Ok().json(json!({"code": 200, "id": id, "version": version})),
+        Err(e) => save_error(e),
+    }
+}
+
+// 获取试卷的当前内容
+pub(crate) async fn get_paper(
+    req: HttpRequest,
+    path: web::Path<String>,
+    sql_server: web::Data<SqlServerHandle>,
+) -> HttpResponse {
+    let client = match authenticate(&req, &sql_server).await {
+        Ok(client) => client,
+        Err(response) => return response,
+    };
+    let paper = match owned_paper(&sql_server, client.id, Some(path.into_inner())).await {
+        Ok(paper) => paper,
+        Err(response) => return response,
+    };
+    match serde_json::from_str::<Value>(&paper.content) {
+        Ok(content) => HttpResponse::Ok().json(json!({"code": 200, "version": paper.version, "paper": paper, "data": content})),
+        Err(e) => internal_error(Box::new(e)),
+    }
+}
+
+// 修改试卷，内容发生变化时生成新版本
+pub(crate) async fn update_paper(
+    req: HttpRequest,
+    path: web::Path<String>,
+    req_body: web::Json<PaperBody>,
+    sql_server: web::Data<SqlServerHandle>,
+) -> HttpResponse {
+    let client = match authenticate(&req, &sql_server).await {
+        Ok(client) => client,
+        Err(response) => return response,
+    };
+    let paper = match owned_paper(&sql_server, client.id, Some(path.into_inner())).await {
+        Ok(paper) => paper,
+        Err(response) => return response,
+    };
+    let body = req_body.into_inner();
+    let content = match check_body(&body) {
+        Ok(content) => content,
+        Err(diagnostics) => return invalid_paper(diagnostics),
+    };
+    if let Err(response) = check_quotas(&sql_server, client.id, &content, |_| false).await {
+        return response;
+    }
+    let update = PaperContentUpdate {
+        paper_id: paper.id,
+        base_version: body.base_version,
+        slug: body.slug,
+        target: body.target,
+        content: content.to_string(),
+        uploaded_by: credential_label(&req),
+        ip_address: ip_address(&req),
+    };
+    match sql_server.update_paper(update).await {
+        Ok(version) => HttpResponse::Ok().json(json!({"code": 200, "id": paper.id, "version": version})),
+        Err(e) => save_error(e),
+    }
+}
+
+// 删除试卷及其历史版本
+pub(crate) async fn delete_paper(
+    req: HttpRequest,
+    path: web::Path<String>,
+    sql_server: web::Data<SqlServerHandle>,
+) -> HttpResponse {
+    let client = match authenticate(&req, &sql_server).await {
+        Ok(client) => client,
+        Err(response) => return response,
+    };
+    let paper = match owned_paper(&sql_server, client.id, Some(path.into_inner())).await {
+        Ok(paper) => paper,
+        Err(response) => return response,
+    };
+    match sql_server.delete_paper(paper.id).await {
+        Ok(()) => HttpResponse::Ok().json(json!({"code": 200})),
+        Err(e) => internal_error(e),
+    }
+}
+
+/// 读取试卷后修改其中的题目并保存为新版本，总分会按题目的分值重新计算。
+/// modify返回false表示题目不存在。未指定base_version时以读取到的版本为准，避免覆盖同时发生的其他修改
+async fn modify_questions(
+    req: &HttpRequest,
+    sql_server: &SqlServerHandle,
+    reference: String,
+    base_version: Option<u32>,
+    modify: impl FnOnce(&mut Vec<Value>) -> bool,
+) -> HttpResponse {
+    let client = match authenticate(req, sql_server).await {
+        Ok(client) => client,
+        Err(response) => return response,
+    };
+    let paper = match owned_paper(sql_server, client.id, Some(reference)).await {
+        Ok(paper) => paper,
+        Err(response) => return response,
+    };
+    let mut content: Value = match serde_json::from_str(&paper.content) {
+        Ok(content) => content,
+        Err(e) => return internal_error(Box::new(e)),
+    };
+    let Some(questions) = content["questions"].as_array_mut() else {
+        return internal_error(Box::new(NoSuchValueError));
+    };
+    if !modify(questions) {
+        return HttpResponse::NotFound().json(json!({"code": 404, "message": "题目不存在"}));
+    }
+    content["score"] = json!(total_score(&content));
+    let diagnostics = validate_paper(&content);
+    if !diagnostics.is_empty() {
+        return invalid_paper(diagnostics);
+    }
+    if let Err(response) = check_quotas(sql_server, client.id, &content, |_| false).await {
+        return response;
+    }
+    let update = PaperContentUpdate {
+        paper_id: paper.id,
+        base_version: Some(base_version.unwrap_or(paper.version)),
+        slug: None,
+        target: None,
+        content: content.to_string(),
+        uploaded_by: credential_label(req),
+        ip_address: ip_address(req),
+    };
+    match sql_server.update_paper(update).await {
+        Ok(version) => HttpResponse::Ok().json(json!({"code": 200, "id": paper.id, "version": version, "score": content["score"]})),
+        Err(e) => save_error(e),
+    }
+}
+
+// 新增题目
+pub(crate) async fn add_question(
+    req: HttpRequest,
+    path: web::Path<String>,
+    req_body: web::Json<QuestionBody>,
+    sql_server: web::Data<SqlServerHandle>,
+) -> HttpResponse {
+    let body = req_body.into_inner();
+    let diagnostics = validate_question("/question", &body.question);
+    if !diagnostics.is_empty() {
+        return invalid_paper(diagnostics);
+    }
+    modify_questions(&req, &sql_server, path.into_inner(), body.base_version, |questions| {
+        let index = body.index.unwrap_or(questions.len());
+        if index > questions.len() {
+            return false;
+        }
+        questions.insert(index, body.question);
+        true
+    })
+    .await
+}
+
+// 修改题目，index从0开始
+pub(crate) async fn update_question(
+    req: HttpRequest,
+    path: web::Path<(String, usize)>,
+    req_body: web::Json<QuestionBody>,
+    sql_server: web::Data<SqlServerHandle>,
+) -> HttpResponse {
+    let (reference, index) = path.into_inner();
+    let body = req_body.into_inner();
+    let diagnostics = validate_question("/question", &body.question);
+    if !diagnostics.is_empty() {
+        return invalid_paper(diagnostics);
+    }
+    modify_questions(&req, &sql_server, reference, body.base_version, |questions| {
+        match questions.get_mut(index) {
+            Some(question) => {
+                *question = body.question;
+                true
+            }
+            None => false,
+        }
+    })
+    .await
+}
+
+// 删除题目
+pub(crate) async fn delete_question(
+    req: HttpRequest,
+    path: web::Path<(String, usize)>,
+    query: web::Query<BaseVersionQuery>,
+    sql_server: web::Data<SqlServerHandle>,
+) -> HttpResponse {
+    let (reference, index) = path.into_inner();
+    modify_questions(&req, &sql_server, reference, query.base_version, |questions| {
+        if index >= questions.len() {
+            return false;
+        }
+        questions.remove(index);
+        true
+    })
+    .await
+}
+
+// 调整题目顺序
+pub(crate) async fn move_question(
+    req: HttpRequest,
+    path: web::Path<(String, usize)>,
+    req_body: web::Json<MoveQuestionRequest>,
+    sql_server: web::Data<SqlServerHandle>,
+) -> HttpResponse {
+    let (reference, index) = path.into_inner();
+    let body = req_body.into_inner();
+    modify_questions(&req, &sql_server, reference, body.base_version, |questions| {
+        if index >= questions.len() || body.to >= questions.len() {
+            return false;
+        }
+        let question = questions.remove(index);
+        questions.insert(body.to, question);
+        true
+    })
+    .await
+}
+
+// 预览已保存的试卷，返回内容与玩家通过get_test获取到的一致
+pub(crate) async fn preview_paper(
+    req: HttpRequest,
+    path: web::Path<String>,
+    sql_server: web::Data<SqlServerHandle>,
+) -> HttpResponse {
+    let client = match authenticate(&req, &sql_server).await {
+        Ok(client) => client,
+        Err(response) => return response,
+    };
+    let paper = match owned_paper(&sql_server, client.id, Some(path.into_inner())).await {
+        Ok(paper) => paper,
+        Err(response) => return response,
+    };
+    match serde_json::from_str::<Value>(&paper.content) {
+        Ok(content) => test_response(&content),
+        Err(e) => internal_error(Box::new(e)),
+    }
+}
+
+// 预览尚未保存的试卷
+pub(crate) async fn preview_draft(
+    req: HttpRequest,
+    req_body: web::Json<Value>,
+    sql_server: web::Data<SqlServerHandle>,
+) -> HttpResponse {
+    if let Err(response) = authenticate(&req, &sql_server).await {
+        return response;
+    }
+    test_response(&req_body.into_inner())
+}
@@ -1,127 +1,355 @@
-use std::io::Read;
-use std::path::Path;
+use std::io;
+use std::time::{Duration, Instant};
 use actix_web::{web, HttpRequest, HttpResponse};
 use serde_json::{json, Value};
 use crate::CONFIG;
-use crate::r#struct::awl_type::Key;
+use crate::error::NoSuchValueError;
+use crate::r#struct::awl_type::ServerId;
 use crate::{SubmitRequest, SubmitResponse};
 use crate::sql_server::SqlServerHandle;
-use crate::utils::{mark, read_file};
+use crate::utils::{mark, unix_timestamp};
+use quizgate_client::action::{is_valid_player_id, mojang_uuid};
 use crate::ws_server::WsServerHandle;
+use crate::webhook_server::WebhookServerHandle;
+use crate::r#struct::webhook::{WebhookEvent, WebhookEventKind};
+use crate::r#struct::admin::NewSubmission;
+use crate::r#struct::paper::public_content;
+use crate::reload_server::ReloadServerHandle;
+use crate::logging::RequestId;
+use crate::{metrics, shutdown};
 
-// 获取试题内容
-pub(crate) async fn get_test(req: HttpRequest) -> HttpResponse {
-    
-    let mut test_info: Value = json!({});
-    let filename: String;
-    let file_path: String;
-    // 自托管模式下默认使用相同目录下的0.json
+/// 读取到的试卷及其所属客户端
+struct LoadedPaper {
+    server_id: ServerId,
+    /// 记录提交时使用的试卷id
+    paper_id: String,
+    /// 试卷的当前版本，自托管模式下为空
+    version: Option<u32>,
+    /// 试卷指定的投递目标
+    target: Option<String>,
+    content: Value,
+}
+
+/// 自托管模式下使用相同目录下的0.json（也可以是0.yaml、0.toml等）或配置文件papers中对应slug的试卷，
+/// 否则按id或slug查询试卷，试卷不存在时返回None
+async fn load_paper(reference: &str, sql_server: &SqlServerHandle, reload_server: &ReloadServerHandle) -> Result<Option<LoadedPaper>, HttpResponse> {
     if CONFIG.self_hosted {
-        file_path = "0.json".to_string();
-    } else {
-        filename = req.match_info().query("filename").parse().unwrap();
-        // 如果get参数不为数字则返回错误
-        match filename.parse::<i32>() {
-            Ok(_) => {}
-            Err(_) => return HttpResponse::BadRequest().body("Invalid file path"),
-        }
-        file_path = format!("tests/{}.json", filename);
-    }
-    
-    if Path::new(&file_path).exists() {
-        let mut file = match read_file(&file_path) {
-            Ok(value) => value,
-            Err(error) => {
-                log::error!("读取文件时出现错误：{error}");
-                return HttpResponse::InternalServerError().json(json!({"code": 500}));
+        // 试卷文件由reload server读取并缓存，文件变化时自动重新加载
+        let Some(paper) = reload_server.paper(reference.to_string()).await else {
+            return Ok(None);
+        };
+        // 每张试卷对应server_info中的一条记录，提交记录和设置按试卷分开
+        let server_id = match sql_server.self_hosted_server_id(paper.slug.clone()).await {
+            Ok(server_id) => server_id,
+            Err(e) => {
+                log::error!("查询试卷{}对应的记录时出现错误：{:?}", paper.slug, e);
+                return Err(HttpResponse::InternalServerError().json(json!({"code": 500})));
             }
         };
+        return Ok(Some(LoadedPaper { server_id, paper_id: paper.slug, version: None, target: None, content: paper.content }));
+    }
 
-        let mut contents = String::new();
-        if let Err(_) = file.read_to_string(&mut contents) {
-            return HttpResponse::InternalServerError().json(json!({"code": 500}));
+    let paper = match sql_server.get_paper(reference.to_string()).await {
+        Ok(paper) => paper,
+        Err(e) if e.is::<NoSuchValueError>() => return Ok(None),
+        Err(e) => {
+            log::error!("查询试卷时出现错误：{:?}", e);
+            return Err(HttpResponse::InternalServerError().json(json!({"code": 500})));
         }
+    };
+    let content = match serde_json::from_str(&paper.content) {
+        Ok(json) => json,
+        Err(_) => return Err(HttpResponse::InternalServerError().json(json!({"code": 500}))),
+    };
+    Ok(Some(LoadedPaper {
+        server_id: paper.server_id,
+        paper_id: paper.id.to_string(),
+        version: Some(paper.version),
+        target: paper.target,
+        content,
+    }))
+}
 
-        test_info = match serde_json::from_str(&contents) {
-            Ok(json) => json,
-            Err(_) => return HttpResponse::InternalServerError().json(json!({"code": 500})),
-        };
-        // 移除不该出现的部分
-        test_info.as_object_mut().unwrap().remove("pass");
-        test_info.as_object_mut().unwrap().remove("client_key");
-        if let Some(questions) = test_info["questions"].as_array_mut() {
-            for question in questions {
-                question.as_object_mut().unwrap().remove("correct");
-                question.as_object_mut().unwrap().remove("score");
-            }
-        }
-        HttpResponse::Ok().json(json!({
-        "code": 200,
-        "data": test_info,
-        "is_server_online": true
-        }))
-    } else {
-        HttpResponse::Ok().json(json!({"code": 404}))
+// 获取试题内容
+pub(crate) async fn get_test(req: HttpRequest, sql_server: web::Data<SqlServerHandle>, reload_server: web::Data<ReloadServerHandle>) -> HttpResponse {
+    let reference = req.match_info().query("filename");
+    let test_info = match load_paper(reference, &sql_server, &reload_server).await {
+        Ok(Some(paper)) => paper.content,
+        Ok(None) => return HttpResponse::Ok().json(json!({"code": 404})),
+        Err(response) => return response,
+    };
+    test_response(&test_info)
+}
+
+/// 向玩家返回试卷，编辑器的预览也使用同样的内容
+pub(crate) fn test_response(content: &Value) -> HttpResponse {
+    HttpResponse::Ok().json(json!({
+    "code": 200,
+    "data": public_content(content),
+    "is_server_online": true
+    }))
+}
+
+/// 只保留与题目数量一致且为非负数的计时数据
+fn valid_durations<'a>(durations: &'a Option<Vec<f64>>, paper_info: &Value) -> Option<&'a Vec<f64>> {
+    let count = paper_info["questions"].as_array().map_or(0, Vec::len);
+    durations
+        .as_ref()
+        .filter(|durations| durations.len() == count && durations.iter().all(|duration| duration.is_finite() && *duration >= 0.0))
+}
+
+/// 服务器当天（UTC）的提交次数达到配额时拒绝新的提交
+async fn check_daily_submissions(sql_server: &SqlServerHandle, server_id: ServerId) -> Result<(), HttpResponse> {
+    let internal_error = |e| {
+        log::error!("查询服务器配额时出错: {:?}", e);
+        HttpResponse::InternalServerError().json(json!({"code": 500}))
+    };
+    let Some(max) = sql_server.get_quotas(server_id).await.map_err(internal_error)?.max_submissions_per_day else {
+        return Ok(());
+    };
+    let today = unix_timestamp() as i64 / 86400 * 86400;
+    let count = sql_server.count_submissions_since(server_id, today).await.map_err(internal_error)?;
+    if count >= max {
+        return Err(HttpResponse::TooManyRequests().json(json!({"code": 429, "message": "该服务器今天的答题次数已达上限，请明天再试"})));
     }
+    Ok(())
 }
 
 // 提交试卷并进行打分
 pub(crate) async fn submit(
     req: HttpRequest,
     req_body: web::Json<SubmitRequest>,
+    request_id: RequestId,
     ws_server: web::Data<WsServerHandle>,
-    sql_server: web::Data<SqlServerHandle>
+    sql_server: web::Data<SqlServerHandle>,
+    webhook_server: web::Data<WebhookServerHandle>,
+    reload_server: web::Data<ReloadServerHandle>,
 ) -> HttpResponse {
+    // 正在关闭时不再接受提交，避免投递在关闭过程中丢失
+    if shutdown::is_shutting_down() {
+        return HttpResponse::ServiceUnavailable().json(json!({"code": 503, "message": "服务正在重启，请稍后再试"}));
+    }
+    let started = Instant::now();
     // 获取post请求内容
     let answer = &req_body.answer;
     let player_id = &req_body.player_id;
-    let paper_id = (&req_body.paper_id).parse::<u32>().unwrap();
-    let file_path: String;
-    // 自托管模式下默认使用相同目录下的0.json
-    if CONFIG.self_hosted {
-        file_path = "0.json".to_string();
-    } else {
-        file_path = format!("tests/{}.json", paper_id)
+    // 玩家ID会出现在投递的命令、导出的记录和webhook中，不合法时不记录提交
+    if !is_valid_player_id(player_id) {
+        return HttpResponse::BadRequest().json(json!({"code": 400, "message": "玩家ID只能包含字母、数字、_和."}));
     }
-    let mut score = 0;
-    let mut paper_info: Value = json!({});
-    // 检测文件是否存在
-    if Path::new(&file_path).exists() {
-        let mut file = match read_file(&file_path) {
-            Ok(value) => value,
-            Err(error) => {
-                log::error!("读取文件时出现错误：{error}");
-                return HttpResponse::InternalServerError().json(json!({"code": 500}));
-            }
-        };
-        // 从文件读取问卷信息
-        let mut contents = String::new();
-        if let Err(_) = file.read_to_string(&mut contents) {
+    // 兼容旧版页面直接提交路径的情况
+    let reference = req_body.paper_id.trim_start_matches('/');
+    let paper = match load_paper(reference, &sql_server, &reload_server).await {
+        Ok(Some(paper)) => paper,
+        Ok(None) => return HttpResponse::NotFound().json(json!({"code": 404})),
+        Err(response) => return response,
+    };
+    let paper_info = &paper.content;
+    let server_id = paper.server_id;
+    if !CONFIG.self_hosted {
+        if let Err(response) = check_daily_submissions(&sql_server, server_id).await {
+            return response;
+        }
+    }
+    // 进行评分
+    let score = mark(answer, paper_info);
+    // 上传时已校验过及格分数，旧版本数据库中的试卷可能缺少该字段
+    let Some(pass_score) = paper_info["pass"].as_i64() else {
+        log::error!(request_id = request_id.as_str(); "试卷{}缺少及格分数", paper.paper_id);
+        return HttpResponse::InternalServerError().json(json!({"code": 500}));
+    };
+    let pass = score >= pass_score;
+    metrics::record_submission(server_id, &paper.paper_id, pass);
+    log::info!(
+        request_id = request_id.as_str(),
+        server_id = server_id,
+        paper_id = paper.paper_id.as_str(),
+        player_id = player_id.as_str(),
+        score = score,
+        pass = pass;
+        "玩家{}提交了试卷，得分{}", player_id, score
+    );
+
+    // 开启审核时先加入审核队列，由服务器所有者批准后再投递
+    let mut pending_approval = false;
+    if pass {
+        match sql_server.get_settings(server_id).await {
+            Ok(settings) => pending_approval = settings.require_approval,
+            Err(e) => log::error!("查询服务器设置时出错: {:?}", e),
+        }
+    }
+    if pending_approval {
+        if let Err(e) = sql_server.queue_approval(server_id, player_id.to_string(), paper.paper_id.clone(), score).await {
+            log::error!(request_id = request_id.as_str(); "将玩家{}加入审核队列时出错: {:?}", player_id, e);
             return HttpResponse::InternalServerError().json(json!({"code": 500}));
         }
-        // 转换为json文件
-        paper_info = match serde_json::from_str(&contents) {
-            Ok(json) => json,
-            Err(_) => return HttpResponse::InternalServerError().json(json!({"code": 500})),
+    } else if pass {
+        ws_server.send_message(server_id, player_id, paper.target.clone(), &request_id).await;
+    }
+    let ip_address = req.connection_info().peer_addr().unwrap().to_string();
+    let result = sql_server.record_submission(NewSubmission {
+        server_id,
+        paper_id: paper.paper_id.clone(),
+        paper_version: paper.version,
+        player_id: player_id.to_string(),
+        score,
+        pass,
+        ip_address,
+        answers: serde_json::to_string(answer).unwrap(),
+        durations: valid_durations(&req_body.durations, paper_info).map(|durations| serde_json::to_string(durations).unwrap()),
+    }).await;
+    // 记录通过时玩家的UUID，玩家之后改名也能与其他记录对应
+    match result {
+        Ok(submission_id) if pass => {
+            let sql_server = (**sql_server).clone();
+            let player_id = player_id.to_string();
+            actix_web::rt::spawn(async move {
+                let lookup = tokio::time::timeout(Duration::from_secs(CONFIG.current().timeouts.uuid_lookup), mojang_uuid(&player_id)).await;
+                match lookup.unwrap_or_else(|_| Err(io::Error::new(io::ErrorKind::TimedOut, "查询超时"))) {
+                    Ok(uuid) => {
+                        if let Err(e) = sql_server.set_submission_uuid(submission_id, uuid).await {
+                            log::error!("记录玩家{}的UUID失败: {:?}", player_id, e);
+                        }
+                    }
+                    Err(e) => log::debug!("查询玩家{}的UUID失败: {}", player_id, e),
+                }
+            });
+        }
+        Ok(_) => {}
+        Err(e) => log::error!(request_id = request_id.as_str(); "记录玩家{}的提交时出错: {:?}", player_id, e),
+    }
+    webhook_server.dispatch(server_id, WebhookEvent {
+        event: match (pass, pending_approval) {
+            (true, true) => WebhookEventKind::Pending,
+            (true, false) => WebhookEventKind::Pass,
+            (false, _) => WebhookEventKind::Fail,
+        },
+        player_id: player_id.to_string(),
+        paper_id: paper.paper_id.clone(),
+        score: Some(score),
+        timestamp: unix_timestamp(),
+    });
+    let count = match sql_server.get_client_player_count(server_id).await {
+        Ok(count) => count,
+        Err(e) => {
+            log::error!(request_id = request_id.as_str(); "查询通过人数时出错: {:?}", e);
+            return HttpResponse::InternalServerError().json(json!({"code": 500}));
+        }
+    };
+    metrics::observe_grading(started.elapsed());
+    HttpResponse::Ok().json(SubmitResponse { score, pass, count, pending_approval })
+}
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use actix_web::{http::StatusCode, test::TestRequest, FromRequest};
+    use crate::config::Config;
+    use crate::r#struct::admin::PaperBody;
+    use crate::r#struct::delivery::PlayerAction;
+    use crate::reload_server::ReloadServer;
+    use crate::service::paper;
+    use crate::sql_server::tests::test_server;
+    use crate::webhook_server::WebhookServer;
+    use crate::ws_server::WsServer;
+
+    const PAPER: &str = r#"{"main_title":"A","score":1,"pass":1,"questions":[{"type":"radio","content":"q","options":["a","b"],"correct":1,"score":1}]}"#;
+
+    struct Handles {
+        sql_server: web::Data<SqlServerHandle>,
+        ws_server: web::Data<WsServerHandle>,
+        webhook_server: web::Data<WebhookServerHandle>,
+        reload_server: web::Data<ReloadServerHandle>,
+    }
+
+    /// 托管模式下提交只用到数据库、ws及webhook服务
+    async fn start(name: &str) -> Handles {
+        let (sql_server, sql_handle) = test_server(name, Config::default()).await;
+        let (ws_server, ws_handle) = WsServer::new(sql_handle.clone());
+        let (webhook_server, webhook_handle) = WebhookServer::new(sql_handle.clone());
+        let (_reload_server, reload_handle) = ReloadServer::new();
+        actix_web::rt::spawn(sql_server.run());
+        actix_web::rt::spawn(ws_server.run());
+        actix_web::rt::spawn(webhook_server.run());
+        Handles {
+            sql_server: web::Data::new(sql_handle),
+            ws_server: web::Data::new(ws_handle),
+            webhook_server: web::Data::new(webhook_handle),
+            reload_server: web::Data::new(reload_handle),
+        }
+    }
+
+    async fn create_paper(handles: &Handles, key: &str, slug: Option<&str>, target: Option<&str>) -> HttpResponse {
+        let req = TestRequest::post().insert_header(("Authorization", format!("Bearer {}", key))).to_http_request();
+        let body = PaperBody {
+            slug: slug.map(str::to_string),
+            target: target.map(str::to_string),
+            content: serde_json::from_str(PAPER).unwrap(),
+            base_version: None,
+        };
+        paper::create_paper(req, web::Json(body), handles.sql_server.clone()).await
+    }
+
+    async fn submit_answer(handles: &Handles, paper_id: &str, player_id: &str) -> HttpResponse {
+        let req = TestRequest::post().peer_addr("127.0.0.1:25565".parse().unwrap()).to_http_request();
+        let request_id = RequestId::extract(&req).await.unwrap();
+        let body = SubmitRequest {
+            answer: vec![json!(1)],
+            player_id: player_id.to_string(),
+            paper_id: paper_id.to_string(),
+            durations: None,
         };
-        // 进行评分
-        score = mark(answer, &paper_info);
-    } else {
-        return HttpResponse::NotFound().json(json!({"code": 404}));
+        submit(
+            req,
+            web::Json(body),
+            request_id,
+            handles.ws_server.clone(),
+            handles.sql_server.clone(),
+            handles.webhook_server.clone(),
+            handles.reload_server.clone(),
+        )
+        .await
     }
-    let mut pass = false;
-
-    if score >= paper_info["pass"].as_i64().unwrap() {
-        pass = true;
-        // 如果为自托管模式则key默认为配置文件中的值
-        if CONFIG.self_hosted {
-            ws_server.send_message(CONFIG.self_hosted_key.clone(), player_id).await;
-        } else {
-            let key: Key = paper_info["client_key"].as_str().unwrap().to_string();
-            ws_server.send_message(key, player_id).await;
-            sql_server.record_player_success_log(paper_id, player_id.to_string(), req.connection_info().peer_addr().unwrap().to_string()).await.unwrap();
+
+    #[actix_web::test]
+    async fn papers_reject_invalid_slug_or_target() {
+        let handles = start("submit-rules").await;
+        let key = handles.sql_server.register_new_client("a".to_string(), "a@example.com".to_string()).await.unwrap();
+        for (slug, target) in [(Some("preview"), None), (Some("123"), None), (Some("Survival"), None), (None, Some("vip;op")), (None, Some("a b"))] {
+            let res = create_paper(&handles, &key, slug, target).await;
+            assert_eq!(res.status(), StatusCode::BAD_REQUEST, "{:?} {:?}", slug, target);
         }
+        assert_eq!(create_paper(&handles, &key, Some("survival"), Some("vip")).await.status(), StatusCode::OK);
+        // slug在所有服务器之间唯一
+        let other = handles.sql_server.register_new_client("b".to_string(), "b@example.com".to_string()).await.unwrap();
+        assert_eq!(create_paper(&handles, &other, Some("survival"), None).await.status(), StatusCode::CONFLICT);
+    }
+
+    #[actix_web::test]
+    async fn passing_player_is_delivered_with_paper_target() {
+        let handles = start("submit-target").await;
+        let key = handles.sql_server.register_new_client("a".to_string(), "a@example.com".to_string()).await.unwrap();
+        let server_id = handles.sql_server.get_client_id(key.clone()).await.unwrap();
+        assert_eq!(create_paper(&handles, &key, Some("staff"), Some("staff_app")).await.status(), StatusCode::OK);
+        assert_eq!(create_paper(&handles, &key, None, None).await.status(), StatusCode::OK);
+        let papers = handles.sql_server.list_papers(server_id).await.unwrap();
+
+        // 按slug或id提交均可，客户端不在线时投递留在队列中
+        assert_eq!(submit_answer(&handles, "staff", "Steve").await.status(), StatusCode::OK);
+        assert_eq!(submit_answer(&handles, &papers[1].id.to_string(), "Alex").await.status(), StatusCode::OK);
+        assert_eq!(submit_answer(&handles, "missing", "Alex").await.status(), StatusCode::NOT_FOUND);
+        assert_eq!(submit_answer(&handles, "staff", "a b").await.status(), StatusCode::BAD_REQUEST);
+
+        let pending: Vec<_> = handles
+            .ws_server
+            .status(server_id)
+            .await
+            .pending
+            .into_iter()
+            .map(|action| match action {
+                PlayerAction::Add { player_id, target, .. } => (player_id, target),
+                other => panic!("{:?}", other),
+            })
+            .collect();
+        assert_eq!(pending, [("Steve".to_string(), Some("staff_app".to_string())), ("Alex".to_string(), None)]);
     }
-    let count = sql_server.get_client_player_count(paper_id).await.unwrap();
-    HttpResponse::Ok().json(SubmitResponse { score, pass, count })
-}
\ No newline at end of file
+}
@@ -1,55 +1,66 @@
 use actix_web::{web, HttpResponse};
 use serde_json::json;
-use crate::email_server::EmailServerHandle;
-use crate::r#struct::submit::{CaptchaResponse, RegisterRequest};
+use crate::email_server::{EmailServerHandle, TokenPurpose};
+use crate::r#struct::submit::{CaptchaResponse, RecoverConfirmRequest, RecoverRequest, RegisterRequest};
+use crate::ws_server::WsServerHandle;
 use crate::sql_server::SqlServerHandle;
+use crate::CONFIG;
 
-pub async fn register_pending(req_body: web::Json<RegisterRequest>, email_server: web::Data<EmailServerHandle>) -> HttpResponse{
-    let email = &req_body.email;
-    let server_name = &req_body.server_name;
-    let captcha_token = &req_body.captcha_token;
-
-    let params = [("secret", "ES_a48a8cc5a4594f80a3393ba309bb5654"), ("response", captcha_token)];
+/// 向hCaptcha验证人机验证结果，失败时返回对应的响应
+async fn verify_captcha(captcha_token: &str) -> Result<(), HttpResponse> {
+    let captcha = CONFIG.current().captcha.clone();
+    let params = [("secret", captcha.secret.as_str()), ("response", captcha_token)];
     let client = reqwest::Client::new();
 
     // 发送captcha验证请求
-    let res = client.post("https://hcaptcha.com/siteverify")
+    let res = client.post(&captcha.verify_url)
         .form(&params)
         .send()
         .await;
 
-    // 验证请求是否有效
-    match res {
+    // 验证请求是否有效，无法确认验证结果时一律视为失败
+    let response = match res {
+        Ok(response) if response.status().is_success() => response,
         Ok(response) => {
-            if response.status().is_success() {
-                let captcha_response: CaptchaResponse = response.json().await.unwrap();
-                if captcha_response.success {
-                    // 请求成功，继续下一步
-                } else {
-                    match captcha_response.error_codes {
-                        Some(codes) => {
-                            for code in codes {
-                                log::error!("Captcha error code: {}", code);
-                            }
-                            return HttpResponse::InternalServerError().json(json!({"msg": "验证失败，请重试"}))
-                        }
-                        None => {
-                            log::error!("Captcha error: No error codes returned.");
-                            return HttpResponse::InternalServerError().json(json!({"msg": "验证失败，请重试"}))
-                        }
-                    }
-
-                }
-            }
+            log::error!("Captcha verify request failed with status {}", response.status());
+            return Err(HttpResponse::InternalServerError().json(json!({"msg": "无法进行验证，请稍后重试"})))
         }
         Err(e) => {
             log::error!("Error sending captcha verify request: {}", e);
-            return HttpResponse::InternalServerError().json(json!({"msg": "无法进行验证，请稍后重试"}))
+            return Err(HttpResponse::InternalServerError().json(json!({"msg": "无法进行验证，请稍后重试"})))
+        }
+    };
+    let captcha_response: CaptchaResponse = match response.json().await {
+        Ok(captcha_response) => captcha_response,
+        Err(e) => {
+            log::error!("Invalid captcha verify response: {}", e);
+            return Err(HttpResponse::InternalServerError().json(json!({"msg": "无法进行验证，请稍后重试"})))
         }
+    };
+    if !captcha_response.success {
+        match captcha_response.error_codes {
+            Some(codes) => {
+                for code in codes {
+                    log::error!("Captcha error code: {}", code);
+                }
+            }
+            None => log::error!("Captcha error: No error codes returned."),
+        }
+        return Err(HttpResponse::InternalServerError().json(json!({"msg": "验证失败，请重试"})))
+    }
+    Ok(())
+}
+
+pub async fn register_pending(req_body: web::Json<RegisterRequest>, email_server: web::Data<EmailServerHandle>) -> HttpResponse{
+    let email = &req_body.email;
+    let server_name = &req_body.server_name;
+
+    if let Err(response) = verify_captcha(&req_body.captcha_token).await {
+        return response;
     }
 
     // 向email_server注册信息
-    let register_token = email_server.send_token(email.to_string(), server_name.to_string()).await;
+    let register_token = email_server.send_token(email.to_string(), TokenPurpose::Register { server_name: server_name.to_string() }).await;
     match register_token {
         Ok(_) => HttpResponse::Ok().json(json!({"code": 200})),
         Err(e) => {
@@ -68,8 +79,8 @@ pub async fn verify(path: web::Path<String>, email_server: web::Data<EmailServer
     // 调用email_server进行验证
     let result = email_server.validate_token(token.to_string()).await;
     match result {
-        Ok(server_name) => {
-            match sql_server_handle.register_new_client(server_name).await{
+        Ok((email, server_name)) => {
+            match sql_server_handle.register_new_client(server_name, email).await{
                 Ok(key) => HttpResponse::Ok().body(format!("注册成功，您的client_key为{},请谨慎保管",key)),
                 Err(e) => {
                     log::error!("添加客户端信息时出错:{:?}",e);
@@ -79,4 +90,56 @@ pub async fn verify(path: web::Path<String>, email_server: web::Data<EmailServer
         }
         Err(_) => HttpResponse::Unauthorized().body("链接错误或已过期，请再次检查后重试".to_string())
     }
-}
\ No newline at end of file
+}
+
+// 申请找回密钥，向该邮箱注册的每个服务器发送一封带有找回链接的邮件
+pub async fn recover_pending(req_body: web::Json<RecoverRequest>, email_server: web::Data<EmailServerHandle>, sql_server_handle: web::Data<SqlServerHandle>) -> HttpResponse {
+    if let Err(response) = verify_captcha(&req_body.captcha_token).await {
+        return response;
+    }
+
+    let email = req_body.email.trim().to_string();
+    let servers = match sql_server_handle.list_servers_by_email(email.clone()).await {
+        Ok(servers) => servers,
+        Err(e) => {
+            log::error!("查询邮箱对应的服务器时出错: {:?}", e);
+            return HttpResponse::InternalServerError().json(json!({"code": 500}));
+        }
+    };
+    // 无论邮箱是否注册过都返回相同的结果，并在后台发送邮件，避免通过返回内容或耗时判断邮箱是否存在
+    let email_server = (**email_server).clone();
+    actix_web::rt::spawn(async move {
+        for server in servers {
+            let purpose = TokenPurpose::Recover { server_id: server.id, server_name: server.name };
+            match email_server.send_token(email.clone(), purpose).await {
+                Ok(_) => log::info!("已向服务器{}的注册邮箱发送找回密钥邮件", server.id),
+                Err(e) => log::error!("发送找回密钥邮件时出错: {:?}", e),
+            }
+        }
+    });
+    HttpResponse::Ok().json(json!({"code": 200}))
+}
+
+// 使用找回链接中的token生成新的密钥，旧密钥立即失效
+pub async fn recover(
+    req_body: web::Json<RecoverConfirmRequest>,
+    email_server: web::Data<EmailServerHandle>,
+    sql_server_handle: web::Data<SqlServerHandle>,
+    ws_server: web::Data<WsServerHandle>,
+) -> HttpResponse {
+    let server_id = match email_server.validate_recovery_token(req_body.token.clone()).await {
+        Ok(server_id) => server_id,
+        Err(_) => return HttpResponse::Unauthorized().json(json!({"code": 401, "message": "链接错误或已过期，请重新申请找回"})),
+    };
+    match sql_server_handle.rotate_key(server_id, 0).await {
+        Ok((key, expires_at)) => {
+            ws_server.key_rotated(server_id, expires_at);
+            log::info!("客户端{}通过邮箱找回了密钥", server_id);
+            HttpResponse::Ok().json(json!({"code": 200, "key": key}))
+        }
+        Err(e) => {
+            log::error!("找回密钥时出错:{:?}", e);
+            HttpResponse::InternalServerError().json(json!({"code": 500, "message": "服务器内部错误，请联系开发者处理"}))
+        }
+    }
+}
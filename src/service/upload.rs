@@ -1,66 +1,172 @@
-use crate::r#struct::awl_type::Key;
-use crate::sql_server::SqlServerHandle;
+use crate::error::{NoSuchValueError, SlugConflictError};
+use crate::format::parse_paper;
+use crate::import::{import_paper, ImportFormat};
+use crate::r#struct::paper::{NewPaper, Paper, UploadQuery};
+use crate::service::paper::{check_quotas, invalid_paper};
+use crate::sql_server::{key_prefix, SqlServerHandle};
+use crate::validate::{validate_paper, Diagnostic, MAX_PAPER_SIZE};
 use actix_multipart::Multipart;
-use actix_web::{web, HttpResponse};
+use actix_web::{web, HttpRequest, HttpResponse};
 use futures_util::{StreamExt, TryStreamExt};
-use serde_json::{json, Value};
-use std::fs::File;
-use std::io::Write;
+use serde_json::json;
 
-pub(crate) async fn upload(mut payload: Multipart, sql_server_handle: web::Data<SqlServerHandle>) -> HttpResponse {
-    while let Ok(Some(mut field)) = payload.try_next().await {
-        // 将接收的数据转换为文本
-        let mut text = String::new();
-        // 同时保存为字节数据以保存文件
+pub(crate) async fn upload(
+    req: HttpRequest,
+    query: web::Query<UploadQuery>,
+    mut payload: Multipart,
+    sql_server_handle: web::Data<SqlServerHandle>,
+) -> HttpResponse {
+    // 试卷文件之外的表单字段会覆盖文件中的同名字段，导入其他格式的题库时可以借此提供client_key等信息
+    let mut file: Option<(Option<String>, Vec<u8>)> = None;
+    let mut fields: Vec<(String, Vec<u8>)> = Vec::new();
+    let mut received = 0;
+    loop {
+        let mut field = match payload.try_next().await {
+            Ok(Some(field)) => field,
+            Ok(None) => break,
+            Err(e) => {
+                log::error!("接收上传的试卷失败: {:?}", e);
+                return invalid_paper(vec![Diagnostic::new("", "无法解析上传的表单")]);
+            }
+        };
+        let file_name = field.content_disposition().and_then(|disposition| disposition.get_filename()).map(str::to_string);
+        let name = field.name().unwrap_or_default().to_string();
+        // 先接收完整的字节数据再转换为文本，避免多字节字符被分在两个数据块中
         let mut raw_data = Vec::new();
         while let Some(chunk) = field.next().await {
             match chunk {
                 Ok(data) => {
-                    raw_data.extend_from_slice(&data);
-                    match std::str::from_utf8(&data) {
-                        Ok(data_str) => text.push_str(data_str),
-                        Err(_) => {
-                            return HttpResponse::BadRequest().json(json!({
-                                "code": 400,
-                                "message": "Invalid UTF-8 data"
-                            }));
-                        }
+                    received += data.len();
+                    if received > MAX_PAPER_SIZE {
+                        return HttpResponse::PayloadTooLarge().json(json!({
+                            "code": 413,
+                            "errors": [Diagnostic::new("", format!("试卷文件不能超过{}KB", MAX_PAPER_SIZE / 1024))]
+                        }));
                     }
+                    raw_data.extend_from_slice(&data);
                 }
-                Err(_) => {
-                    return HttpResponse::InternalServerError().json(json!({
-                        "code": 500,
-                        "message": "Internal server error"
-                    }));
+                Err(e) => {
+                    log::error!("接收上传的试卷失败: {:?}", e);
+                    return HttpResponse::InternalServerError().json(json!({"code": 500}));
                 }
-
             }
         }
+        if file.is_none() && (file_name.is_some() || name == "file") {
+            file = Some((file_name, raw_data));
+        } else {
+            fields.push((name, raw_data));
+        }
+    }
+    let Some((file_name, raw_data)) = file else {
+        return invalid_paper(vec![Diagnostic::new("", "没有收到试卷文件")]);
+    };
+    let Ok(text) = String::from_utf8(raw_data) else {
+        return invalid_paper(vec![Diagnostic::new("", "试卷文件不是有效的UTF-8文本")]);
+    };
 
-        // 检测内容是否为json格式
-        if let Ok(json) = serde_json::from_str::<Value>(&text) {
-            // 验证json格式是否正确
-            if !json.is_object() || !json.as_object().unwrap().contains_key("questions") {
-                return HttpResponse::BadRequest().json(json!({"code": 400}));
-            }
-            // 读取客户端密钥
-            let key: Key = json["client_key"].as_str().unwrap().to_string();
-            if key.is_empty() {
-                return HttpResponse::BadRequest().json(json!({"code": 400}));
+    // 支持json、json5、yaml和toml，统一转换为json后校验和保存；Moodle XML、GIFT和Markdown会先导入为试卷
+    let mut warnings = Vec::new();
+    let parsed = match ImportFormat::detect(file_name.as_deref(), &text) {
+        Some(format) => {
+            let title = file_name.as_deref().and_then(|name| name.rsplit_once('.')).map_or("导入的试卷", |(stem, _)| stem);
+            import_paper(format, &text, title).map(|imported| {
+                warnings = imported.warnings;
+                imported.content
+            })
+        }
+        None => parse_paper(&text, file_name.as_deref()),
+    };
+    let mut json = match parsed {
+        Ok(json) => json,
+        Err(message) => return invalid_paper(vec![Diagnostic::new("", message)]),
+    };
+    if let Some(object) = json.as_object_mut() {
+        for (name, value) in fields {
+            let value = String::from_utf8_lossy(&value).trim().to_string();
+            match name.as_str() {
+                "client_key" | "slug" | "target" | "main_title" if !value.is_empty() => {
+                    object.insert(name, json!(value));
+                }
+                "pass" if !value.is_empty() => {
+                    object.insert(name, value.parse::<i64>().map_or(json!(value), |pass| json!(pass)));
+                }
+                _ => {}
             }
-            let result = sql_server_handle.get_client_id(key).await;
-            match result {
-                Ok(id) => {
-                    let file_path = format!("tests/{}.json", id);
-                    let mut file = File::create(file_path).unwrap();
-                    file.write_all(&raw_data).unwrap();
-                    return HttpResponse::Ok().json(json!({"code": 200}))
+        }
+    }
+    let mut diagnostics = validate_paper(&json);
+    // 读取客户端密钥
+    let key = json["client_key"].as_str().unwrap_or_default().to_string();
+    if key.is_empty() {
+        diagnostics.push(Diagnostic::new("/client_key", "缺少client_key"));
+    }
+    if !diagnostics.is_empty() {
+        return invalid_paper(diagnostics);
+    }
+
+    // 可选的slug和投递目标，未指定slug时覆盖服务器的第一张试卷
+    let slug = json["slug"].as_str().map(str::to_string);
+    let target = json["target"].as_str().map(str::to_string);
+    let uploaded_by = format!("key:{}", key_prefix(&key));
+    let id = match sql_server_handle.get_client_id(key).await {
+        Ok(id) => id,
+        Err(e) => return HttpResponse::Forbidden().json(json!({
+            "code": 403,
+            "errors": [Diagnostic::new("/client_key", e.to_string())]
+        })),
+    };
+    let slug_conflict = || HttpResponse::Conflict().json(json!({
+        "code": 409,
+        "errors": [Diagnostic::new("/slug", SlugConflictError.to_string())]
+    }));
+
+    // 保存到已有的同名试卷或服务器的第一张试卷时不会新建试卷
+    let creates = |papers: &[Paper]| match &slug {
+        Some(slug) => !papers.iter().any(|paper| paper.slug.as_ref() == Some(slug)),
+        None => papers.is_empty(),
+    };
+    if let Err(response) = check_quotas(&sql_server_handle, id, &json, creates).await {
+        return response;
+    }
+
+    // 只校验而不保存，slug是否可用也一并检查
+    if query.dry_run {
+        if let Some(slug) = &slug {
+            match sql_server_handle.get_paper(slug.clone()).await {
+                Ok(paper) if paper.server_id != id => return slug_conflict(),
+                Ok(_) => {}
+                Err(e) if e.is::<NoSuchValueError>() => {}
+                Err(e) => {
+                    log::error!("查询试卷失败: {:?}", e);
+                    return HttpResponse::InternalServerError().json(json!({"code": 500}));
                 }
-                Err(e) => return HttpResponse::Forbidden().json(json!({"code": e.to_string()})),
             }
-        } else {
-            return HttpResponse::BadRequest().json(json!({"code": 400}))
+        }
+        return HttpResponse::Ok().json(json!({"code": 200, "dry_run": true, "errors": [], "warnings": warnings}));
+    }
+
+    // 密钥只用于验证上传者，不随试卷明文保存
+    let object = json.as_object_mut().unwrap();
+    object.remove("client_key");
+    object.remove("slug");
+    object.remove("target");
+    let paper = NewPaper {
+        server_id: id,
+        slug: slug.clone(),
+        title: json["main_title"].as_str().unwrap_or_default().to_string(),
+        target,
+        content: json.to_string(),
+        uploaded_by,
+        ip_address: req.connection_info().peer_addr().unwrap_or_default().to_string(),
+    };
+    match sql_server_handle.save_paper(paper).await {
+        Ok((paper_id, version)) => HttpResponse::Ok().json(json!({
+            "code": 200, "id": paper_id, "slug": slug, "version": version, "warnings": warnings
+        })),
+        Err(e) if e.is::<SlugConflictError>() => slug_conflict(),
+        Err(e) => {
+            log::error!("保存试卷失败: {:?}", e);
+            HttpResponse::InternalServerError().json(json!({"code": 500}))
         }
     }
-    HttpResponse::InternalServerError().json(json!({"code": 500}))
-}
\ No newline at end of file
+}
@@ -0,0 +1,70 @@
+use actix_web::{web, HttpResponse};
+use serde_json::json;
+use std::error::Error;
+use url::Url;
+use crate::error::{NoSuchValueError, ServerSuspendedError};
+use crate::r#struct::webhook::WebhookRequest;
+use crate::sql_server::SqlServerHandle;
+use crate::webhook_server::resolve_webhook;
+
+fn error_response(e: Box<dyn Error + Send + Sync>) -> HttpResponse {
+    if e.is::<NoSuchValueError>() {
+        return HttpResponse::Forbidden().json(json!({"code": 403}));
+    }
+    if e.is::<ServerSuspendedError>() {
+        return HttpResponse::Forbidden().json(json!({"code": 403, "message": e.to_string()}));
+    }
+    log::error!("处理webhook请求时出错: {:?}", e);
+    HttpResponse::InternalServerError().json(json!({"code": 500}))
+}
+
+// 注册webhook，签名密钥只在此时返回一次
+pub(crate) async fn add_webhook(req_body: web::Json<WebhookRequest>, sql_server: web::Data<SqlServerHandle>) -> HttpResponse {
+    let req_body = req_body.into_inner();
+    let url = match req_body.url.as_deref().map(Url::parse) {
+        Some(Ok(url)) if url.scheme() == "http" || url.scheme() == "https" => url,
+        _ => return HttpResponse::BadRequest().json(json!({"code": 400})),
+    };
+    if let Err(e) = resolve_webhook(&url).await {
+        return HttpResponse::BadRequest().json(json!({"code": 400, "message": format!("webhook地址{}不可用: {}", url, e)}));
+    }
+    match sql_server.add_webhook(req_body.client_key, url.to_string()).await {
+        Ok((id, target)) => HttpResponse::Ok().json(json!({
+            "code": 200,
+            "id": id,
+            "url": target.url,
+            "secret": target.secret
+        })),
+        Err(e) => error_response(e),
+    }
+}
+
+// 列出已注册的webhook
+pub(crate) async fn list_webhooks(req_body: web::Json<WebhookRequest>, sql_server: web::Data<SqlServerHandle>) -> HttpResponse {
+    let server_id = match sql_server.get_client_id(req_body.client_key.clone()).await {
+        Ok(server_id) => server_id,
+        Err(e) => return error_response(e),
+    };
+    match sql_server.get_webhooks(server_id).await {
+        Ok(webhooks) => {
+            let webhooks: Vec<_> = webhooks
+                .into_iter()
+                .map(|(id, target)| json!({"id": id, "url": target.url}))
+                .collect();
+            HttpResponse::Ok().json(json!({"code": 200, "data": webhooks}))
+        }
+        Err(e) => error_response(e),
+    }
+}
+
+// 删除webhook
+pub(crate) async fn delete_webhook(req_body: web::Json<WebhookRequest>, sql_server: web::Data<SqlServerHandle>) -> HttpResponse {
+    let req_body = req_body.into_inner();
+    let Some(id) = req_body.id else {
+        return HttpResponse::BadRequest().json(json!({"code": 400}));
+    };
+    match sql_server.delete_webhook(req_body.client_key, id).await {
+        Ok(()) => HttpResponse::Ok().json(json!({"code": 200})),
+        Err(e) => error_response(e),
+    }
+}
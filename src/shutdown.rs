@@ -0,0 +1,36 @@
+use std::io;
+use std::sync::atomic::{AtomicBool, Ordering};
+
+/// 收到SIGTERM或Ctrl+C后置为true，此后不再接受新的提交
+static SHUTTING_DOWN: AtomicBool = AtomicBool::new(false);
+
+pub fn is_shutting_down() -> bool {
+    SHUTTING_DOWN.load(Ordering::SeqCst)
+}
+
+pub fn begin() {
+    SHUTTING_DOWN.store(true, Ordering::SeqCst);
+}
+
+/// 等待SIGTERM或Ctrl+C
+#[cfg(unix)]
+pub async fn wait_for_signal() -> io::Result<()> {
+    use tokio::signal::unix::{signal, SignalKind};
+    let mut terminate = signal(SignalKind::terminate())?;
+    tokio::select! {
+        _ = terminate.recv() => log::info!("收到SIGTERM，开始关闭"),
+        result = tokio::signal::ctrl_c() => {
+            result?;
+            log::info!("收到Ctrl+C，开始关闭");
+        }
+    }
+    Ok(())
+}
+
+/// 非unix平台没有SIGTERM，只能通过Ctrl+C关闭
+#[cfg(not(unix))]
+pub async fn wait_for_signal() -> io::Result<()> {
+    tokio::signal::ctrl_c().await?;
+    log::info!("收到Ctrl+C，开始关闭");
+    Ok(())
+}
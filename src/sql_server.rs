@@ -1,35 +1,246 @@
-use log;
 use tokio::time::{self, Duration};
-use tokio::sync::{mpsc, oneshot};
-use sqlx::{pool::Pool, sqlite::{Sqlite, SqlitePoolOptions}};
+use crate::metrics::{self, CommandReceiver, CommandSender};
+use tokio::sync::oneshot;
+use sqlx::{pool::Pool, sqlite::{Sqlite, SqliteConnection, SqlitePoolOptions}, QueryBuilder};
 use std::{error::Error, io};
 use std::path::Path;
 use std::time::{SystemTime, UNIX_EPOCH};
-use crate::{error::NoSuchValueError, r#struct::awl_type::SqlFile};
-use crate::r#struct::awl_type::Key;
+use crate::{error::{NoSuchValueError, ServerSuspendedError, SlugConflictError, VersionConflictError}, r#struct::awl_type::SqlFile};
+use crate::r#struct::awl_type::{Key, ServerId};
+use crate::r#struct::delivery::{Delivery, PendingDeliveries};
+use crate::r#struct::admin::{ApprovalRecord, ClientInfo, ClientKey, DailyStats, NewSubmission, ServerOverview, ServerSettings, SubmissionAnswers, SubmissionFilter, SubmissionRecord, SubmissionStats};
+use crate::rcon::RconTarget;
+use crate::r#struct::webhook::{WebhookList, WebhookTarget};
+use crate::r#struct::quota::Quotas;
+use crate::CONFIG;
+use crate::r#struct::paper::{NewPaper, Paper, PaperContentUpdate, PaperVersion};
+use crate::utils::{constant_time_eq, hmac_sha256_hex, unix_timestamp, verify_hmac_sha256_hex};
+use rand::distributions::Alphanumeric;
+use rand::{thread_rng, Rng};
+
+/// 自托管模式下使用self_hosted_key的客户端的名称
+pub const SELF_HOSTED_NAME: &str = "self_hosted";
+
+/// 自托管模式下0.json的试卷id
+pub const SELF_HOSTED_SLUG: &str = "0";
 
 #[derive(Debug)]
 enum Command {
-    Execute {
-        sql_statement: SqlStatement,
-        res_tx: oneshot::Sender<Result<String, Box<dyn Error + Send + Sync>>>,
-    },
     GetClientID{
         key:Key,
         res_tx:oneshot::Sender<Result<u32, Box<dyn Error + Send + Sync>>>
     },
     RegisterNewClient{
         name:String,
+        email:String,
         res_tx:oneshot::Sender<Result<String, Box<dyn Error + Send + Sync>>>
     },
-    RecordSuccessLog{
-        client_id:u32,
+    ListServersByEmail{
+        email:String,
+        res_tx:oneshot::Sender<Result<Vec<ClientInfo>, Box<dyn Error + Send + Sync>>>
+    },
+    ListServers{
+        res_tx:oneshot::Sender<Result<Vec<ServerOverview>, Box<dyn Error + Send + Sync>>>
+    },
+    SetSuspended{
+        server_id:ServerId,
+        suspended:bool,
+        res_tx:oneshot::Sender<Result<(), Box<dyn Error + Send + Sync>>>
+    },
+    PurgeServer{
+        server_id:ServerId,
+        res_tx:oneshot::Sender<Result<(), Box<dyn Error + Send + Sync>>>
+    },
+    TouchClient{
+        server_id:ServerId,
+    },
+    GetQuotas{
+        server_id:ServerId,
+        res_tx:oneshot::Sender<Result<Quotas, Box<dyn Error + Send + Sync>>>
+    },
+    GetServerQuotas{
+        server_id:ServerId,
+        res_tx:oneshot::Sender<Result<Quotas, Box<dyn Error + Send + Sync>>>
+    },
+    SetServerQuotas{
+        server_id:ServerId,
+        quotas:Quotas,
+        res_tx:oneshot::Sender<Result<(), Box<dyn Error + Send + Sync>>>
+    },
+    CountSubmissionsSince{
+        server_id:ServerId,
+        since:i64,
+        res_tx:oneshot::Sender<Result<u32, Box<dyn Error + Send + Sync>>>
+    },
+    VerifyKey{
+        key:Key,
+        res_tx:oneshot::Sender<Result<ClientKey, Box<dyn Error + Send + Sync>>>
+    },
+    RotateKey{
+        server_id:ServerId,
+        grace_period:u64,
+        res_tx:oneshot::Sender<Result<(Key, i64), Box<dyn Error + Send + Sync>>>
+    },
+    RecordSubmission{
+        submission:NewSubmission,
+        res_tx:oneshot::Sender<Result<i64, Box<dyn Error + Send + Sync>>>
+    },
+    GetClientInfo{
+        key:Key,
+        res_tx:oneshot::Sender<Result<ClientInfo, Box<dyn Error + Send + Sync>>>
+    },
+    GetClientInfoById{
+        id:u32,
+        res_tx:oneshot::Sender<Result<ClientInfo, Box<dyn Error + Send + Sync>>>
+    },
+    ListSubmissions{
+        server_id:u32,
+        filter:SubmissionFilter,
+        res_tx:oneshot::Sender<Result<Vec<SubmissionRecord>, Box<dyn Error + Send + Sync>>>
+    },
+    GetSubmissionStats{
+        server_id:u32,
+        res_tx:oneshot::Sender<Result<SubmissionStats, Box<dyn Error + Send + Sync>>>
+    },
+    RevokePlayer{
+        server_id:u32,
+        player_id:String,
+        res_tx:oneshot::Sender<Result<Vec<String>, Box<dyn Error + Send + Sync>>>
+    },
+    GetDailyStats{
+        server_id:u32,
+        days:u32,
+        res_tx:oneshot::Sender<Result<Vec<DailyStats>, Box<dyn Error + Send + Sync>>>
+    },
+    GetSettings{
+        server_id:u32,
+        res_tx:oneshot::Sender<Result<ServerSettings, Box<dyn Error + Send + Sync>>>
+    },
+    SetSettings{
+        server_id:u32,
+        settings:ServerSettings,
+        res_tx:oneshot::Sender<Result<(), Box<dyn Error + Send + Sync>>>
+    },
+    QueueApproval{
+        server_id:u32,
+        player_id:String,
+        paper_id:String,
+        score:i64,
+    },
+    ListApprovals{
+        server_id:u32,
+        res_tx:oneshot::Sender<Result<Vec<ApprovalRecord>, Box<dyn Error + Send + Sync>>>
+    },
+    RemoveApproval{
+        server_id:u32,
         player_id:String,
+        res_tx:oneshot::Sender<Result<Vec<String>, Box<dyn Error + Send + Sync>>>
+    },
+    GetPaper{
+        reference:String,
+        res_tx:oneshot::Sender<Result<Paper, Box<dyn Error + Send + Sync>>>
+    },
+    ListPapers{
+        server_id:ServerId,
+        res_tx:oneshot::Sender<Result<Vec<Paper>, Box<dyn Error + Send + Sync>>>
+    },
+    SavePaper{
+        paper:NewPaper,
+        res_tx:oneshot::Sender<Result<(u32, u32), Box<dyn Error + Send + Sync>>>
+    },
+    CreatePaper{
+        paper:NewPaper,
+        res_tx:oneshot::Sender<Result<(u32, u32), Box<dyn Error + Send + Sync>>>
+    },
+    UpdatePaper{
+        update:PaperContentUpdate,
+        res_tx:oneshot::Sender<Result<u32, Box<dyn Error + Send + Sync>>>
+    },
+    DeletePaper{
+        paper_id:u32,
+        res_tx:oneshot::Sender<Result<(), Box<dyn Error + Send + Sync>>>
+    },
+    ListPaperVersions{
+        paper_id:u32,
+        res_tx:oneshot::Sender<Result<Vec<PaperVersion>, Box<dyn Error + Send + Sync>>>
+    },
+    GetPaperVersion{
+        paper_id:u32,
+        version:u32,
+        res_tx:oneshot::Sender<Result<PaperVersion, Box<dyn Error + Send + Sync>>>
+    },
+    RollbackPaper{
+        paper_id:u32,
+        version:u32,
+        uploaded_by:String,
         ip_address:String,
+        res_tx:oneshot::Sender<Result<u32, Box<dyn Error + Send + Sync>>>
+    },
+    ListSubmissionAnswers{
+        server_id:u32,
+        paper_id:u32,
+        version:u32,
+        res_tx:oneshot::Sender<Result<Vec<SubmissionAnswers>, Box<dyn Error + Send + Sync>>>
+    },
+    ExportSubmissions{
+        server_id:u32,
+        filter:SubmissionFilter,
+        after_id:i64,
+        limit:u32,
+        res_tx:oneshot::Sender<Result<Vec<SubmissionRecord>, Box<dyn Error + Send + Sync>>>
+    },
+    SetSubmissionUuid{
+        submission_id:i64,
+        uuid:String,
+        res_tx:oneshot::Sender<Result<(), Box<dyn Error + Send + Sync>>>
     },
     GetClientPlayerCount{
         server_id:u32,
         res_tx:oneshot::Sender<Result<u32, Box<dyn Error + Send + Sync>>>
+    },
+    SelfHostedServerId{
+        slug:String,
+        res_tx:oneshot::Sender<Result<ServerId, Box<dyn Error + Send + Sync>>>
+    },
+    /// 关闭前保存尚未投递的消息
+    SavePendingDeliveries{
+        deliveries:PendingDeliveries,
+        res_tx:oneshot::Sender<Result<(), Box<dyn Error + Send + Sync>>>
+    },
+    /// 启动时取出上次关闭前保存的消息
+    TakePendingDeliveries{
+        res_tx:oneshot::Sender<Result<PendingDeliveries, Box<dyn Error + Send + Sync>>>
+    },
+    /// 检查数据库是否可用
+    Ping{
+        res_tx:oneshot::Sender<Result<(), Box<dyn Error + Send + Sync>>>
+    },
+    /// 处理完之前的所有命令后关闭连接池
+    Shutdown{
+        res_tx:oneshot::Sender<()>
+    },
+    GetDelivery{
+        server_id:ServerId,
+        res_tx:oneshot::Sender<Result<Delivery, Box<dyn Error + Send + Sync>>>
+    },
+    SetDelivery{
+        key:Key,
+        delivery:Delivery,
+        res_tx:oneshot::Sender<Result<(), Box<dyn Error + Send + Sync>>>
+    },
+    AddWebhook{
+        key:Key,
+        url:String,
+        res_tx:oneshot::Sender<Result<(u32, WebhookTarget), Box<dyn Error + Send + Sync>>>
+    },
+    GetWebhooks{
+        server_id:ServerId,
+        res_tx:oneshot::Sender<Result<WebhookList, Box<dyn Error + Send + Sync>>>
+    },
+    DeleteWebhook{
+        key:Key,
+        id:u32,
+        res_tx:oneshot::Sender<Result<(), Box<dyn Error + Send + Sync>>>
     }
 }
 
@@ -38,29 +249,166 @@ pub struct SqlServer {
     pool: Pool<Sqlite>,
     
     /// 接收命令的管道
-    cmd_rx: mpsc::UnboundedReceiver<Command>,
+    cmd_rx: CommandReceiver<Command>,
 }
 
-#[derive(Debug, Clone)]
-pub struct SqlStatement {
-    pub sql: String,
-    pub params: Vec<String>,
+/// sqlite不支持`ADD COLUMN IF NOT EXISTS`，先查询表结构再决定是否添加
+async fn add_column_if_missing(pool: &Pool<Sqlite>, table: &str, column: &str, definition: &str) -> Result<(), sqlx::Error> {
+    let columns = sqlx::query_as::<_, (String,)>(&format!("SELECT name FROM pragma_table_info('{}')", table))
+        .fetch_all(pool)
+        .await?;
+    if !columns.iter().any(|(name,)| name == column) {
+        sqlx::query(&format!("ALTER TABLE {} ADD COLUMN {} {}", table, column, definition))
+            .execute(pool)
+            .await?;
+    }
+    Ok(())
 }
 
-impl SqlStatement {
-    pub fn as_str(&self) -> &str {
-        &self.sql
+/// 旧版本每个客户端只有一份保存在试卷目录（tests_dir）下{客户端id}.json的试卷，
+/// 迁移时沿用客户端id作为试卷id，原有的问卷链接保持不变
+async fn migrate_legacy_papers(pool: &Pool<Sqlite>) -> Result<(), Box<dyn Error>> {
+    let servers = sqlx::query_as::<_, (u32,)>(
+        "SELECT id FROM server_info WHERE id NOT IN (SELECT server_id FROM papers) AND id NOT IN (SELECT id FROM papers)"
+    )
+        .fetch_all(pool)
+        .await?;
+    for (server_id,) in servers {
+        let file_path = std::path::Path::new(&CONFIG.tests_dir).join(format!("{}.json", server_id));
+        let Ok(contents) = std::fs::read_to_string(&file_path) else {
+            continue;
+        };
+        let mut content: serde_json::Value = match serde_json::from_str(&contents) {
+            Ok(content) => content,
+            Err(e) => {
+                log::warn!("{}不是有效的json，跳过迁移: {}", file_path.display(), e);
+                continue;
+            }
+        };
+        if let Some(object) = content.as_object_mut() {
+            object.remove("client_key");
+        }
+        let now = unix_timestamp() as i64;
+        sqlx::query("INSERT INTO papers (id, server_id, title, content, created_at, updated_at) VALUES (?, ?, ?, ?, ?, ?)")
+            .bind(server_id)
+            .bind(server_id)
+            .bind(content["main_title"].as_str().unwrap_or_default())
+            .bind(content.to_string())
+            .bind(now)
+            .bind(now)
+            .execute(pool)
+            .await?;
+        log::info!("已将{}迁移为试卷{}", file_path.display(), server_id);
     }
+    Ok(())
+}
+
+/// 密钥的前8位明文保存，用于验证时缩小查找范围
+pub(crate) fn key_prefix(key: &str) -> &str {
+    key.get(..8).unwrap_or(key)
+}
 
-    pub fn params(&self) -> &[String] {
-        &self.params
+/// 拼接提交记录的过滤条件，不包括分页
+fn push_submission_filter(query: &mut QueryBuilder<Sqlite>, filter: &SubmissionFilter) {
+    if let Some(paper_id) = &filter.paper_id {
+        query.push(" AND paper_id = ").push_bind(paper_id.clone());
+    }
+    if let Some(player_id) = &filter.player_id {
+        query.push(" AND player_id = ").push_bind(player_id.clone());
+    }
+    if let Some(pass) = filter.pass {
+        query.push(" AND pass = ").push_bind(pass);
+    }
+    if let Some(since) = filter.since {
+        query.push(" AND created_at >= ").push_bind(since);
+    }
+    if let Some(until) = filter.until {
+        query.push(" AND created_at < ").push_bind(until);
     }
 }
 
+/// 新建试卷记录，内容随后作为第一个版本追加
+async fn insert_paper(conn: &mut SqliteConnection, paper: &NewPaper) -> Result<u32, sqlx::Error> {
+    let now = unix_timestamp() as i64;
+    let result = sqlx::query("INSERT INTO papers (server_id, slug, title, target, content, created_at, updated_at) VALUES (?, ?, ?, ?, ?, ?, ?)")
+        .bind(paper.server_id)
+        .bind(&paper.slug)
+        .bind(&paper.title)
+        .bind(&paper.target)
+        .bind(&paper.content)
+        .bind(now)
+        .bind(now)
+        .execute(&mut *conn)
+        .await?;
+    Ok(result.last_insert_rowid() as u32)
+}
+
+/// 为试卷追加一个新版本并设为当前版本，返回新的版本号
+async fn append_paper_version(
+    conn: &mut SqliteConnection,
+    paper_id: u32,
+    content: &str,
+    uploaded_by: &str,
+    ip_address: &str,
+    source_version: Option<u32>,
+) -> Result<u32, sqlx::Error> {
+    let (version,) = sqlx::query_as::<_, (u32,)>("SELECT COALESCE(MAX(version), 0) + 1 FROM paper_versions WHERE paper_id = ?")
+        .bind(paper_id)
+        .fetch_one(&mut *conn)
+        .await?;
+    let now = unix_timestamp() as i64;
+    sqlx::query("INSERT INTO paper_versions (paper_id, version, content, uploaded_by, ip_address, source_version, created_at) VALUES (?, ?, ?, ?, ?, ?, ?)")
+        .bind(paper_id)
+        .bind(version)
+        .bind(content)
+        .bind(uploaded_by)
+        .bind(ip_address)
+        .bind(source_version)
+        .bind(now)
+        .execute(&mut *conn)
+        .await?;
+    let title = serde_json::from_str::<serde_json::Value>(content)
+        .ok()
+        .and_then(|content| content["main_title"].as_str().map(str::to_string))
+        .unwrap_or_default();
+    sqlx::query("UPDATE papers SET title = ?, content = ?, version = ?, updated_at = ? WHERE id = ?")
+        .bind(title)
+        .bind(content)
+        .bind(version)
+        .bind(now)
+        .bind(paper_id)
+        .execute(&mut *conn)
+        .await?;
+    Ok(version)
+}
+
+fn random_string(len: usize) -> String {
+    thread_rng()
+        .sample_iter(&Alphanumeric)
+        .take(len)
+        .map(char::from)
+        .collect()
+}
+
+/// 以随机盐对密钥做哈希后保存，同时生成签发管理令牌用的随机密钥
+async fn insert_key(conn: &mut SqliteConnection, server_id: ServerId, key: &str) -> Result<(), sqlx::Error> {
+    let salt = random_string(16);
+    sqlx::query("INSERT INTO client_keys (server_id, prefix, salt, hash, token_secret, created_at) VALUES (?, ?, ?, ?, ?, ?)")
+        .bind(server_id)
+        .bind(key_prefix(key))
+        .bind(&salt)
+        .bind(hmac_sha256_hex(&salt, key))
+        .bind(random_string(32))
+        .bind(unix_timestamp() as i64)
+        .execute(conn)
+        .await?;
+    Ok(())
+}
+
 /// 命令执行层
 impl SqlServer {
     pub async fn new(sql_file: SqlFile) -> Result<(SqlServer, SqlServerHandle), Box<dyn Error>> {
-        let (cmd_tx, cmd_rx) = mpsc::unbounded_channel();
+        let (cmd_tx, cmd_rx) = metrics::command_channel("sql_server");
         // 检测数据库文件是否存在，不存在则新建
         if !Path::new(sql_file.as_str()).exists() {
             log::info!("数据库文件不存在，创建数据库文件: {}", sql_file.as_str());
@@ -88,7 +436,321 @@ impl SqlServer {
             "CREATE TABLE IF NOT EXISTS server_info (
                 id    INTEGER PRIMARY KEY AUTOINCREMENT,
                 name  TEXT NOT NULL,
-                key   TEXT NOT NULL
+                key   TEXT NOT NULL,
+                email TEXT,
+                created_at INTEGER,
+                last_seen  INTEGER,
+                suspended  BOOLEAN NOT NULL DEFAULT 0
+            )"
+        )
+        .execute(&pool)
+        .await
+        .map_err(|e| {
+            log::error!("执行创建表命令失败: {:?}", e);
+            Box::new(e) as Box<dyn Error>
+        })?;
+        // 注册时的邮箱，用于找回密钥，旧版本注册的服务器为空
+        add_column_if_missing(&pool, "server_info", "email", "TEXT").await.map_err(|e| {
+            log::error!("修改表结构失败: {:?}", e);
+            Box::new(e) as Box<dyn Error>
+        })?;
+        for (column, definition) in [("created_at", "INTEGER"), ("last_seen", "INTEGER"), ("suspended", "BOOLEAN NOT NULL DEFAULT 0")] {
+            add_column_if_missing(&pool, "server_info", column, definition).await.map_err(|e| {
+                log::error!("修改表结构失败: {:?}", e);
+                Box::new(e) as Box<dyn Error>
+            })?;
+        }
+
+        // 客户端密钥只保存加盐哈希，prefix为密钥前8位，用于在验证时缩小查找范围
+        sqlx::query(
+            "CREATE TABLE IF NOT EXISTS client_keys (
+                id          INTEGER PRIMARY KEY AUTOINCREMENT,
+                server_id   INTEGER NOT NULL,
+                prefix      TEXT NOT NULL,
+                salt        TEXT NOT NULL,
+                hash        TEXT NOT NULL,
+                created_at  INTEGER NOT NULL,
+                expires_at  INTEGER
+            )"
+        )
+        .execute(&pool)
+        .await
+        .map_err(|e| {
+            log::error!("执行创建表命令失败: {:?}", e);
+            Box::new(e) as Box<dyn Error>
+        })?;
+        sqlx::query("CREATE INDEX IF NOT EXISTS client_keys_prefix ON client_keys (prefix)")
+            .execute(&pool)
+            .await
+            .map_err(|e| {
+                log::error!("执行创建索引命令失败: {:?}", e);
+                Box::new(e) as Box<dyn Error>
+            })?;
+        // 管理令牌的签名密钥，与密钥的哈希分开保存，旧版本以哈希签发的令牌在升级后失效
+        add_column_if_missing(&pool, "client_keys", "token_secret", "TEXT").await.map_err(|e| {
+            log::error!("修改表结构失败: {:?}", e);
+            Box::new(e) as Box<dyn Error>
+        })?;
+        let missing = sqlx::query_as::<_, (i64,)>("SELECT id FROM client_keys WHERE token_secret IS NULL")
+            .fetch_all(&pool)
+            .await
+            .map_err(|e| Box::new(e) as Box<dyn Error>)?;
+        for (id,) in missing {
+            sqlx::query("UPDATE client_keys SET token_secret = ? WHERE id = ?")
+                .bind(random_string(32))
+                .bind(id)
+                .execute(&pool)
+                .await
+                .map_err(|e| Box::new(e) as Box<dyn Error>)?;
+        }
+
+        // 旧版本在server_info.key中明文保存密钥，迁移到client_keys后清空
+        let legacy = sqlx::query_as::<_, (u32, String)>("SELECT id, key FROM server_info WHERE key != ''")
+            .fetch_all(&pool)
+            .await
+            .map_err(|e| Box::new(e) as Box<dyn Error>)?;
+        for (server_id, key) in legacy {
+            let mut conn = pool.acquire().await.map_err(|e| Box::new(e) as Box<dyn Error>)?;
+            insert_key(&mut conn, server_id, &key).await.map_err(|e| {
+                log::error!("迁移客户端密钥失败: {:?}", e);
+                Box::new(e) as Box<dyn Error>
+            })?;
+            sqlx::query("UPDATE server_info SET key = '' WHERE id = ?")
+                .bind(server_id)
+                .execute(&pool)
+                .await
+                .map_err(|e| Box::new(e) as Box<dyn Error>)?;
+            log::info!("已将客户端{}的密钥迁移为哈希存储", server_id);
+        }
+
+        // 自托管模式下试卷对应的客户端按slug查找，与托管模式的服务器名分开，
+        // 旧版本按name查找，没有密钥的记录即为自托管试卷创建的客户端
+        add_column_if_missing(&pool, "server_info", "self_hosted_slug", "TEXT").await.map_err(|e| {
+            log::error!("修改表结构失败: {:?}", e);
+            Box::new(e) as Box<dyn Error>
+        })?;
+        sqlx::query(
+            "UPDATE server_info SET self_hosted_slug = name
+             WHERE id IN (SELECT MIN(id) FROM server_info
+                          WHERE self_hosted_slug IS NULL AND key = '' AND id NOT IN (SELECT server_id FROM client_keys)
+                          GROUP BY name)
+             AND name NOT IN (SELECT self_hosted_slug FROM server_info WHERE self_hosted_slug IS NOT NULL)"
+        )
+        .execute(&pool)
+        .await
+        .map_err(|e| Box::new(e) as Box<dyn Error>)?;
+        sqlx::query("CREATE UNIQUE INDEX IF NOT EXISTS server_info_self_hosted_slug ON server_info (self_hosted_slug)")
+            .execute(&pool)
+            .await
+            .map_err(|e| {
+                log::error!("执行创建索引命令失败: {:?}", e);
+                Box::new(e) as Box<dyn Error>
+            })?;
+
+        // 所有提交记录，通过的记录可以被撤销
+        sqlx::query(
+            "CREATE TABLE IF NOT EXISTS submissions (
+                id          INTEGER PRIMARY KEY AUTOINCREMENT,
+                server_id   INTEGER NOT NULL,
+                paper_id    TEXT NOT NULL,
+                paper_version INTEGER,
+                player_id   TEXT NOT NULL,
+                player_uuid TEXT,
+                score       INTEGER NOT NULL,
+                pass        BOOLEAN NOT NULL,
+                revoked     BOOLEAN NOT NULL DEFAULT 0,
+                ip_address  TEXT NOT NULL,
+                answers     TEXT NOT NULL,
+                durations   TEXT,
+                created_at  INTEGER NOT NULL
+            )"
+        )
+        .execute(&pool)
+        .await
+        .map_err(|e| {
+            log::error!("执行创建表命令失败: {:?}", e);
+            Box::new(e) as Box<dyn Error>
+        })?;
+        add_column_if_missing(&pool, "submissions", "paper_version", "INTEGER").await.map_err(|e| {
+            log::error!("修改表结构失败: {:?}", e);
+            Box::new(e) as Box<dyn Error>
+        })?;
+        add_column_if_missing(&pool, "submissions", "player_uuid", "TEXT").await.map_err(|e| {
+            log::error!("修改表结构失败: {:?}", e);
+            Box::new(e) as Box<dyn Error>
+        })?;
+        add_column_if_missing(&pool, "submissions", "durations", "TEXT").await.map_err(|e| {
+            log::error!("修改表结构失败: {:?}", e);
+            Box::new(e) as Box<dyn Error>
+        })?;
+        sqlx::query("CREATE INDEX IF NOT EXISTS submissions_server ON submissions (server_id, created_at)")
+            .execute(&pool)
+            .await
+            .map_err(|e| {
+                log::error!("执行创建索引命令失败: {:?}", e);
+                Box::new(e) as Box<dyn Error>
+            })?;
+
+        // 服务器的设置，没有记录时使用默认值
+        sqlx::query(
+            "CREATE TABLE IF NOT EXISTS server_settings (
+                server_id         INTEGER PRIMARY KEY,
+                require_approval  BOOLEAN NOT NULL DEFAULT 0
+            )"
+        )
+        .execute(&pool)
+        .await
+        .map_err(|e| {
+            log::error!("执行创建表命令失败: {:?}", e);
+            Box::new(e) as Box<dyn Error>
+        })?;
+
+        // 等待审核的玩家
+        sqlx::query(
+            "CREATE TABLE IF NOT EXISTS approvals (
+                id          INTEGER PRIMARY KEY AUTOINCREMENT,
+                server_id   INTEGER NOT NULL,
+                player_id   TEXT NOT NULL,
+                paper_id    TEXT NOT NULL DEFAULT '',
+                score       INTEGER NOT NULL,
+                created_at  INTEGER NOT NULL
+            )"
+        )
+        .execute(&pool)
+        .await
+        .map_err(|e| {
+            log::error!("执行创建表命令失败: {:?}", e);
+            Box::new(e) as Box<dyn Error>
+        })?;
+        add_column_if_missing(&pool, "approvals", "paper_id", "TEXT NOT NULL DEFAULT ''").await.map_err(|e| {
+            log::error!("修改表结构失败: {:?}", e);
+            Box::new(e) as Box<dyn Error>
+        })?;
+
+        // 客户端的投递方式，没有记录时使用websocket
+        sqlx::query(
+            "CREATE TABLE IF NOT EXISTS delivery_config (
+                server_id      INTEGER PRIMARY KEY,
+                mode           TEXT NOT NULL,
+                rcon_address   TEXT,
+                rcon_password  TEXT,
+                command        TEXT
+            )"
+        )
+        .execute(&pool)
+        .await
+        .map_err(|e| {
+            log::error!("执行创建表命令失败: {:?}", e);
+            Box::new(e) as Box<dyn Error>
+        })?;
+
+        // 旧版本的投递配置没有按投递目标区分的命令
+        add_column_if_missing(&pool, "delivery_config", "targets", "TEXT").await.map_err(|e| {
+            log::error!("修改表结构失败: {:?}", e);
+            Box::new(e) as Box<dyn Error>
+        })?;
+
+        // 客户端上传的试卷，slug全局唯一
+        sqlx::query(
+            "CREATE TABLE IF NOT EXISTS papers (
+                id          INTEGER PRIMARY KEY AUTOINCREMENT,
+                server_id   INTEGER NOT NULL,
+                slug        TEXT UNIQUE,
+                title       TEXT NOT NULL,
+                target      TEXT,
+                content     TEXT NOT NULL,
+                version     INTEGER NOT NULL DEFAULT 1,
+                created_at  INTEGER NOT NULL,
+                updated_at  INTEGER NOT NULL
+            )"
+        )
+        .execute(&pool)
+        .await
+        .map_err(|e| {
+            log::error!("执行创建表命令失败: {:?}", e);
+            Box::new(e) as Box<dyn Error>
+        })?;
+        add_column_if_missing(&pool, "papers", "version", "INTEGER NOT NULL DEFAULT 1").await.map_err(|e| {
+            log::error!("修改表结构失败: {:?}", e);
+            Box::new(e) as Box<dyn Error>
+        })?;
+        migrate_legacy_papers(&pool).await.map_err(|e| {
+            log::error!("迁移旧版试卷失败: {:?}", e);
+            e
+        })?;
+
+        // 试卷的所有历史版本，papers中保存的是当前版本的副本
+        sqlx::query(
+            "CREATE TABLE IF NOT EXISTS paper_versions (
+                id             INTEGER PRIMARY KEY AUTOINCREMENT,
+                paper_id       INTEGER NOT NULL,
+                version        INTEGER NOT NULL,
+                content        TEXT NOT NULL,
+                uploaded_by    TEXT NOT NULL,
+                ip_address     TEXT NOT NULL,
+                source_version INTEGER,
+                created_at     INTEGER NOT NULL,
+                UNIQUE (paper_id, version)
+            )"
+        )
+        .execute(&pool)
+        .await
+        .map_err(|e| {
+            log::error!("执行创建表命令失败: {:?}", e);
+            Box::new(e) as Box<dyn Error>
+        })?;
+        // 没有历史记录的试卷以当前内容作为第一个版本
+        sqlx::query(
+            "INSERT INTO paper_versions (paper_id, version, content, uploaded_by, ip_address, created_at) \
+             SELECT id, version, content, '', '', updated_at FROM papers WHERE id NOT IN (SELECT paper_id FROM paper_versions)"
+        )
+        .execute(&pool)
+        .await
+        .map_err(|e| {
+            log::error!("迁移试卷版本失败: {:?}", e);
+            Box::new(e) as Box<dyn Error>
+        })?;
+
+        // 客户端注册的webhook
+        sqlx::query(
+            "CREATE TABLE IF NOT EXISTS webhooks (
+                id         INTEGER PRIMARY KEY AUTOINCREMENT,
+                server_id  INTEGER NOT NULL,
+                url        TEXT NOT NULL,
+                secret     TEXT NOT NULL
+            )"
+        )
+        .execute(&pool)
+        .await
+        .map_err(|e| {
+            log::error!("执行创建表命令失败: {:?}", e);
+            Box::new(e) as Box<dyn Error>
+        })?;
+
+        // 运营者为单个服务器设置的配额，为空的字段使用默认值
+        sqlx::query(
+            "CREATE TABLE IF NOT EXISTS server_quotas (
+                server_id                INTEGER PRIMARY KEY,
+                max_papers               INTEGER,
+                max_paper_bytes          INTEGER,
+                max_questions            INTEGER,
+                max_submissions_per_day  INTEGER,
+                retention_days           INTEGER
+            )"
+        )
+        .execute(&pool)
+        .await
+        .map_err(|e| {
+            log::error!("执行创建表命令失败: {:?}", e);
+            Box::new(e) as Box<dyn Error>
+        })?;
+
+        // 关闭时尚未投递的消息，下次启动时放回队列
+        sqlx::query(
+            "CREATE TABLE IF NOT EXISTS pending_deliveries (
+                id         INTEGER PRIMARY KEY AUTOINCREMENT,
+                server_id  INTEGER NOT NULL,
+                action     TEXT NOT NULL
             )"
         )
         .execute(&pool)
@@ -109,67 +771,689 @@ impl SqlServer {
         ))
     }
 
-    async fn execute_statement(&mut self, sql_statement: SqlStatement) -> Result<String, Box<dyn Error + Send + Sync>> {
-        let mut query = sqlx::query_as::<_, (String,)>(sql_statement.as_str());
-        // 先绑定参数后查询
-        for param in sql_statement.params() {
-            query = query.bind(param);
+    /// 自托管模式下按slug为每张试卷分配固定的客户端id，0.json对应的客户端为0，
+    /// 保存在server_info的self_hosted_slug中，修改配置文件中试卷的顺序不影响已有的记录
+    async fn self_hosted_server_id(&mut self, slug: &str) -> Result<ServerId, Box<dyn Error + Send + Sync>> {
+        if slug == SELF_HOSTED_SLUG {
+            return Ok(0);
         }
-        let result: Result<Option<(String,)>, sqlx::Error> = query.fetch_optional(&self.pool).await;
-        match result {
-            Ok(Some(row)) => Ok(row.0),
-            Ok(None) => Err(Box::new(NoSuchValueError)),
-            Err(e) => Err(Box::new(e)),
+        let existing = sqlx::query_as::<_, (u32,)>("SELECT id FROM server_info WHERE self_hosted_slug = ?")
+            .bind(slug)
+            .fetch_optional(&self.pool)
+            .await
+            .map_err(|e| Box::new(e) as Box<dyn Error + Send + Sync>)?;
+        if let Some((id,)) = existing {
+            return Ok(id);
         }
+        let result = sqlx::query("INSERT INTO server_info (name, key, self_hosted_slug, created_at) VALUES (?, '', ?, ?)")
+            .bind(slug)
+            .bind(slug)
+            .bind(unix_timestamp() as i64)
+            .execute(&self.pool)
+            .await
+            .map_err(|e| Box::new(e) as Box<dyn Error + Send + Sync>)?;
+        Ok(result.last_insert_rowid() as u32)
+    }
+
+    async fn self_hosted_slug(&mut self, server_id: ServerId) -> Result<String, Box<dyn Error + Send + Sync>> {
+        sqlx::query_as::<_, (String,)>("SELECT self_hosted_slug FROM server_info WHERE id = ? AND self_hosted_slug IS NOT NULL")
+            .bind(server_id)
+            .fetch_optional(&self.pool)
+            .await
+            .map_err(|e| Box::new(e) as Box<dyn Error + Send + Sync>)?
+            .map(|(slug,)| slug)
+            .ok_or_else(|| Box::new(NoSuchValueError) as Box<dyn Error + Send + Sync>)
     }
 
-    /// 查询客户端密钥对应的id
+    /// 查询客户端密钥对应的id，已停用的服务器不能使用
     async fn get_client_id(&mut self, key: Key) -> Result<u32,Box<dyn Error + Send + Sync>>{
-        let mut query = sqlx::query_as::<_, (u32,)>("SELECT id FROM server_info WHERE key = ?");
-        query = query.bind(key);
-        let result: Result<Option<(u32,)>, sqlx::Error> = query.fetch_optional(&self.pool).await;
-        match result{
-            Ok(Some(row)) => {
-                Ok(row.0)
-            }
-            Ok(None) => Err(Box::new(NoSuchValueError)),
-            Err(e) => {
-                Err(Box::new(e))
+        let client = self.verify_current_key(key).await?;
+        if client.suspended {
+            return Err(Box::new(ServerSuspendedError));
+        }
+        Ok(client.server_id)
+    }
+
+    /// 验证密钥，只接受当前密钥。被轮换的旧密钥在宽限期内只能用于客户端连接，
+    /// 不能用于管理接口，否则泄露的旧密钥可以签发令牌或再次轮换密钥
+    async fn verify_current_key(&mut self, key: Key) -> Result<ClientKey, Box<dyn Error + Send + Sync>> {
+        let client = self.verify_key(key).await?;
+        if client.expires_at.is_some() {
+            return Err(Box::new(NoSuchValueError));
+        }
+        Ok(client)
+    }
+
+    /// 验证密钥，已过宽限期的旧密钥视为不存在
+    async fn verify_key(&mut self, key: Key) -> Result<ClientKey, Box<dyn Error + Send + Sync>> {
+        // 自托管模式下使用self_hosted_key的客户端id为0，papers中的试卷各自对应一个客户端
+        if CONFIG.self_hosted {
+            let config = CONFIG.current();
+            if !config.self_hosted_key.is_empty() && constant_time_eq(&key, &config.self_hosted_key) {
+                return Ok(ClientKey { server_id: 0, name: SELF_HOSTED_NAME.to_string(), expires_at: None, suspended: false });
             }
+            let paper = config.papers.iter().find(|paper| constant_time_eq(&key, &paper.key)).ok_or(NoSuchValueError)?;
+            let server_id = self.self_hosted_server_id(&paper.slug).await?;
+            return Ok(ClientKey { server_id, name: paper.slug.clone(), expires_at: None, suspended: false });
         }
+        let rows = sqlx::query_as::<_, (u32, String, String, String, Option<i64>, bool)>(
+            "SELECT k.server_id, s.name, k.salt, k.hash, k.expires_at, s.suspended FROM client_keys k \
+             JOIN server_info s ON s.id = k.server_id \
+             WHERE k.prefix = ? AND (k.expires_at IS NULL OR k.expires_at > ?)"
+        )
+            .bind(key_prefix(&key))
+            .bind(unix_timestamp() as i64)
+            .fetch_all(&self.pool)
+            .await
+            .map_err(|e| Box::new(e) as Box<dyn Error + Send + Sync>)?;
+        rows.into_iter()
+            .find(|(_, _, salt, hash, _, _)| verify_hmac_sha256_hex(salt, &key, hash))
+            .map(|(server_id, name, _, _, expires_at, suspended)| ClientKey { server_id, name, expires_at, suspended })
+            .ok_or_else(|| Box::new(NoSuchValueError) as Box<dyn Error + Send + Sync>)
     }
     
     /// 新建客户端账号信息
-    async fn register_new_client(&mut self, name: String) -> Result<String, Box<dyn Error + Send + Sync>> {
+    async fn register_new_client(&mut self, name: String, email: String) -> Result<String, Box<dyn Error + Send + Sync>> {
         let key = uuid::Uuid::new_v4().to_string();
-        sqlx::query("INSERT INTO server_info (name, key) VALUES (?, ?)")
+        let mut tx = self.pool.begin().await.map_err(|e| Box::new(e) as Box<dyn Error + Send + Sync>)?;
+        let result = sqlx::query("INSERT INTO server_info (name, key, email, created_at) VALUES (?, '', ?, ?)")
             .bind(name)
-            .bind(&key)
-            .execute(&self.pool)
+            .bind(email)
+            .bind(unix_timestamp() as i64)
+            .execute(&mut *tx)
+            .await
+            .map_err(|e| Box::new(e) as Box<dyn Error + Send + Sync>)?;
+        insert_key(&mut tx, result.last_insert_rowid() as u32, &key)
             .await
             .map_err(|e| Box::new(e) as Box<dyn Error + Send + Sync>)?;
+        tx.commit().await.map_err(|e| Box::new(e) as Box<dyn Error + Send + Sync>)?;
         Ok(key)
     }
+
+    /// 查询使用该邮箱注册的服务器，邮箱不区分大小写
+    async fn list_servers_by_email(&mut self, email: String) -> Result<Vec<ClientInfo>, Box<dyn Error + Send + Sync>> {
+        let servers = sqlx::query_as::<_, (u32, String, String, bool)>(
+            "SELECT s.id, s.name, k.token_secret, s.suspended FROM server_info s \
+             JOIN client_keys k ON k.server_id = s.id AND k.expires_at IS NULL \
+             WHERE s.email = ? COLLATE NOCASE ORDER BY s.id"
+        )
+            .bind(email)
+            .fetch_all(&self.pool)
+            .await
+            .map_err(|e| Box::new(e) as Box<dyn Error + Send + Sync>)?;
+        Ok(servers.into_iter().map(|(id, name, token_secret, suspended)| ClientInfo { id, name, token_secret, suspended }).collect())
+    }
+
+    /// 列出所有注册的服务器及其统计数据
+    async fn list_servers(&mut self) -> Result<Vec<ServerOverview>, Box<dyn Error + Send + Sync>> {
+        sqlx::query_as::<_, ServerOverview>(
+            "SELECT s.id, s.name, s.email, s.created_at, s.last_seen, s.suspended, \
+             (SELECT COUNT(*) FROM papers p WHERE p.server_id = s.id) AS papers, \
+             (SELECT COUNT(*) FROM submissions m WHERE m.server_id = s.id) AS submissions, \
+             (SELECT COUNT(*) FROM submissions m WHERE m.server_id = s.id AND m.pass) AS passes \
+             FROM server_info s ORDER BY s.id"
+        )
+            .fetch_all(&self.pool)
+            .await
+            .map_err(|e| Box::new(e) as Box<dyn Error + Send + Sync>)
+    }
+
+    /// 停用或恢复服务器，服务器不存在时返回NoSuchValueError
+    async fn set_suspended(&mut self, server_id: ServerId, suspended: bool) -> Result<(), Box<dyn Error + Send + Sync>> {
+        let result = sqlx::query("UPDATE server_info SET suspended = ? WHERE id = ?")
+            .bind(suspended)
+            .bind(server_id)
+            .execute(&self.pool)
+            .await
+            .map_err(|e| Box::new(e) as Box<dyn Error + Send + Sync>)?;
+        if result.rows_affected() == 0 {
+            return Err(Box::new(NoSuchValueError));
+        }
+        Ok(())
+    }
+
+    /// 删除服务器及其所有数据，包括密钥、试卷及历史版本、提交记录和各项设置
+    async fn purge_server(&mut self, server_id: ServerId) -> Result<(), Box<dyn Error + Send + Sync>> {
+        let mut tx = self.pool.begin().await.map_err(|e| Box::new(e) as Box<dyn Error + Send + Sync>)?;
+        let result = sqlx::query("DELETE FROM server_info WHERE id = ?")
+            .bind(server_id)
+            .execute(&mut *tx)
+            .await
+            .map_err(|e| Box::new(e) as Box<dyn Error + Send + Sync>)?;
+        if result.rows_affected() == 0 {
+            return Err(Box::new(NoSuchValueError));
+        }
+        sqlx::query("DELETE FROM paper_versions WHERE paper_id IN (SELECT id FROM papers WHERE server_id = ?)")
+            .bind(server_id)
+            .execute(&mut *tx)
+            .await
+            .map_err(|e| Box::new(e) as Box<dyn Error + Send + Sync>)?;
+        for table in ["client_keys", "papers", "submissions", "server_settings", "approvals", "delivery_config", "webhooks", "server_quotas", "pending_deliveries"] {
+            sqlx::query(&format!("DELETE FROM {} WHERE server_id = ?", table))
+                .bind(server_id)
+                .execute(&mut *tx)
+                .await
+                .map_err(|e| Box::new(e) as Box<dyn Error + Send + Sync>)?;
+        }
+        tx.commit().await.map_err(|e| Box::new(e) as Box<dyn Error + Send + Sync>)?;
+        Ok(())
+    }
+
+    /// 运营者为服务器单独设置的配额
+    async fn get_server_quotas(&mut self, server_id: ServerId) -> Result<Quotas, Box<dyn Error + Send + Sync>> {
+        let quotas = sqlx::query_as::<_, Quotas>(
+            "SELECT max_papers, max_paper_bytes, max_questions, max_submissions_per_day, retention_days FROM server_quotas WHERE server_id = ?"
+        )
+            .bind(server_id)
+            .fetch_optional(&self.pool)
+            .await
+            .map_err(|e| Box::new(e) as Box<dyn Error + Send + Sync>)?;
+        Ok(quotas.unwrap_or_default())
+    }
+
+    /// 服务器实际生效的配额
+    async fn get_quotas(&mut self, server_id: ServerId) -> Result<Quotas, Box<dyn Error + Send + Sync>> {
+        Ok(self.get_server_quotas(server_id).await?.or(&CONFIG.current().quotas))
+    }
+
+    /// 覆盖服务器单独设置的配额，服务器不存在时返回NoSuchValueError
+    async fn set_server_quotas(&mut self, server_id: ServerId, quotas: Quotas) -> Result<(), Box<dyn Error + Send + Sync>> {
+        let result = sqlx::query(
+            "INSERT OR REPLACE INTO server_quotas \
+             (server_id, max_papers, max_paper_bytes, max_questions, max_submissions_per_day, retention_days) \
+             SELECT id, ?, ?, ?, ?, ? FROM server_info WHERE id = ?"
+        )
+            .bind(quotas.max_papers)
+            .bind(quotas.max_paper_bytes)
+            .bind(quotas.max_questions)
+            .bind(quotas.max_submissions_per_day)
+            .bind(quotas.retention_days)
+            .bind(server_id)
+            .execute(&self.pool)
+            .await
+            .map_err(|e| Box::new(e) as Box<dyn Error + Send + Sync>)?;
+        if result.rows_affected() == 0 {
+            return Err(Box::new(NoSuchValueError));
+        }
+        Ok(())
+    }
+
+    /// 统计服务器在since之后的提交次数
+    async fn count_submissions_since(&mut self, server_id: ServerId, since: i64) -> Result<u32, Box<dyn Error + Send + Sync>> {
+        sqlx::query_scalar::<_, u32>("SELECT COUNT(*) FROM submissions WHERE server_id = ? AND created_at >= ?")
+            .bind(server_id)
+            .bind(since)
+            .fetch_one(&self.pool)
+            .await
+            .map_err(|e| Box::new(e) as Box<dyn Error + Send + Sync>)
+    }
+
+    /// 删除超过保存天数的提交记录，返回删除的数量
+    async fn prune_submissions(&mut self) -> Result<u64, Box<dyn Error + Send + Sync>> {
+        // 服务器没有单独设置且没有默认值时COALESCE为NULL，比较结果也为NULL，不会删除任何记录
+        let result = sqlx::query(
+            "DELETE FROM submissions WHERE created_at < ? - 86400 * \
+             COALESCE((SELECT q.retention_days FROM server_quotas q WHERE q.server_id = submissions.server_id), ?)"
+        )
+            .bind(unix_timestamp() as i64)
+            .bind(CONFIG.current().quotas.retention_days)
+            .execute(&self.pool)
+            .await
+            .map_err(|e| Box::new(e) as Box<dyn Error + Send + Sync>)?;
+        Ok(result.rows_affected())
+    }
+
+    /// 记录客户端最后一次连接或断开的时间
+    async fn touch_client(&mut self, server_id: ServerId) -> Result<(), Box<dyn Error + Send + Sync>> {
+        sqlx::query("UPDATE server_info SET last_seen = ? WHERE id = ?")
+            .bind(unix_timestamp() as i64)
+            .bind(server_id)
+            .execute(&self.pool)
+            .await
+            .map_err(|e| Box::new(e) as Box<dyn Error + Send + Sync>)?;
+        Ok(())
+    }
+
+    /// 生成新密钥，旧密钥在宽限期后失效，返回新密钥及旧密钥的失效时间
+    async fn rotate_key(&mut self, server_id: ServerId, grace_period: u64) -> Result<(Key, i64), Box<dyn Error + Send + Sync>> {
+        let now = unix_timestamp() as i64;
+        let expires_at = now + grace_period as i64;
+        // 三个步骤必须一同成功，否则可能所有密钥都在宽限期后失效而没有新密钥
+        let mut tx = self.pool.begin().await.map_err(|e| Box::new(e) as Box<dyn Error + Send + Sync>)?;
+        // 已处于宽限期的密钥只会提前失效，不会被延长
+        sqlx::query("UPDATE client_keys SET expires_at = ? WHERE server_id = ? AND (expires_at IS NULL OR expires_at > ?)")
+            .bind(expires_at)
+            .bind(server_id)
+            .bind(expires_at)
+            .execute(&mut *tx)
+            .await
+            .map_err(|e| Box::new(e) as Box<dyn Error + Send + Sync>)?;
+        sqlx::query("DELETE FROM client_keys WHERE server_id = ? AND expires_at <= ?")
+            .bind(server_id)
+            .bind(now)
+            .execute(&mut *tx)
+            .await
+            .map_err(|e| Box::new(e) as Box<dyn Error + Send + Sync>)?;
+        let key = uuid::Uuid::new_v4().to_string();
+        insert_key(&mut tx, server_id, &key)
+            .await
+            .map_err(|e| Box::new(e) as Box<dyn Error + Send + Sync>)?;
+        tx.commit().await.map_err(|e| Box::new(e) as Box<dyn Error + Send + Sync>)?;
+        Ok((key, expires_at))
+    }
     
-    /// 记录玩家答题成功记录
-    async fn record_player_success_log(&mut self,client_id:u32, player_id: String, ip_address: String) -> Result<(), Box<dyn Error + Send + Sync>> {
-        sqlx::query("INSERT INTO quiz_log (client_id, player_id, passed_time, ip_address) VALUES (?, ?, ?, ?)")
-            .bind(client_id)
-            .bind(player_id)
+    /// 记录玩家的答题记录
+    async fn record_submission(&mut self, submission: NewSubmission) -> Result<i64, Box<dyn Error + Send + Sync>> {
+        let result = sqlx::query("INSERT INTO submissions (server_id, paper_id, paper_version, player_id, score, pass, ip_address, answers, durations, created_at) VALUES (?, ?, ?, ?, ?, ?, ?, ?, ?, ?)")
+            .bind(submission.server_id)
+            .bind(submission.paper_id)
+            .bind(submission.paper_version)
+            .bind(submission.player_id)
+            .bind(submission.score)
+            .bind(submission.pass)
+            .bind(submission.ip_address)
+            .bind(submission.answers)
+            .bind(submission.durations)
             .bind(SystemTime::now()
                       .duration_since(UNIX_EPOCH)
                       .unwrap()
                       .as_secs() as i64)
-            .bind(ip_address)
+            .execute(&self.pool)
+            .await
+            .map_err(|e| Box::new(e) as Box<dyn Error + Send + Sync>)?;
+        Ok(result.last_insert_rowid())
+    }
+
+    /// 记录提交时玩家的UUID
+    async fn set_submission_uuid(&mut self, submission_id: i64, uuid: String) -> Result<(), Box<dyn Error + Send + Sync>> {
+        sqlx::query("UPDATE submissions SET player_uuid = ? WHERE id = ?")
+            .bind(uuid)
+            .bind(submission_id)
             .execute(&self.pool)
             .await
             .map_err(|e| Box::new(e) as Box<dyn Error + Send + Sync>)?;
         Ok(())
     }
+
+    /// 查询客户端密钥对应的信息
+    async fn get_client_info(&mut self, key: Key) -> Result<ClientInfo, Box<dyn Error + Send + Sync>> {
+        let server_id = self.verify_current_key(key).await?.server_id;
+        self.get_client_info_by_id(server_id).await
+    }
+
+    /// 查询客户端id对应的信息
+    async fn get_client_info_by_id(&mut self, id: u32) -> Result<ClientInfo, Box<dyn Error + Send + Sync>> {
+        if CONFIG.self_hosted {
+            // 管理令牌的密钥由客户端的key派生，修改key后旧令牌随之失效
+            let config = CONFIG.current();
+            let (name, key) = if id == 0 {
+                (SELF_HOSTED_NAME.to_string(), config.self_hosted_key.clone())
+            } else {
+                let slug = self.self_hosted_slug(id).await?;
+                let paper = config.papers.iter().find(|paper| paper.slug == slug).ok_or(NoSuchValueError)?;
+                (slug, paper.key.clone())
+            };
+            if key.is_empty() {
+                return Err(Box::new(NoSuchValueError));
+            }
+            let token_secret = hmac_sha256_hex(&key, &name);
+            return Ok(ClientInfo { id, name, token_secret, suspended: false });
+        }
+        let query = sqlx::query_as::<_, (u32, String, String, bool)>(
+            "SELECT s.id, s.name, k.token_secret, s.suspended FROM server_info s \
+             JOIN client_keys k ON k.server_id = s.id AND k.expires_at IS NULL WHERE s.id = ?"
+        )
+            .bind(id);
+        match query.fetch_optional(&self.pool).await {
+            Ok(Some((id, name, token_secret, suspended))) => Ok(ClientInfo { id, name, token_secret, suspended }),
+            Ok(None) => Err(Box::new(NoSuchValueError)),
+            Err(e) => Err(Box::new(e)),
+        }
+    }
+
+    /// 按条件查询提交记录，按时间倒序排列
+    async fn list_submissions(&mut self, server_id: u32, filter: SubmissionFilter) -> Result<Vec<SubmissionRecord>, Box<dyn Error + Send + Sync>> {
+        let mut query = QueryBuilder::<Sqlite>::new(
+            "SELECT id, paper_id, paper_version, player_id, player_uuid, score, pass, revoked, ip_address, created_at FROM submissions WHERE server_id = "
+        );
+        query.push_bind(server_id);
+        push_submission_filter(&mut query, &filter);
+        query.push(" ORDER BY created_at DESC, id DESC LIMIT ")
+            .push_bind(filter.limit.unwrap_or(100).min(1000))
+            .push(" OFFSET ")
+            .push_bind(filter.offset.unwrap_or(0));
+        query.build_query_as::<SubmissionRecord>()
+            .fetch_all(&self.pool)
+            .await
+            .map_err(|e| Box::new(e) as Box<dyn Error + Send + Sync>)
+    }
+
+    /// 导出时按id顺序分批读取提交记录，after_id为上一批最后一条记录的id
+    async fn export_submissions(&mut self, server_id: u32, filter: SubmissionFilter, after_id: i64, limit: u32) -> Result<Vec<SubmissionRecord>, Box<dyn Error + Send + Sync>> {
+        let mut query = QueryBuilder::<Sqlite>::new(
+            "SELECT id, paper_id, paper_version, player_id, player_uuid, score, pass, revoked, ip_address, created_at FROM submissions WHERE server_id = "
+        );
+        query.push_bind(server_id);
+        push_submission_filter(&mut query, &filter);
+        query.push(" AND id > ").push_bind(after_id)
+            .push(" ORDER BY id LIMIT ")
+            .push_bind(limit);
+        query.build_query_as::<SubmissionRecord>()
+            .fetch_all(&self.pool)
+            .await
+            .map_err(|e| Box::new(e) as Box<dyn Error + Send + Sync>)
+    }
+
+    /// 读取使用试卷某个版本作答的所有答案
+    async fn list_submission_answers(&mut self, server_id: u32, paper_id: u32, version: u32) -> Result<Vec<SubmissionAnswers>, Box<dyn Error + Send + Sync>> {
+        sqlx::query_as::<_, SubmissionAnswers>(
+            "SELECT answers, score, durations FROM submissions WHERE server_id = ? AND paper_id = ? AND paper_version = ?"
+        )
+            .bind(server_id)
+            .bind(paper_id.to_string())
+            .bind(version)
+            .fetch_all(&self.pool)
+            .await
+            .map_err(|e| Box::new(e) as Box<dyn Error + Send + Sync>)
+    }
+
+    /// 统计提交、通过及被撤销的数量
+    async fn get_submission_stats(&mut self, server_id: u32) -> Result<SubmissionStats, Box<dyn Error + Send + Sync>> {
+        let query = sqlx::query_as::<_, (u32, u32, u32)>(
+            "SELECT COUNT(*), COALESCE(SUM(pass), 0), COALESCE(SUM(pass AND revoked), 0) FROM submissions WHERE server_id = ?"
+        )
+            .bind(server_id);
+        match query.fetch_one(&self.pool).await {
+            Ok((submissions, passes, revoked)) => Ok(SubmissionStats { submissions, passes, revoked }),
+            Err(e) => Err(Box::new(e)),
+        }
+    }
+
+    /// 将玩家所有通过的记录标记为已撤销，返回每条受影响记录的试卷id
+    async fn revoke_player(&mut self, server_id: u32, player_id: String) -> Result<Vec<String>, Box<dyn Error + Send + Sync>> {
+        let rows = sqlx::query_as::<_, (String,)>(
+            "UPDATE submissions SET revoked = 1 WHERE server_id = ? AND player_id = ? AND pass = 1 AND revoked = 0 RETURNING paper_id"
+        )
+            .bind(server_id)
+            .bind(player_id)
+            .fetch_all(&self.pool)
+            .await
+            .map_err(|e| Box::new(e) as Box<dyn Error + Send + Sync>)?;
+        Ok(rows.into_iter().map(|(paper_id,)| paper_id).collect())
+    }
     
+    /// 按天统计最近若干天的提交及通过数量
+    async fn get_daily_stats(&mut self, server_id: u32, days: u32) -> Result<Vec<DailyStats>, Box<dyn Error + Send + Sync>> {
+        let since = SystemTime::now().duration_since(UNIX_EPOCH).unwrap().as_secs() as i64 - days as i64 * 86400;
+        sqlx::query_as::<_, DailyStats>(
+            "SELECT created_at / 86400 * 86400 AS day, COUNT(*) AS submissions, SUM(pass) AS passes \
+             FROM submissions WHERE server_id = ? AND created_at >= ? GROUP BY day ORDER BY day"
+        )
+            .bind(server_id)
+            .bind(since)
+            .fetch_all(&self.pool)
+            .await
+            .map_err(|e| Box::new(e) as Box<dyn Error + Send + Sync>)
+    }
+
+    /// 查询服务器的设置
+    async fn get_settings(&mut self, server_id: u32) -> Result<ServerSettings, Box<dyn Error + Send + Sync>> {
+        let query = sqlx::query_as::<_, (bool,)>("SELECT require_approval FROM server_settings WHERE server_id = ?")
+            .bind(server_id);
+        match query.fetch_optional(&self.pool).await {
+            Ok(Some((require_approval,))) => Ok(ServerSettings { require_approval }),
+            Ok(None) => Ok(ServerSettings::default()),
+            Err(e) => Err(Box::new(e)),
+        }
+    }
+
+    /// 修改服务器的设置
+    async fn set_settings(&mut self, server_id: u32, settings: ServerSettings) -> Result<(), Box<dyn Error + Send + Sync>> {
+        sqlx::query("INSERT OR REPLACE INTO server_settings (server_id, require_approval) VALUES (?, ?)")
+            .bind(server_id)
+            .bind(settings.require_approval)
+            .execute(&self.pool)
+            .await
+            .map_err(|e| Box::new(e) as Box<dyn Error + Send + Sync>)?;
+        Ok(())
+    }
+
+    /// 将通过考试的玩家加入审核队列
+    async fn queue_approval(&mut self, server_id: u32, player_id: String, paper_id: String, score: i64) -> Result<(), Box<dyn Error + Send + Sync>> {
+        sqlx::query("INSERT INTO approvals (server_id, player_id, paper_id, score, created_at) VALUES (?, ?, ?, ?, ?)")
+            .bind(server_id)
+            .bind(player_id)
+            .bind(paper_id)
+            .bind(score)
+            .bind(SystemTime::now()
+                      .duration_since(UNIX_EPOCH)
+                      .unwrap()
+                      .as_secs() as i64)
+            .execute(&self.pool)
+            .await
+            .map_err(|e| Box::new(e) as Box<dyn Error + Send + Sync>)?;
+        Ok(())
+    }
+
+    /// 查询等待审核的玩家，同一玩家的同一试卷只保留最早的一条
+    async fn list_approvals(&mut self, server_id: u32) -> Result<Vec<ApprovalRecord>, Box<dyn Error + Send + Sync>> {
+        sqlx::query_as::<_, ApprovalRecord>(
+            "SELECT player_id, paper_id, MAX(score) AS score, MIN(created_at) AS created_at FROM approvals \
+             WHERE server_id = ? GROUP BY player_id, paper_id ORDER BY created_at"
+        )
+            .bind(server_id)
+            .fetch_all(&self.pool)
+            .await
+            .map_err(|e| Box::new(e) as Box<dyn Error + Send + Sync>)
+    }
+
+    /// 将玩家移出审核队列，返回被移除的记录对应的试卷id
+    async fn remove_approval(&mut self, server_id: u32, player_id: String) -> Result<Vec<String>, Box<dyn Error + Send + Sync>> {
+        let rows = sqlx::query_as::<_, (String,)>("DELETE FROM approvals WHERE server_id = ? AND player_id = ? RETURNING paper_id")
+            .bind(server_id)
+            .bind(player_id)
+            .fetch_all(&self.pool)
+            .await
+            .map_err(|e| Box::new(e) as Box<dyn Error + Send + Sync>)?;
+        let mut paper_ids: Vec<String> = rows.into_iter().map(|(paper_id,)| paper_id).collect();
+        paper_ids.sort();
+        paper_ids.dedup();
+        Ok(paper_ids)
+    }
+
+    /// 按id或slug查询试卷
+    async fn get_paper(&mut self, reference: String) -> Result<Paper, Box<dyn Error + Send + Sync>> {
+        let query = match reference.parse::<u32>() {
+            Ok(id) => sqlx::query_as::<_, Paper>("SELECT * FROM papers WHERE id = ?").bind(id),
+            Err(_) => sqlx::query_as::<_, Paper>("SELECT * FROM papers WHERE slug = ?").bind(reference),
+        };
+        match query.fetch_optional(&self.pool).await {
+            Ok(Some(paper)) => Ok(paper),
+            Ok(None) => Err(Box::new(NoSuchValueError)),
+            Err(e) => Err(Box::new(e)),
+        }
+    }
+
+    /// 查询客户端的所有试卷
+    async fn list_papers(&mut self, server_id: ServerId) -> Result<Vec<Paper>, Box<dyn Error + Send + Sync>> {
+        sqlx::query_as::<_, Paper>("SELECT * FROM papers WHERE server_id = ? ORDER BY id")
+            .bind(server_id)
+            .fetch_all(&self.pool)
+            .await
+            .map_err(|e| Box::new(e) as Box<dyn Error + Send + Sync>)
+    }
+
+    /// 保存试卷并返回试卷id及版本号。指定了slug时为该slug对应的试卷追加新版本，
+    /// 否则为客户端的第一份试卷追加新版本，没有对应试卷时新建
+    async fn save_paper(&mut self, paper: NewPaper) -> Result<(u32, u32), Box<dyn Error + Send + Sync>> {
+        let mut tx = self.pool.begin().await.map_err(|e| Box::new(e) as Box<dyn Error + Send + Sync>)?;
+        let existing = match &paper.slug {
+            Some(slug) => sqlx::query_as::<_, (u32, u32)>("SELECT id, server_id FROM papers WHERE slug = ?")
+                .bind(slug)
+                .fetch_optional(&mut *tx)
+                .await,
+            None => sqlx::query_as::<_, (u32, u32)>("SELECT id, server_id FROM papers WHERE server_id = ? ORDER BY id LIMIT 1")
+                .bind(paper.server_id)
+                .fetch_optional(&mut *tx)
+                .await,
+        }
+            .map_err(|e| Box::new(e) as Box<dyn Error + Send + Sync>)?;
+        let id = match existing {
+            Some((_, server_id)) if server_id != paper.server_id => return Err(Box::new(SlugConflictError)),
+            Some((id, _)) => {
+                sqlx::query("UPDATE papers SET target = ? WHERE id = ?")
+                    .bind(&paper.target)
+                    .bind(id)
+                    .execute(&mut *tx)
+                    .await
+                    .map_err(|e| Box::new(e) as Box<dyn Error + Send + Sync>)?;
+                id
+            }
+            None => insert_paper(&mut tx, &paper).await.map_err(|e| Box::new(e) as Box<dyn Error + Send + Sync>)?,
+        };
+        let version = append_paper_version(&mut tx, id, &paper.content, &paper.uploaded_by, &paper.ip_address, None)
+            .await
+            .map_err(|e| Box::new(e) as Box<dyn Error + Send + Sync>)?;
+        tx.commit().await.map_err(|e| Box::new(e) as Box<dyn Error + Send + Sync>)?;
+        Ok((id, version))
+    }
+
+    /// 新建一张试卷，slug已被使用时返回SlugConflictError
+    async fn create_paper(&mut self, paper: NewPaper) -> Result<(u32, u32), Box<dyn Error + Send + Sync>> {
+        let mut tx = self.pool.begin().await.map_err(|e| Box::new(e) as Box<dyn Error + Send + Sync>)?;
+        if let Some(slug) = &paper.slug {
+            let existing = sqlx::query_as::<_, (u32,)>("SELECT id FROM papers WHERE slug = ?")
+                .bind(slug)
+                .fetch_optional(&mut *tx)
+                .await
+                .map_err(|e| Box::new(e) as Box<dyn Error + Send + Sync>)?;
+            if existing.is_some() {
+                return Err(Box::new(SlugConflictError));
+            }
+        }
+        let id = insert_paper(&mut tx, &paper).await.map_err(|e| Box::new(e) as Box<dyn Error + Send + Sync>)?;
+        let version = append_paper_version(&mut tx, id, &paper.content, &paper.uploaded_by, &paper.ip_address, None)
+            .await
+            .map_err(|e| Box::new(e) as Box<dyn Error + Send + Sync>)?;
+        tx.commit().await.map_err(|e| Box::new(e) as Box<dyn Error + Send + Sync>)?;
+        Ok((id, version))
+    }
+
+    /// 修改试卷，内容发生变化时追加一个版本，返回修改后的版本。
+    /// 指定了base_version而试卷已被其他人修改时返回VersionConflictError，slug已被其他试卷使用时返回SlugConflictError
+    async fn update_paper(&mut self, update: PaperContentUpdate) -> Result<u32, Box<dyn Error + Send + Sync>> {
+        let mut tx = self.pool.begin().await.map_err(|e| Box::new(e) as Box<dyn Error + Send + Sync>)?;
+        let current = sqlx::query_as::<_, (u32, String)>("SELECT version, content FROM papers WHERE id = ?")
+            .bind(update.paper_id)
+            .fetch_optional(&mut *tx)
+            .await
+            .map_err(|e| Box::new(e) as Box<dyn Error + Send + Sync>)?;
+        let (current, content) = match current {
+            None => return Err(Box::new(NoSuchValueError)),
+            Some((current, _)) if update.base_version.is_some_and(|base| base != current) => {
+                return Err(Box::new(VersionConflictError));
+            }
+            Some(current) => current,
+        };
+        if let Some(slug) = &update.slug {
+            let existing = sqlx::query_as::<_, (u32,)>("SELECT id FROM papers WHERE slug = ? AND id != ?")
+                .bind(slug)
+                .bind(update.paper_id)
+                .fetch_optional(&mut *tx)
+                .await
+                .map_err(|e| Box::new(e) as Box<dyn Error + Send + Sync>)?;
+            if existing.is_some() {
+                return Err(Box::new(SlugConflictError));
+            }
+            sqlx::query("UPDATE papers SET slug = NULLIF(?, '') WHERE id = ?")
+                .bind(slug)
+                .bind(update.paper_id)
+                .execute(&mut *tx)
+                .await
+                .map_err(|e| Box::new(e) as Box<dyn Error + Send + Sync>)?;
+        }
+        if let Some(target) = &update.target {
+            sqlx::query("UPDATE papers SET target = NULLIF(?, '') WHERE id = ?")
+                .bind(target)
+                .bind(update.paper_id)
+                .execute(&mut *tx)
+                .await
+                .map_err(|e| Box::new(e) as Box<dyn Error + Send + Sync>)?;
+        }
+        // 内容没有变化时不生成新版本，只保存slug和target的修改
+        let unchanged = match (serde_json::from_str::<serde_json::Value>(&content), serde_json::from_str::<serde_json::Value>(&update.content)) {
+            (Ok(content), Ok(new)) => content == new,
+            _ => false,
+        };
+        let version = if unchanged {
+            current
+        } else {
+            append_paper_version(&mut tx, update.paper_id, &update.content, &update.uploaded_by, &update.ip_address, None)
+                .await
+                .map_err(|e| Box::new(e) as Box<dyn Error + Send + Sync>)?
+        };
+        tx.commit().await.map_err(|e| Box::new(e) as Box<dyn Error + Send + Sync>)?;
+        Ok(version)
+    }
+
+    /// 删除试卷及其所有版本，已有的提交记录保留
+    async fn delete_paper(&mut self, paper_id: u32) -> Result<(), Box<dyn Error + Send + Sync>> {
+        let mut tx = self.pool.begin().await.map_err(|e| Box::new(e) as Box<dyn Error + Send + Sync>)?;
+        sqlx::query("DELETE FROM paper_versions WHERE paper_id = ?")
+            .bind(paper_id)
+            .execute(&mut *tx)
+            .await
+            .map_err(|e| Box::new(e) as Box<dyn Error + Send + Sync>)?;
+        sqlx::query("DELETE FROM papers WHERE id = ?")
+            .bind(paper_id)
+            .execute(&mut *tx)
+            .await
+            .map_err(|e| Box::new(e) as Box<dyn Error + Send + Sync>)?;
+        tx.commit().await.map_err(|e| Box::new(e) as Box<dyn Error + Send + Sync>)?;
+        Ok(())
+    }
+
+    /// 查询试卷的所有版本，最新的版本排在前面
+    async fn list_paper_versions(&mut self, paper_id: u32) -> Result<Vec<PaperVersion>, Box<dyn Error + Send + Sync>> {
+        sqlx::query_as::<_, PaperVersion>(
+            "SELECT paper_id, version, uploaded_by, ip_address, source_version, content, created_at FROM paper_versions \
+             WHERE paper_id = ? ORDER BY version DESC"
+        )
+            .bind(paper_id)
+            .fetch_all(&self.pool)
+            .await
+            .map_err(|e| Box::new(e) as Box<dyn Error + Send + Sync>)
+    }
+
+    /// 查询试卷的指定版本
+    async fn get_paper_version(&mut self, paper_id: u32, version: u32) -> Result<PaperVersion, Box<dyn Error + Send + Sync>> {
+        let query = sqlx::query_as::<_, PaperVersion>(
+            "SELECT paper_id, version, uploaded_by, ip_address, source_version, content, created_at FROM paper_versions \
+             WHERE paper_id = ? AND version = ?"
+        )
+            .bind(paper_id)
+            .bind(version);
+        match query.fetch_optional(&self.pool).await {
+            Ok(Some(version)) => Ok(version),
+            Ok(None) => Err(Box::new(NoSuchValueError)),
+            Err(e) => Err(Box::new(e)),
+        }
+    }
+
+    /// 回滚到指定版本。历史版本不会被修改，而是以该版本的内容追加一个新版本，返回新的版本号
+    async fn rollback_paper(&mut self, paper_id: u32, version: u32, uploaded_by: String, ip_address: String) -> Result<u32, Box<dyn Error + Send + Sync>> {
+        let source = self.get_paper_version(paper_id, version).await?;
+        let mut tx = self.pool.begin().await.map_err(|e| Box::new(e) as Box<dyn Error + Send + Sync>)?;
+        let version = append_paper_version(&mut tx, paper_id, &source.content, &uploaded_by, &ip_address, Some(version))
+            .await
+            .map_err(|e| Box::new(e) as Box<dyn Error + Send + Sync>)?;
+        tx.commit().await.map_err(|e| Box::new(e) as Box<dyn Error + Send + Sync>)?;
+        Ok(version)
+    }
+
     /// 获取对应客户端注册成功的玩家数量
     async fn get_client_player_count(&mut self, server_id: u32) -> Result<u32, Box<dyn Error + Send + Sync>> {
-        let query = sqlx::query_as::<_, (u32,)>("SELECT COUNT(*) FROM quiz_log WHERE client_id = ?")
+        let query = sqlx::query_as::<_, (u32,)>("SELECT COUNT(*) FROM submissions WHERE server_id = ? AND pass = 1 AND revoked = 0")
             .bind(server_id);
         let result: Result<(u32,), sqlx::Error> = query.fetch_one(&self.pool).await;
         match result {
@@ -178,39 +1462,364 @@ impl SqlServer {
         }
     }
     
+    async fn ping(&mut self) -> Result<(), Box<dyn Error + Send + Sync>> {
+        sqlx::query("SELECT 1")
+            .execute(&self.pool)
+            .await
+            .map_err(|e| Box::new(e) as Box<dyn Error + Send + Sync>)?;
+        Ok(())
+    }
+
+    async fn save_pending_deliveries(&mut self, deliveries: PendingDeliveries) -> Result<(), Box<dyn Error + Send + Sync>> {
+        let mut tx = self.pool.begin().await.map_err(|e| Box::new(e) as Box<dyn Error + Send + Sync>)?;
+        for (server_id, action) in deliveries {
+            sqlx::query("INSERT INTO pending_deliveries (server_id, action) VALUES (?, ?)")
+                .bind(server_id)
+                .bind(serde_json::to_string(&action)?)
+                .execute(&mut *tx)
+                .await
+                .map_err(|e| Box::new(e) as Box<dyn Error + Send + Sync>)?;
+        }
+        tx.commit().await.map_err(|e| Box::new(e) as Box<dyn Error + Send + Sync>)?;
+        Ok(())
+    }
+
+    /// 按保存的顺序取出并删除所有消息，无法解析的记录会被丢弃
+    async fn take_pending_deliveries(&mut self) -> Result<PendingDeliveries, Box<dyn Error + Send + Sync>> {
+        let mut tx = self.pool.begin().await.map_err(|e| Box::new(e) as Box<dyn Error + Send + Sync>)?;
+        let rows = sqlx::query_as::<_, (ServerId, String)>("SELECT server_id, action FROM pending_deliveries ORDER BY id")
+            .fetch_all(&mut *tx)
+            .await
+            .map_err(|e| Box::new(e) as Box<dyn Error + Send + Sync>)?;
+        sqlx::query("DELETE FROM pending_deliveries")
+            .execute(&mut *tx)
+            .await
+            .map_err(|e| Box::new(e) as Box<dyn Error + Send + Sync>)?;
+        tx.commit().await.map_err(|e| Box::new(e) as Box<dyn Error + Send + Sync>)?;
+        Ok(rows
+            .into_iter()
+            .filter_map(|(server_id, action)| match serde_json::from_str(&action) {
+                Ok(action) => Some((server_id, action)),
+                Err(e) => {
+                    log::error!("无法解析保存的投递消息{}: {}", action, e);
+                    None
+                }
+            })
+            .collect())
+    }
+
+    /// 查询客户端的投递方式
+    async fn get_delivery(&mut self, server_id: ServerId) -> Result<Delivery, Box<dyn Error + Send + Sync>> {
+        // 自托管模式下读取配置文件
+        if CONFIG.self_hosted {
+            let config = CONFIG.current();
+            let rcon = if server_id == 0 {
+                config.rcon.clone()
+            } else {
+                let slug = self.self_hosted_slug(server_id).await?;
+                config.papers.iter().find(|paper| paper.slug == slug).and_then(|paper| paper.rcon.clone())
+            };
+            return Ok(rcon.map_or(Delivery::WebSocket, Delivery::Rcon));
+        }
+        let query = sqlx::query_as::<_, (String, Option<String>, Option<String>, Option<String>, Option<String>)>(
+            "SELECT mode, rcon_address, rcon_password, command, targets FROM delivery_config WHERE server_id = ?"
+        )
+            .bind(server_id);
+        let result = query.fetch_optional(&self.pool).await;
+        match result {
+            Ok(Some((mode, Some(address), Some(password), command, targets))) if mode == "rcon" => {
+                let targets = match targets {
+                    Some(targets) => serde_json::from_str(&targets)?,
+                    None => Default::default(),
+                };
+                Ok(Delivery::Rcon(RconTarget { address, password, command, targets }))
+            }
+            Ok(_) => Ok(Delivery::WebSocket),
+            Err(e) => Err(Box::new(e)),
+        }
+    }
+
+    /// 修改客户端的投递方式
+    async fn set_delivery(&mut self, key: Key, delivery: Delivery) -> Result<(), Box<dyn Error + Send + Sync>> {
+        let server_id = self.get_client_id(key).await?;
+        let (mode, target) = match delivery {
+            Delivery::WebSocket => ("websocket", None),
+            Delivery::Rcon(target) => ("rcon", Some(target)),
+        };
+        let targets = match &target {
+            Some(target) => Some(serde_json::to_string(&target.targets)?),
+            None => None,
+        };
+        sqlx::query("INSERT OR REPLACE INTO delivery_config (server_id, mode, rcon_address, rcon_password, command, targets) VALUES (?, ?, ?, ?, ?, ?)")
+            .bind(server_id)
+            .bind(mode)
+            .bind(target.as_ref().map(|t| t.address.clone()))
+            .bind(target.as_ref().map(|t| t.password.clone()))
+            .bind(target.and_then(|t| t.command))
+            .bind(targets)
+            .execute(&self.pool)
+            .await
+            .map_err(|e| Box::new(e) as Box<dyn Error + Send + Sync>)?;
+        Ok(())
+    }
+
+    /// 添加webhook并生成签名密钥
+    async fn add_webhook(&mut self, key: Key, url: String) -> Result<(u32, WebhookTarget), Box<dyn Error + Send + Sync>> {
+        let server_id = self.get_client_id(key).await?;
+        let secret = random_string(32);
+        let result = sqlx::query("INSERT INTO webhooks (server_id, url, secret) VALUES (?, ?, ?)")
+            .bind(server_id)
+            .bind(&url)
+            .bind(&secret)
+            .execute(&self.pool)
+            .await
+            .map_err(|e| Box::new(e) as Box<dyn Error + Send + Sync>)?;
+        Ok((result.last_insert_rowid() as u32, WebhookTarget { url, secret }))
+    }
+
+    /// 查询客户端注册的所有webhook
+    async fn get_webhooks(&mut self, server_id: ServerId) -> Result<WebhookList, Box<dyn Error + Send + Sync>> {
+        let rows = sqlx::query_as::<_, (u32, String, String)>(
+            "SELECT id, url, secret FROM webhooks WHERE server_id = ?"
+        )
+            .bind(server_id)
+            .fetch_all(&self.pool)
+            .await
+            .map_err(|e| Box::new(e) as Box<dyn Error + Send + Sync>)?;
+        Ok(rows.into_iter().map(|(id, url, secret)| (id, WebhookTarget { url, secret })).collect())
+    }
+
+    /// 删除webhook，只能删除属于该客户端的记录
+    async fn delete_webhook(&mut self, key: Key, id: u32) -> Result<(), Box<dyn Error + Send + Sync>> {
+        let server_id = self.get_client_id(key).await?;
+        let result = sqlx::query("DELETE FROM webhooks WHERE id = ? AND server_id = ?")
+            .bind(id)
+            .bind(server_id)
+            .execute(&self.pool)
+            .await
+            .map_err(|e| Box::new(e) as Box<dyn Error + Send + Sync>)?;
+        if result.rows_affected() == 0 {
+            return Err(Box::new(NoSuchValueError));
+        }
+        Ok(())
+    }
+
     pub async fn run(mut self) -> io::Result<()> {
-        let mut interval = time::interval(Duration::from_secs(5));
+        // 每小时清理一次过期的提交记录，启动时立即执行一次
+        let mut interval = time::interval(Duration::from_secs(3600));
 
         loop {
             tokio::select! {
                 Some(cmd) = self.cmd_rx.recv() => {
                     match cmd {
-                        Command::Execute { sql_statement, res_tx } => {
-                            let result = self.execute_statement(sql_statement).await;
-                            let _ = res_tx.send(result);
-                        },
                         Command::GetClientID { key, res_tx } => {
                             let result = self.get_client_id(key).await;
                             let _ = res_tx.send(result);
                         },
-                        Command::RegisterNewClient { name, res_tx } => {
-                            let result = self.register_new_client(name).await;
+                        Command::RegisterNewClient { name, email, res_tx } => {
+                            let result = self.register_new_client(name, email).await;
                             let _ = res_tx.send(result);
                         },
-                        Command::RecordSuccessLog { client_id, player_id, ip_address } => {
-                            let result = self.record_player_success_log(client_id, player_id, ip_address).await;
-                            if let Err(e) = result {
-                                log::error!("记录玩家答题成功记录时出错: {:?}", e);
-                            }
+                        Command::ListServersByEmail { email, res_tx } => {
+                            let result = self.list_servers_by_email(email).await;
+                            let _ = res_tx.send(result);
                         },
-                        Command::GetClientPlayerCount { server_id, res_tx } => {
-                            let result = self.get_client_player_count(server_id).await;
+                        Command::ListServers { res_tx } => {
+                            let result = self.list_servers().await;
                             let _ = res_tx.send(result);
-                        }
+                        },
+                        Command::SetSuspended { server_id, suspended, res_tx } => {
+                            let result = self.set_suspended(server_id, suspended).await;
+                            let _ = res_tx.send(result);
+                        },
+                        Command::PurgeServer { server_id, res_tx } => {
+                            let result = self.purge_server(server_id).await;
+                            let _ = res_tx.send(result);
+                        },
+                        Command::GetQuotas { server_id, res_tx } => {
+                            let result = self.get_quotas(server_id).await;
+                            let _ = res_tx.send(result);
+                        },
+                        Command::GetServerQuotas { server_id, res_tx } => {
+                            let result = self.get_server_quotas(server_id).await;
+                            let _ = res_tx.send(result);
+                        },
+                        Command::SetServerQuotas { server_id, quotas, res_tx } => {
+                            let result = self.set_server_quotas(server_id, quotas).await;
+                            let _ = res_tx.send(result);
+                        },
+                        Command::CountSubmissionsSince { server_id, since, res_tx } => {
+                            let result = self.count_submissions_since(server_id, since).await;
+                            let _ = res_tx.send(result);
+                        },
+                        Command::TouchClient { server_id } => {
+                            if let Err(e) = self.touch_client(server_id).await {
+                                log::error!("记录客户端{}的在线时间时出错: {:?}", server_id, e);
+                            }
+                        },
+                        Command::VerifyKey { key, res_tx } => {
+                            let result = self.verify_key(key).await;
+                            let _ = res_tx.send(result);
+                        },
+                        Command::RotateKey { server_id, grace_period, res_tx } => {
+                            let result = self.rotate_key(server_id, grace_period).await;
+                            let _ = res_tx.send(result);
+                        },
+                        Command::RecordSubmission { submission, res_tx } => {
+                            let result = self.record_submission(submission).await;
+                            if let Err(e) = &result {
+                                log::error!("记录玩家答题记录时出错: {:?}", e);
+                            }
+                            let _ = res_tx.send(result);
+                        },
+                        Command::GetClientInfo { key, res_tx } => {
+                            let result = self.get_client_info(key).await;
+                            let _ = res_tx.send(result);
+                        },
+                        Command::GetClientInfoById { id, res_tx } => {
+                            let result = self.get_client_info_by_id(id).await;
+                            let _ = res_tx.send(result);
+                        },
+                        Command::ListSubmissions { server_id, filter, res_tx } => {
+                            let result = self.list_submissions(server_id, filter).await;
+                            let _ = res_tx.send(result);
+                        },
+                        Command::GetSubmissionStats { server_id, res_tx } => {
+                            let result = self.get_submission_stats(server_id).await;
+                            let _ = res_tx.send(result);
+                        },
+                        Command::RevokePlayer { server_id, player_id, res_tx } => {
+                            let result = self.revoke_player(server_id, player_id).await;
+                            let _ = res_tx.send(result);
+                        },
+                        Command::GetDailyStats { server_id, days, res_tx } => {
+                            let result = self.get_daily_stats(server_id, days).await;
+                            let _ = res_tx.send(result);
+                        },
+                        Command::GetSettings { server_id, res_tx } => {
+                            let result = self.get_settings(server_id).await;
+                            let _ = res_tx.send(result);
+                        },
+                        Command::SetSettings { server_id, settings, res_tx } => {
+                            let result = self.set_settings(server_id, settings).await;
+                            let _ = res_tx.send(result);
+                        },
+                        Command::QueueApproval { server_id, player_id, paper_id, score } => {
+                            let result = self.queue_approval(server_id, player_id, paper_id, score).await;
+                            if let Err(e) = result {
+                                log::error!("加入审核队列时出错: {:?}", e);
+                            }
+                        },
+                        Command::ListApprovals { server_id, res_tx } => {
+                            let result = self.list_approvals(server_id).await;
+                            let _ = res_tx.send(result);
+                        },
+                        Command::RemoveApproval { server_id, player_id, res_tx } => {
+                            let result = self.remove_approval(server_id, player_id).await;
+                            let _ = res_tx.send(result);
+                        },
+                        Command::GetPaper { reference, res_tx } => {
+                            let result = self.get_paper(reference).await;
+                            let _ = res_tx.send(result);
+                        },
+                        Command::ListPapers { server_id, res_tx } => {
+                            let result = self.list_papers(server_id).await;
+                            let _ = res_tx.send(result);
+                        },
+                        Command::SavePaper { paper, res_tx } => {
+                            let result = self.save_paper(paper).await;
+                            let _ = res_tx.send(result);
+                        },
+                        Command::CreatePaper { paper, res_tx } => {
+                            let result = self.create_paper(paper).await;
+                            let _ = res_tx.send(result);
+                        },
+                        Command::UpdatePaper { update, res_tx } => {
+                            let result = self.update_paper(update).await;
+                            let _ = res_tx.send(result);
+                        },
+                        Command::DeletePaper { paper_id, res_tx } => {
+                            let result = self.delete_paper(paper_id).await;
+                            let _ = res_tx.send(result);
+                        },
+                        Command::ListPaperVersions { paper_id, res_tx } => {
+                            let result = self.list_paper_versions(paper_id).await;
+                            let _ = res_tx.send(result);
+                        },
+                        Command::GetPaperVersion { paper_id, version, res_tx } => {
+                            let result = self.get_paper_version(paper_id, version).await;
+                            let _ = res_tx.send(result);
+                        },
+                        Command::RollbackPaper { paper_id, version, uploaded_by, ip_address, res_tx } => {
+                            let result = self.rollback_paper(paper_id, version, uploaded_by, ip_address).await;
+                            let _ = res_tx.send(result);
+                        },
+                        Command::ListSubmissionAnswers { server_id, paper_id, version, res_tx } => {
+                            let result = self.list_submission_answers(server_id, paper_id, version).await;
+                            let _ = res_tx.send(result);
+                        },
+                        Command::ExportSubmissions { server_id, filter, after_id, limit, res_tx } => {
+                            let result = self.export_submissions(server_id, filter, after_id, limit).await;
+                            let _ = res_tx.send(result);
+                        },
+                        Command::SetSubmissionUuid { submission_id, uuid, res_tx } => {
+                            let result = self.set_submission_uuid(submission_id, uuid).await;
+                            let _ = res_tx.send(result);
+                        },
+                        Command::GetClientPlayerCount { server_id, res_tx } => {
+                            let result = self.get_client_player_count(server_id).await;
+                            let _ = res_tx.send(result);
+                        },
+                        Command::SelfHostedServerId { slug, res_tx } => {
+                            let result = self.self_hosted_server_id(&slug).await;
+                            let _ = res_tx.send(result);
+                        },
+                        Command::SavePendingDeliveries { deliveries, res_tx } => {
+                            let result = self.save_pending_deliveries(deliveries).await;
+                            let _ = res_tx.send(result);
+                        },
+                        Command::TakePendingDeliveries { res_tx } => {
+                            let result = self.take_pending_deliveries().await;
+                            let _ = res_tx.send(result);
+                        },
+                        Command::Ping { res_tx } => {
+                            let result = self.ping().await;
+                            let _ = res_tx.send(result);
+                        },
+                        Command::Shutdown { res_tx } => {
+                            self.pool.close().await;
+                            log::info!("数据库连接已关闭");
+                            let _ = res_tx.send(());
+                            return Ok(());
+                        },
+                        Command::GetDelivery { server_id, res_tx } => {
+                            let result = self.get_delivery(server_id).await;
+                            let _ = res_tx.send(result);
+                        },
+                        Command::SetDelivery { key, delivery, res_tx } => {
+                            let result = self.set_delivery(key, delivery).await;
+                            let _ = res_tx.send(result);
+                        },
+                        Command::AddWebhook { key, url, res_tx } => {
+                            let result = self.add_webhook(key, url).await;
+                            let _ = res_tx.send(result);
+                        },
+                        Command::GetWebhooks { server_id, res_tx } => {
+                            let result = self.get_webhooks(server_id).await;
+                            let _ = res_tx.send(result);
+                        },
+                        Command::DeleteWebhook { key, id, res_tx } => {
+                            let result = self.delete_webhook(key, id).await;
+                            let _ = res_tx.send(result);
+                        }
                     }
                 }
                 _ = interval.tick() => {
-                    // 这里可以添加定期执行的任务
+                    match self.prune_submissions().await {
+                        Ok(0) => {}
+                        Ok(count) => log::info!("已清理{}条超过保存期限的提交记录", count),
+                        Err(e) => log::error!("清理过期提交记录时出错: {:?}", e),
+                    }
                 }
             }
         }
@@ -220,37 +1829,266 @@ impl SqlServer {
 /// handler层
 #[derive(Debug, Clone)]
 pub struct SqlServerHandle {
-    cmd_tx: mpsc::UnboundedSender<Command>,
+    cmd_tx: CommandSender<Command>,
 }
 impl SqlServerHandle {
-    pub async fn execute(&self,sql_statement:SqlStatement) -> Result<String, Box<dyn Error + Send + Sync>> {
+    pub async fn get_client_id(&self, key: Key) -> Result<u32, Box<dyn Error + Send + Sync>> {
+        let (res_tx, res_rx) = oneshot::channel();
+        self.cmd_tx
+            .send(Command::GetClientID { key, res_tx })
+            .unwrap();
+        // unwrap: chat server does not drop out response channel
+        res_rx.await.unwrap()
+    }
+    pub async fn register_new_client(&self, name: String, email: String) -> Result<String, Box<dyn Error + Send + Sync>> {
         let (res_tx, res_rx) = oneshot::channel();
         self.cmd_tx
-            .send(Command::Execute { sql_statement, res_tx })
+            .send(Command::RegisterNewClient { name, email, res_tx })
             .unwrap();
         res_rx.await.unwrap()
     }
-    pub async fn get_client_id(&self, key: Key) -> Result<u32, Box<dyn Error + Send + Sync>> {
+    pub async fn list_servers(&self) -> Result<Vec<ServerOverview>, Box<dyn Error + Send + Sync>> {
         let (res_tx, res_rx) = oneshot::channel();
         self.cmd_tx
-            .send(Command::GetClientID { key, res_tx })
+            .send(Command::ListServers { res_tx })
+            .unwrap();
+        res_rx.await.unwrap()
+    }
+    pub async fn set_suspended(&self, server_id: ServerId, suspended: bool) -> Result<(), Box<dyn Error + Send + Sync>> {
+        let (res_tx, res_rx) = oneshot::channel();
+        self.cmd_tx
+            .send(Command::SetSuspended { server_id, suspended, res_tx })
+            .unwrap();
+        res_rx.await.unwrap()
+    }
+    pub async fn purge_server(&self, server_id: ServerId) -> Result<(), Box<dyn Error + Send + Sync>> {
+        let (res_tx, res_rx) = oneshot::channel();
+        self.cmd_tx
+            .send(Command::PurgeServer { server_id, res_tx })
+            .unwrap();
+        res_rx.await.unwrap()
+    }
+    pub async fn get_quotas(&self, server_id: ServerId) -> Result<Quotas, Box<dyn Error + Send + Sync>> {
+        let (res_tx, res_rx) = oneshot::channel();
+        self.cmd_tx
+            .send(Command::GetQuotas { server_id, res_tx })
+            .unwrap();
+        res_rx.await.unwrap()
+    }
+    pub async fn get_server_quotas(&self, server_id: ServerId) -> Result<Quotas, Box<dyn Error + Send + Sync>> {
+        let (res_tx, res_rx) = oneshot::channel();
+        self.cmd_tx
+            .send(Command::GetServerQuotas { server_id, res_tx })
+            .unwrap();
+        res_rx.await.unwrap()
+    }
+    pub async fn set_server_quotas(&self, server_id: ServerId, quotas: Quotas) -> Result<(), Box<dyn Error + Send + Sync>> {
+        let (res_tx, res_rx) = oneshot::channel();
+        self.cmd_tx
+            .send(Command::SetServerQuotas { server_id, quotas, res_tx })
+            .unwrap();
+        res_rx.await.unwrap()
+    }
+    pub async fn count_submissions_since(&self, server_id: ServerId, since: i64) -> Result<u32, Box<dyn Error + Send + Sync>> {
+        let (res_tx, res_rx) = oneshot::channel();
+        self.cmd_tx
+            .send(Command::CountSubmissionsSince { server_id, since, res_tx })
+            .unwrap();
+        res_rx.await.unwrap()
+    }
+    pub fn touch_client(&self, server_id: ServerId) {
+        self.cmd_tx
+            .send(Command::TouchClient { server_id })
+            .unwrap();
+    }
+    pub async fn list_servers_by_email(&self, email: String) -> Result<Vec<ClientInfo>, Box<dyn Error + Send + Sync>> {
+        let (res_tx, res_rx) = oneshot::channel();
+        self.cmd_tx
+            .send(Command::ListServersByEmail { email, res_tx })
+            .unwrap();
+        res_rx.await.unwrap()
+    }
+    pub async fn verify_key(&self, key: Key) -> Result<ClientKey, Box<dyn Error + Send + Sync>> {
+        let (res_tx, res_rx) = oneshot::channel();
+        self.cmd_tx
+            .send(Command::VerifyKey { key, res_tx })
+            .unwrap();
+        res_rx.await.unwrap()
+    }
+    pub async fn rotate_key(&self, server_id: ServerId, grace_period: u64) -> Result<(Key, i64), Box<dyn Error + Send + Sync>> {
+        let (res_tx, res_rx) = oneshot::channel();
+        self.cmd_tx
+            .send(Command::RotateKey { server_id, grace_period, res_tx })
+            .unwrap();
+        res_rx.await.unwrap()
+    }
+    pub async fn record_submission(&self, submission: NewSubmission) -> Result<i64, Box<dyn Error + Send + Sync>> {
+        let (res_tx, res_rx) = oneshot::channel();
+        self.cmd_tx
+            .send(Command::RecordSubmission { submission, res_tx })
+            .unwrap();
+        res_rx.await.unwrap()
+    }
+    pub async fn get_client_info(&self, key: Key) -> Result<ClientInfo, Box<dyn Error + Send + Sync>> {
+        let (res_tx, res_rx) = oneshot::channel();
+        self.cmd_tx
+            .send(Command::GetClientInfo { key, res_tx })
+            .unwrap();
+        res_rx.await.unwrap()
+    }
+    pub async fn get_client_info_by_id(&self, id: u32) -> Result<ClientInfo, Box<dyn Error + Send + Sync>> {
+        let (res_tx, res_rx) = oneshot::channel();
+        self.cmd_tx
+            .send(Command::GetClientInfoById { id, res_tx })
+            .unwrap();
+        res_rx.await.unwrap()
+    }
+    pub async fn list_submissions(&self, server_id: u32, filter: SubmissionFilter) -> Result<Vec<SubmissionRecord>, Box<dyn Error + Send + Sync>> {
+        let (res_tx, res_rx) = oneshot::channel();
+        self.cmd_tx
+            .send(Command::ListSubmissions { server_id, filter, res_tx })
+            .unwrap();
+        res_rx.await.unwrap()
+    }
+    pub async fn get_submission_stats(&self, server_id: u32) -> Result<SubmissionStats, Box<dyn Error + Send + Sync>> {
+        let (res_tx, res_rx) = oneshot::channel();
+        self.cmd_tx
+            .send(Command::GetSubmissionStats { server_id, res_tx })
+            .unwrap();
+        res_rx.await.unwrap()
+    }
+    pub async fn revoke_player(&self, server_id: u32, player_id: String) -> Result<Vec<String>, Box<dyn Error + Send + Sync>> {
+        let (res_tx, res_rx) = oneshot::channel();
+        self.cmd_tx
+            .send(Command::RevokePlayer { server_id, player_id, res_tx })
+            .unwrap();
+        res_rx.await.unwrap()
+    }
+    pub async fn get_daily_stats(&self, server_id: u32, days: u32) -> Result<Vec<DailyStats>, Box<dyn Error + Send + Sync>> {
+        let (res_tx, res_rx) = oneshot::channel();
+        self.cmd_tx
+            .send(Command::GetDailyStats { server_id, days, res_tx })
+            .unwrap();
+        res_rx.await.unwrap()
+    }
+    pub async fn get_settings(&self, server_id: u32) -> Result<ServerSettings, Box<dyn Error + Send + Sync>> {
+        let (res_tx, res_rx) = oneshot::channel();
+        self.cmd_tx
+            .send(Command::GetSettings { server_id, res_tx })
             .unwrap();
-        // unwrap: chat server does not drop out response channel
         res_rx.await.unwrap()
     }
-    pub async fn register_new_client(&self, name: String) -> Result<String, Box<dyn Error + Send + Sync>> {
+    pub async fn set_settings(&self, server_id: u32, settings: ServerSettings) -> Result<(), Box<dyn Error + Send + Sync>> {
         let (res_tx, res_rx) = oneshot::channel();
         self.cmd_tx
-            .send(Command::RegisterNewClient { name, res_tx })
+            .send(Command::SetSettings { server_id, settings, res_tx })
             .unwrap();
         res_rx.await.unwrap()
     }
-    pub async fn record_player_success_log(&self, client_id: u32, player_id: String, ip_address: String) -> Result<(), Box<dyn Error + Send + Sync>> {
+    pub async fn queue_approval(&self, server_id: u32, player_id: String, paper_id: String, score: i64) -> Result<(), Box<dyn Error + Send + Sync>> {
         self.cmd_tx
-            .send(Command::RecordSuccessLog { client_id, player_id, ip_address })
+            .send(Command::QueueApproval { server_id, player_id, paper_id, score })
             .unwrap();
         Ok(())
     }
+    pub async fn list_approvals(&self, server_id: u32) -> Result<Vec<ApprovalRecord>, Box<dyn Error + Send + Sync>> {
+        let (res_tx, res_rx) = oneshot::channel();
+        self.cmd_tx
+            .send(Command::ListApprovals { server_id, res_tx })
+            .unwrap();
+        res_rx.await.unwrap()
+    }
+    pub async fn remove_approval(&self, server_id: u32, player_id: String) -> Result<Vec<String>, Box<dyn Error + Send + Sync>> {
+        let (res_tx, res_rx) = oneshot::channel();
+        self.cmd_tx
+            .send(Command::RemoveApproval { server_id, player_id, res_tx })
+            .unwrap();
+        res_rx.await.unwrap()
+    }
+    pub async fn get_paper(&self, reference: String) -> Result<Paper, Box<dyn Error + Send + Sync>> {
+        let (res_tx, res_rx) = oneshot::channel();
+        self.cmd_tx
+            .send(Command::GetPaper { reference, res_tx })
+            .unwrap();
+        res_rx.await.unwrap()
+    }
+    pub async fn list_papers(&self, server_id: ServerId) -> Result<Vec<Paper>, Box<dyn Error + Send + Sync>> {
+        let (res_tx, res_rx) = oneshot::channel();
+        self.cmd_tx
+            .send(Command::ListPapers { server_id, res_tx })
+            .unwrap();
+        res_rx.await.unwrap()
+    }
+    pub async fn save_paper(&self, paper: NewPaper) -> Result<(u32, u32), Box<dyn Error + Send + Sync>> {
+        let (res_tx, res_rx) = oneshot::channel();
+        self.cmd_tx
+            .send(Command::SavePaper { paper, res_tx })
+            .unwrap();
+        res_rx.await.unwrap()
+    }
+    pub async fn create_paper(&self, paper: NewPaper) -> Result<(u32, u32), Box<dyn Error + Send + Sync>> {
+        let (res_tx, res_rx) = oneshot::channel();
+        self.cmd_tx
+            .send(Command::CreatePaper { paper, res_tx })
+            .unwrap();
+        res_rx.await.unwrap()
+    }
+    pub async fn update_paper(&self, update: PaperContentUpdate) -> Result<u32, Box<dyn Error + Send + Sync>> {
+        let (res_tx, res_rx) = oneshot::channel();
+        self.cmd_tx
+            .send(Command::UpdatePaper { update, res_tx })
+            .unwrap();
+        res_rx.await.unwrap()
+    }
+    pub async fn delete_paper(&self, paper_id: u32) -> Result<(), Box<dyn Error + Send + Sync>> {
+        let (res_tx, res_rx) = oneshot::channel();
+        self.cmd_tx
+            .send(Command::DeletePaper { paper_id, res_tx })
+            .unwrap();
+        res_rx.await.unwrap()
+    }
+    pub async fn list_paper_versions(&self, paper_id: u32) -> Result<Vec<PaperVersion>, Box<dyn Error + Send + Sync>> {
+        let (res_tx, res_rx) = oneshot::channel();
+        self.cmd_tx
+            .send(Command::ListPaperVersions { paper_id, res_tx })
+            .unwrap();
+        res_rx.await.unwrap()
+    }
+    pub async fn get_paper_version(&self, paper_id: u32, version: u32) -> Result<PaperVersion, Box<dyn Error + Send + Sync>> {
+        let (res_tx, res_rx) = oneshot::channel();
+        self.cmd_tx
+            .send(Command::GetPaperVersion { paper_id, version, res_tx })
+            .unwrap();
+        res_rx.await.unwrap()
+    }
+    pub async fn rollback_paper(&self, paper_id: u32, version: u32, uploaded_by: String, ip_address: String) -> Result<u32, Box<dyn Error + Send + Sync>> {
+        let (res_tx, res_rx) = oneshot::channel();
+        self.cmd_tx
+            .send(Command::RollbackPaper { paper_id, version, uploaded_by, ip_address, res_tx })
+            .unwrap();
+        res_rx.await.unwrap()
+    }
+    pub async fn list_submission_answers(&self, server_id: u32, paper_id: u32, version: u32) -> Result<Vec<SubmissionAnswers>, Box<dyn Error + Send + Sync>> {
+        let (res_tx, res_rx) = oneshot::channel();
+        self.cmd_tx
+            .send(Command::ListSubmissionAnswers { server_id, paper_id, version, res_tx })
+            .unwrap();
+        res_rx.await.unwrap()
+    }
+    pub async fn export_submissions(&self, server_id: u32, filter: SubmissionFilter, after_id: i64, limit: u32) -> Result<Vec<SubmissionRecord>, Box<dyn Error + Send + Sync>> {
+        let (res_tx, res_rx) = oneshot::channel();
+        self.cmd_tx
+            .send(Command::ExportSubmissions { server_id, filter, after_id, limit, res_tx })
+            .unwrap();
+        res_rx.await.unwrap()
+    }
+    pub async fn set_submission_uuid(&self, submission_id: i64, uuid: String) -> Result<(), Box<dyn Error + Send + Sync>> {
+        let (res_tx, res_rx) = oneshot::channel();
+        self.cmd_tx
+            .send(Command::SetSubmissionUuid { submission_id, uuid, res_tx })
+            .unwrap();
+        res_rx.await.unwrap()
+    }
     pub async fn get_client_player_count(&self, server_id: u32) -> Result<u32, Box<dyn Error + Send + Sync>> {
         let (res_tx, res_rx) = oneshot::channel();
         self.cmd_tx
@@ -258,4 +2096,424 @@ impl SqlServerHandle {
             .unwrap();
         res_rx.await.unwrap()
     }
-}
\ No newline at end of file
+    /// 自托管模式下试卷对应的客户端id
+    pub async fn self_hosted_server_id(&self, slug: String) -> Result<ServerId, Box<dyn Error + Send + Sync>> {
+        let (res_tx, res_rx) = oneshot::channel();
+        self.cmd_tx
+            .send(Command::SelfHostedServerId { slug, res_tx })
+            .unwrap();
+        res_rx.await.unwrap()
+    }
+    /// 保存尚未投递的消息，下次启动时由ws server取回
+    pub async fn save_pending_deliveries(&self, deliveries: PendingDeliveries) -> Result<(), Box<dyn Error + Send + Sync>> {
+        let (res_tx, res_rx) = oneshot::channel();
+        self.cmd_tx
+            .send(Command::SavePendingDeliveries { deliveries, res_tx })
+            .unwrap();
+        res_rx.await.unwrap()
+    }
+    pub async fn take_pending_deliveries(&self) -> Result<PendingDeliveries, Box<dyn Error + Send + Sync>> {
+        let (res_tx, res_rx) = oneshot::channel();
+        self.cmd_tx
+            .send(Command::TakePendingDeliveries { res_tx })
+            .unwrap();
+        res_rx.await.unwrap()
+    }
+    /// 检查数据库是否可用，sql server繁忙时需要等待之前的命令处理完毕
+    pub async fn ping(&self) -> Result<(), Box<dyn Error + Send + Sync>> {
+        let (res_tx, res_rx) = oneshot::channel();
+        self.cmd_tx
+            .send(Command::Ping { res_tx })
+            .unwrap();
+        res_rx.await.unwrap()
+    }
+    /// 等待之前发出的命令全部处理完毕后关闭数据库，之后不能再发送命令
+    pub async fn shutdown(&self) {
+        let (res_tx, res_rx) = oneshot::channel();
+        self.cmd_tx
+            .send(Command::Shutdown { res_tx })
+            .unwrap();
+        let _ = res_rx.await;
+    }
+    pub async fn get_delivery(&self, server_id: ServerId) -> Result<Delivery, Box<dyn Error + Send + Sync>> {
+        let (res_tx, res_rx) = oneshot::channel();
+        self.cmd_tx
+            .send(Command::GetDelivery { server_id, res_tx })
+            .unwrap();
+        res_rx.await.unwrap()
+    }
+    pub async fn set_delivery(&self, key: Key, delivery: Delivery) -> Result<(), Box<dyn Error + Send + Sync>> {
+        let (res_tx, res_rx) = oneshot::channel();
+        self.cmd_tx
+            .send(Command::SetDelivery { key, delivery, res_tx })
+            .unwrap();
+        res_rx.await.unwrap()
+    }
+    pub async fn add_webhook(&self, key: Key, url: String) -> Result<(u32, WebhookTarget), Box<dyn Error + Send + Sync>> {
+        let (res_tx, res_rx) = oneshot::channel();
+        self.cmd_tx
+            .send(Command::AddWebhook { key, url, res_tx })
+            .unwrap();
+        res_rx.await.unwrap()
+    }
+    pub async fn get_webhooks(&self, server_id: ServerId) -> Result<WebhookList, Box<dyn Error + Send + Sync>> {
+        let (res_tx, res_rx) = oneshot::channel();
+        self.cmd_tx
+            .send(Command::GetWebhooks { server_id, res_tx })
+            .unwrap();
+        res_rx.await.unwrap()
+    }
+    pub async fn delete_webhook(&self, key: Key, id: u32) -> Result<(), Box<dyn Error + Send + Sync>> {
+        let (res_tx, res_rx) = oneshot::channel();
+        self.cmd_tx
+            .send(Command::DeleteWebhook { key, id, res_tx })
+            .unwrap();
+        res_rx.await.unwrap()
+    }
+}
+#[cfg(test)]
+pub(crate) mod tests {
+    use super::*;
+    use crate::config::{self, Config, LocalPaper};
+    use std::env;
+
+    /// 使用临时数据库文件创建SqlServer，同时设置当前线程的配置
+    pub(crate) async fn test_server(name: &str, config: Config) -> (SqlServer, SqlServerHandle) {
+        let file = env::temp_dir().join(format!("quizgate-sql-{}-{}.db", name, std::process::id()));
+        let _ = std::fs::remove_file(&file);
+        // 不迁移tests_dir中的旧版试卷
+        let tests_dir = env::temp_dir().join("quizgate-sql-no-tests").to_string_lossy().to_string();
+        config::init_for_test(Config { tests_dir, ..config });
+        SqlServer::new(file.to_string_lossy().to_string()).await.unwrap()
+    }
+
+    #[tokio::test]
+    async fn keys_are_stored_as_salted_hashes() {
+        let (mut server, _handle) = test_server("salted", Config::default()).await;
+        let first = server.register_new_client("a".to_string(), "a@example.com".to_string()).await.unwrap();
+        let second = server.register_new_client("b".to_string(), "b@example.com".to_string()).await.unwrap();
+
+        let rows = sqlx::query_as::<_, (String, String, String)>("SELECT prefix, salt, hash FROM client_keys ORDER BY server_id")
+            .fetch_all(&server.pool)
+            .await
+            .unwrap();
+        assert_eq!(rows.len(), 2);
+        for ((prefix, salt, hash), key) in rows.iter().zip([&first, &second]) {
+            assert_eq!(prefix, key_prefix(key));
+            assert!(!hash.contains(key.as_str()));
+            assert_eq!(hash, &hmac_sha256_hex(salt, key));
+        }
+        // 每个密钥使用独立的盐
+        assert_ne!(rows[0].1, rows[1].1);
+        let legacy = sqlx::query_as::<_, (String,)>("SELECT key FROM server_info WHERE key != ''")
+            .fetch_all(&server.pool)
+            .await
+            .unwrap();
+        assert!(legacy.is_empty());
+    }
+
+    #[tokio::test]
+    async fn verify_key_accepts_only_matching_key() {
+        let (mut server, _handle) = test_server("verify", Config::default()).await;
+        let key = server.register_new_client("a".to_string(), "a@example.com".to_string()).await.unwrap();
+
+        let client = server.verify_key(key.clone()).await.unwrap();
+        assert_eq!(client.name, "a");
+        assert_eq!(client.expires_at, None);
+        assert_eq!(server.get_client_id(key.clone()).await.unwrap(), client.server_id);
+
+        // 前缀相同但其余部分不同的密钥同样被拒绝
+        let forged = format!("{}{}", key_prefix(&key), "0".repeat(key.len() - 8));
+        assert!(server.verify_key(forged).await.unwrap_err().is::<NoSuchValueError>());
+        assert!(server.verify_key(String::new()).await.unwrap_err().is::<NoSuchValueError>());
+    }
+
+    #[tokio::test]
+    async fn self_hosted_papers_use_their_own_keys() {
+        let paper = |slug: &str, key: &str| LocalPaper { slug: slug.to_string(), file: format!("{}.json", slug), key: key.to_string(), rcon: None };
+        let config = Config {
+            self_hosted: true,
+            self_hosted_key: "main-key".to_string(),
+            papers: vec![paper("survival", "survival-key"), paper("creative", "creative-key")],
+            ..Config::default()
+        };
+        let (mut server, _handle) = test_server("self-hosted", config).await;
+
+        let main = server.verify_key("main-key".to_string()).await.unwrap();
+        assert_eq!((main.server_id, main.name.as_str()), (0, SELF_HOSTED_NAME));
+
+        let survival = server.verify_key("survival-key".to_string()).await.unwrap();
+        let creative = server.verify_key("creative-key".to_string()).await.unwrap();
+        assert_eq!(survival.name, "survival");
+        assert_eq!(creative.name, "creative");
+        assert_ne!(survival.server_id, 0);
+        assert_ne!(survival.server_id, creative.server_id);
+        // 每张试卷的客户端id固定，与按slug查询的结果一致
+        assert_eq!(server.self_hosted_server_id("survival").await.unwrap(), survival.server_id);
+        assert_eq!(server.verify_key("survival-key".to_string()).await.unwrap().server_id, survival.server_id);
+
+        assert!(server.verify_key("other-key".to_string()).await.unwrap_err().is::<NoSuchValueError>());
+        assert!(server.verify_key(String::new()).await.unwrap_err().is::<NoSuchValueError>());
+    }
+
+    #[tokio::test]
+    async fn rotated_key_only_connects_during_grace_period() {
+        let (mut server, _handle) = test_server("grace", Config::default()).await;
+        let old = server.register_new_client("a".to_string(), "a@example.com".to_string()).await.unwrap();
+        let server_id = server.get_client_id(old.clone()).await.unwrap();
+
+        let (new, expires_at) = server.rotate_key(server_id, 3600).await.unwrap();
+        assert_ne!(new, old);
+        // 宽限期内旧密钥仍可用于客户端连接
+        assert_eq!(server.verify_key(old.clone()).await.unwrap().expires_at, Some(expires_at));
+        // 但不能签发令牌或再次轮换密钥
+        assert!(server.get_client_info(old.clone()).await.unwrap_err().is::<NoSuchValueError>());
+        assert!(server.get_client_id(old.clone()).await.unwrap_err().is::<NoSuchValueError>());
+        assert_eq!(server.get_client_info(new.clone()).await.unwrap().id, server_id);
+    }
+
+    #[tokio::test]
+    async fn rotated_key_expires_after_grace_period() {
+        let (mut server, _handle) = test_server("expiry", Config::default()).await;
+        let old = server.register_new_client("a".to_string(), "a@example.com".to_string()).await.unwrap();
+        let server_id = server.get_client_id(old.clone()).await.unwrap();
+
+        let (new, _) = server.rotate_key(server_id, 0).await.unwrap();
+        assert!(server.verify_key(old.clone()).await.unwrap_err().is::<NoSuchValueError>());
+        assert_eq!(server.verify_key(new.clone()).await.unwrap().server_id, server_id);
+
+        // 再次轮换时清理已失效的密钥，只保留新旧两个
+        let (newest, _) = server.rotate_key(server_id, 3600).await.unwrap();
+        let count = sqlx::query_as::<_, (i64,)>("SELECT COUNT(*) FROM client_keys WHERE server_id = ?")
+            .bind(server_id)
+            .fetch_one(&server.pool)
+            .await
+            .unwrap()
+            .0;
+        assert_eq!(count, 2);
+        assert!(server.verify_key(new).await.is_ok());
+        assert!(server.verify_key(newest).await.is_ok());
+    }
+
+    const PAPER: &str = r#"{"main_title":"A","score":1,"pass":1,"questions":[{"type":"radio","content":"q","options":["a","b"],"correct":1,"score":1}]}"#;
+
+    fn new_paper(server_id: ServerId, slug: &str) -> NewPaper {
+        NewPaper {
+            server_id,
+            slug: Some(slug.to_string()),
+            title: "A".to_string(),
+            target: Some("vip".to_string()),
+            content: PAPER.to_string(),
+            uploaded_by: "test".to_string(),
+            ip_address: "127.0.0.1".to_string(),
+        }
+    }
+
+    fn paper_update(paper_id: u32, base_version: Option<u32>, content: &str) -> PaperContentUpdate {
+        PaperContentUpdate {
+            paper_id,
+            base_version,
+            slug: None,
+            target: None,
+            content: content.to_string(),
+            uploaded_by: "test".to_string(),
+            ip_address: "127.0.0.1".to_string(),
+        }
+    }
+
+    #[tokio::test]
+    async fn update_paper_keeps_omitted_meta() {
+        let (mut server, _handle) = test_server("paper-meta", Config::default()).await;
+        let (id, _) = server.create_paper(new_paper(1, "survival")).await.unwrap();
+
+        // 内容未变化且未提供slug和target时不生成新版本，也不清除已有的值
+        assert_eq!(server.update_paper(paper_update(id, None, PAPER)).await.unwrap(), 1);
+        let paper = server.get_paper(id.to_string()).await.unwrap();
+        assert_eq!((paper.slug.as_deref(), paper.target.as_deref()), (Some("survival"), Some("vip")));
+
+        let update = PaperContentUpdate { slug: Some("creative".to_string()), ..paper_update(id, Some(1), PAPER) };
+        assert_eq!(server.update_paper(update).await.unwrap(), 1);
+        let update = PaperContentUpdate { target: Some(String::new()), ..paper_update(id, Some(1), &PAPER.replace("\"A\"", "\"B\"")) };
+        assert_eq!(server.update_paper(update).await.unwrap(), 2);
+        let paper = server.get_paper(id.to_string()).await.unwrap();
+        assert_eq!((paper.slug.as_deref(), paper.target), (Some("creative"), None));
+    }
+
+    #[tokio::test]
+    async fn update_paper_checks_version_before_meta() {
+        let (mut server, _handle) = test_server("paper-conflict", Config::default()).await;
+        let (id, _) = server.create_paper(new_paper(1, "survival")).await.unwrap();
+        server.create_paper(new_paper(1, "taken")).await.unwrap();
+        server.update_paper(paper_update(id, Some(1), &PAPER.replace("\"A\"", "\"B\""))).await.unwrap();
+
+        // 基于旧版本的修改被拒绝时，slug和target也不能被修改
+        let update = PaperContentUpdate { slug: Some("stale".to_string()), target: Some(String::new()), ..paper_update(id, Some(1), PAPER) };
+        assert!(server.update_paper(update).await.unwrap_err().is::<VersionConflictError>());
+        // slug冲突时内容同样不会被保存
+        let update = PaperContentUpdate { slug: Some("taken".to_string()), ..paper_update(id, Some(2), PAPER) };
+        assert!(server.update_paper(update).await.unwrap_err().is::<SlugConflictError>());
+        let paper = server.get_paper(id.to_string()).await.unwrap();
+        assert_eq!((paper.slug.as_deref(), paper.target.as_deref(), paper.version), (Some("survival"), Some("vip"), 2));
+    }
+
+    fn submission(server_id: ServerId, paper_id: &str, player_id: &str, pass: bool) -> NewSubmission {
+        NewSubmission {
+            server_id,
+            paper_id: paper_id.to_string(),
+            paper_version: Some(1),
+            player_id: player_id.to_string(),
+            score: if pass { 100 } else { 0 },
+            pass,
+            ip_address: "127.0.0.1".to_string(),
+            answers: "{}".to_string(),
+            durations: None,
+        }
+    }
+
+    #[tokio::test]
+    async fn revoke_player_returns_revoked_papers() {
+        let (mut server, _handle) = test_server("revoke", Config::default()).await;
+        server.record_submission(submission(1, "survival", "Steve", true)).await.unwrap();
+        server.record_submission(submission(1, "creative", "Steve", true)).await.unwrap();
+        server.record_submission(submission(1, "hardcore", "Steve", false)).await.unwrap();
+        server.record_submission(submission(1, "survival", "Alex", true)).await.unwrap();
+        server.record_submission(submission(2, "other", "Steve", true)).await.unwrap();
+
+        let mut paper_ids = server.revoke_player(1, "Steve".to_string()).await.unwrap();
+        paper_ids.sort();
+        assert_eq!(paper_ids, ["creative", "survival"]);
+        // 已撤销的记录不会再次返回
+        assert!(server.revoke_player(1, "Steve".to_string()).await.unwrap().is_empty());
+        assert_eq!(server.revoke_player(2, "Steve".to_string()).await.unwrap(), ["other"]);
+    }
+
+    #[tokio::test]
+    async fn rollback_appends_new_version() {
+        let (mut server, _handle) = test_server("rollback", Config::default()).await;
+        let (id, _) = server.create_paper(new_paper(1, "survival")).await.unwrap();
+        let changed = PAPER.replace("\"A\"", "\"B\"");
+        assert_eq!(server.update_paper(paper_update(id, Some(1), &changed)).await.unwrap(), 2);
+
+        // 回滚不删除历史，而是以旧版本的内容追加新版本
+        assert_eq!(server.rollback_paper(id, 1, "token".to_string(), "127.0.0.1".to_string()).await.unwrap(), 3);
+        let paper = server.get_paper(id.to_string()).await.unwrap();
+        assert_eq!((paper.version, paper.content.as_str()), (3, PAPER));
+        let versions = server.list_paper_versions(id).await.unwrap();
+        let summary: Vec<_> = versions.iter().map(|version| (version.version, version.source_version)).collect();
+        assert_eq!(summary, [(3, Some(1)), (2, None), (1, None)]);
+        assert_eq!(versions[0].uploaded_by, "token");
+        assert_eq!(versions[1].content, changed);
+
+        assert!(server.rollback_paper(id, 9, "token".to_string(), "127.0.0.1".to_string()).await.unwrap_err().is::<NoSuchValueError>());
+        assert_eq!(server.get_paper(id.to_string()).await.unwrap().version, 3);
+    }
+
+    #[tokio::test]
+    async fn dashboard_stats_count_passes_and_revocations() {
+        let (mut server, _handle) = test_server("stats", Config::default()).await;
+        server.record_submission(submission(1, "survival", "Steve", true)).await.unwrap();
+        server.record_submission(submission(1, "survival", "Alex", true)).await.unwrap();
+        server.record_submission(submission(1, "survival", "Herobrine", false)).await.unwrap();
+        server.record_submission(submission(2, "other", "Steve", true)).await.unwrap();
+        server.revoke_player(1, "Alex".to_string()).await.unwrap();
+
+        let stats = server.get_submission_stats(1).await.unwrap();
+        assert_eq!((stats.submissions, stats.passes, stats.revoked), (3, 2, 1));
+        assert_eq!(server.get_client_player_count(1).await.unwrap(), 1);
+        let stats = server.get_submission_stats(3).await.unwrap();
+        assert_eq!((stats.submissions, stats.passes, stats.revoked), (0, 0, 0));
+    }
+
+    #[tokio::test]
+    async fn daily_stats_group_by_utc_day() {
+        let (mut server, _handle) = test_server("daily", Config::default()).await;
+        let today = unix_timestamp() as i64 / 86400 * 86400;
+        for (player_id, pass, created_at) in [
+            ("a", true, today + 1),
+            ("b", false, today),
+            ("c", true, today - 1),
+            ("d", true, today - 86400 * 10),
+        ] {
+            let id = server.record_submission(submission(1, "survival", player_id, pass)).await.unwrap();
+            sqlx::query("UPDATE submissions SET created_at = ? WHERE id = ?").bind(created_at).bind(id).execute(&server.pool).await.unwrap();
+        }
+
+        let stats = server.get_daily_stats(1, 2).await.unwrap();
+        let days: Vec<_> = stats.iter().map(|day| (day.day, day.submissions, day.passes)).collect();
+        assert_eq!(days, [(today - 86400, 1, 1), (today, 2, 1)]);
+        // 超出天数的记录不统计
+        assert_eq!(server.get_daily_stats(1, 30).await.unwrap().len(), 3);
+        assert!(server.get_daily_stats(2, 30).await.unwrap().is_empty());
+    }
+
+    #[tokio::test]
+    async fn approval_queue_merges_and_removes_players() {
+        let (mut server, _handle) = test_server("approvals", Config::default()).await;
+        server.queue_approval(1, "Steve".to_string(), "survival".to_string(), 60).await.unwrap();
+        server.queue_approval(1, "Steve".to_string(), "survival".to_string(), 80).await.unwrap();
+        server.queue_approval(1, "Steve".to_string(), "creative".to_string(), 70).await.unwrap();
+        server.queue_approval(1, "Alex".to_string(), "survival".to_string(), 90).await.unwrap();
+        server.queue_approval(2, "Steve".to_string(), "other".to_string(), 90).await.unwrap();
+
+        // 同一玩家的同一试卷只保留一条，分数取最高的一次
+        let mut queue: Vec<_> = server
+            .list_approvals(1)
+            .await
+            .unwrap()
+            .into_iter()
+            .map(|record| (record.player_id, record.paper_id, record.score))
+            .collect();
+        queue.sort();
+        assert_eq!(queue, [
+            ("Alex".to_string(), "survival".to_string(), 90),
+            ("Steve".to_string(), "creative".to_string(), 70),
+            ("Steve".to_string(), "survival".to_string(), 80),
+        ]);
+
+        assert_eq!(server.remove_approval(1, "Steve".to_string()).await.unwrap(), ["creative", "survival"]);
+        assert!(server.remove_approval(1, "Steve".to_string()).await.unwrap().is_empty());
+        assert_eq!(server.list_approvals(1).await.unwrap().len(), 1);
+        assert_eq!(server.list_approvals(2).await.unwrap().len(), 1);
+    }
+
+    #[tokio::test]
+    async fn prune_uses_server_or_default_retention() {
+        let config = Config { quotas: Quotas { retention_days: Some(30), ..Quotas::default() }, ..Config::default() };
+        let (mut server, _handle) = test_server("prune", config).await;
+        let mut ids = Vec::new();
+        for name in ["default", "short", "unset"] {
+            let key = server.register_new_client(name.to_string(), format!("{}@example.com", name)).await.unwrap();
+            ids.push(server.get_client_id(key).await.unwrap());
+        }
+        server.set_server_quotas(ids[1], Quotas { retention_days: Some(7), ..Quotas::default() }).await.unwrap();
+        // 单独设置了其他配额但没有设置保存天数时使用默认值
+        server.set_server_quotas(ids[2], Quotas { max_papers: Some(1), ..Quotas::default() }).await.unwrap();
+
+        let now = unix_timestamp() as i64;
+        for &server_id in &ids {
+            for (player_id, days) in [("a", 1), ("b", 8), ("c", 31)] {
+                let id = server.record_submission(submission(server_id, "survival", player_id, true)).await.unwrap();
+                // 留出余量，避免测试期间时间变化影响结果
+                sqlx::query("UPDATE submissions SET created_at = ? WHERE id = ?")
+                    .bind(now - days * 86400 - 60)
+                    .bind(id)
+                    .execute(&server.pool)
+                    .await
+                    .unwrap();
+            }
+        }
+
+        assert_eq!(server.prune_submissions().await.unwrap(), 4);
+        assert_eq!(server.get_submission_stats(ids[0]).await.unwrap().submissions, 2);
+        assert_eq!(server.get_submission_stats(ids[1]).await.unwrap().submissions, 1);
+        assert_eq!(server.get_submission_stats(ids[2]).await.unwrap().submissions, 2);
+        assert_eq!(server.prune_submissions().await.unwrap(), 0);
+
+        // 没有默认保存天数时只清理单独设置了的服务器
+        config::init_for_test(Config { quotas: Quotas::default(), tests_dir: CONFIG.tests_dir.clone(), ..Config::default() });
+        server.set_server_quotas(ids[1], Quotas { retention_days: Some(0), ..Quotas::default() }).await.unwrap();
+        assert_eq!(server.prune_submissions().await.unwrap(), 1);
+        assert_eq!(server.get_submission_stats(ids[0]).await.unwrap().submissions, 2);
+        assert_eq!(server.get_submission_stats(ids[2]).await.unwrap().submissions, 2);
+    }
+}
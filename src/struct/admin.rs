@@ -0,0 +1,222 @@
+use crate::r#struct::delivery::PlayerAction;
+use crate::r#struct::awl_type::ServerId;
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+
+/// 客户端的在线状态及投递队列
+#[derive(Serialize, Debug)]
+pub struct SessionStatus {
+    pub online: bool,
+    /// 在线客户端是否会确认投递
+    pub ack: bool,
+    pub delivery: &'static str,
+    /// 等待投递的操作
+    pub pending: Vec<PlayerAction>,
+    /// 已发送但尚未确认的操作
+    pub inflight: Vec<PlayerAction>,
+}
+
+/// 密钥验证通过后得到的客户端信息
+#[derive(Debug, Clone)]
+pub struct ClientKey {
+    pub server_id: ServerId,
+    pub name: String,
+    /// 已被轮换的旧密钥在宽限期结束后失效
+    pub expires_at: Option<i64>,
+    /// 服务器已被运营者停用
+    pub suspended: bool,
+}
+
+// 轮换密钥的请求，grace_period为旧密钥继续有效的秒数
+#[derive(Deserialize, Debug)]
+pub struct RotateKeyRequest {
+    pub grace_period: Option<u64>,
+}
+
+/// 客户端的基本信息
+#[derive(Debug, Clone)]
+pub struct ClientInfo {
+    pub id: ServerId,
+    pub name: String,
+    /// 签发管理令牌的密钥，与当前密钥一同生成，轮换密钥后旧令牌随之失效
+    pub token_secret: String,
+    /// 服务器已被运营者停用，只能查询数据
+    pub suspended: bool,
+}
+
+/// 运营者查看的服务器概况，时间均为unix时间戳，旧版本注册的服务器没有邮箱和注册时间
+#[derive(Serialize, sqlx::FromRow, Debug)]
+pub struct ServerOverview {
+    pub id: ServerId,
+    pub name: String,
+    pub email: Option<String>,
+    pub created_at: Option<i64>,
+    /// 客户端最后一次连接或断开的时间
+    pub last_seen: Option<i64>,
+    pub suspended: bool,
+    pub papers: i64,
+    pub submissions: i64,
+    pub passes: i64,
+    #[sqlx(skip)]
+    pub online: bool,
+}
+
+/// 一次提交的记录
+#[derive(Debug, Clone)]
+pub struct NewSubmission {
+    pub server_id: u32,
+    pub paper_id: String,
+    /// 评分时使用的试卷版本，自托管模式下为空
+    pub paper_version: Option<u32>,
+    pub player_id: String,
+    pub score: i64,
+    pub pass: bool,
+    pub ip_address: String,
+    /// json格式的答案
+    pub answers: String,
+    /// json格式的每题作答时间，没有计时数据时为空
+    pub durations: Option<String>,
+}
+
+#[derive(Serialize, sqlx::FromRow, Debug)]
+pub struct SubmissionRecord {
+    pub id: i64,
+    pub paper_id: String,
+    pub paper_version: Option<u32>,
+    pub player_id: String,
+    /// 玩家通过后向Mojang查询到的UUID，离线玩家或查询失败时为空
+    pub player_uuid: Option<String>,
+    pub score: i64,
+    pub pass: bool,
+    pub revoked: bool,
+    pub ip_address: String,
+    pub created_at: i64,
+}
+
+/// 统计题目时读取的答案及总分
+#[derive(sqlx::FromRow, Debug)]
+pub struct SubmissionAnswers {
+    pub answers: String,
+    pub score: i64,
+    pub durations: Option<String>,
+}
+
+// 查询提交记录时的过滤条件，时间均为unix时间戳
+#[derive(Deserialize, Debug, Default, Clone)]
+pub struct SubmissionFilter {
+    pub paper_id: Option<String>,
+    pub player_id: Option<String>,
+    pub pass: Option<bool>,
+    pub since: Option<i64>,
+    pub until: Option<i64>,
+    pub limit: Option<u32>,
+    pub offset: Option<u32>,
+}
+
+/// 导出提交记录的参数，paper为试卷id或slug，format为csv（默认）或ndjson
+#[derive(Deserialize, Debug)]
+pub struct ExportQuery {
+    pub format: Option<String>,
+    pub paper: Option<String>,
+    pub player_id: Option<String>,
+    pub since: Option<i64>,
+    pub until: Option<i64>,
+}
+
+#[derive(Serialize, Debug)]
+pub struct SubmissionStats {
+    pub submissions: u32,
+    pub passes: u32,
+    pub revoked: u32,
+}
+
+/// 每日的提交及通过数量
+#[derive(Serialize, sqlx::FromRow, Debug)]
+pub struct DailyStats {
+    /// 当天零点(UTC)的unix时间戳
+    pub day: i64,
+    pub submissions: u32,
+    pub passes: u32,
+}
+
+// 查询每日统计时的参数
+#[derive(Deserialize, Debug)]
+pub struct DailyStatsQuery {
+    pub days: Option<u32>,
+}
+
+/// 等待服务器所有者审核的玩家
+#[derive(Serialize, sqlx::FromRow, Debug)]
+pub struct ApprovalRecord {
+    pub player_id: String,
+    pub paper_id: String,
+    pub score: i64,
+    pub created_at: i64,
+}
+
+/// 服务器的设置
+#[derive(Serialize, Deserialize, Debug, Clone, Default)]
+pub struct ServerSettings {
+    /// 通过考试的玩家需要审核后才会投递
+    pub require_approval: bool,
+}
+
+// 撤销、批准或重新投递玩家的请求
+#[derive(Deserialize, Debug)]
+pub struct PlayerRequest {
+    pub(crate) player_id: String,
+}
+
+// 查询试卷时的参数，id可以是试卷id或slug
+#[derive(Deserialize, Debug)]
+pub struct PaperQuery {
+    pub id: Option<String>,
+    /// 查询指定的历史版本，默认为当前版本
+    pub version: Option<u32>,
+}
+
+// 比较试卷的两个版本，to默认为当前版本
+#[derive(Deserialize, Debug)]
+pub struct PaperDiffQuery {
+    pub id: String,
+    pub from: u32,
+    pub to: Option<u32>,
+}
+
+// 将试卷回滚到指定版本
+#[derive(Deserialize, Debug)]
+pub struct RollbackRequest {
+    pub id: String,
+    pub version: u32,
+}
+
+// 新建或修改试卷的请求体，content为问卷json
+#[derive(Deserialize, Debug)]
+pub struct PaperBody {
+    pub slug: Option<String>,
+    pub target: Option<String>,
+    pub content: Value,
+    /// 修改时所基于的版本，试卷已被其他人修改时拒绝保存
+    pub base_version: Option<u32>,
+}
+
+// 新增或修改题目，新增时index为插入的位置，默认添加到末尾
+#[derive(Deserialize, Debug)]
+pub struct QuestionBody {
+    pub question: Value,
+    pub index: Option<usize>,
+    pub base_version: Option<u32>,
+}
+
+// 将题目移动到新的位置
+#[derive(Deserialize, Debug)]
+pub struct MoveQuestionRequest {
+    pub to: usize,
+    pub base_version: Option<u32>,
+}
+
+// 删除题目时通过查询参数指定所基于的版本
+#[derive(Deserialize, Debug)]
+pub struct BaseVersionQuery {
+    pub base_version: Option<u32>,
+}
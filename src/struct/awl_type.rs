@@ -2,6 +2,8 @@
 pub type ConnId = u32;
 // 客户端发送的验证密钥
 pub type Key = String;
+// 客户端在数据库中的id，自托管模式下固定为0
+pub type ServerId = u32;
 // 通过考试的玩家ID
 pub type PlayerId = String;
 // 准备连接的db文件
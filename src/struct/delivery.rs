@@ -0,0 +1,72 @@
+use crate::rcon::RconTarget;
+use crate::r#struct::awl_type::{Key, PlayerId, ServerId};
+use serde::{Deserialize, Serialize};
+
+/// 客户端的投递方式
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Delivery {
+    /// 通过已连接的插件投递，默认方式
+    WebSocket,
+    /// 直接连接服务器的RCON端口执行命令
+    Rcon(RconTarget),
+}
+
+// 修改投递方式的请求
+#[derive(Deserialize, Debug)]
+pub struct DeliveryRequest {
+    pub(crate) client_key: Key,
+    /// websocket或rcon
+    pub(crate) mode: String,
+    pub(crate) rcon: Option<RconTarget>,
+}
+
+/// 投递给客户端的操作
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq, Eq)]
+#[serde(tag = "action", rename_all = "lowercase")]
+pub enum PlayerAction {
+    /// 加入白名单，target为试卷指定的投递目标
+    Add {
+        player_id: PlayerId,
+        #[serde(skip_serializing_if = "Option::is_none")]
+        target: Option<String>,
+        /// 产生该操作的HTTP请求id，用于在日志中追踪投递过程
+        #[serde(default, skip_serializing_if = "Option::is_none")]
+        request_id: Option<String>,
+    },
+    /// 移出白名单
+    Remove {
+        player_id: PlayerId,
+        #[serde(default, skip_serializing_if = "Option::is_none")]
+        request_id: Option<String>,
+    },
+}
+
+impl PlayerAction {
+    pub fn player_id(&self) -> &PlayerId {
+        match self {
+            PlayerAction::Add { player_id, .. } | PlayerAction::Remove { player_id, .. } => player_id,
+        }
+    }
+
+    /// 产生该操作的请求id，旧版本保存的操作没有id，此时返回空字符串
+    pub fn request_id(&self) -> &str {
+        match self {
+            PlayerAction::Add { request_id, .. } | PlayerAction::Remove { request_id, .. } => request_id.as_deref().unwrap_or_default(),
+        }
+    }
+}
+
+/// 单次WebSocket投递的编号，用于匹配客户端的确认
+pub type DeliveryId = u64;
+
+/// 关闭时尚未投递的消息及其所属客户端，按投递顺序排列
+pub type PendingDeliveries = Vec<(ServerId, PlayerAction)>;
+
+/// ws server发送给单个链接的消息
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum SessionMessage {
+    /// id由ws server分配，客户端确认时原样返回
+    Deliver { id: DeliveryId, action: PlayerAction },
+    /// 服务端即将关闭，通知客户端在reconnect_in秒后重连
+    GoingAway { reconnect_in: u64 },
+}
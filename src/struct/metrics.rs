@@ -0,0 +1,21 @@
+use crate::r#struct::awl_type::ServerId;
+use std::collections::BTreeMap;
+
+/// ws server当前的链接及队列情况
+#[derive(Debug, Default)]
+pub struct SessionMetrics {
+    /// 所有websocket链接，包括尚未验证的
+    pub connections: usize,
+    /// 已通过密钥验证的链接
+    pub verified: usize,
+    /// 有消息积压的客户端
+    pub queues: BTreeMap<ServerId, QueueDepth>,
+}
+
+#[derive(Debug, Default)]
+pub struct QueueDepth {
+    /// 等待投递的消息数
+    pub pending: usize,
+    /// 已发送但尚未确认的消息数
+    pub inflight: usize,
+}
@@ -1,4 +1,8 @@
-pub mod respond;
 pub mod submit;
-pub mod request;
 pub mod awl_type;
+pub mod delivery;
+pub mod webhook;
+pub mod admin;
+pub mod paper;
+pub mod quota;
+pub mod metrics;
@@ -0,0 +1,225 @@
+use crate::r#struct::awl_type::ServerId;
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+
+/// 客户端上传的试卷
+#[derive(Serialize, sqlx::FromRow, Debug, Clone)]
+pub struct Paper {
+    pub id: u32,
+    pub server_id: ServerId,
+    /// 可选的短名称，可以代替id出现在问卷链接中
+    pub slug: Option<String>,
+    pub title: String,
+    /// 投递目标，客户端或RCON按该名称选择执行的命令，为空时使用默认命令
+    pub target: Option<String>,
+    /// 当前生效的版本号
+    pub version: u32,
+    #[serde(skip)]
+    pub content: String,
+    pub created_at: i64,
+    pub updated_at: i64,
+}
+
+/// 试卷的一个历史版本，保存后不再修改
+#[derive(Serialize, sqlx::FromRow, Debug, Clone)]
+pub struct PaperVersion {
+    pub paper_id: u32,
+    pub version: u32,
+    /// 上传者，使用密钥上传时为`key:密钥前缀`，使用管理令牌时为`token`
+    pub uploaded_by: String,
+    pub ip_address: String,
+    /// 由回滚产生的版本记录回滚到的版本号
+    pub source_version: Option<u32>,
+    #[serde(skip)]
+    pub content: String,
+    pub created_at: i64,
+}
+
+/// 新建或覆盖试卷时的内容
+#[derive(Debug, Clone)]
+pub struct NewPaper {
+    pub server_id: ServerId,
+    pub slug: Option<String>,
+    pub title: String,
+    pub target: Option<String>,
+    pub content: String,
+    pub uploaded_by: String,
+    pub ip_address: String,
+}
+
+/// 以新内容修改试卷，base_version为修改时所基于的版本，用于发现并发修改。
+/// slug和target为None时保持不变，为空字符串时清除
+#[derive(Debug, Clone)]
+pub struct PaperContentUpdate {
+    pub paper_id: u32,
+    pub base_version: Option<u32>,
+    pub slug: Option<String>,
+    pub target: Option<String>,
+    pub content: String,
+    pub uploaded_by: String,
+    pub ip_address: String,
+}
+
+/// 两个版本之间的一处差异，path为json pointer格式，新增或删除时对应一侧为空
+#[derive(Serialize, Debug, Clone, PartialEq)]
+pub struct PaperChange {
+    pub path: String,
+    pub old: Option<Value>,
+    pub new: Option<Value>,
+}
+
+/// 逐层比较两份试卷，返回所有发生变化的叶子节点
+pub fn diff_papers(old: &Value, new: &Value) -> Vec<PaperChange> {
+    let mut changes = Vec::new();
+    diff_value(String::new(), Some(old), Some(new), &mut changes);
+    changes
+}
+
+fn diff_value(path: String, old: Option<&Value>, new: Option<&Value>, changes: &mut Vec<PaperChange>) {
+    match (old, new) {
+        (Some(Value::Object(old)), Some(Value::Object(new))) => {
+            let mut keys: Vec<&String> = old.keys().chain(new.keys()).collect();
+            keys.sort();
+            keys.dedup();
+            for key in keys {
+                let escaped = key.replace('~', "~0").replace('/', "~1");
+                diff_value(format!("{}/{}", path, escaped), old.get(key), new.get(key), changes);
+            }
+        }
+        (Some(Value::Array(old)), Some(Value::Array(new))) => {
+            for index in 0..old.len().max(new.len()) {
+                diff_value(format!("{}/{}", path, index), old.get(index), new.get(index), changes);
+            }
+        }
+        (old, new) if old != new => changes.push(PaperChange {
+            path,
+            old: old.cloned(),
+            new: new.cloned(),
+        }),
+        _ => {}
+    }
+}
+
+/// 上传试卷时的参数，dry_run为true时只校验不保存
+#[derive(Deserialize, Debug)]
+pub struct UploadQuery {
+    #[serde(default)]
+    pub dry_run: bool,
+}
+
+/// 已被页面或接口占用的路径，不能作为slug
+const RESERVED_SLUGS: [&str; 13] = ["api", "ws", "upload", "register", "recover", "dashboard", "editor", "preview", "verify", "resources", "healthz", "readyz", "metrics"];
+
+/// slug只允许小写字母、数字和`-`，且不能是纯数字以免与试卷id混淆
+pub fn is_valid_slug(slug: &str) -> bool {
+    !slug.is_empty()
+        && slug.len() <= 32
+        && slug.chars().all(|c| c.is_ascii_lowercase() || c.is_ascii_digit() || c == '-')
+        && !slug.chars().all(|c| c.is_ascii_digit())
+        && !RESERVED_SLUGS.contains(&slug)
+}
+
+/// 投递目标会作为命令的名称出现在客户端配置中，只允许字母、数字、`-`和`_`
+pub fn is_valid_target(target: &str) -> bool {
+    !target.is_empty()
+        && target.len() <= 32
+        && target.chars().all(|c| c.is_ascii_alphanumeric() || c == '-' || c == '_')
+}
+
+/// 玩家看到的试卷内容，去掉及格线、答案和分值等不该出现的部分
+pub fn public_content(content: &Value) -> Value {
+    let mut content = content.clone();
+    if let Some(object) = content.as_object_mut() {
+        object.remove("pass");
+        object.remove("client_key");
+        object.remove("target");
+    }
+    if let Some(questions) = content["questions"].as_array_mut() {
+        for question in questions.iter_mut().filter_map(Value::as_object_mut) {
+            question.remove("correct");
+            question.remove("score");
+        }
+    }
+    content
+}
+
+/// 试卷的满分，多选题按全部正确时的分值计算
+pub fn total_score(content: &Value) -> i64 {
+    content["questions"]
+        .as_array()
+        .map(|questions| {
+            questions
+                .iter()
+                .map(|question| match question["type"].as_str() {
+                    Some("multiple") => question["score"][1].as_i64().unwrap_or_default(),
+                    _ => question["score"].as_i64().unwrap_or_default(),
+                })
+                .sum()
+        })
+        .unwrap_or_default()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    #[test]
+    fn slugs_do_not_shadow_routes() {
+        for slug in ["api", "ws", "upload", "register", "recover", "dashboard", "editor", "preview", "verify", "resources", "healthz", "readyz", "metrics"] {
+            assert!(!is_valid_slug(slug), "{}", slug);
+        }
+        assert!(!is_valid_slug("123"));
+        assert!(!is_valid_slug("Survival"));
+        assert!(!is_valid_slug(""));
+        assert!(is_valid_slug("survival-2"));
+        assert!(is_valid_slug("recovery"));
+        assert!(is_valid_slug(&"a".repeat(32)));
+        assert!(!is_valid_slug(&"a".repeat(33)));
+        assert!(!is_valid_slug("staff_quiz"));
+        assert!(!is_valid_slug("a/b"));
+    }
+
+    #[test]
+    fn targets_are_command_names() {
+        for target in ["vip", "VIP_2", "staff-app", &"a".repeat(32)] {
+            assert!(is_valid_target(target), "{}", target);
+        }
+        for target in ["", "a b", "vip;op", "{player}", "a.b", &"a".repeat(33)] {
+            assert!(!is_valid_target(target), "{}", target);
+        }
+    }
+
+    fn change(path: &str, old: Option<Value>, new: Option<Value>) -> PaperChange {
+        PaperChange { path: path.to_string(), old, new }
+    }
+
+    #[test]
+    fn diff_reports_changed_leaves() {
+        let old = json!({"main_title": "A", "questions": [{"content": "q1", "score": 1}, {"content": "q2", "score": 1}]});
+        assert!(diff_papers(&old, &old).is_empty());
+
+        let new = json!({"main_title": "B", "questions": [{"content": "q1", "score": 2}, {"content": "q2", "score": 1}]});
+        assert_eq!(diff_papers(&old, &new), [
+            change("/main_title", Some(json!("A")), Some(json!("B"))),
+            change("/questions/0/score", Some(json!(1)), Some(json!(2))),
+        ]);
+    }
+
+    #[test]
+    fn diff_reports_added_and_removed_questions() {
+        let old = json!({"questions": [{"content": "q1"}]});
+        let new = json!({"questions": [{"content": "q1"}, {"content": "q2"}]});
+        assert_eq!(diff_papers(&old, &new), [change("/questions/1", None, Some(json!({"content": "q2"})))]);
+        assert_eq!(diff_papers(&new, &old), [change("/questions/1", Some(json!({"content": "q2"})), None)]);
+
+        // 类型不同的节点整体替换，键名中的`/`和`~`按json pointer转义
+        let old = json!({"a/b": 1, "c~d": [1]});
+        let new = json!({"a/b": [1], "e": true});
+        assert_eq!(diff_papers(&old, &new), [
+            change("/a~1b", Some(json!(1)), Some(json!([1]))),
+            change("/c~0d", Some(json!([1])), None),
+            change("/e", None, Some(json!(true))),
+        ]);
+    }
+}
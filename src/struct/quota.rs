@@ -0,0 +1,85 @@
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+use crate::validate::Diagnostic;
+
+/// 服务器的配额，为空表示不限制。
+/// config.toml中的`[quotas]`为所有服务器的默认值，运营者可以为单个服务器单独设置
+#[derive(Serialize, Deserialize, sqlx::FromRow, Debug, Clone, Default, PartialEq, Eq)]
+pub struct Quotas {
+    /// 试卷数量
+    pub max_papers: Option<u32>,
+    /// 单张试卷转换为json后的字节数
+    pub max_paper_bytes: Option<u32>,
+    /// 单张试卷的题目数量
+    pub max_questions: Option<u32>,
+    /// 每天（UTC）的提交次数
+    pub max_submissions_per_day: Option<u32>,
+    /// 提交记录的保存天数，过期的记录会被定期清理
+    pub retention_days: Option<u32>,
+}
+
+impl Quotas {
+    /// 未单独设置的配额使用默认值
+    pub fn or(self, defaults: &Quotas) -> Quotas {
+        Quotas {
+            max_papers: self.max_papers.or(defaults.max_papers),
+            max_paper_bytes: self.max_paper_bytes.or(defaults.max_paper_bytes),
+            max_questions: self.max_questions.or(defaults.max_questions),
+            max_submissions_per_day: self.max_submissions_per_day.or(defaults.max_submissions_per_day),
+            retention_days: self.retention_days.or(defaults.retention_days),
+        }
+    }
+
+    /// 检查试卷的大小和题目数量
+    pub fn check_paper(&self, content: &Value) -> Vec<Diagnostic> {
+        let mut diagnostics = Vec::new();
+        if let Some(max) = self.max_paper_bytes {
+            let bytes = content.to_string().len();
+            if bytes > max as usize {
+                diagnostics.push(Diagnostic::new("", format!("试卷大小为{}字节，超过了配额{}字节", bytes, max)));
+            }
+        }
+        if let Some(max) = self.max_questions {
+            let count = content["questions"].as_array().map_or(0, Vec::len);
+            if count > max as usize {
+                diagnostics.push(Diagnostic::new("/questions", format!("试卷有{}道题目，超过了配额{}道", count, max)));
+            }
+        }
+        diagnostics
+    }
+
+    /// 已有count张试卷时能否再新建一张
+    pub fn check_new_paper(&self, count: usize) -> Option<Diagnostic> {
+        self.max_papers
+            .filter(|max| count >= *max as usize)
+            .map(|max| Diagnostic::new("", format!("试卷数量已达到配额{}张，请先删除不需要的试卷", max)))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn server_quotas_fall_back_to_defaults() {
+        let defaults = Quotas {
+            max_papers: Some(10),
+            max_paper_bytes: Some(1024),
+            max_questions: None,
+            max_submissions_per_day: Some(100),
+            retention_days: Some(365),
+        };
+        assert_eq!(Quotas::default().or(&defaults), defaults);
+
+        let server = Quotas { max_papers: Some(1), max_questions: Some(5), retention_days: Some(0), ..Quotas::default() };
+        assert_eq!(server.or(&defaults), Quotas {
+            max_papers: Some(1),
+            max_paper_bytes: Some(1024),
+            max_questions: Some(5),
+            max_submissions_per_day: Some(100),
+            retention_days: Some(0),
+        });
+        // 两边都没有设置时不限制
+        assert_eq!(Quotas::default().or(&Quotas::default()), Quotas::default());
+    }
+}
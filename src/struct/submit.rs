@@ -7,6 +7,9 @@ pub struct SubmitRequest {
     pub(crate) answer: Vec<Value>,
     pub(crate) player_id: String,
     pub(crate) paper_id: String,
+    /// 每道题的作答时间（秒），旧版页面不会提交
+    #[serde(default)]
+    pub(crate) durations: Option<Vec<f64>>,
 }
 
 #[derive(Serialize)]
@@ -14,6 +17,8 @@ pub struct SubmitResponse {
     pub(crate) score: i64,
     pub(crate) pass: bool,
     pub(crate) count: u32,
+    /// 通过考试但需要等待服务器所有者审核
+    pub(crate) pending_approval: bool,
 }
 
 #[derive(Deserialize,Debug)]
@@ -23,6 +28,17 @@ pub struct RegisterRequest {
     pub(crate) captcha_token: String,
 }
 
+#[derive(Deserialize, Debug)]
+pub struct RecoverRequest {
+    pub(crate) email: String,
+    pub(crate) captcha_token: String,
+}
+
+#[derive(Deserialize, Debug)]
+pub struct RecoverConfirmRequest {
+    pub(crate) token: String,
+}
+
 #[derive(Deserialize, Debug)]
 pub struct CaptchaResponse {
     pub(crate) success: bool,
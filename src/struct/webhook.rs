@@ -0,0 +1,53 @@
+use crate::r#struct::awl_type::Key;
+use serde::{Deserialize, Serialize};
+
+/// 接收事件的webhook地址及签名密钥
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct WebhookTarget {
+    pub url: String,
+    pub secret: String,
+}
+
+// webhook的id及目标
+pub type WebhookList = Vec<(u32, WebhookTarget)>;
+
+#[derive(Serialize, Debug, Clone, Copy, PartialEq, Eq)]
+#[serde(rename_all = "lowercase")]
+pub enum WebhookEventKind {
+    Pass,
+    Fail,
+    /// 玩家通过但需要等待服务器所有者审核，批准后再发送approved
+    Pending,
+    Revoked,
+    Approved,
+}
+
+impl WebhookEventKind {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            WebhookEventKind::Pass => "pass",
+            WebhookEventKind::Fail => "fail",
+            WebhookEventKind::Pending => "pending",
+            WebhookEventKind::Revoked => "revoked",
+            WebhookEventKind::Approved => "approved",
+        }
+    }
+}
+
+/// 发送给webhook的事件内容
+#[derive(Serialize, Debug, Clone)]
+pub struct WebhookEvent {
+    pub event: WebhookEventKind,
+    pub player_id: String,
+    pub paper_id: String,
+    pub score: Option<i64>,
+    pub timestamp: u64,
+}
+
+// 添加、列出或删除webhook的请求
+#[derive(Deserialize, Debug)]
+pub struct WebhookRequest {
+    pub(crate) client_key: Key,
+    pub(crate) url: Option<String>,
+    pub(crate) id: Option<u32>,
+}
@@ -1,19 +1,10 @@
+use hmac::{Hmac, Mac};
 use serde_json::Value;
-use std::fs::File;
-
-pub fn read_file(file_path: &String) -> actix_web::Result<File, std::io::Error> {
-    let file = match File::open(&file_path) {
-        Ok(file) => file,
-        Err(e) => {
-            return Err(e);
-        },
-    };
-    Ok(file)
-}
-
+use sha2::Sha256;
+use std::time::{SystemTime, UNIX_EPOCH};
 
 // 干得好，我要给你打易佰昏！
-pub fn mark(answer: &Vec<Value>, paper_info: &Value) -> i64 {
+pub fn mark(answer: &[Value], paper_info: &Value) -> i64 {
     let mut score: i64 = 0;
     let questions = paper_info["questions"].as_array().unwrap();
     for (i, question) in questions.iter().enumerate() {
@@ -27,11 +18,36 @@ pub fn mark(answer: &Vec<Value>, paper_info: &Value) -> i64 {
                 score += question["score"][0].as_i64().unwrap();
             }
             // 单选题
-        } else if question["type"] == "radio" {
-            if answer[i] == question["correct"] {
-                score += question["score"].as_i64().unwrap();
-            }
+        } else if question["type"] == "radio" && answer[i] == question["correct"] {
+            score += question["score"].as_i64().unwrap();
         }
     }
     score
-}
\ No newline at end of file
+}
+
+/// 使用HMAC-SHA256签名，返回十六进制字符串
+pub fn hmac_sha256_hex(secret: &str, message: &str) -> String {
+    let mut mac = Hmac::<Sha256>::new_from_slice(secret.as_bytes()).expect("HMAC可以接受任意长度的密钥");
+    mac.update(message.as_bytes());
+    hex::encode(mac.finalize().into_bytes())
+}
+
+/// 当前的unix时间戳
+pub fn unix_timestamp() -> u64 {
+    SystemTime::now().duration_since(UNIX_EPOCH).unwrap().as_secs()
+}
+
+/// 以常数时间校验HMAC-SHA256签名
+pub fn verify_hmac_sha256_hex(secret: &str, message: &str, signature: &str) -> bool {
+    let Ok(signature) = hex::decode(signature) else {
+        return false;
+    };
+    let mut mac = Hmac::<Sha256>::new_from_slice(secret.as_bytes()).expect("HMAC可以接受任意长度的密钥");
+    mac.update(message.as_bytes());
+    mac.verify_slice(&signature).is_ok()
+}
+
+/// 以常数时间比较两个字符串，用于校验配置中的密钥
+pub fn constant_time_eq(a: &str, b: &str) -> bool {
+    a.len() == b.len() && a.bytes().zip(b.bytes()).fold(0, |diff, (a, b)| diff | (a ^ b)) == 0
+}
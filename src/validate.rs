@@ -0,0 +1,245 @@
+use std::collections::HashSet;
+use serde::Serialize;
+use serde_json::Value;
+use crate::r#struct::paper::{is_valid_slug, is_valid_target, total_score};
+
+/// 上传的试卷文件的大小上限
+pub const MAX_PAPER_SIZE: usize = 1024 * 1024;
+
+/// 校验试卷时发现的问题，path为json pointer格式，指向出错的字段
+#[derive(Serialize, Debug, Clone, PartialEq, Eq)]
+pub struct Diagnostic {
+    pub path: String,
+    pub message: String,
+}
+
+impl Diagnostic {
+    pub fn new(path: impl Into<String>, message: impl Into<String>) -> Self {
+        Diagnostic { path: path.into(), message: message.into() }
+    }
+}
+
+/// 校验整张试卷，返回所有发现的问题，为空时表示试卷可以正常使用
+pub fn validate_paper(content: &Value) -> Vec<Diagnostic> {
+    let mut diagnostics = Vec::new();
+    let Some(object) = content.as_object() else {
+        diagnostics.push(Diagnostic::new("", "试卷必须是一个json对象"));
+        return diagnostics;
+    };
+
+    if content["main_title"].as_str().is_none_or(|title| title.trim().is_empty()) {
+        diagnostics.push(Diagnostic::new("/main_title", "缺少主标题"));
+    }
+    if object.contains_key("sub_title") && !content["sub_title"].is_string() {
+        diagnostics.push(Diagnostic::new("/sub_title", "副标题必须是字符串"));
+    }
+    if let Some(slug) = object.get("slug") {
+        if !slug.as_str().is_some_and(is_valid_slug) {
+            diagnostics.push(Diagnostic::new("/slug", "slug只能包含小写字母、数字和-，且不能是纯数字或保留路径"));
+        }
+    }
+    if let Some(target) = object.get("target") {
+        if !target.as_str().is_some_and(is_valid_target) {
+            diagnostics.push(Diagnostic::new("/target", "投递目标只能包含字母、数字、-和_"));
+        }
+    }
+
+    match content["questions"].as_array() {
+        Some(questions) if !questions.is_empty() => {
+            for (index, question) in questions.iter().enumerate() {
+                diagnostics.extend(validate_question(&format!("/questions/{}", index), question));
+            }
+        }
+        Some(_) => diagnostics.push(Diagnostic::new("/questions", "试卷中没有题目")),
+        None => {
+            diagnostics.push(Diagnostic::new("/questions", "缺少题目列表"));
+            return diagnostics;
+        }
+    }
+
+    // 题目本身有问题时总分没有意义，只检查及格线的类型
+    let total = total_score(content);
+    let questions_valid = diagnostics.iter().all(|diagnostic| !diagnostic.path.starts_with("/questions"));
+    match content["score"].as_i64() {
+        Some(score) if questions_valid && score != total => {
+            diagnostics.push(Diagnostic::new("/score", format!("总分为{}，与各题分值之和{}不一致", score, total)));
+        }
+        Some(_) => {}
+        None => diagnostics.push(Diagnostic::new("/score", "缺少总分")),
+    }
+    match content["pass"].as_i64() {
+        Some(pass) if pass <= 0 => diagnostics.push(Diagnostic::new("/pass", "及格分数必须大于0")),
+        Some(pass) if questions_valid && pass > total => {
+            diagnostics.push(Diagnostic::new("/pass", format!("及格分数{}超过了试卷的满分{}，没有人能够通过", pass, total)));
+        }
+        Some(_) => {}
+        None => diagnostics.push(Diagnostic::new("/pass", "缺少及格分数")),
+    }
+    diagnostics
+}
+
+/// 校验单个题目：单选题的答案为选项序号（从1开始），
+/// 多选题的答案为从小到大排列的选项序号数组，分值为部分正确和全部正确时的分值
+pub fn validate_question(path: &str, question: &Value) -> Vec<Diagnostic> {
+    let mut diagnostics = Vec::new();
+    if !question.is_object() {
+        diagnostics.push(Diagnostic::new(path, "题目必须是一个json对象"));
+        return diagnostics;
+    }
+    if question["content"].as_str().is_none_or(|content| content.trim().is_empty()) {
+        diagnostics.push(Diagnostic::new(format!("{}/content", path), "缺少题目内容"));
+    }
+
+    let options = match question["options"].as_array() {
+        Some(options) if options.len() >= 2 => options,
+        Some(_) => {
+            diagnostics.push(Diagnostic::new(format!("{}/options", path), "至少需要两个选项"));
+            return diagnostics;
+        }
+        None => {
+            diagnostics.push(Diagnostic::new(format!("{}/options", path), "缺少选项列表"));
+            return diagnostics;
+        }
+    };
+    let mut seen = HashSet::new();
+    for (index, option) in options.iter().enumerate() {
+        match option.as_str() {
+            Some(option) if option.trim().is_empty() => {
+                diagnostics.push(Diagnostic::new(format!("{}/options/{}", path, index), "选项内容不能为空"));
+            }
+            Some(option) if !seen.insert(option.trim()) => {
+                diagnostics.push(Diagnostic::new(format!("{}/options/{}", path, index), format!("选项“{}”重复", option.trim())));
+            }
+            Some(_) => {}
+            None => diagnostics.push(Diagnostic::new(format!("{}/options/{}", path, index), "选项必须是字符串")),
+        }
+    }
+
+    let correct_path = format!("{}/correct", path);
+    let score_path = format!("{}/score", path);
+    let check_index = |index: &Value| match index.as_u64() {
+        Some(index) if index >= 1 && index as usize <= options.len() => None,
+        Some(index) => Some(format!("答案{}超出了选项范围1~{}", index, options.len())),
+        None => Some("答案必须是选项的序号（从1开始）".to_string()),
+    };
+    match question["type"].as_str() {
+        Some("radio") => {
+            if let Some(message) = check_index(&question["correct"]) {
+                diagnostics.push(Diagnostic::new(correct_path, message));
+            }
+            if question["score"].as_u64().is_none() {
+                diagnostics.push(Diagnostic::new(score_path, "单选题的分值必须是一个非负整数"));
+            }
+        }
+        Some("multiple") => {
+            match question["correct"].as_array() {
+                Some(correct) if !correct.is_empty() => {
+                    for (index, value) in correct.iter().enumerate() {
+                        if let Some(message) = check_index(value) {
+                            diagnostics.push(Diagnostic::new(format!("{}/{}", correct_path, index), message));
+                        }
+                    }
+                    let indices: Vec<u64> = correct.iter().filter_map(Value::as_u64).collect();
+                    if indices.windows(2).any(|pair| pair[0] >= pair[1]) {
+                        diagnostics.push(Diagnostic::new(correct_path, "多选题的答案必须从小到大排列且不能重复"));
+                    }
+                }
+                _ => diagnostics.push(Diagnostic::new(correct_path, "多选题的答案必须是非空的选项序号数组")),
+            }
+            match question["score"].as_array().map(|score| score.iter().map(Value::as_u64).collect::<Vec<_>>()) {
+                Some(score) if score.len() == 2 && score.iter().all(Option::is_some) => {
+                    if score[0] > score[1] {
+                        diagnostics.push(Diagnostic::new(score_path, "部分正确的分值不能高于全部正确的分值"));
+                    }
+                }
+                _ => diagnostics.push(Diagnostic::new(score_path, "多选题的分值必须是[部分正确分值, 全部正确分值]")),
+            }
+        }
+        Some(other) => diagnostics.push(Diagnostic::new(format!("{}/type", path), format!("不支持的题型{}，只能是radio或multiple", other))),
+        None => diagnostics.push(Diagnostic::new(format!("{}/type", path), "缺少题型")),
+    }
+    diagnostics
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    fn paper() -> Value {
+        json!({
+            "main_title": "生存服入服考试",
+            "score": 5,
+            "pass": 3,
+            "questions": [
+                {"type": "radio", "content": "q1", "options": ["a", "b"], "correct": 2, "score": 2},
+                {"type": "multiple", "content": "q2", "options": ["a", "b", "c"], "correct": [1, 3], "score": [1, 3]}
+            ]
+        })
+    }
+
+    fn paths(diagnostics: &[Diagnostic]) -> Vec<&str> {
+        diagnostics.iter().map(|diagnostic| diagnostic.path.as_str()).collect()
+    }
+
+    #[test]
+    fn valid_paper_has_no_diagnostics() {
+        assert_eq!(validate_paper(&paper()), vec![]);
+    }
+
+    #[test]
+    fn missing_fields_are_reported() {
+        assert_eq!(paths(&validate_paper(&json!([]))), vec![""]);
+        assert_eq!(paths(&validate_paper(&json!({"main_title": " "}))), vec!["/main_title", "/questions"]);
+        let mut content = paper();
+        content["questions"] = json!([]);
+        assert_eq!(paths(&validate_paper(&content)), vec!["/questions"]);
+    }
+
+    #[test]
+    fn score_must_match_questions() {
+        let mut content = paper();
+        content["score"] = json!(6);
+        assert_eq!(paths(&validate_paper(&content)), vec!["/score"]);
+
+        let mut content = paper();
+        content["pass"] = json!(6);
+        assert_eq!(paths(&validate_paper(&content)), vec!["/pass"]);
+
+        let mut content = paper();
+        content["pass"] = json!(0);
+        assert_eq!(paths(&validate_paper(&content)), vec!["/pass"]);
+    }
+
+    #[test]
+    fn broken_questions_skip_total_checks() {
+        // 题目有误时总分与各题之和不一致不再重复报告
+        let mut content = paper();
+        content["score"] = json!(100);
+        content["questions"][0]["correct"] = json!(3);
+        assert_eq!(paths(&validate_paper(&content)), vec!["/questions/0/correct"]);
+    }
+
+    #[test]
+    fn question_errors_point_at_the_field() {
+        let question = json!({"type": "radio", "content": "q", "options": ["a", " a "], "correct": 0, "score": -1});
+        assert_eq!(paths(&validate_question("/questions/0", &question)), vec!["/questions/0/options/1", "/questions/0/correct", "/questions/0/score"]);
+
+        let question = json!({"type": "multiple", "content": "q", "options": ["a", "b", "c"], "correct": [3, 1], "score": [3, 1]});
+        assert_eq!(paths(&validate_question("/questions/1", &question)), vec!["/questions/1/correct", "/questions/1/score"]);
+
+        let question = json!({"type": "text", "content": "q", "options": ["a", "b"]});
+        assert_eq!(paths(&validate_question("/questions/2", &question)), vec!["/questions/2/type"]);
+
+        let question = json!({"type": "radio", "content": "q", "options": ["a"]});
+        assert_eq!(paths(&validate_question("/questions/3", &question)), vec!["/questions/3/options"]);
+    }
+
+    #[test]
+    fn invalid_slug_and_target_are_reported() {
+        let mut content = paper();
+        content["slug"] = json!("api");
+        content["target"] = json!("vip group");
+        assert_eq!(paths(&validate_paper(&content)), vec!["/slug", "/target"]);
+    }
+}
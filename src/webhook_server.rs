@@ -0,0 +1,360 @@
+use crate::r#struct::awl_type::ServerId;
+use crate::metrics::{self, CommandReceiver, CommandSender};
+use crate::rcon::resolve_public;
+use crate::r#struct::webhook::{WebhookEvent, WebhookTarget};
+use crate::sql_server::SqlServerHandle;
+use crate::utils::{hmac_sha256_hex, unix_timestamp};
+use crate::CONFIG;
+use std::io;
+use std::net::SocketAddr;
+use std::time::Instant;
+use tokio::sync::oneshot;
+use tokio::time::{self, Duration};
+use url::Url;
+
+/// 单个webhook最多尝试投递的次数
+const MAX_ATTEMPTS: u32 = 6;
+
+/// 第一次重试前的等待时间，之后每次翻倍
+fn retry_backoff() -> Duration {
+    Duration::from_secs(CONFIG.current().timeouts.webhook_retry)
+}
+
+/// 单次请求超时时间
+fn request_timeout() -> Duration {
+    Duration::from_secs(CONFIG.current().timeouts.webhook_timeout)
+}
+
+#[derive(Debug)]
+enum Command {
+    Dispatch {
+        server_id: ServerId,
+        event: WebhookEvent,
+    },
+
+    /// 后台投递失败，等待重试
+    Failed {
+        delivery: PendingDelivery,
+    },
+
+    /// 检查服务是否仍在处理命令
+    Ping {
+        res_tx: oneshot::Sender<()>,
+    },
+
+    /// 处理完之前的所有命令后退出
+    Shutdown {
+        res_tx: oneshot::Sender<()>,
+    },
+}
+
+#[derive(Debug)]
+struct PendingDelivery {
+    target: WebhookTarget,
+    event: &'static str,
+    body: String,
+    /// 已尝试的次数
+    attempt: u32,
+    next_try: Instant,
+}
+
+pub struct WebhookServer {
+    /// 接收命令的管道
+    cmd_rx: CommandReceiver<Command>,
+
+    /// 用于让后台任务向自身回报结果
+    cmd_tx: CommandSender<Command>,
+
+    /// sql命令池
+    sql_handler: SqlServerHandle,
+
+    client: reqwest::Client,
+
+    /// 等待重试的投递
+    retry_queue: Vec<PendingDelivery>,
+}
+
+impl WebhookServer {
+    pub fn new(sql_handler: SqlServerHandle) -> (WebhookServer, WebhookServerHandle) {
+        let (cmd_tx, cmd_rx) = metrics::command_channel("webhook_server");
+        (
+            WebhookServer {
+                cmd_rx,
+                cmd_tx: cmd_tx.clone(),
+                sql_handler,
+                // unwrap: 与reqwest::Client::new()相同，只在TLS后端初始化失败时出错
+                client: client_builder().build().unwrap(),
+                retry_queue: Vec::new(),
+            },
+            WebhookServerHandle { cmd_tx },
+        )
+    }
+
+    /// 查询客户端注册的webhook，自托管模式下读取配置文件
+    async fn targets(&self, server_id: ServerId) -> Vec<WebhookTarget> {
+        if CONFIG.self_hosted {
+            return CONFIG.current().webhooks.clone();
+        }
+        match self.sql_handler.get_webhooks(server_id).await {
+            Ok(webhooks) => webhooks.into_iter().map(|(_, target)| target).collect(),
+            Err(e) => {
+                log::error!("查询webhook失败: {}", e);
+                Vec::new()
+            }
+        }
+    }
+
+    async fn dispatch(&mut self, server_id: ServerId, event: WebhookEvent) {
+        let targets = self.targets(server_id).await;
+        if targets.is_empty() {
+            return;
+        }
+        let body = match serde_json::to_string(&event) {
+            Ok(body) => body,
+            Err(e) => {
+                log::error!("序列化webhook事件失败: {}", e);
+                return;
+            }
+        };
+        for target in targets {
+            self.spawn_delivery(PendingDelivery {
+                target,
+                event: event.event.as_str(),
+                body: body.clone(),
+                attempt: 0,
+                next_try: Instant::now(),
+            });
+        }
+    }
+
+    fn spawn_delivery(&self, mut delivery: PendingDelivery) {
+        let client = self.client.clone();
+        let cmd_tx = self.cmd_tx.clone();
+        tokio::spawn(async move {
+            delivery.attempt += 1;
+            match send(&client, &delivery).await {
+                Ok(()) => {}
+                Err(e) if delivery.attempt >= MAX_ATTEMPTS => {
+                    log::error!("webhook {}投递失败{}次，放弃投递: {}", delivery.target.url, delivery.attempt, e);
+                }
+                Err(e) => {
+                    log::warn!("webhook {}投递失败，稍后重试: {}", delivery.target.url, e);
+                    delivery.next_try = Instant::now() + retry_backoff() * 2u32.pow(delivery.attempt - 1);
+                    let _ = cmd_tx.send(Command::Failed { delivery });
+                }
+            }
+        });
+    }
+
+    fn retry_due(&mut self) {
+        let now = Instant::now();
+        let (due, waiting) = std::mem::take(&mut self.retry_queue)
+            .into_iter()
+            .partition(|delivery| delivery.next_try <= now);
+        self.retry_queue = waiting;
+        for delivery in due {
+            self.spawn_delivery(delivery);
+        }
+    }
+
+    pub async fn run(mut self) -> io::Result<()> {
+        let mut interval = time::interval(Duration::from_secs(1));
+
+        loop {
+            tokio::select! {
+                Some(cmd) = self.cmd_rx.recv() => {
+                    match cmd {
+                        Command::Dispatch { server_id, event } => {
+                            self.dispatch(server_id, event).await;
+                        }
+                        Command::Failed { delivery } => {
+                            self.retry_queue.push(delivery);
+                        }
+                        Command::Ping { res_tx } => {
+                            let _ = res_tx.send(());
+                        }
+                        Command::Shutdown { res_tx } => {
+                            // 重试队列只保存在内存中，关闭后不再重试
+                            if !self.retry_queue.is_empty() {
+                                log::warn!("{}个webhook投递等待重试，关闭后将不再重试", self.retry_queue.len());
+                            }
+                            let _ = res_tx.send(());
+                            return Ok(());
+                        }
+                    }
+                }
+                _ = interval.tick() => {
+                    self.retry_due();
+                }
+            }
+        }
+    }
+}
+
+/// 不跟随重定向，否则外部地址可以把请求转到内网
+fn client_builder() -> reqwest::ClientBuilder {
+    reqwest::Client::builder().redirect(reqwest::redirect::Policy::none())
+}
+
+/// 解析webhook地址，拒绝内网或保留地址
+pub(crate) async fn resolve_webhook(url: &Url) -> io::Result<SocketAddr> {
+    let host = url.host_str().ok_or_else(|| io::Error::new(io::ErrorKind::InvalidInput, "缺少主机名"))?;
+    let port = url.port_or_known_default().ok_or_else(|| io::Error::new(io::ErrorKind::InvalidInput, "缺少端口"))?;
+    resolve_public(&format!("{}:{}", host, port)).await
+}
+
+/// 签名内容为`时间戳.请求体`，接收方应校验时间戳以防止重放
+async fn send(client: &reqwest::Client, delivery: &PendingDelivery) -> Result<(), String> {
+    // 托管模式下每次投递前重新检查，并固定使用检查过的地址，避免域名在注册后被解析到内网地址
+    let pinned;
+    let client = if CONFIG.self_hosted {
+        client
+    } else {
+        let url = Url::parse(&delivery.target.url).map_err(|e| e.to_string())?;
+        let address = resolve_webhook(&url).await.map_err(|e| e.to_string())?;
+        match url.domain() {
+            Some(domain) => {
+                pinned = client_builder().resolve(domain, address).build().map_err(|e| e.to_string())?;
+                &pinned
+            }
+            None => client,
+        }
+    };
+    let timestamp = unix_timestamp().to_string();
+    let signature = hmac_sha256_hex(&delivery.target.secret, &format!("{}.{}", timestamp, delivery.body));
+    let response = client
+        .post(&delivery.target.url)
+        .header("Content-Type", "application/json")
+        .header("X-QuizGate-Event", delivery.event)
+        .header("X-QuizGate-Timestamp", &timestamp)
+        .header("X-QuizGate-Signature", format!("sha256={}", signature))
+        .body(delivery.body.clone())
+        .timeout(request_timeout())
+        .send()
+        .await
+        .map_err(|e| e.to_string())?;
+    if response.status().is_success() {
+        Ok(())
+    } else {
+        Err(format!("服务端返回{}", response.status()))
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct WebhookServerHandle {
+    cmd_tx: CommandSender<Command>,
+}
+
+impl WebhookServerHandle {
+    /// 向客户端注册的所有webhook发送事件，不等待投递结果
+    pub fn dispatch(&self, server_id: ServerId, event: WebhookEvent) {
+        // unwrap: webhook server should not have been dropped
+        self.cmd_tx.send(Command::Dispatch { server_id, event }).unwrap();
+    }
+
+    /// 服务已退出时返回false
+    pub async fn ping(&self) -> bool {
+        let (res_tx, res_rx) = oneshot::channel();
+        self.cmd_tx.send(Command::Ping { res_tx }).is_ok() && res_rx.await.is_ok()
+    }
+
+    /// 处理完之前的所有事件后退出，等待重试的投递会被丢弃
+    pub async fn shutdown(&self) {
+        let (res_tx, res_rx) = oneshot::channel();
+        // unwrap: webhook server should not have been dropped
+        self.cmd_tx.send(Command::Shutdown { res_tx }).unwrap();
+        let _ = res_rx.await;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::config::{self, Config};
+    use tokio::io::{AsyncReadExt, AsyncWriteExt};
+    use tokio::net::TcpListener;
+    use tokio::task::JoinHandle;
+
+    /// 接受一个请求并返回指定的响应，任务结果为收到的请求头及请求体
+    async fn serve_once(response: &'static str) -> (String, JoinHandle<String>) {
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let url = format!("http://{}/hook", listener.local_addr().unwrap());
+        let task = tokio::spawn(async move {
+            let (mut stream, _) = listener.accept().await.unwrap();
+            let mut request = Vec::new();
+            let mut buf = [0u8; 1024];
+            loop {
+                let n = stream.read(&mut buf).await.unwrap();
+                request.extend_from_slice(&buf[..n]);
+                let text = String::from_utf8_lossy(&request).to_string();
+                if let Some((head, body)) = text.split_once("\r\n\r\n") {
+                    let length = head
+                        .lines()
+                        .find_map(|line| line.to_ascii_lowercase().strip_prefix("content-length: ").map(|v| v.parse::<usize>().unwrap()))
+                        .unwrap_or(0);
+                    if body.len() >= length {
+                        stream.write_all(response.as_bytes()).await.unwrap();
+                        return text;
+                    }
+                }
+                if n == 0 {
+                    return text;
+                }
+            }
+        });
+        (url, task)
+    }
+
+    fn delivery(url: String) -> PendingDelivery {
+        PendingDelivery {
+            target: WebhookTarget { url, secret: "secret".to_string() },
+            event: "pass",
+            body: r#"{"event":"pass"}"#.to_string(),
+            attempt: 0,
+            next_try: Instant::now(),
+        }
+    }
+
+    fn header<'a>(request: &'a str, name: &str) -> &'a str {
+        request
+            .lines()
+            .find_map(|line| line.split_once(": ").filter(|(key, _)| key.eq_ignore_ascii_case(name)).map(|(_, value)| value))
+            .unwrap()
+    }
+
+    #[tokio::test]
+    async fn signs_timestamp_and_body() {
+        config::init_for_test(Config { self_hosted: true, ..Config::default() });
+        let (url, task) = serve_once("HTTP/1.1 200 OK\r\nContent-Length: 0\r\n\r\n").await;
+        let delivery = delivery(url);
+        send(&client_builder().build().unwrap(), &delivery).await.unwrap();
+
+        let request = task.await.unwrap();
+        let timestamp = header(&request, "X-QuizGate-Timestamp");
+        assert_eq!(header(&request, "X-QuizGate-Event"), "pass");
+        assert_eq!(
+            header(&request, "X-QuizGate-Signature"),
+            format!("sha256={}", hmac_sha256_hex("secret", &format!("{}.{}", timestamp, delivery.body)))
+        );
+        assert!(request.ends_with(&delivery.body));
+    }
+
+    #[tokio::test]
+    async fn does_not_follow_redirects() {
+        config::init_for_test(Config { self_hosted: true, ..Config::default() });
+        let (url, task) = serve_once("HTTP/1.1 302 Found\r\nLocation: http://127.0.0.1:1/\r\nContent-Length: 0\r\n\r\n").await;
+        let error = send(&client_builder().build().unwrap(), &delivery(url)).await.unwrap_err();
+        assert!(error.contains("302"), "{}", error);
+        task.await.unwrap();
+    }
+
+    #[tokio::test]
+    async fn hosted_mode_rejects_internal_addresses() {
+        config::init_for_test(Config { self_hosted: false, ..Config::default() });
+        let client = client_builder().build().unwrap();
+        for url in ["http://127.0.0.1:8080/hook", "http://localhost/hook", "http://[::1]/hook", "http://169.254.169.254/latest"] {
+            assert!(send(&client, &delivery(url.to_string())).await.is_err(), "{}", url);
+            assert!(resolve_webhook(&Url::parse(url).unwrap()).await.is_err(), "{}", url);
+        }
+    }
+}
@@ -9,15 +9,13 @@ use futures_util::{
 };
 use serde_json::json;
 use tokio::{sync::mpsc, time::interval};
-use crate::error::{DuplicateConnectionsError, NoSuchValueError};
+use crate::error::{DuplicateConnectionsError, NoSuchValueError, ServerSuspendedError};
 use crate::r#struct::awl_type::{ConnId, Key};
+use crate::r#struct::delivery::{PlayerAction, SessionMessage};
 use crate::ws_server::WsServerHandle;
-
-/// 心跳包发送频率
-const HEARTBEAT_INTERVAL: Duration = Duration::from_secs(5);
-
-/// 超时时间
-const CLIENT_TIMEOUT: Duration = Duration::from_secs(10);
+use crate::sql_server::key_prefix;
+use crate::logging::RequestId;
+use crate::CONFIG;
 
 /// Echo text & binary messages received from the client, respond to ping messages, and monitor
 /// connection health to detect network issues and free up resources.
@@ -25,18 +23,22 @@ pub async fn chat_ws(
     chat_server: WsServerHandle,
     mut session: actix_ws::Session,
     msg_stream: actix_ws::MessageStream,
+    request_id: RequestId,
 ) {
 
     let mut last_heartbeat = Instant::now();
     let first_connect = Instant::now();
-    let mut interval = interval(HEARTBEAT_INTERVAL);
+    // 心跳包发送频率及超时时间
+    let timeouts = CONFIG.current().timeouts.clone();
+    let mut interval = interval(Duration::from_secs(timeouts.heartbeat_interval));
+    let client_timeout = Duration::from_secs(timeouts.client_timeout);
     // 当前链接的验证状态
     let mut verified = false;
 
     let (conn_tx, mut conn_rx) = mpsc::unbounded_channel();
 
     let conn_id = chat_server.connect(conn_tx).await.unwrap();
-    log::info!("新建链接{}",conn_id);
+    log::info!(conn_id = conn_id, request_id = request_id.as_str(); "新建链接{}",conn_id);
     let msg_stream = msg_stream
         .max_frame_size(128 * 1024)
         .aggregate_continuations()
@@ -55,7 +57,7 @@ pub async fn chat_ws(
         match select(messages, tick).await {
             // 从客户端接受指令
             Either::Left((Either::Left((Some(Ok(msg)), _)), _)) => {
-                log::debug!("msg: {msg:?}");
+                log::debug!(conn_id = conn_id; "msg: {msg:?}");
 
                 match msg {
                     AggregatedMessage::Ping(bytes) => {
@@ -73,11 +75,13 @@ pub async fn chat_ws(
                         if !verified {
                             verified = process_text_msg(&chat_server, &mut session, &text, conn_id)
                                 .await;
+                        } else {
+                            process_verified_msg(&chat_server, &text, conn_id);
                         }
                     }
 
                     AggregatedMessage::Binary(_bin) => {
-                        log::warn!("unexpected binary message");
+                        log::warn!(conn_id = conn_id; "unexpected binary message");
                     }
 
                     AggregatedMessage::Close(reason) => break reason,
@@ -86,39 +90,73 @@ pub async fn chat_ws(
 
             // client WebSocket stream error
             Either::Left((Either::Left((Some(Err(err)), _)), _)) => {
-                log::error!("{}", err);
+                log::error!(conn_id = conn_id; "{}", err);
                 break None;
             }
 
             // client WebSocket stream ended
             Either::Left((Either::Left((None, _)), _)) => break None,
 
-            // chat messages received from other room participants
-            Either::Left((Either::Right((Some(chat_msg), _)), _)) => {
+            // 服务端即将关闭，通知客户端稍后重连
+            Either::Left((Either::Right((Some(SessionMessage::GoingAway { reconnect_in }), _)), _)) => {
                 let template = json!({
-                    "code": 2,
-                    "msg": chat_msg
+                    "code": 5,
+                    "reconnect_in": reconnect_in
+                });
+                let _ = session.text(template.to_string()).await;
+                break Some(actix_ws::CloseReason {
+                    code: actix_ws::CloseCode::Restart,
+                    description: Some("server shutting down".to_string()),
                 });
+            }
+
+            // chat messages received from other room participants
+            Either::Left((Either::Right((Some(SessionMessage::Deliver { id, action }), _)), _)) => {
+                // 2为加入白名单，4为移出白名单，id需在确认时原样返回
+                let template = match action {
+                    PlayerAction::Add { player_id, target: Some(target), .. } => json!({
+                        "code": 2,
+                        "msg": player_id,
+                        "target": target,
+                        "id": id
+                    }),
+                    PlayerAction::Add { player_id, target: None, .. } => json!({
+                        "code": 2,
+                        "msg": player_id,
+                        "id": id
+                    }),
+                    PlayerAction::Remove { player_id, .. } => json!({
+                        "code": 4,
+                        "msg": player_id,
+                        "id": id
+                    }),
+                };
                 session.text(template.to_string()).await.unwrap();
             }
 
-            // all connection's message senders were dropped
-            Either::Left((Either::Right((None, _)), _)) => unreachable!(
-                "all connection message senders were dropped; chat server may have panicked"
-            ),
+            // 服务端丢弃了消息管道，说明该链接被主动断开，例如使用的密钥已失效
+            Either::Left((Either::Right((None, _)), _)) => {
+                log::info!(conn_id = conn_id; "客户端{conn_id}的链接被服务端关闭");
+                break Some(actix_ws::CloseReason {
+                    code: actix_ws::CloseCode::Policy,
+                    description: Some("key revoked".to_string()),
+                });
+            }
 
             // 心跳包和验证超时判断
             Either::Right((_inst, _)) => {
                 // 如果长时间未收到心跳包则断开链接
-                if Instant::now().duration_since(last_heartbeat) > CLIENT_TIMEOUT {
+                if Instant::now().duration_since(last_heartbeat) > client_timeout {
                     log::info!(
-                        "客户端{conn_id}在{CLIENT_TIMEOUT:?}秒内未发送心跳包，断开链接"
+                        conn_id = conn_id;
+                        "客户端{conn_id}在{client_timeout:?}秒内未发送心跳包，断开链接"
                     );
                     session.text("Heartbeat timed out, disconnecting").await.unwrap();
                     break None;
-                } else if !verified && Instant::now().duration_since(first_connect) > CLIENT_TIMEOUT {
+                } else if !verified && Instant::now().duration_since(first_connect) > client_timeout {
                     log::info!(
-                        "{conn_id}在{CLIENT_TIMEOUT:?}秒内未进行验证，断开链接"
+                        conn_id = conn_id;
+                        "{conn_id}在{client_timeout:?}秒内未进行验证，断开链接"
                     );
                     session.text("Verification timed out, disconnecting").await.unwrap();
                     break None;
@@ -133,7 +171,7 @@ pub async fn chat_ws(
     chat_server.disconnect(conn_id);
     // attempt to close connection gracefully
     let _ = session.close(close_reason).await;
-    log::info!("客户端{}断开链接",conn_id);
+    log::info!(conn_id = conn_id; "客户端{}断开链接",conn_id);
 }
 
 async fn process_text_msg(
@@ -148,9 +186,11 @@ async fn process_text_msg(
             // 通过键来获取值
             let _code = json["code"].as_i64();  // 获取 "code" 的值
             let key:Key = json["key"].as_str().unwrap().to_string();    // 获取 "key" 的值
-            match chat_server.verify(key.clone(),conn).await {
+            // 新版客户端会确认每条投递，旧版插件不携带该字段
+            let ack = json["ack"].as_bool().unwrap_or(false);
+            match chat_server.verify(key.clone(),conn,ack).await {
                 Ok(server_name) => {
-                    log::info!("{}已上线",server_name);
+                    log::info!(conn_id = conn; "{}已上线",server_name);
                     let template = json!({
                     "code": 1,
                     "server_name": server_name
@@ -158,33 +198,46 @@ async fn process_text_msg(
                     match session.text(template.to_string()).await{
                         Ok(_) => {true}
                         Err(e) => {
-                            log::error!("向客户端发送响应失败:{}",e);
+                            log::error!(conn_id = conn; "向客户端发送响应失败:{}",e);
                             false
                         }
                     }
                 },
                 Err(e) if e.is::<DuplicateConnectionsError>() => {
-                    log::error!("密钥{}当前已有在线的客户端",key);
+                    log::error!(conn_id = conn; "密钥{}当前已有在线的客户端",masked_key(&key));
                     let template = json!({
                         "code": -2,
                     });
                     match session.text(template.to_string()).await{
                         Ok(_) => {false}
                         Err(e) => {
-                            log::error!("向客户端发送响应失败:{}",e);
+                            log::error!(conn_id = conn; "向客户端发送响应失败:{}",e);
                             false
                         }
                     }
                 }
                 Err(e) if e.is::<NoSuchValueError>() => {
-                    log::error!("客户端密钥{}无效",key);
+                    log::error!(conn_id = conn; "客户端密钥{}无效",masked_key(&key));
                     let template = json!({
                         "code": -1,
                     });
                     match session.text(template.to_string()).await{
                         Ok(_) => {false}
                         Err(e) => {
-                            log::error!("向客户端发送响应失败:{}",e);
+                            log::error!(conn_id = conn; "向客户端发送响应失败:{}",e);
+                            false
+                        }
+                    }
+                },
+                Err(e) if e.is::<ServerSuspendedError>() => {
+                    log::error!(conn_id = conn; "密钥{}对应的服务器已被停用",masked_key(&key));
+                    let template = json!({
+                        "code": -3,
+                    });
+                    match session.text(template.to_string()).await{
+                        Ok(_) => {false}
+                        Err(e) => {
+                            log::error!(conn_id = conn; "向客户端发送响应失败:{}",e);
                             false
                         }
                     }
@@ -194,9 +247,47 @@ async fn process_text_msg(
             }
         }
         Err(e) => {
-            log::error!("客户端发送了无效的消息:{}",e);
+            log::error!(conn_id = conn; "客户端发送了无效的消息:{}",e);
             session.text("Invalid message").await.unwrap();
             false
         },
     }
-}
\ No newline at end of file
+}
+
+/// 处理验证通过后客户端发来的消息，目前只有投递确认
+fn process_verified_msg(chat_server: &WsServerHandle, text: &str, conn: ConnId) {
+    let Ok(json) = serde_json::from_str::<serde_json::Value>(text) else {
+        log::warn!(conn_id = conn; "客户端{}发送了无效的消息", conn);
+        return;
+    };
+    if json["code"].as_i64() == Some(3) {
+        if let Some(player_id) = json["msg"].as_str() {
+            chat_server.ack(conn, player_id.to_string(), json["id"].as_u64());
+        }
+    }
+}
+
+/// 日志中不记录完整的密钥，只显示与数据库中相同的前缀，过短或无法按字节截取前缀的密钥只显示长度
+fn masked_key(key: &str) -> String {
+    if key.len() < 16 || !key.is_char_boundary(8) {
+        format!("<{}位>", key.chars().count())
+    } else {
+        format!("{}…", key_prefix(key))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn masked_key_never_shows_full_key() {
+        assert_eq!(masked_key("abcdefgh12345678ijkl"), "abcdefgh…");
+        assert_eq!(masked_key("abcdefgh1234567"), "<15位>");
+        assert_eq!(masked_key(""), "<0位>");
+        // 前缀不在字符边界上时不能退回到完整的密钥
+        let key = "密钥密钥密钥密钥";
+        assert_eq!(masked_key(key), "<8位>");
+        assert_eq!(masked_key("ab密钥密钥密钥密钥"), "ab密钥…");
+    }
+}
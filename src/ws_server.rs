@@ -1,7 +1,15 @@
-use crate::{CONFIG, sql_server::{SqlServerHandle, SqlStatement}, error::DuplicateConnectionsError, r#struct::awl_type::{ConnId, Key, PlayerId}};
+use crate::{CONFIG, sql_server::SqlServerHandle, error::{DuplicateConnectionsError, ServerSuspendedError}, r#struct::awl_type::{ConnId, Key, ServerId, PlayerId}};
+use crate::metrics::{self, CommandReceiver, CommandSender};
+use crate::utils::unix_timestamp;
+use crate::r#struct::admin::SessionStatus;
+use crate::r#struct::metrics::{QueueDepth, SessionMetrics};
+use crate::r#struct::delivery::{Delivery, DeliveryId, PendingDeliveries, PlayerAction, SessionMessage};
+use crate::logging::RequestId;
+use crate::rcon::RconTarget;
 use rand::random;
-use std::collections::VecDeque;
+use std::collections::{BTreeMap, HashSet, VecDeque};
 use std::error::Error;
+use std::time::Instant;
 use std::{
     collections::HashMap,
     io,
@@ -13,10 +21,19 @@ use std::{
 use tokio::sync::{mpsc, oneshot};
 use tokio::time::{self, Duration};
 
+/// RCON投递失败后第一次重试前的等待时间，之后每次失败翻倍
+const RCON_MIN_BACKOFF: Duration = Duration::from_secs(5);
+const RCON_MAX_BACKOFF: Duration = Duration::from_secs(300);
+
+/// 开启投递确认的客户端超过该时间未确认则重新投递
+fn ack_timeout() -> Duration {
+    Duration::from_secs(CONFIG.current().timeouts.ack_timeout)
+}
+
 #[derive(Debug)]
 enum Command {
     Connect {
-        conn_tx: mpsc::UnboundedSender<Key>,
+        conn_tx: mpsc::UnboundedSender<SessionMessage>,
         res_tx: oneshot::Sender<ConnId>,
     },
 
@@ -25,62 +42,162 @@ enum Command {
     },
 
     AddPlayer {
-        id: PlayerId,
-        key: Key,
+        action: PlayerAction,
+        server_id: ServerId,
         res_tx: oneshot::Sender<()>,
     },
 
+    /// 撤销玩家的资格，丢弃尚未投递的加入操作并投递移出操作
+    RevokePlayer {
+        server_id: ServerId,
+        id: PlayerId,
+        request_id: String,
+    },
+
+    Status {
+        server_id: ServerId,
+        res_tx: oneshot::Sender<SessionStatus>,
+    },
+
+    /// 查询所有在线的客户端
+    OnlineServers {
+        res_tx: oneshot::Sender<HashSet<ServerId>>,
+    },
+
+    /// 查询链接数及各客户端积压的消息数
+    Metrics {
+        res_tx: oneshot::Sender<SessionMetrics>,
+    },
+
     Verify {
         key:Key,
         conn_id:ConnId,
+        ack: bool,
         res_tx: oneshot::Sender<Result<String, Box<dyn Error + Send + Sync>>>
     },
+
+    Ack {
+        conn: ConnId,
+        player_id: PlayerId,
+        delivery_id: Option<DeliveryId>,
+    },
+
+    /// 投递方式被修改，清除缓存
+    ReloadDelivery {
+        server_id: ServerId,
+    },
+
+    /// 客户端轮换了密钥，使用旧密钥的链接将在expires_at后断开
+    KeyRotated {
+        server_id: ServerId,
+        expires_at: i64,
+    },
+
+    /// 服务器被运营者停用或删除
+    Kick {
+        server_id: ServerId,
+        purge: bool,
+    },
+
+    /// RCON投递任务结束，返回未能投递的玩家
+    RconFinished {
+        server_id: ServerId,
+        failed: Vec<PlayerAction>,
+    },
+
+    /// 服务端即将关闭，通知所有链接稍后重连，之后的消息只进入队列
+    GoingAway,
+
+    /// 等待进行中的RCON投递结束，保存尚未投递的消息后退出
+    Shutdown {
+        res_tx: oneshot::Sender<()>,
+    },
 }
 
 #[derive(Debug)]
 pub struct WsServer {
     /// 链接ID和消息发送管道的键值对
-    sessions: HashMap<ConnId, mpsc::UnboundedSender<PlayerId>>,
+    sessions: HashMap<ConnId, mpsc::UnboundedSender<SessionMessage>>,
+
+    /// 客户端id和链接id的键值对
+    client_list: HashMap<ServerId,ConnId>,
 
-    /// 客户端key和链接id的键值对
-    client_list: HashMap<Key,ConnId>,
+    /// 链接id和客户端id的键值对
+    client_list_reverse: HashMap<ConnId,ServerId>,
 
-    /// 链接id和客户端key的键值对
-    client_list_reverse: HashMap<ConnId,Key>,
+    /// 使用旧密钥验证的链接及密钥失效时间，到期后断开链接
+    key_expiry: HashMap<ConnId, i64>,
 
     /// 维护的链接总数
     visitor_count: Arc<AtomicUsize>,
 
     /// 接收命令的管道
-    cmd_rx: mpsc::UnboundedReceiver<Command>,
+    cmd_rx: CommandReceiver<Command>,
 
     /// sql命令池
     sql_handler: SqlServerHandle,
 
     /// 缓存中的消息队列
-    pending_messages: HashMap<Key, VecDeque<PlayerId>>,
+    pending_messages: HashMap<ServerId, VecDeque<PlayerAction>>,
+
+    /// 开启了投递确认的链接
+    ack_sessions: HashSet<ConnId>,
+
+    /// 已发送但尚未被客户端确认的消息及其编号、发送时间
+    inflight: HashMap<ServerId, VecDeque<(DeliveryId, PlayerAction, Instant)>>,
+
+    /// 下一次WebSocket投递使用的编号
+    next_delivery_id: DeliveryId,
+
+    /// 客户端投递方式的缓存
+    deliveries: HashMap<ServerId, Delivery>,
+
+    /// 正在进行RCON投递的客户端，同一客户端同时只有一个投递任务
+    rcon_busy: HashSet<ServerId>,
+
+    /// RCON投递失败的客户端下一次重试的时间及当前的等待时间
+    rcon_backoff: HashMap<ServerId, (Instant, Duration)>,
+
+    /// 用于让后台任务向自身回报结果
+    cmd_tx: CommandSender<Command>,
+
+    /// 已通知客户端即将关闭，不再发送新的投递
+    shutting_down: bool,
 }
 
 impl WsServer {
     pub fn new(sql_handler:SqlServerHandle) -> (WsServer, WsServerHandle) {
 
-        let (cmd_tx,cmd_rx) = mpsc::unbounded_channel();
+        let (cmd_tx,cmd_rx) = metrics::command_channel("ws_server");
         (
             WsServer{
                 sessions: HashMap::new(),
                 client_list: HashMap::new(),
                 client_list_reverse: HashMap::new(),
+                key_expiry: HashMap::new(),
                 visitor_count: Arc::new(AtomicUsize::new(0)),
                 cmd_rx,
                 sql_handler,
-                pending_messages: HashMap::new()
+                pending_messages: HashMap::new(),
+                ack_sessions: HashSet::new(),
+                inflight: HashMap::new(),
+                next_delivery_id: 1,
+                deliveries: HashMap::new(),
+                rcon_busy: HashSet::new(),
+                rcon_backoff: HashMap::new(),
+                cmd_tx: cmd_tx.clone(),
+                shutting_down: false,
             },
             WsServerHandle {
                 cmd_tx,
             }
         )
     }
-    async fn connect(&mut self, tx: mpsc::UnboundedSender<PlayerId>) -> ConnId{
+    async fn connect(&mut self, tx: mpsc::UnboundedSender<SessionMessage>) -> ConnId{
+        // 关闭期间新建的链接直接通知重连
+        if self.shutting_down {
+            let _ = tx.send(SessionMessage::GoingAway { reconnect_in: CONFIG.current().timeouts.reconnect_delay });
+        }
         // 生成id并插入表
         let id = random::<ConnId>();
         self.sessions.insert(id,tx);
@@ -88,117 +205,446 @@ impl WsServer {
         self.visitor_count.fetch_add(1, Ordering::SeqCst);
         id
     }
-    async fn disconnect(&mut self, conn_id: ConnId) {
-        // 从表中移除链接
-        self.sessions.remove(&conn_id);
+    fn disconnect(&mut self, conn_id: ConnId) {
+        // 从表中移除链接，计数器-1
+        if self.sessions.remove(&conn_id).is_some() {
+            self.visitor_count.fetch_sub(1, Ordering::SeqCst);
+        }
+        self.ack_sessions.remove(&conn_id);
+        self.key_expiry.remove(&conn_id);
         // 获取key和链接id的键值对，如果为空则表示该链接尚未注册，如果有值则从两个表中移除对应键值对
-        if let Some(key) = self.client_list_reverse.remove(&conn_id) {
-                self.client_list.remove(&key);
+        if let Some(server_id) = self.client_list_reverse.remove(&conn_id) {
+            self.client_list.remove(&server_id);
+            // 未确认的消息放回队列头部，等待客户端重连后重新投递
+            if let Some(inflight) = self.inflight.remove(&server_id) {
+                let queue = self.pending_messages.entry(server_id).or_default();
+                for (_, action, _) in inflight.into_iter().rev() {
+                    queue.push_front(action);
+                }
+            }
+            if !CONFIG.self_hosted {
+                self.sql_handler.touch_client(server_id);
+            }
+        }
+    }
+    async fn verify(&mut self, key: Key, conn_id:ConnId, ack: bool) -> Result<String,Box<dyn Error + Send + Sync>>{
+        let result = self.verify_key(key, conn_id).await;
+        if result.is_ok() && ack {
+            self.ack_sessions.insert(conn_id);
         }
+        result
     }
-    async fn verify(&mut self, key: Key, conn_id:ConnId) -> Result<String,Box<dyn Error + Send + Sync>>{
-        // 如果当前密钥已注册则断开链接
-        if self.client_list.contains_key(&key) {
+    async fn verify_key(&mut self, key: Key, conn_id:ConnId) -> Result<String,Box<dyn Error + Send + Sync>>{
+        // 自托管模式下sql server只接受已配置的密钥
+        let client = self.sql_handler.verify_key(key).await?;
+        if client.suspended {
+            return Err(ServerSuspendedError.into())
+        }
+        let (server_id, name, expires_at) = (client.server_id, client.name, client.expires_at);
+        // 如果该客户端已有在线链接则断开链接
+        if self.client_list.contains_key(&server_id) {
             return Err(DuplicateConnectionsError.into())
         }
-        // 自托管模式下只验证已配置的密钥
-            if !CONFIG.self_hosted {
-                let sql_statement = SqlStatement{
-                    sql:"SELECT name FROM server_info WHERE key = ?".to_string(),
-                    params:[key.clone()].to_vec()
-                };
-                let result = self.sql_handler.execute(sql_statement).await;
-                match result {
-                    Ok(row) => {
-                        // 将key和connID的键值对插入表
-                        self.client_list.insert(key.clone(),conn_id);
-                        self.client_list_reverse.insert(conn_id,key);
-                        Ok(row)
-                }
-                    Err(e) => {
-                        Err(e)
-                    }
-                }
-            } else { 
-                if key == CONFIG.self_hosted_key {
-                    self.client_list.insert(key.clone(),conn_id);
-                    self.client_list_reverse.insert(conn_id,key);
-                    Ok("self_hosted".to_string())
-                } else {
-                    Err(DuplicateConnectionsError.into())
+        // 将客户端id和connID的键值对插入表
+        self.client_list.insert(server_id, conn_id);
+        self.client_list_reverse.insert(conn_id, server_id);
+        if let Some(expires_at) = expires_at {
+            self.key_expiry.insert(conn_id, expires_at);
+        }
+        if !CONFIG.self_hosted {
+            self.sql_handler.touch_client(server_id);
+        }
+        Ok(name)
+    }
+
+    /// 旧密钥在宽限期后失效，当前链接也随之到期
+    fn key_rotated(&mut self, server_id: ServerId, expires_at: i64) {
+        if let Some(&conn_id) = self.client_list.get(&server_id) {
+            let expiry = self.key_expiry.entry(conn_id).or_insert(expires_at);
+            *expiry = (*expiry).min(expires_at);
+        }
+        self.expire_sessions();
+    }
+
+    /// 服务器被停用或删除，断开在线的链接，purge为true时同时丢弃尚未投递的消息
+    fn kick(&mut self, server_id: ServerId, purge: bool) {
+        if let Some(&conn_id) = self.client_list.get(&server_id) {
+            log::info!("服务器{}已被停用，断开链接{}", server_id, conn_id);
+            self.disconnect(conn_id);
+        }
+        if purge {
+            self.pending_messages.remove(&server_id);
+            self.inflight.remove(&server_id);
+            self.deliveries.remove(&server_id);
+            self.rcon_backoff.remove(&server_id);
+        }
+    }
+
+    /// 断开密钥已失效的链接，丢弃消息管道后ws_handler会关闭链接
+    fn expire_sessions(&mut self) {
+        let now = unix_timestamp() as i64;
+        let expired: Vec<ConnId> = self.key_expiry
+            .iter()
+            .filter(|(_, expires_at)| **expires_at <= now)
+            .map(|(conn_id, _)| *conn_id)
+            .collect();
+        for conn_id in expired {
+            log::info!("链接{}使用的密钥已失效，断开链接", conn_id);
+            self.disconnect(conn_id);
+        }
+    }
+    async fn add_player(&mut self, server_id: ServerId, action: PlayerAction) {
+        match self.delivery(server_id).await {
+            Delivery::WebSocket => {
+                if !self.try_send(server_id, &action) {
+                    log::info!(
+                        request_id = action.request_id(),
+                        server_id = server_id,
+                        player_id = action.player_id().as_str();
+                        "客户端不在线，玩家{}的投递已加入队列", action.player_id()
+                    );
+                    self.queue_message(server_id, action).await;
                 }
             }
+            Delivery::Rcon(target) => {
+                self.queue_message(server_id, action).await;
+                self.flush_rcon(server_id, target);
+            }
+        }
+    }
+
+    async fn revoke_player(&mut self, server_id: ServerId, player_id: PlayerId, request_id: String) {
+        if let Some(queue) = self.pending_messages.get_mut(&server_id) {
+            queue.retain(|action| !matches!(action, PlayerAction::Add { player_id: id, .. } if *id == player_id));
+        }
+        self.add_player(server_id, PlayerAction::Remove { player_id, request_id: Some(request_id) }).await;
+    }
+
+    async fn status(&mut self, server_id: ServerId) -> SessionStatus {
+        let conn_id = self.client_list.get(&server_id).copied();
+        SessionStatus {
+            online: conn_id.is_some(),
+            ack: conn_id.is_some_and(|conn_id| self.ack_sessions.contains(&conn_id)),
+            delivery: match self.delivery(server_id).await {
+                Delivery::WebSocket => "websocket",
+                Delivery::Rcon(_) => "rcon",
+            },
+            pending: self.pending_messages.get(&server_id).map(|queue| queue.iter().cloned().collect()).unwrap_or_default(),
+            inflight: self.inflight.get(&server_id).map(|queue| queue.iter().map(|(_, action, _)| action.clone()).collect()).unwrap_or_default(),
+        }
+    }
+
+    fn metrics(&self) -> SessionMetrics {
+        let mut queues: BTreeMap<ServerId, QueueDepth> = BTreeMap::new();
+        for (server_id, queue) in self.pending_messages.iter().filter(|(_, queue)| !queue.is_empty()) {
+            queues.entry(*server_id).or_default().pending = queue.len();
+        }
+        for (server_id, queue) in self.inflight.iter().filter(|(_, queue)| !queue.is_empty()) {
+            queues.entry(*server_id).or_default().inflight = queue.len();
+        }
+        SessionMetrics {
+            connections: self.visitor_count.load(Ordering::SeqCst),
+            verified: self.client_list.len(),
+            queues,
+        }
+    }
+
+    /// 获取客户端的投递方式
+    async fn delivery(&mut self, server_id: ServerId) -> Delivery {
+        if let Some(delivery) = self.deliveries.get(&server_id) {
+            return delivery.clone();
+        }
+        let delivery = match self.sql_handler.get_delivery(server_id).await {
+            Ok(delivery) => delivery,
+            Err(e) => {
+                // 查询失败时不写入缓存，下次重新查询
+                log::error!("查询客户端投递方式失败: {}", e);
+                return Delivery::WebSocket;
+            }
+        };
+        self.deliveries.insert(server_id, delivery.clone());
+        delivery
+    }
+
+    /// 将队列中的玩家交给后台任务通过RCON投递，失败的玩家会在任务结束后放回队列
+    fn flush_rcon(&mut self, server_id: ServerId, target: RconTarget) {
+        if self.shutting_down || self.rcon_busy.contains(&server_id) {
+            return;
+        }
+        if self.rcon_backoff.get(&server_id).is_some_and(|(retry_at, _)| Instant::now() < *retry_at) {
+            return;
+        }
+        let Some(queue) = self.pending_messages.remove(&server_id) else {
+            return;
+        };
+        self.rcon_busy.insert(server_id);
+        let cmd_tx = self.cmd_tx.clone();
+        tokio::spawn(async move {
+            let failed = target.deliver(queue.into()).await;
+            let _ = cmd_tx.send(Command::RconFinished { server_id, failed });
+        });
+    }
+
+    fn rcon_finished(&mut self, server_id: ServerId, failed: Vec<PlayerAction>) {
+        self.rcon_busy.remove(&server_id);
+        if failed.is_empty() {
+            self.rcon_backoff.remove(&server_id);
+            return;
+        }
+        // 与客户端重连相同，连续失败时等待时间翻倍，避免RCON服务器离线时频繁重试
+        let delay = self
+            .rcon_backoff
+            .get(&server_id)
+            .map_or(RCON_MIN_BACKOFF, |(_, delay)| (*delay * 2).min(RCON_MAX_BACKOFF));
+        self.rcon_backoff.insert(server_id, (Instant::now() + delay, delay));
+        log::warn!(server_id = server_id; "客户端{}的RCON投递失败，{}个玩家将在{:?}后重试", server_id, failed.len(), delay);
+        let queue = self.pending_messages.entry(server_id).or_default();
+        for action in failed.into_iter().rev() {
+            queue.push_front(action);
+        }
+    }
+
+    /// 尝试向在线客户端发送消息，客户端不在线或发送失败时返回false
+    fn try_send(&mut self, server_id: ServerId, action: &PlayerAction) -> bool {
+        if self.shutting_down {
+            return false;
+        }
+        let Some(conn_id) = self.client_list.get(&server_id).copied() else {
+            return false;
+        };
+        let Some(session) = self.sessions.get(&conn_id) else {
+            return false;
+        };
+        let id = self.next_delivery_id;
+        if session.send(SessionMessage::Deliver { id, action: action.clone() }).is_err() {
+            return false;
+        }
+        self.next_delivery_id += 1;
+        log::info!(
+            request_id = action.request_id(),
+            server_id = server_id,
+            conn_id = conn_id,
+            delivery_id = id,
+            player_id = action.player_id().as_str();
+            "已向客户端发送玩家{}的投递", action.player_id()
+        );
+        if self.ack_sessions.contains(&conn_id) {
+            self.inflight.entry(server_id).or_default().push_back((id, action.clone(), Instant::now()));
+        }
+        true
     }
-    async fn add_player(&mut self, key: Key, player_id: PlayerId) {
-        if let Some(conn_id) = self.client_list.get(&key) {
-            if let Some(session) = self.sessions.get(conn_id) {
-                if session.send(player_id.clone()).is_err() {
-                    self.queue_message(key, player_id).await;
+
+    /// 按投递编号确认消息，未携带编号的旧版客户端按玩家id确认最早的一条
+    async fn ack(&mut self, conn_id: ConnId, player_id: PlayerId, delivery_id: Option<DeliveryId>) {
+        if let Some(server_id) = self.client_list_reverse.get(&conn_id) {
+            if let Some(queue) = self.inflight.get_mut(server_id) {
+                let pos = match delivery_id {
+                    Some(delivery_id) => queue.iter().position(|(id, action, _)| *id == delivery_id && *action.player_id() == player_id),
+                    None => queue.iter().position(|(_, action, _)| *action.player_id() == player_id),
+                };
+                if let Some(pos) = pos {
+                    if let Some((id, action, _)) = queue.remove(pos) {
+                        log::info!(
+                            request_id = action.request_id(),
+                            server_id = *server_id,
+                            conn_id = conn_id,
+                            delivery_id = id,
+                            player_id = player_id.as_str();
+                            "客户端已确认玩家{}的投递", player_id
+                        );
+                    }
                 }
-            } else {
-                self.queue_message(key, player_id).await;
             }
-        } else {
-            self.queue_message(key, player_id).await;
         }
     }
+
     async fn process_pending_messages(&mut self) {
-        let mut keys_to_remove = Vec::new();
-
-        for (key, queue) in &mut self.pending_messages {
-            if let Some(conn_id) = self.client_list.get(key) {
-                if let Some(session) = self.sessions.get(conn_id) {
-                    while let Some(player_id) = queue.pop_front() {
-                        if session.send(player_id.clone()).is_err() {
-                            queue.push_front(player_id);
-                            break;
-                        }
-                    }
+        // 超时未确认的消息重新放入队列
+        let now = Instant::now();
+        for (server_id, inflight) in &mut self.inflight {
+            while inflight.front().is_some_and(|(_, _, sent)| now.duration_since(*sent) > ack_timeout()) {
+                let (_, action, _) = inflight.pop_front().unwrap();
+                log::warn!(
+                    request_id = action.request_id(),
+                    server_id = *server_id,
+                    player_id = action.player_id().as_str();
+                    "玩家{}的投递超时未确认，重新投递", action.player_id()
+                );
+                self.pending_messages.entry(*server_id).or_default().push_back(action);
+            }
+        }
+        self.inflight.retain(|_, inflight| !inflight.is_empty());
+
+        let server_ids: Vec<ServerId> = self.pending_messages.keys().cloned().collect();
+        for server_id in server_ids {
+            if let Delivery::Rcon(target) = self.delivery(server_id).await {
+                self.flush_rcon(server_id, target);
+                continue;
+            }
+            let Some(mut queue) = self.pending_messages.remove(&server_id) else {
+                continue;
+            };
+            while let Some(action) = queue.pop_front() {
+                if !self.try_send(server_id, &action) {
+                    queue.push_front(action);
+                    break;
                 }
             }
+            if !queue.is_empty() {
+                self.pending_messages.insert(server_id, queue);
+            }
+        }
+    }
 
-            if queue.is_empty() {
-                keys_to_remove.push(key.clone());
+    async fn queue_message(&mut self, server_id: ServerId, action: PlayerAction) {
+        self.pending_messages.entry(server_id).or_default().push_back(action);
+    }
+
+    /// 通知所有链接服务端即将关闭，ws_handler收到后会关闭链接
+    fn going_away(&mut self) {
+        self.shutting_down = true;
+        let reconnect_in = CONFIG.current().timeouts.reconnect_delay;
+        for session in self.sessions.values() {
+            let _ = session.send(SessionMessage::GoingAway { reconnect_in });
+        }
+        log::info!("已通知{}个链接服务端即将关闭", self.sessions.len());
+    }
+
+    /// 上次关闭前保存的消息放回队列，客户端上线后重新投递
+    async fn restore_pending(&mut self) {
+        match self.sql_handler.take_pending_deliveries().await {
+            Ok(deliveries) => {
+                if !deliveries.is_empty() {
+                    log::info!("已恢复{}条上次关闭前尚未投递的消息", deliveries.len());
+                }
+                for (server_id, action) in deliveries {
+                    self.pending_messages.entry(server_id).or_default().push_back(action);
+                }
             }
+            Err(e) => log::error!("读取上次关闭前保存的消息失败: {}", e),
         }
+    }
 
-        for key in keys_to_remove {
-            self.pending_messages.remove(&key);
+    /// 等待进行中的RCON投递结束后保存所有尚未投递及尚未确认的消息
+    async fn shutdown(&mut self) {
+        if !self.shutting_down {
+            self.going_away();
+        }
+        let deadline = time::Instant::now() + Duration::from_secs(CONFIG.current().timeouts.shutdown_grace);
+        while !self.rcon_busy.is_empty() {
+            match time::timeout_at(deadline, self.cmd_rx.recv()).await {
+                Ok(Some(cmd)) => self.handle(cmd).await,
+                _ => {
+                    log::warn!("等待RCON投递超时，{}个客户端的投递结果未知", self.rcon_busy.len());
+                    break;
+                }
+            }
+        }
+        for (server_id, inflight) in std::mem::take(&mut self.inflight) {
+            let queue = self.pending_messages.entry(server_id).or_default();
+            for (_, action, _) in inflight.into_iter().rev() {
+                queue.push_front(action);
+            }
+        }
+        let deliveries: PendingDeliveries = std::mem::take(&mut self.pending_messages)
+            .into_iter()
+            .flat_map(|(server_id, queue)| queue.into_iter().map(move |action| (server_id, action)))
+            .collect();
+        if deliveries.is_empty() {
+            return;
+        }
+        let count = deliveries.len();
+        match self.sql_handler.save_pending_deliveries(deliveries).await {
+            Ok(()) => log::info!("已保存{}条尚未投递的消息，下次启动后继续投递", count),
+            Err(e) => log::error!("保存尚未投递的消息失败，{}条消息将丢失: {}", count, e),
         }
     }
 
-    async fn queue_message(&mut self, key: Key, player_id: PlayerId) {
-        self.pending_messages.entry(key).or_default().push_back(player_id);
+    async fn handle(&mut self, cmd: Command) {
+        match cmd {
+            Command::Connect { conn_tx, res_tx } => {
+                let conn_id = self.connect(conn_tx).await;
+                let _ = res_tx.send(conn_id);
+            }
+
+            Command::Disconnect { conn } => {
+                self.disconnect(conn);
+            }
+
+            Command::AddPlayer { server_id, action, res_tx } => {
+                self.add_player(server_id, action).await;
+                let _ = res_tx.send(());
+            }
+
+            Command::RevokePlayer { server_id, id, request_id } => {
+                self.revoke_player(server_id, id, request_id).await;
+            }
+
+            Command::Status { server_id, res_tx } => {
+                let status = self.status(server_id).await;
+                let _ = res_tx.send(status);
+            }
+
+            Command::OnlineServers { res_tx } => {
+                let _ = res_tx.send(self.client_list.keys().copied().collect());
+            }
+
+            Command::Metrics { res_tx } => {
+                let _ = res_tx.send(self.metrics());
+            }
+
+            Command::Verify { key, res_tx, conn_id, ack } => {
+                let res = self.verify(key, conn_id, ack).await;
+                let _ = res_tx.send(res);
+            }
+
+            Command::Ack { conn, player_id, delivery_id } => {
+                self.ack(conn, player_id, delivery_id).await;
+            }
+
+            Command::ReloadDelivery { server_id } => {
+                self.deliveries.remove(&server_id);
+                // 投递方式可能已修改，立即重试
+                self.rcon_backoff.remove(&server_id);
+            }
+
+            Command::KeyRotated { server_id, expires_at } => {
+                self.key_rotated(server_id, expires_at);
+            }
+
+            Command::Kick { server_id, purge } => {
+                self.kick(server_id, purge);
+            }
+
+            Command::RconFinished { server_id, failed } => {
+                self.rcon_finished(server_id, failed);
+            }
+
+            Command::GoingAway => {
+                self.going_away();
+            }
+
+            // 由run处理
+            Command::Shutdown { .. } => {}
+        }
     }
 
     pub async fn run(mut self) -> io::Result<()> {
         let mut interval = time::interval(Duration::from_secs(5));
+        self.restore_pending().await;
 
         loop {
             tokio::select! {
                 Some(cmd) = self.cmd_rx.recv() => {
-                    match cmd {
-                        Command::Connect { conn_tx, res_tx } => {
-                            let conn_id = self.connect(conn_tx).await;
-                            let _ = res_tx.send(conn_id);
-                        }
-
-                        Command::Disconnect { conn } => {
-                            self.disconnect(conn).await;
-                        }
-
-                        Command::AddPlayer { key, id, res_tx } => {
-                            self.add_player(key, id).await;
-                            let _ = res_tx.send(());
-                        }
-
-                        Command::Verify { key, res_tx, conn_id } => {
-                            let res = self.verify(key, conn_id).await;
-                            let _ = res_tx.send(res);
-                        }
+                    if let Command::Shutdown { res_tx } = cmd {
+                        self.shutdown().await;
+                        let _ = res_tx.send(());
+                        return Ok(());
                     }
+                    self.handle(cmd).await;
                 }
                 _ = interval.tick() => {
+                    self.expire_sessions();
                     self.process_pending_messages().await;
                 }
             }
@@ -208,12 +654,12 @@ impl WsServer {
 }
 #[derive(Debug, Clone)]
 pub struct WsServerHandle {
-    cmd_tx: mpsc::UnboundedSender<Command>,
+    cmd_tx: CommandSender<Command>,
 }
 
 impl WsServerHandle {
     /// 处理来自客户端的连接
-    pub async fn connect(&self, conn_tx: mpsc::UnboundedSender<Key>) -> Result<ConnId, io::Error> {
+    pub async fn connect(&self, conn_tx: mpsc::UnboundedSender<SessionMessage>) -> Result<ConnId, io::Error> {
         let (res_tx, res_rx) = oneshot::channel();
         self.cmd_tx
             .send(Command::Connect { conn_tx, res_tx })
@@ -223,12 +669,12 @@ impl WsServerHandle {
         Ok( res_rx.await.unwrap())
     }
 
-    /// 验证客户端密钥
-    pub async fn verify(&self, key: Key, conn_id: ConnId) -> Result<String, Box<dyn Error + Send + Sync>> {
+    /// 验证客户端密钥，ack为true时该链接需要确认每条投递
+    pub async fn verify(&self, key: Key, conn_id: ConnId, ack: bool) -> Result<String, Box<dyn Error + Send + Sync>> {
         
         let (res_tx, res_rx) = oneshot::channel();
         self.cmd_tx
-            .send(Command::Verify {key, conn_id, res_tx })
+            .send(Command::Verify {key, conn_id, ack, res_tx })
             .unwrap();
 
         // unwrap: chat server does not drop out response channel
@@ -237,15 +683,15 @@ impl WsServerHandle {
     }
 
     
-    /// 向特定客户端发送消息
-    pub async fn send_message(&self, key: Key, player_id: impl Into<PlayerId>) {
+    /// 向特定客户端发送消息，target为试卷指定的投递目标，request_id用于在日志中追踪投递过程
+    pub async fn send_message(&self, server_id: ServerId, player_id: impl Into<PlayerId>, target: Option<String>, request_id: &RequestId) {
         let (res_tx, res_rx) = oneshot::channel();
 
         // 将指令发送到指定的客户端
         self.cmd_tx
             .send(Command::AddPlayer {
-                id: player_id.into(),
-                key,
+                action: PlayerAction::Add { player_id: player_id.into(), target, request_id: Some(request_id.to_string()) },
+                server_id,
                 res_tx,
             })
             .unwrap();
@@ -254,9 +700,107 @@ impl WsServerHandle {
         res_rx.await.unwrap();
     }
 
+    /// 撤销玩家的资格，通知客户端将其移出白名单
+    pub fn revoke_player(&self, server_id: ServerId, player_id: impl Into<PlayerId>, request_id: &RequestId) {
+        // unwrap: chat server should not have been dropped
+        self.cmd_tx.send(Command::RevokePlayer { server_id, id: player_id.into(), request_id: request_id.to_string() }).unwrap();
+    }
+
+    /// 查询客户端的连接状态和投递队列
+    pub async fn status(&self, server_id: ServerId) -> SessionStatus {
+        let (res_tx, res_rx) = oneshot::channel();
+        self.cmd_tx.send(Command::Status { server_id, res_tx }).unwrap();
+        // unwrap: chat server does not drop our response channel
+        res_rx.await.unwrap()
+    }
+
+    /// 查询所有在线客户端的id
+    pub async fn online_servers(&self) -> HashSet<ServerId> {
+        let (res_tx, res_rx) = oneshot::channel();
+        self.cmd_tx.send(Command::OnlineServers { res_tx }).unwrap();
+        // unwrap: chat server does not drop our response channel
+        res_rx.await.unwrap()
+    }
+
+    /// 查询链接数及各客户端积压的消息数
+    pub async fn metrics(&self) -> SessionMetrics {
+        let (res_tx, res_rx) = oneshot::channel();
+        self.cmd_tx.send(Command::Metrics { res_tx }).unwrap();
+        // unwrap: chat server does not drop our response channel
+        res_rx.await.unwrap()
+    }
+
+    /// 客户端确认已处理某个玩家，delivery_id为投递时下发的编号
+    pub fn ack(&self, conn: ConnId, player_id: PlayerId, delivery_id: Option<DeliveryId>) {
+        // unwrap: chat server should not have been dropped
+        self.cmd_tx.send(Command::Ack { conn, player_id, delivery_id }).unwrap();
+    }
+
+    /// 客户端轮换了密钥，使用旧密钥的链接会在旧密钥失效时断开
+    pub fn key_rotated(&self, server_id: ServerId, expires_at: i64) {
+        // unwrap: chat server should not have been dropped
+        self.cmd_tx.send(Command::KeyRotated { server_id, expires_at }).unwrap();
+    }
+
+    /// 断开服务器的在线链接，purge为true时同时丢弃尚未投递的消息
+    pub fn kick(&self, server_id: ServerId, purge: bool) {
+        // unwrap: chat server should not have been dropped
+        self.cmd_tx.send(Command::Kick { server_id, purge }).unwrap();
+    }
+
+    /// 客户端修改了投递方式
+    pub fn reload_delivery(&self, server_id: ServerId) {
+        // unwrap: chat server should not have been dropped
+        self.cmd_tx.send(Command::ReloadDelivery { server_id }).unwrap();
+    }
+
     /// 断开链接并从服务器注销链接
     pub fn disconnect(&self, conn: ConnId) {
+        // 关闭过程中ws server可能已先于链接退出，此时无需注销
+        let _ = self.cmd_tx.send(Command::Disconnect { conn });
+    }
+
+    /// 通知所有链接服务端即将关闭，之后的投递只进入队列
+    pub fn going_away(&self) {
+        // unwrap: chat server should not have been dropped
+        self.cmd_tx.send(Command::GoingAway).unwrap();
+    }
+
+    /// 保存尚未投递的消息后退出，之后不能再发送命令
+    pub async fn shutdown(&self) {
+        let (res_tx, res_rx) = oneshot::channel();
         // unwrap: chat server should not have been dropped
-        self.cmd_tx.send(Command::Disconnect { conn }).unwrap();
+        self.cmd_tx.send(Command::Shutdown { res_tx }).unwrap();
+        let _ = res_rx.await;
     }
-}
\ No newline at end of file
+}
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::config::Config;
+    use crate::sql_server::tests::test_server;
+    use actix_web::{test::TestRequest, FromRequest};
+
+    #[actix_web::test]
+    async fn pending_deliveries_survive_restart() {
+        let (sql_server, sql_handle) = test_server("ws-restart", Config::default()).await;
+        actix_web::rt::spawn(sql_server.run());
+        let request_id = RequestId::extract(&TestRequest::default().to_http_request()).await.unwrap();
+        let add = |player_id: &str| PlayerAction::Add { player_id: player_id.into(), target: None, request_id: Some(request_id.to_string()) };
+
+        let (server, handle) = WsServer::new(sql_handle.clone());
+        actix_web::rt::spawn(server.run());
+        // 客户端都不在线，消息进入队列
+        handle.send_message(1, "Steve", None, &request_id).await;
+        handle.send_message(1, "Alex", None, &request_id).await;
+        handle.send_message(2, "jeb_", None, &request_id).await;
+        handle.shutdown().await;
+
+        let (server, handle) = WsServer::new(sql_handle.clone());
+        actix_web::rt::spawn(server.run());
+        assert_eq!(handle.status(1).await.pending, [add("Steve"), add("Alex")]);
+        assert_eq!(handle.status(2).await.pending, [add("jeb_")]);
+        // 恢复后数据库中不再保留，避免下次启动重复投递
+        assert!(sql_handle.take_pending_deliveries().await.unwrap().is_empty());
+    }
+}